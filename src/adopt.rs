@@ -0,0 +1,200 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use crate::capabilities;
+use crate::changes::collect_files;
+use crate::config::{NasConfig, SyncProfile};
+use crate::conflict::{self, ConflictResolver, FileMetadata};
+use crate::remote::{shell_quote, RemoteShell};
+use crate::smb::SmbMount;
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+
+/// How the local and NAS sides of a profile compared
+#[derive(Debug, Default)]
+pub struct AdoptReport {
+    /// Identical on both sides, recorded as synced
+    pub adopted: usize,
+    /// Present on both sides with different content, relative to the transfer root
+    pub differing: Vec<String>,
+    pub local_only: Vec<String>,
+    pub remote_only: Vec<String>,
+}
+
+/// Hash both sides of a profile and record files with identical content as already
+/// synced, so the first two-way sync of existing data only sees genuine differences
+pub async fn adopt(nas: &NasConfig, profile: &SyncProfile, resolver: &ConflictResolver, dry_run: bool) -> Result<AdoptReport> {
+    let local = local_hashes(profile)?;
+    let remote = match (&nas.smb, profile.use_smb_mount) {
+        (Some(smb_config), true) if smb_config.enabled => {
+            let mut mount = SmbMount::new(smb_config.clone());
+            mount.mount().await?;
+            let hashes = mounted_hashes(Path::new(&profile.remote_path));
+            if mount.should_auto_unmount() {
+                mount.unmount().await?;
+            }
+            hashes?
+        }
+        _ => remote_hashes(nas, profile)?,
+    };
+
+    let (report, matched) = compare(&local, &remote);
+    if !dry_run {
+        resolver.record_adopted(&matched)?;
+    }
+    Ok(report)
+}
+
+/// Local files of a profile by their path below the transfer root, with metadata and hash
+fn local_hashes(profile: &SyncProfile) -> Result<BTreeMap<String, FileMetadata>> {
+    let root = profile.transfer_root();
+    let mut files = Vec::new();
+    for source in profile.source_paths() {
+        collect_files(Path::new(source), &profile.effective_excludes(), &mut files, &mut Vec::new());
+    }
+
+    let mut hashes = BTreeMap::new();
+    for file in files {
+        let Ok(relative) = file.strip_prefix(&root) else {
+            continue;
+        };
+        // Files that cannot be read are left for the next sync to report
+        let metadata = match local_metadata(&file) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                warn!("Not adopting {}: {:#}", file.display(), e);
+                continue;
+            }
+        };
+        hashes.insert(relative.to_string_lossy().to_string(), metadata);
+    }
+    debug!("Hashed {} local file(s) of profile {}", hashes.len(), profile.name);
+    Ok(hashes)
+}
+
+fn local_metadata(file: &Path) -> Result<FileMetadata> {
+    let metadata = fs::metadata(file).with_context(|| format!("Failed to read {}", file.display()))?;
+    let modified = metadata
+        .modified()?
+        .duration_since(std::time::UNIX_EPOCH)
+        .context("Invalid modification time")?
+        .as_secs() as i64;
+    Ok(FileMetadata {
+        path: conflict::state_key(file),
+        size: metadata.len(),
+        modified,
+        hash: conflict::hash_file(file)?,
+    })
+}
+
+/// Hashes of the files below a locally mounted remote path
+fn mounted_hashes(remote_path: &Path) -> Result<BTreeMap<String, String>> {
+    let mut files: Vec<PathBuf> = Vec::new();
+    collect_files(remote_path, &[], &mut files, &mut Vec::new());
+    files
+        .iter()
+        .filter_map(|file| Some((file.strip_prefix(remote_path).ok()?.to_string_lossy().to_string(), file)))
+        .map(|(relative, file)| Ok((relative, conflict::hash_file(file)?)))
+        .collect()
+}
+
+/// Hashes of the files below the remote path, computed on the NAS with `sha256sum`
+fn remote_hashes(nas: &NasConfig, profile: &SyncProfile) -> Result<BTreeMap<String, String>> {
+    let shell = RemoteShell::new(nas)?.with_owner(profile.owner.as_ref());
+    let capabilities = capabilities::of(&shell)?;
+    capabilities.require(capabilities.sha256sum, "sha256sum", "Adopting")?;
+    let output = shell
+        .run(&format!(
+            "cd {} && find . -type f -exec sha256sum {{}} +",
+            shell_quote(&profile.remote_path)
+        ))
+        .with_context(|| format!("Failed to hash {} on the NAS", profile.remote_path))?;
+    Ok(parse_sha256sum(&output))
+}
+
+/// `<hash>  ./<path>` lines; names sha256sum had to escape are left out and so count
+/// as differing
+pub(crate) fn parse_sha256sum(output: &str) -> BTreeMap<String, String> {
+    output
+        .lines()
+        .filter(|line| !line.starts_with('\\'))
+        .filter_map(|line| {
+            let (hash, path) = line.split_once("  ")?;
+            Some((path.trim_start_matches("./").to_string(), hash.to_string()))
+        })
+        .collect()
+}
+
+/// Split both sides into identical, differing and one-sided files
+fn compare(local: &BTreeMap<String, FileMetadata>, remote: &BTreeMap<String, String>) -> (AdoptReport, Vec<FileMetadata>) {
+    let mut report = AdoptReport::default();
+    let mut matched = Vec::new();
+
+    for (relative, meta) in local {
+        match remote.get(relative) {
+            Some(hash) if *hash == meta.hash => matched.push(meta.clone()),
+            Some(_) => report.differing.push(relative.clone()),
+            None => report.local_only.push(relative.clone()),
+        }
+    }
+    report.remote_only = remote.keys().filter(|relative| !local.contains_key(*relative)).cloned().collect();
+    report.adopted = matched.len();
+    (report, matched)
+}
+
+pub fn print_report(profile: &SyncProfile, report: &AdoptReport, dry_run: bool) {
+    println!(
+        "{}: {} identical file(s) {}",
+        profile.name,
+        report.adopted,
+        if dry_run { "would be adopted" } else { "adopted as already synced" }
+    );
+    if !report.differing.is_empty() {
+        warn!("{} file(s) differ and will be handled by the next sync", report.differing.len());
+        for relative in &report.differing {
+            println!("  differs     {}", relative);
+        }
+    }
+    for relative in &report.local_only {
+        println!("  local only  {}", relative);
+    }
+    for relative in &report.remote_only {
+        println!("  NAS only    {}", relative);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare_adopts_identical_files_only() {
+        let remote = parse_sha256sum("aaa  ./docs/same.txt\nbbb  ./docs/changed.txt\nccc  ./docs/remote.txt\n\\ddd  ./docs/odd\\nname\n");
+        assert_eq!(remote.len(), 3);
+
+        let mut local = BTreeMap::new();
+        for (relative, hash) in [("docs/same.txt", "aaa"), ("docs/changed.txt", "xxx"), ("docs/local.txt", "eee")] {
+            local.insert(
+                relative.to_string(),
+                FileMetadata {
+                    path: format!("/home/me/{}", relative),
+                    size: 1,
+                    modified: 1,
+                    hash: hash.to_string(),
+                },
+            );
+        }
+
+        let (report, matched) = compare(&local, &remote);
+        assert_eq!(report.adopted, 1);
+        assert_eq!(matched[0].path, "/home/me/docs/same.txt");
+        assert_eq!(report.differing, vec!["docs/changed.txt"]);
+        assert_eq!(report.local_only, vec!["docs/local.txt"]);
+        assert_eq!(report.remote_only, vec!["docs/remote.txt"]);
+    }
+}
@@ -0,0 +1,343 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use crate::changes;
+use crate::config::{ArchiveStub, Config, SyncProfile};
+use crate::conflict;
+use crate::device;
+use crate::remote::RemoteShell;
+use crate::smb::SmbMount;
+use crate::sync::FileError;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, SystemTime};
+use tracing::{info, warn};
+
+/// Extension of the placeholder left behind for an archived file
+pub const STUB_EXTENSION: &str = "ugnassync-stub";
+
+/// Audit log entry for an archived file; placeholder stubs hold the same record
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArchiveRecord {
+    pub archived_at: DateTime<Local>,
+    pub profile: String,
+    /// Name of the machine that archived it; empty in entries written before devices were named
+    #[serde(default)]
+    pub device: String,
+    pub local_path: String,
+    pub remote_path: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+/// rsync `--files-from` list of the current archive run
+pub fn list_path(profile: &SyncProfile) -> PathBuf {
+    let name: String = profile
+        .name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    std::env::temp_dir().join(format!("ugnassync-archive-{}.list", name))
+}
+
+/// Pick files not modified for `archive_after_days` and write them to the rsync file
+/// list; also returns what could not be read and was skipped
+pub fn select(profile: &SyncProfile) -> Result<(Vec<String>, Vec<FileError>)> {
+    let cutoff = SystemTime::now() - Duration::from_secs(profile.archive_after_days * 24 * 60 * 60);
+    let root = profile.transfer_root();
+    let excludes = profile.effective_excludes();
+
+    let (mut files, mut unreadable) = (Vec::new(), Vec::new());
+    for source in profile.source_paths() {
+        changes::collect_files(Path::new(source), &excludes, &mut files, &mut unreadable);
+    }
+
+    let mut selected: Vec<String> = files
+        .into_iter()
+        .filter(|path| path.extension().is_none_or(|ext| ext != STUB_EXTENSION))
+        .filter(|path| {
+            fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .is_ok_and(|modified| modified < cutoff)
+        })
+        .filter_map(|path| {
+            path.strip_prefix(&root)
+                .ok()
+                .map(|relative| relative.to_string_lossy().to_string())
+        })
+        .collect();
+    selected.sort();
+
+    let list = list_path(profile);
+    let mut contents = selected.join("\n");
+    contents.push('\n');
+    fs::write(&list, contents).with_context(|| format!("Failed to write {}", list.display()))?;
+
+    info!(
+        "Selected {} file(s) older than {} days for archiving",
+        selected.len(),
+        profile.archive_after_days
+    );
+    Ok((selected, unreadable))
+}
+
+/// Delete verified files locally, leave the configured stub and record each in the audit log
+pub fn release(profile: &SyncProfile, verified: &[String]) -> (Vec<String>, Vec<String>) {
+    let root = profile.transfer_root();
+    let mut released = Vec::new();
+    let mut warnings = Vec::new();
+
+    for relative in verified {
+        let local = Path::new(&root).join(relative);
+        let remote = format!("{}/{}", profile.remote_path.trim_end_matches('/'), relative);
+
+        match release_file(profile, &local, &remote) {
+            Ok(()) => released.push(relative.clone()),
+            Err(e) => {
+                warn!("Failed to archive {}: {:#}", local.display(), e);
+                warnings.push(format!("Failed to archive {}: {:#}", local.display(), e));
+            }
+        }
+    }
+
+    let _ = fs::remove_file(list_path(profile));
+    info!("Archived {} file(s) to {}", released.len(), profile.remote_path);
+    (released, warnings)
+}
+
+fn release_file(profile: &SyncProfile, local: &Path, remote: &str) -> Result<()> {
+    let record = ArchiveRecord {
+        archived_at: Local::now(),
+        profile: profile.name.clone(),
+        device: device::current().name.clone(),
+        local_path: local.to_string_lossy().to_string(),
+        remote_path: remote.to_string(),
+        size: fs::metadata(local)?.len(),
+        sha256: conflict::hash_file(local)?,
+    };
+
+    // Log first so no deleted file is missing from the audit trail
+    append_audit(profile, &record)?;
+    fs::remove_file(local).with_context(|| format!("Failed to delete {}", local.display()))?;
+
+    match profile.archive_stub {
+        ArchiveStub::None => {}
+        ArchiveStub::Symlink => std::os::unix::fs::symlink(remote, local)
+            .with_context(|| format!("Failed to create symlink {}", local.display()))?,
+        ArchiveStub::Placeholder => {
+            let stub = stub_path(local);
+            fs::write(&stub, serde_json::to_string_pretty(&record)?)
+                .with_context(|| format!("Failed to write {}", stub.display()))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// `video.mkv` -> `video.mkv.ugnassync-stub`
+pub fn stub_path(local: &Path) -> PathBuf {
+    let mut name = local.as_os_str().to_owned();
+    name.push(format!(".{}", STUB_EXTENSION));
+    PathBuf::from(name)
+}
+
+/// Placeholder stubs for a path: the file itself, its stub, or every stub below a directory
+fn find_stubs(path: &Path) -> Result<Vec<PathBuf>> {
+    if path.extension().is_some_and(|ext| ext == STUB_EXTENSION) {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    if stub_path(path).is_file() {
+        return Ok(vec![stub_path(path)]);
+    }
+    if path.is_dir() {
+        let mut files = Vec::new();
+        changes::collect_files(path, &[], &mut files, &mut Vec::new());
+        files.retain(|file| file.extension().is_some_and(|ext| ext == STUB_EXTENSION));
+        files.sort();
+        return Ok(files);
+    }
+    anyhow::bail!("{} is not an archived file (no .{} placeholder)", path.display(), STUB_EXTENSION)
+}
+
+/// Fetch archived files back from the NAS and replace their placeholder stubs
+pub async fn hydrate(config: &Config, path: &Path) -> Result<Vec<PathBuf>> {
+    let stubs = find_stubs(path)?;
+    let mut smb_mount: Option<SmbMount> = None;
+    let mut shell: Option<RemoteShell> = None;
+    let mut hydrated = Vec::new();
+    let mut failures = Vec::new();
+
+    for stub in stubs {
+        let record: ArchiveRecord = match fs::read_to_string(&stub)
+            .map_err(anyhow::Error::from)
+            .and_then(|contents| Ok(serde_json::from_str(&contents)?))
+        {
+            Ok(record) => record,
+            Err(e) => {
+                failures.push(format!("{}: invalid placeholder: {:#}", stub.display(), e));
+                continue;
+            }
+        };
+        let profile = config.sync_profiles.iter().find(|p| p.name == record.profile);
+        // SMB profiles archived to a path on the mounted share
+        let smb_config = config
+            .nas
+            .smb
+            .as_ref()
+            .filter(|smb| smb.enabled)
+            .filter(|_| profile.is_some_and(|p| p.use_smb_mount));
+
+        let (source, via) = match smb_config {
+            Some(smb_config) => {
+                if smb_mount.is_none() {
+                    let mut mount = SmbMount::new(smb_config.clone());
+                    mount.mount().await?;
+                    smb_mount = Some(mount);
+                }
+                (record.remote_path.clone(), None)
+            }
+            None => {
+                if shell.is_none() {
+                    shell = Some(RemoteShell::new(&config.nas)?);
+                }
+                let shell = shell.as_ref().unwrap();
+                (shell.destination(&record.remote_path), Some(shell))
+            }
+        };
+
+        let mut cmd = Command::new(config.nas.rsync_binary());
+        if let Some(remote_rsync) = profile.and_then(|p| p.remote_rsync_command()) {
+            cmd.arg(format!("--rsync-path={}", remote_rsync));
+        }
+        match fetch(&record, &source, via, cmd) {
+            Ok(()) => {
+                let _ = fs::remove_file(&stub);
+                info!("Hydrated {}", record.local_path);
+                hydrated.push(PathBuf::from(&record.local_path));
+            }
+            Err(e) => failures.push(format!("{}: {:#}", record.local_path, e)),
+        }
+    }
+
+    if let Some(mut mount) = smb_mount {
+        if mount.should_auto_unmount() {
+            mount.unmount().await?;
+        }
+    }
+
+    if !failures.is_empty() {
+        anyhow::bail!(
+            "Hydrated {} file(s), {} failed:\n  {}",
+            hydrated.len(),
+            failures.len(),
+            failures.join("\n  ")
+        );
+    }
+    Ok(hydrated)
+}
+
+/// Copy one archived file back and check it against the hash recorded when it was archived
+fn fetch(record: &ArchiveRecord, source: &str, shell: Option<&RemoteShell>, mut cmd: Command) -> Result<()> {
+    let local = Path::new(&record.local_path);
+    if local.exists() {
+        anyhow::bail!("{} already exists locally", local.display());
+    }
+
+    cmd.arg("-a");
+    if let Some(shell) = shell {
+        cmd.arg("-e").arg(shell.rsync_rsh());
+        shell.authenticate(&mut cmd)?;
+    }
+    let output = cmd.arg(source).arg(local).output().context("Failed to execute rsync")?;
+    if !output.status.success() {
+        anyhow::bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+
+    if conflict::hash_file(local)? != record.sha256 {
+        fs::remove_file(local)?;
+        anyhow::bail!("Checksum mismatch, the NAS copy changed since it was archived");
+    }
+    Ok(())
+}
+
+pub fn audit_log_path(profile: &SyncProfile) -> Result<PathBuf> {
+    match &profile.archive_log {
+        Some(path) => Ok(PathBuf::from(path)),
+        None => Ok(dirs::home_dir()
+            .context("Could not determine home directory")?
+            .join(".ugnassync")
+            .join("archive.log")),
+    }
+}
+
+fn append_audit(profile: &SyncProfile, record: &ArchiveRecord) -> Result<()> {
+    let path = audit_log_path(profile)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let mut log = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open archive log: {}", path.display()))?;
+    writeln!(log, "{}", serde_json::to_string(record)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_release_leaves_placeholder_and_audit_entry() {
+        let dir = std::env::temp_dir().join(format!("ugnassync-archive-test-{}", std::process::id()));
+        fs::create_dir_all(dir.join("media")).unwrap();
+        fs::write(dir.join("media/old.mkv"), "video").unwrap();
+
+        let profile: SyncProfile = toml::from_str(&format!(
+            r#"
+            name = "media"
+            local_path = "{0}/media"
+            remote_path = "/volume1/archive"
+            sync_type = "archive"
+            enabled = true
+            archive_after_days = 0
+            archive_stub = "placeholder"
+            archive_log = "{0}/archive.log"
+            "#,
+            dir.display()
+        ))
+        .unwrap();
+
+        let (selected, _) = select(&profile).unwrap();
+        assert_eq!(selected, vec!["media/old.mkv".to_string()]);
+
+        let (released, warnings) = release(&profile, &selected);
+        assert_eq!(released, selected);
+        assert!(warnings.is_empty());
+        assert!(!dir.join("media/old.mkv").exists());
+
+        let stub: ArchiveRecord =
+            serde_json::from_str(&fs::read_to_string(dir.join("media/old.mkv.ugnassync-stub")).unwrap()).unwrap();
+        assert_eq!(stub.remote_path, "/volume1/archive/media/old.mkv");
+        assert_eq!(stub.size, 5);
+        assert_eq!(fs::read_to_string(dir.join("archive.log")).unwrap().lines().count(), 1);
+
+        // The placeholder itself is never archived
+        assert!(select(&profile).unwrap().0.is_empty());
+
+        let stub = dir.join("media/old.mkv.ugnassync-stub");
+        assert_eq!(find_stubs(&dir.join("media/old.mkv")).unwrap(), vec![stub.clone()]);
+        assert_eq!(find_stubs(&dir).unwrap(), vec![stub]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
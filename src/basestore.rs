@@ -0,0 +1,140 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use crate::config::BaseStoreConfig;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tracing::{debug, info};
+
+/// Content-addressed copies of files as they were last synced, keyed by SHA-256
+#[derive(Clone)]
+pub struct BaseStore {
+    dir: PathBuf,
+    max_file_bytes: u64,
+    max_total_bytes: u64,
+}
+
+impl BaseStore {
+    pub fn new(config: &BaseStoreConfig) -> Result<Self> {
+        Self::with_dir(Self::default_dir()?, config)
+    }
+
+    /// `~/.ugnassync/bases`
+    pub fn default_dir() -> Result<PathBuf> {
+        Ok(dirs::home_dir()
+            .context("Could not determine home directory")?
+            .join(".ugnassync")
+            .join("bases"))
+    }
+
+    fn with_dir(dir: PathBuf, config: &BaseStoreConfig) -> Result<Self> {
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create base store: {}", dir.display()))?;
+
+        Ok(Self {
+            dir,
+            max_file_bytes: config.max_file_mb * 1024 * 1024,
+            max_total_bytes: config.max_total_mb * 1024 * 1024,
+        })
+    }
+
+    /// Keep a copy of `path` under its content hash; large files are skipped
+    pub fn put(&self, path: &Path, hash: &str) -> Result<()> {
+        let size = fs::metadata(path)?.len();
+        if size > self.max_file_bytes {
+            debug!("Not storing base version of {} ({} bytes)", path.display(), size);
+            return Ok(());
+        }
+
+        let object = self.object_path(hash);
+        if object.exists() {
+            // Refresh the mtime so pruning treats it as recently used
+            fs::File::options().append(true).open(&object)?.set_modified(SystemTime::now())?;
+            return Ok(());
+        }
+
+        if let Some(dir) = object.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // Copy to a temporary name first so a crash never leaves a truncated object
+        let partial = object.with_extension("partial");
+        fs::copy(path, &partial)
+            .with_context(|| format!("Failed to store base version of {}", path.display()))?;
+        fs::rename(&partial, &object)?;
+
+        Ok(())
+    }
+
+    /// Stored copy for a content hash, if it is still in the store
+    pub fn get(&self, hash: &str) -> Option<PathBuf> {
+        let object = self.object_path(hash);
+        object.exists().then_some(object)
+    }
+
+    /// Delete the least recently stored objects until the store fits its size cap
+    pub fn prune(&self) -> Result<()> {
+        let mut objects = Vec::new();
+        for shard in fs::read_dir(&self.dir)? {
+            for entry in fs::read_dir(shard?.path())? {
+                let entry = entry?;
+                let metadata = entry.metadata()?;
+                objects.push((metadata.modified()?, metadata.len(), entry.path()));
+            }
+        }
+
+        let mut total: u64 = objects.iter().map(|(_, size, _)| size).sum();
+        if total <= self.max_total_bytes {
+            return Ok(());
+        }
+
+        objects.sort();
+        let mut removed = 0;
+        for (_, size, path) in objects {
+            if total <= self.max_total_bytes {
+                break;
+            }
+            fs::remove_file(&path)?;
+            total -= size;
+            removed += 1;
+        }
+
+        info!("Pruned {} base version(s) from {}", removed, self.dir.display());
+        Ok(())
+    }
+
+    fn object_path(&self, hash: &str) -> PathBuf {
+        let shard = hash.get(..2).unwrap_or("00");
+        self.dir.join(shard).join(hash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_put_get_and_prune() {
+        let root = std::env::temp_dir().join(format!("ugnassync-basestore-test-{}", std::process::id()));
+        let config = BaseStoreConfig {
+            max_file_mb: 1,
+            max_total_mb: 0,
+        };
+        let store = BaseStore::with_dir(root.join("bases"), &config).unwrap();
+
+        let file = root.join("doc.txt");
+        fs::write(&file, "base content").unwrap();
+        store.put(&file, "abcdef").unwrap();
+
+        let object = store.get("abcdef").unwrap();
+        assert_eq!(fs::read_to_string(object).unwrap(), "base content");
+
+        store.prune().unwrap();
+        assert!(store.get("abcdef").is_none());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
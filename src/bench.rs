@@ -0,0 +1,262 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use crate::config::NasConfig;
+use crate::capabilities;
+use crate::remote::{shell_quote, RemoteShell};
+use crate::smb::SmbMount;
+use anyhow::{Context, Result};
+use std::fs;
+use std::io::Write;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+const BENCH_FILE_NAME: &str = ".ugnassync-bench.bin";
+const LATENCY_PROBES: u32 = 3;
+
+/// Throughput and latency measured for one transport
+pub struct BenchResult {
+    pub transport: &'static str,
+    pub latency_ms: Option<f64>,
+    pub write_mb_s: f64,
+    pub read_mb_s: f64,
+}
+
+/// Benchmark every configured transport with `size_mb` of incompressible data
+pub async fn run(nas: &NasConfig, remote_dir: &str, size_mb: u64) -> Result<Vec<(&'static str, Result<BenchResult>)>> {
+    let local_file = std::env::temp_dir().join(format!("ugnassync-bench-{}.bin", std::process::id()));
+    let read_back = local_file.with_extension("read");
+    write_test_file(&local_file, size_mb)?;
+
+    let mut results = Vec::new();
+
+    info!("Benchmarking rsync/SSH with {} MB", size_mb);
+    results.push(("rsync/SSH", bench_rsync(nas, remote_dir, &local_file, &read_back, size_mb)));
+
+    info!("Benchmarking SFTP with {} MB", size_mb);
+    results.push(("SFTP", bench_sftp(nas, remote_dir, &local_file, &read_back, size_mb)));
+
+    match &nas.smb {
+        Some(smb) if smb.enabled => {
+            info!("Benchmarking SMB mount with {} MB", size_mb);
+            results.push(("SMB mount", bench_smb(nas, &local_file, &read_back, size_mb).await));
+        }
+        _ => info!("SMB not configured, skipping SMB benchmark"),
+    }
+
+    let _ = fs::remove_file(&local_file);
+    let _ = fs::remove_file(&read_back);
+
+    Ok(results)
+}
+
+pub fn print_results(results: &[(&'static str, Result<BenchResult>)]) {
+    println!("{:<12} {:>12} {:>14} {:>14}", "Transport", "Latency", "Write", "Read");
+
+    for (transport, result) in results {
+        match result {
+            Ok(r) => println!(
+                "{:<12} {:>12} {:>9.1} MB/s {:>9.1} MB/s",
+                r.transport,
+                r.latency_ms
+                    .map(|ms| format!("{:.1} ms", ms))
+                    .unwrap_or_else(|| "-".to_string()),
+                r.write_mb_s,
+                r.read_mb_s
+            ),
+            Err(e) => println!("{:<12} unavailable: {:#}", transport, e),
+        }
+    }
+}
+
+fn bench_rsync(
+    nas: &NasConfig,
+    remote_dir: &str,
+    local_file: &Path,
+    read_back: &Path,
+    size_mb: u64,
+) -> Result<BenchResult> {
+    let shell = RemoteShell::new(nas)?;
+    let remote_file = format!("{}/{}", remote_dir.trim_end_matches('/'), BENCH_FILE_NAME);
+
+    let rsync = |src: &str, dst: &str| -> Result<Duration> {
+        let start = Instant::now();
+        let mut cmd = Command::new(nas.rsync_binary());
+        shell.authenticate(&mut cmd)?;
+        let output = cmd
+            .arg("--whole-file")
+            .arg("-e")
+            .arg(shell.rsync_rsh())
+            .arg(src)
+            .arg(dst)
+            .output()
+            .context("Failed to execute rsync")?;
+        if !output.status.success() {
+            anyhow::bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+        }
+        Ok(start.elapsed())
+    };
+
+    let write = rsync(&local_file.to_string_lossy(), &shell.destination(&remote_file))?;
+    let read = rsync(&shell.destination(&remote_file), &read_back.to_string_lossy())?;
+
+    if let Err(e) = shell.run(&format!("rm -f {}", shell_quote(&remote_file))) {
+        warn!("Failed to remove benchmark file: {:#}", e);
+    }
+
+    Ok(BenchResult {
+        transport: "rsync/SSH",
+        latency_ms: tcp_latency_ms(shell.host(), nas.port),
+        write_mb_s: throughput(size_mb, write),
+        read_mb_s: throughput(size_mb, read),
+    })
+}
+
+fn bench_sftp(
+    nas: &NasConfig,
+    remote_dir: &str,
+    local_file: &Path,
+    read_back: &Path,
+    size_mb: u64,
+) -> Result<BenchResult> {
+    let shell = RemoteShell::new(nas)?;
+    if capabilities::of(&shell).is_ok_and(|capabilities| capabilities.sftp == Some(false)) {
+        anyhow::bail!("sshd on the NAS does not offer the sftp subsystem");
+    }
+    let remote_file = format!("{}/{}", remote_dir.trim_end_matches('/'), BENCH_FILE_NAME);
+
+    let sftp = |batch: String| -> Result<Duration> {
+        let start = Instant::now();
+        let mut child = shell
+            .command("sftp")?
+            .args(shell.sftp_args())
+            .arg("-b")
+            .arg("-")
+            .arg(shell.login())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context("Failed to execute sftp")?;
+        child
+            .stdin
+            .take()
+            .context("Failed to open sftp stdin")?
+            .write_all(batch.as_bytes())?;
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            anyhow::bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+        }
+        Ok(start.elapsed())
+    };
+
+    let write = sftp(format!("put \"{}\" \"{}\"\n", local_file.display(), remote_file))?;
+    let read = sftp(format!("get \"{}\" \"{}\"\n", remote_file, read_back.display()))?;
+    if let Err(e) = sftp(format!("rm \"{}\"\n", remote_file)) {
+        warn!("Failed to remove benchmark file: {:#}", e);
+    }
+
+    Ok(BenchResult {
+        transport: "SFTP",
+        latency_ms: tcp_latency_ms(shell.host(), nas.port),
+        write_mb_s: throughput(size_mb, write),
+        read_mb_s: throughput(size_mb, read),
+    })
+}
+
+async fn bench_smb(nas: &NasConfig, local_file: &Path, read_back: &Path, size_mb: u64) -> Result<BenchResult> {
+    let smb_config = nas.smb.clone().context("SMB not configured")?;
+    let smb_host = smb_config
+        .share_path
+        .trim_start_matches('/')
+        .split('/')
+        .next()
+        .unwrap_or_default()
+        .to_string();
+
+    let mut mount = SmbMount::new(smb_config);
+    mount.mount().await?;
+
+    let remote_file = PathBuf::from(mount.mount_point()).join(BENCH_FILE_NAME);
+    let result = (|| -> Result<BenchResult> {
+        let start = Instant::now();
+        fs::copy(local_file, &remote_file).context("Failed to write to SMB share")?;
+        let write = start.elapsed();
+
+        let start = Instant::now();
+        fs::copy(&remote_file, read_back).context("Failed to read from SMB share")?;
+        let read = start.elapsed();
+
+        Ok(BenchResult {
+            transport: "SMB mount",
+            latency_ms: tcp_latency_ms(&smb_host, 445),
+            write_mb_s: throughput(size_mb, write),
+            read_mb_s: throughput(size_mb, read),
+        })
+    })();
+
+    let _ = fs::remove_file(&remote_file);
+    if mount.should_auto_unmount() {
+        mount.unmount().await?;
+    }
+
+    result
+}
+
+/// Fill the test file with pseudo-random bytes so compression cannot skew results
+fn write_test_file(path: &Path, size_mb: u64) -> Result<()> {
+    let mut file = fs::File::create(path)
+        .with_context(|| format!("Failed to create benchmark file: {}", path.display()))?;
+    let mut state: u64 = 0x9E37_79B9_7F4A_7C15;
+    let mut chunk = vec![0u8; 1024 * 1024];
+
+    for _ in 0..size_mb {
+        for bytes in chunk.chunks_mut(8) {
+            // xorshift64
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            bytes.copy_from_slice(&state.to_le_bytes()[..bytes.len()]);
+        }
+        file.write_all(&chunk)?;
+    }
+
+    Ok(())
+}
+
+/// Average TCP connect time over a few probes
+fn tcp_latency_ms(host: &str, port: u16) -> Option<f64> {
+    let addr = (host, port).to_socket_addrs().ok()?.next()?;
+    let mut total = Duration::ZERO;
+
+    for _ in 0..LATENCY_PROBES {
+        let start = Instant::now();
+        TcpStream::connect_timeout(&addr, Duration::from_secs(3)).ok()?;
+        total += start.elapsed();
+    }
+
+    Some(total.as_secs_f64() * 1000.0 / LATENCY_PROBES as f64)
+}
+
+fn throughput(size_mb: u64, elapsed: Duration) -> f64 {
+    size_mb as f64 / elapsed.as_secs_f64().max(f64::EPSILON)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_test_file_size() {
+        let path = std::env::temp_dir().join(format!("ugnassync-bench-test-{}.bin", std::process::id()));
+        write_test_file(&path, 1).unwrap();
+
+        assert_eq!(fs::metadata(&path).unwrap().len(), 1024 * 1024);
+        fs::remove_file(&path).unwrap();
+    }
+}
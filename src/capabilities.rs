@@ -0,0 +1,189 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use crate::error::SyncError;
+use crate::remote::RemoteShell;
+use crate::rsync::RsyncVersion;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tracing::{debug, info, warn};
+
+/// Probed capabilities are trusted for this long before the NAS is asked again
+const CACHE_DAYS: i64 = 7;
+
+/// Capabilities already looked up by this process, by `user@host`
+static KNOWN: Mutex<BTreeMap<String, Capabilities>> = Mutex::new(BTreeMap::new());
+
+/// rsync is looked up with the PATH ssh commands get, as rsync itself is started that
+/// way; the other tools often live in sbin or Entware's /opt/bin
+const PROBE_SCRIPT: &str = r#"echo "rsync=$(rsync --version 2>/dev/null | head -n 1)"
+PATH="$PATH:/sbin:/usr/sbin:/usr/local/sbin:/opt/bin"
+for tool in sha256sum btrfs zfs; do command -v $tool >/dev/null 2>&1 && echo "$tool=yes"; done
+if [ -r /etc/ssh/sshd_config ]; then
+  if grep -qi '^[[:space:]]*subsystem[[:space:]][[:space:]]*sftp' /etc/ssh/sshd_config; then echo sftp=yes; else echo sftp=no; fi
+fi
+ls --help 2>&1 | grep -q BusyBox && echo busybox=yes
+true"#;
+
+/// What a NAS offers over SSH, so features can tell up front what they cannot do there
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Capabilities {
+    /// `user@host` the probe logged in as
+    pub login: String,
+    pub probed: DateTime<Local>,
+    /// First line of `rsync --version`; None when rsync is not installed or not enabled
+    pub rsync: Option<String>,
+    /// Whether sshd offers the sftp subsystem; None when its config is not readable
+    pub sftp: Option<bool>,
+    pub sha256sum: bool,
+    pub btrfs: bool,
+    pub zfs: bool,
+    /// BusyBox applets instead of GNU coreutils
+    pub busybox: bool,
+}
+
+impl Capabilities {
+    /// `key=value` lines printed by the probe script
+    fn parse(login: &str, output: &str, probed: DateTime<Local>) -> Self {
+        let values: BTreeMap<&str, &str> = output.lines().filter_map(|line| line.split_once('=')).collect();
+        let yes = |key: &str| values.get(key) == Some(&"yes");
+        Self {
+            login: login.to_string(),
+            probed,
+            rsync: values.get("rsync").map(|line| line.trim().to_string()).filter(|line| !line.is_empty()),
+            sftp: values.get("sftp").map(|value| *value == "yes"),
+            sha256sum: yes("sha256sum"),
+            btrfs: yes("btrfs"),
+            zfs: yes("zfs"),
+            busybox: yes("busybox"),
+        }
+    }
+
+    pub fn rsync_version(&self) -> Option<RsyncVersion> {
+        self.rsync.as_deref().and_then(RsyncVersion::parse)
+    }
+
+    /// Fail with a clear message when `feature` needs `tool` and the NAS lacks it
+    pub fn require(&self, available: bool, tool: &str, feature: &str) -> Result<()> {
+        if available {
+            return Ok(());
+        }
+        let userland = if self.busybox { " (BusyBox userland)" } else { "" };
+        Err(SyncError::ToolMissing(format!(
+            "{} needs {} on the NAS, which {}{} does not have; install it (e.g. from Entware) \
+             or run `ugnassync capabilities --refresh` after adding it",
+            feature, tool, self.login, userland
+        ))
+        .into())
+    }
+
+    fn is_fresh(&self, now: DateTime<Local>) -> bool {
+        (now - self.probed).num_days() < CACHE_DAYS
+    }
+
+    pub fn print(&self) {
+        let flag = |present: bool| if present { "yes" } else { "no" };
+        println!("NAS {} (probed {})", self.login, self.probed.format("%Y-%m-%d %H:%M"));
+        println!("  rsync:     {}", self.rsync.as_deref().unwrap_or("not available"));
+        println!(
+            "  sftp:      {}",
+            self.sftp.map(flag).unwrap_or("unknown (sshd_config not readable)")
+        );
+        println!("  sha256sum: {}", flag(self.sha256sum));
+        println!("  btrfs:     {}", flag(self.btrfs));
+        println!("  zfs:       {}", flag(self.zfs));
+        println!("  userland:  {}", if self.busybox { "BusyBox" } else { "GNU" });
+    }
+}
+
+fn cache_path() -> Result<PathBuf> {
+    Ok(dirs::home_dir()
+        .context("Failed to get home directory")?
+        .join(".ugnassync")
+        .join("capabilities.json"))
+}
+
+fn load_cache() -> BTreeMap<String, Capabilities> {
+    cache_path()
+        .ok()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+fn save_cache(capabilities: &Capabilities) -> Result<()> {
+    let mut cache = load_cache();
+    cache.insert(capabilities.login.clone(), capabilities.clone());
+    let path = cache_path()?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).context("Failed to create ~/.ugnassync")?;
+    }
+    fs::write(&path, serde_json::to_string_pretty(&cache)?).context("Failed to write capabilities cache")
+}
+
+/// Capabilities of the NAS behind `shell`, probed on first contact and then taken from
+/// ~/.ugnassync/capabilities.json for a week
+pub fn of(shell: &RemoteShell) -> Result<Capabilities> {
+    let login = shell.login();
+    let now = Local::now();
+    if let Some(known) = KNOWN.lock().unwrap().get(&login) {
+        return Ok(known.clone());
+    }
+    if let Some(cached) = load_cache().remove(&login).filter(|cached| cached.is_fresh(now)) {
+        debug!("Using capabilities of {} probed {}", login, cached.probed);
+        KNOWN.lock().unwrap().insert(login, cached.clone());
+        return Ok(cached);
+    }
+    refresh(shell)
+}
+
+/// Probe the NAS again, replacing what was cached
+pub fn refresh(shell: &RemoteShell) -> Result<Capabilities> {
+    let login = shell.login();
+    let output = shell.run(PROBE_SCRIPT).with_context(|| format!("Failed to probe {}", login))?;
+    let capabilities = Capabilities::parse(&login, &output, Local::now());
+    info!(
+        "Probed {}: {}, sha256sum {}, {} userland",
+        login,
+        capabilities.rsync.as_deref().unwrap_or("no rsync"),
+        if capabilities.sha256sum { "available" } else { "missing" },
+        if capabilities.busybox { "BusyBox" } else { "GNU" }
+    );
+    if let Err(e) = save_cache(&capabilities) {
+        warn!("{:#}", e);
+    }
+    KNOWN.lock().unwrap().insert(login, capabilities.clone());
+    Ok(capabilities)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_probe_output() {
+        let now = Local::now();
+        let synology = Capabilities::parse(
+            "admin@nas",
+            "rsync=rsync  version 3.1.2  protocol version 31\nsha256sum=yes\nbtrfs=yes\nsftp=yes\n",
+            now,
+        );
+        assert_eq!(synology.rsync_version().map(|v| (v.major, v.minor)), Some((3, 1)));
+        assert!(synology.sha256sum && synology.btrfs && !synology.zfs && !synology.busybox);
+        assert_eq!(synology.sftp, Some(true));
+        assert!(synology.require(synology.sha256sum, "sha256sum", "Adopting").is_ok());
+
+        let router = Capabilities::parse("root@router", "rsync=\nbusybox=yes\n", now);
+        assert_eq!((router.rsync.as_deref(), router.sftp), (None, None));
+        let error = router.require(router.sha256sum, "sha256sum", "Conflict detection").unwrap_err();
+        assert!(error.to_string().contains("root@router (BusyBox userland) does not have"));
+        assert!(router.is_fresh(now) && !router.is_fresh(now + chrono::Duration::days(CACHE_DAYS)));
+    }
+}
@@ -0,0 +1,118 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use crate::config::{ReplicaHop, SyncProfile};
+use crate::remote::{shell_quote, RemoteShell};
+use anyhow::{Context, Result};
+use std::process::Command;
+use tracing::{debug, info};
+
+/// Copy the profile's NAS copy along its `replicate_to` chain, up to and including hop `index`.
+/// The first hop runs on the NAS (or locally from the SMB mount); later hops run on the
+/// previous hop's host, reached through nested ssh.
+pub fn replicate(shell: Option<&RemoteShell>, profile: &SyncProfile, index: usize, dry_run: bool) -> Result<()> {
+    let hops = &profile.replicate_to;
+    let hop = &hops[index];
+    let source = match index {
+        0 => &profile.remote_path,
+        _ => &hops[index - 1].remote_path,
+    };
+
+    let command = nest(&hops[..index], rsync_command(hop, source, dry_run));
+    info!("Replicating {} to {}:{}", source, hop.host, hop.remote_path);
+    debug!("Replication command: {}", command);
+
+    match shell {
+        Some(shell) => {
+            shell
+                .run(&command)
+                .with_context(|| format!("Replication to {} failed", hop.host))?;
+        }
+        None => {
+            let output = Command::new("sh")
+                .arg("-c")
+                .arg(&command)
+                .output()
+                .context("Failed to execute rsync")?;
+            if !output.status.success() {
+                anyhow::bail!(
+                    "Replication to {} failed: {}",
+                    hop.host,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn ssh(hop: &ReplicaHop) -> String {
+    format!("ssh -o BatchMode=yes -p {}", hop.port)
+}
+
+fn rsync_command(hop: &ReplicaHop, source: &str, dry_run: bool) -> String {
+    let mut args = vec!["rsync".to_string(), "-az".to_string()];
+    if dry_run {
+        args.push("--dry-run".to_string());
+    }
+    if hop.delete {
+        args.push("--delete".to_string());
+    }
+    if let Some(rsync_path) = &hop.rsync_path {
+        args.push(shell_quote(&format!("--rsync-path={}", rsync_path)));
+    }
+    args.push("-e".to_string());
+    args.push(shell_quote(&ssh(hop)));
+    // Replicate the contents of the source directory, not the directory itself
+    args.push(shell_quote(&format!("{}/", source.trim_end_matches('/'))));
+    args.push(shell_quote(&format!("{}@{}:{}", hop.username, hop.host, hop.remote_path)));
+    args.join(" ")
+}
+
+/// Wrap a command so it runs on the last of `via`, each host reached from the one before
+fn nest(via: &[ReplicaHop], command: String) -> String {
+    via.iter().rev().fold(command, |command, hop| {
+        format!("{} {}@{} {}", ssh(hop), hop.username, hop.host, shell_quote(&command))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_second_hop_runs_on_first_hop() {
+        let profile: SyncProfile = toml::from_str(
+            r#"
+            name = "docs"
+            local_path = "/home/user/Documents"
+            remote_path = "/volume1/backups/Documents"
+            sync_type = "mirror"
+            enabled = true
+
+            [[replicate_to]]
+            host = "offsite1"
+            username = "backup"
+            remote_path = "/srv/docs"
+
+            [[replicate_to]]
+            host = "offsite2"
+            port = 2222
+            username = "vault"
+            remote_path = "/vault/docs"
+            delete = true
+            "#,
+        )
+        .unwrap();
+
+        let hops = &profile.replicate_to;
+        let command = nest(&hops[..1], rsync_command(&hops[1], &hops[0].remote_path, false));
+        assert_eq!(
+            command,
+            r#"ssh -o BatchMode=yes -p 22 backup@offsite1 'rsync -az --delete -e '\''ssh -o BatchMode=yes -p 2222'\'' '\''/srv/docs/'\'' '\''vault@offsite2:/vault/docs'\'''"#
+        );
+    }
+}
@@ -0,0 +1,174 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use crate::config::{SyncProfile, SyncType};
+use crate::conflict::{state_key, ConflictResolver, FileMetadata};
+use crate::sync::FileError;
+use crate::watch::WatchManager;
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+#[derive(Debug, PartialEq)]
+pub enum ChangeKind {
+    New,
+    Modified,
+    Deleted,
+}
+
+/// A local file that differs from the state recorded at its last sync
+#[derive(Debug)]
+pub struct PendingChange {
+    pub path: PathBuf,
+    pub kind: ChangeKind,
+}
+
+/// Diff the local tree of a profile against the recorded sync state, without touching the NAS
+pub fn pending_changes(profile: &SyncProfile, resolver: &ConflictResolver) -> Result<Vec<PendingChange>> {
+    let roots: Vec<&Path> = profile.source_paths().into_iter().map(Path::new).collect();
+    let excludes = profile.effective_excludes();
+
+    let mut recorded: HashMap<String, FileMetadata> = resolver
+        .recorded_files()?
+        .into_iter()
+        .filter(|meta| roots.iter().any(|root| Path::new(&meta.path).starts_with(root)))
+        .map(|meta| (meta.path.clone(), meta))
+        .collect();
+
+    let mut files = Vec::new();
+    for root in &roots {
+        collect_files(root, &excludes, &mut files, &mut Vec::new());
+    }
+
+    let mut changes = Vec::new();
+    for path in files {
+        let kind = match recorded.remove(&state_key(&path)) {
+            None => Some(ChangeKind::New),
+            Some(state) => is_modified(resolver, &path, &state)?.then_some(ChangeKind::Modified),
+        };
+        if let Some(kind) = kind {
+            changes.push(PendingChange { path, kind });
+        }
+    }
+
+    // Whatever is left was synced before but no longer exists locally
+    for path in recorded.into_keys() {
+        let path = PathBuf::from(path);
+        if !WatchManager::is_excluded(&path, &excludes) {
+            changes.push(PendingChange {
+                path,
+                kind: ChangeKind::Deleted,
+            });
+        }
+    }
+
+    changes.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(changes)
+}
+
+pub fn print_changes(profile: &SyncProfile, changes: &[PendingChange]) {
+    if changes.is_empty() {
+        println!("No pending changes for profile {}", profile.name);
+        return;
+    }
+
+    println!("Pending changes for profile {}:", profile.name);
+    for change in changes {
+        let label = match change.kind {
+            ChangeKind::New => "new",
+            ChangeKind::Modified => "modified",
+            // Only mirror profiles propagate local deletions
+            ChangeKind::Deleted if profile.sync_type == SyncType::Mirror => "deleted",
+            ChangeKind::Deleted => "deleted (kept on NAS)",
+        };
+        println!("  {:<22} {}", label, change.path.display());
+    }
+    println!("{} file(s)", changes.len());
+}
+
+/// Metadata is checked first so unchanged files are never hashed
+fn is_modified(resolver: &ConflictResolver, path: &Path, state: &FileMetadata) -> Result<bool> {
+    let metadata = fs::metadata(path)
+        .with_context(|| format!("Failed to read file metadata: {}", path.display()))?;
+    let modified = metadata
+        .modified()?
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default();
+
+    if metadata.len() == state.size && modified == state.modified {
+        return Ok(false);
+    }
+
+    Ok(resolver.calculate_file_hash(path)? != state.hash)
+}
+
+/// Files below `dir`; what cannot be read is logged, added to `unreadable` and skipped
+pub(crate) fn collect_files(dir: &Path, excludes: &[String], files: &mut Vec<PathBuf>, unreadable: &mut Vec<FileError>) {
+    let mut skip = |path: &Path, e: io::Error| {
+        warn!("Cannot read {}: {}", path.display(), e);
+        unreadable.push(FileError::unreadable(path, &e));
+    };
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => return skip(dir, e),
+    };
+
+    let mut subdirs = Vec::new();
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                skip(dir, e);
+                continue;
+            }
+        };
+        let path = entry.path();
+        if WatchManager::is_excluded(&path, excludes) {
+            continue;
+        }
+
+        match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => subdirs.push(path),
+            Ok(file_type) if file_type.is_file() => files.push(path),
+            Ok(_) => {}
+            Err(e) => skip(&path, e),
+        }
+    }
+
+    for subdir in subdirs {
+        collect_files(&subdir, excludes, files, unreadable);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collect_files_skips_excluded() {
+        let dir = std::env::temp_dir().join(format!("ugnassync-changes-test-{}", std::process::id()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("a.txt"), "a").unwrap();
+        fs::write(dir.join("sub/b.txt"), "b").unwrap();
+        fs::write(dir.join("sub/b.txt.swp"), "swap").unwrap();
+
+        let (mut files, mut unreadable) = (Vec::new(), Vec::new());
+        collect_files(&dir, &["*.swp".to_string()], &mut files, &mut unreadable);
+        files.sort();
+
+        assert_eq!(files, vec![dir.join("a.txt"), dir.join("sub/b.txt")]);
+        assert!(unreadable.is_empty());
+
+        // A directory that cannot be read is reported, not fatal
+        collect_files(&dir.join("missing"), &[], &mut files, &mut unreadable);
+        assert_eq!(unreadable.len(), 1);
+        assert!(unreadable[0].path.ends_with("missing") && unreadable[0].reason.starts_with("unreadable"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
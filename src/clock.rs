@@ -0,0 +1,103 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use crate::config::NasConfig;
+use crate::remote::RemoteShell;
+use anyhow::{Context, Result};
+use chrono::Utc;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+/// A measured skew is trusted this long before the NAS is asked again
+const REMEASURE_AFTER: Duration = Duration::from_secs(3600);
+
+/// Skew of each NAS address by this process, and when it was measured
+static MEASURED: Mutex<BTreeMap<String, (Instant, i64)>> = Mutex::new(BTreeMap::new());
+
+/// Seconds the NAS clock is ahead of this machine's (negative when behind), reading
+/// `date +%s` there against the middle of the ssh round trip
+pub fn measure(shell: &RemoteShell) -> Result<i64> {
+    let before = Utc::now().timestamp_millis();
+    let output = shell.run("date +%s").context("Failed to read the NAS clock")?;
+    let after = Utc::now().timestamp_millis();
+    let remote: i64 = output.trim().parse().with_context(|| format!("Unexpected output of date: {}", output.trim()))?;
+    Ok(skew(before, after, remote))
+}
+
+/// `remote` has whole seconds, so anything below a second rounds away
+fn skew(before_millis: i64, after_millis: i64, remote_secs: i64) -> i64 {
+    let local_millis = before_millis + (after_millis - before_millis) / 2;
+    ((remote_secs * 1000 + 500 - local_millis) as f64 / 1000.0).round() as i64
+}
+
+/// Compare the clocks (at most hourly per NAS address) during the connectivity check;
+/// returns a warning for the run when they differ by more than max_clock_skew_secs
+pub fn check(nas: &NasConfig, shell: &RemoteShell) -> Option<String> {
+    let host = shell.host().to_string();
+    let cached = MEASURED.lock().unwrap().get(&host).filter(|(at, _)| at.elapsed() < REMEASURE_AFTER).map(|(_, skew)| *skew);
+    let skew = match cached {
+        Some(skew) => skew,
+        None => match measure(shell) {
+            Ok(skew) => {
+                debug!("Clock of {} differs from this machine's by {}s", host, skew);
+                MEASURED.lock().unwrap().insert(host.clone(), (Instant::now(), skew));
+                skew
+            }
+            Err(e) => {
+                warn!("Could not compare clocks with {}: {:#}", host, e);
+                return None;
+            }
+        },
+    };
+    if skew.unsigned_abs() <= nas.max_clock_skew_secs {
+        return None;
+    }
+    let warning = format!(
+        "The clock of {} is {}s {} this machine's; mtime comparisons (newer-wins, incremental --update) \
+         may pick the wrong file. {}",
+        host,
+        skew.unsigned_abs(),
+        if skew > 0 { "ahead of" } else { "behind" },
+        if nas.correct_clock_skew {
+            "Correcting NAS mtimes by the measured skew"
+        } else {
+            "Sync both clocks with NTP, or set correct_clock_skew = true"
+        }
+    );
+    if cached.is_none() {
+        warn!("{}", warning);
+    }
+    Some(warning)
+}
+
+/// Seconds to subtract from NAS mtimes before comparing them with local ones: the skew
+/// measured for any of the NAS addresses with correct_clock_skew, otherwise 0
+pub fn offset(nas: &NasConfig) -> i64 {
+    if !nas.correct_clock_skew {
+        return 0;
+    }
+    let measured = MEASURED.lock().unwrap();
+    std::iter::once(&nas.host)
+        .chain(&nas.fallback_hosts)
+        .find_map(|host| measured.get(host))
+        .map(|(_, skew)| *skew)
+        .filter(|skew| skew.unsigned_abs() > nas.max_clock_skew_secs)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_skew() {
+        // NAS answered 1700000300 while this machine was at 1700000000.2 .. 1700000000.4
+        assert_eq!(skew(1_700_000_000_200, 1_700_000_000_400, 1_700_000_300), 300);
+        assert_eq!(skew(1_700_000_000_200, 1_700_000_000_400, 1_700_000_000), 0);
+        assert_eq!(skew(1_700_000_000_900, 1_700_000_001_100, 1_699_999_881), -120);
+    }
+}
@@ -0,0 +1,172 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use crate::config::Config;
+use anyhow::{Context, Result};
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use toml_edit::{value, Array, ArrayOfTables, DocumentMut, Item, Table};
+
+/// A profile to append with `ugnassync profile add` or `ugnassync import`
+#[derive(Debug, Clone)]
+pub struct NewProfile {
+    pub name: String,
+    pub local_path: String,
+    pub remote_path: String,
+    pub sync_type: String,
+    pub enabled: bool,
+    pub exclude: Vec<String>,
+    pub watch_mode: bool,
+    pub interval_minutes: Option<u64>,
+    pub allowed_window: Option<String>,
+    pub keep_versions: Option<usize>,
+}
+
+/// Apply `change` to the config file, keeping its comments and layout; the result is
+/// validated like a loaded config before it replaces the file
+pub fn edit<T>(path: &Path, change: impl FnOnce(&mut DocumentMut) -> Result<T>) -> Result<T> {
+    let content = fs::read_to_string(path).with_context(|| format!("Failed to read config file: {}", path.display()))?;
+    let mut doc: DocumentMut = content
+        .parse()
+        .with_context(|| format!("Failed to parse config file: {}", path.display()))?;
+    let result = change(&mut doc)?;
+
+    let edited = doc.to_string();
+    Config::check(&edited).context("The edited config would not be valid, left unchanged")?;
+
+    // Written next to the file and renamed over it. It holds the same passwords, so it is
+    // created with the file's permissions, and named per edit so concurrent ones cannot mix
+    let permissions = fs::metadata(path)?.permissions();
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().subsec_nanos();
+    let tmp = path.with_extension(format!("toml.{}-{}.tmp", std::process::id(), nanos));
+    let written = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(permissions.mode())
+        .open(&tmp)
+        .and_then(|mut file| {
+            file.write_all(edited.as_bytes())?;
+            // The umask may have narrowed the mode further
+            fs::set_permissions(&tmp, permissions)
+        });
+    if let Err(e) = written {
+        let _ = fs::remove_file(&tmp);
+        return Err(e).with_context(|| format!("Failed to write {}", tmp.display()));
+    }
+    fs::rename(&tmp, path).with_context(|| format!("Failed to replace {}", path.display()))?;
+    Ok(result)
+}
+
+/// Set `enabled` of the named profile; false when it already had that value
+pub fn set_enabled(doc: &mut DocumentMut, name: &str, enabled: bool) -> Result<bool> {
+    let profile = profiles(doc)?
+        .iter_mut()
+        .find(|table| table.get("name").and_then(Item::as_str) == Some(name))
+        .with_context(|| format!("Profile not found: {}", name))?;
+
+    match profile.get_mut("enabled").and_then(Item::as_value_mut) {
+        Some(current) if current.as_bool() == Some(enabled) => Ok(false),
+        Some(current) => {
+            // Keep the whitespace and any trailing comment around the value
+            let decor = current.decor().clone();
+            *current = enabled.into();
+            *current.decor_mut() = decor;
+            Ok(true)
+        }
+        None => {
+            profile.insert("enabled", value(enabled));
+            Ok(true)
+        }
+    }
+}
+
+/// Append a `[[sync_profiles]]` entry
+pub fn add_profile(doc: &mut DocumentMut, new: &NewProfile) -> Result<()> {
+    if !doc.contains_key("sync_profiles") {
+        doc.insert("sync_profiles", Item::ArrayOfTables(ArrayOfTables::new()));
+    }
+    let profiles = profiles(doc)?;
+    if profiles.iter().any(|table| table.get("name").and_then(Item::as_str) == Some(new.name.as_str())) {
+        anyhow::bail!("Profile '{}' already exists", new.name);
+    }
+
+    let mut table = Table::new();
+    table.insert("name", value(&new.name));
+    table.insert("local_path", value(&new.local_path));
+    table.insert("remote_path", value(&new.remote_path));
+    table.insert("sync_type", value(&new.sync_type));
+    table.insert("enabled", value(new.enabled));
+    if !new.exclude.is_empty() {
+        table.insert("exclude", value(new.exclude.iter().collect::<Array>()));
+    }
+    if new.watch_mode {
+        table.insert("watch_mode", value(true));
+    }
+    if let Some(minutes) = new.interval_minutes {
+        table.insert("interval_minutes", value(minutes as i64));
+    }
+    if let Some(window) = &new.allowed_window {
+        table.insert("allowed_window", value(window));
+    }
+    if let Some(keep) = new.keep_versions {
+        table.insert("keep_versions", value(keep as i64));
+    }
+    profiles.push(table);
+    Ok(())
+}
+
+fn profiles(doc: &mut DocumentMut) -> Result<&mut ArrayOfTables> {
+    doc.get_mut("sync_profiles")
+        .and_then(Item::as_array_of_tables_mut)
+        .context("The config file has no [[sync_profiles]] entries")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edits_keep_comments() {
+        let mut doc: DocumentMut = r#"# My NAS
+[[sync_profiles]]
+name = "Photos"
+local_path = "/home/me/Photos"
+remote_path = "/volume1/photos"
+sync_type = "mirror"
+enabled = true  # on until the NAS is full
+"#
+        .parse()
+        .unwrap();
+
+        assert!(set_enabled(&mut doc, "Photos", false).unwrap());
+        assert!(!set_enabled(&mut doc, "Photos", false).unwrap());
+        assert!(set_enabled(&mut doc, "Music", true).is_err());
+
+        let new = NewProfile {
+            name: "Music".to_string(),
+            local_path: "/home/me/Music".to_string(),
+            remote_path: "/volume1/music".to_string(),
+            sync_type: "one-way".to_string(),
+            enabled: true,
+            exclude: vec!["*.tmp".to_string()],
+            watch_mode: false,
+            interval_minutes: Some(60),
+            allowed_window: None,
+            keep_versions: None,
+        };
+        add_profile(&mut doc, &new).unwrap();
+        assert!(add_profile(&mut doc, &new).is_err());
+
+        let edited = doc.to_string();
+        assert!(edited.starts_with("# My NAS\n"));
+        assert!(edited.contains("enabled = false  # on until the NAS is full\n"));
+        assert!(edited.contains("name = \"Music\""));
+        assert!(edited.contains("exclude = [\"*.tmp\"]"));
+        assert!(edited.contains("interval_minutes = 60"));
+    }
+}
@@ -3,39 +3,105 @@
 // Author: Immanuel Jeyaraj <irj@sefier.com>
 // License: GPL-3.0
 
-use crate::config::ConflictResolution;
+use crate::config::{ConflictResolution, SyncProfile, COARSE_MTIME_SECS};
+use crate::device;
+use crate::error::SyncError;
 use anyhow::{Context, Result};
-use chrono::Local;
-use rusqlite::{params, Connection};
+use chrono::{DateTime, Local, TimeZone, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
 use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
 use std::fs;
+use std::io::Read;
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use tracing::{debug, info, warn};
+use unicode_normalization::UnicodeNormalization;
 
+/// Read size for hashing, independent of the file size
+const HASH_BUFFER_BYTES: usize = 256 * 1024;
+
+/// Version of the state database layout, kept in `PRAGMA user_version`
+const SCHEMA_VERSION: i32 = 2;
+
+/// In-memory fallback databases, kept open so their state survives between runs
+static MEMORY_STATE: Mutex<BTreeMap<String, ConflictResolver>> = Mutex::new(BTreeMap::new());
+
+#[derive(Clone)]
 pub struct ConflictResolver {
     db_path: PathBuf,
+    /// Keeps an in-memory database alive between connections
+    _keepalive: Option<Arc<Mutex<Connection>>>,
+    /// Seconds by which mtimes may differ and still count as the same
+    mtime_tolerance: u64,
+    /// Seconds the NAS clock is ahead of this machine's, taken off NAS mtimes
+    clock_offset: i64,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct FileMetadata {
     pub path: String,
     pub size: u64,
+    /// Unix seconds (UTC), so time zone and DST changes do not make files look modified
     pub modified: i64,
     pub hash: String,
 }
 
 impl ConflictResolver {
     pub fn new() -> Result<Self> {
-        let home = dirs::home_dir().context("Could not determine home directory")?;
-        let db_dir = home.join(".ugnassync");
+        Self::open(&default_db_path()?)
+    }
+
+    /// Use a state database at a specific path (e.g. one per profile)
+    pub fn open(db_path: &Path) -> Result<Self> {
+        if let Some(dir) = db_path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create sync state directory: {}", dir.display()))?;
+        }
 
-        fs::create_dir_all(&db_dir)
-            .context("Failed to create sync state directory")?;
+        let resolver = Self {
+            db_path: db_path.to_path_buf(),
+            _keepalive: None,
+            mtime_tolerance: COARSE_MTIME_SECS,
+            clock_offset: 0,
+        };
+        resolver
+            .init_database()
+            .map_err(|e| SyncError::StateDatabase(format!("{}: {:#}", db_path.display(), e)))?;
 
-        let db_path = db_dir.join("sync_state.db");
+        Ok(resolver)
+    }
+
+    /// State database for a profile: its own `state_db` if set, otherwise the shared one
+    pub fn for_profile(profile: &SyncProfile) -> Result<Self> {
+        match &profile.state_db {
+            Some(path) => Self::open(Path::new(path)),
+            None => Self::new(),
+        }
+    }
+
+    /// Non-persistent state that lives for the rest of the process
+    pub fn in_memory(name: &str) -> Result<Self> {
+        let name: String = name.chars().filter(|c| c.is_ascii_alphanumeric()).collect();
+        let mut cache = MEMORY_STATE.lock().unwrap();
+        if let Some(resolver) = cache.get(&name) {
+            return Ok(resolver.clone());
+        }
 
-        let resolver = Self { db_path };
+        let db_path = PathBuf::from(format!("file:ugnassync-{}?mode=memory&cache=shared", name));
+
+        let keepalive = Connection::open(&db_path)
+            .context("Failed to open in-memory sync state database")?;
+
+        let resolver = Self {
+            db_path,
+            _keepalive: Some(Arc::new(Mutex::new(keepalive))),
+            mtime_tolerance: COARSE_MTIME_SECS,
+            clock_offset: 0,
+        };
         resolver.init_database()?;
+        cache.insert(name, resolver.clone());
 
         Ok(resolver)
     }
@@ -56,7 +122,85 @@ impl ConflictResolver {
         )
         .context("Failed to create sync_state table")?;
 
-        Ok(())
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS transfer_totals (
+                month TEXT NOT NULL,
+                profile TEXT NOT NULL,
+                bytes INTEGER NOT NULL,
+                files INTEGER NOT NULL,
+                PRIMARY KEY (month, profile)
+            )",
+            [],
+        )
+        .context("Failed to create transfer_totals table")?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS remote_only (
+                profile TEXT NOT NULL,
+                path TEXT NOT NULL,
+                PRIMARY KEY (profile, path)
+            )",
+            [],
+        )
+        .context("Failed to create remote_only table")?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS profile_runs (
+                profile TEXT PRIMARY KEY,
+                last_run INTEGER NOT NULL
+            )",
+            [],
+        )
+        .context("Failed to create profile_runs table")?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS run_history (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                run_id TEXT NOT NULL,
+                profile TEXT NOT NULL,
+                started_at INTEGER NOT NULL,
+                duration_secs REAL NOT NULL,
+                status TEXT NOT NULL,
+                files INTEGER NOT NULL,
+                bytes INTEGER NOT NULL,
+                network_bytes INTEGER NOT NULL,
+                conflicts INTEGER NOT NULL,
+                error TEXT
+            )",
+            [],
+        )
+        .context("Failed to create run_history table")?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS alert_state (
+                profile TEXT PRIMARY KEY,
+                failures INTEGER NOT NULL,
+                first_failure INTEGER,
+                last_success INTEGER,
+                alerted INTEGER NOT NULL
+            )",
+            [],
+        )
+        .context("Failed to create alert_state table")?;
+
+        migrate(&conn)
+    }
+
+    /// Compare mtimes with the profile's tolerance instead of the SMB and FAT default
+    pub fn with_mtime_tolerance(mut self, secs: u64) -> Self {
+        self.mtime_tolerance = secs;
+        self
+    }
+
+    /// Correct the mtimes of NAS copies by a measured clock skew (see `clock::offset`)
+    pub fn with_clock_offset(mut self, secs: i64) -> Self {
+        self.clock_offset = secs;
+        self
+    }
+
+    /// Whether `modified` is later than `since`, beyond the rounding of the target's mtimes
+    fn modified_since(&self, modified: i64, since: i64) -> bool {
+        modified - since > self.mtime_tolerance as i64
     }
 
     pub fn detect_conflict(
@@ -65,13 +209,14 @@ impl ConflictResolver {
         remote_file: &Path,
     ) -> Result<bool> {
         let local_meta = self.get_file_metadata(local_file)?;
-        let remote_meta = self.get_file_metadata(remote_file)?;
+        let mut remote_meta = self.get_file_metadata(remote_file)?;
+        remote_meta.modified -= self.clock_offset;
 
         // Check if we have a record of last sync
         let conn = Connection::open(&self.db_path)?;
         let mut stmt = conn.prepare("SELECT modified, hash FROM sync_state WHERE path = ?")?;
 
-        let path_str = local_file.to_string_lossy().to_string();
+        let path_str = state_key(local_file);
         let last_sync: Option<(i64, String)> = stmt
             .query_row(params![path_str], |row| {
                 Ok((row.get(0)?, row.get(1)?))
@@ -80,8 +225,8 @@ impl ConflictResolver {
 
         if let Some((last_modified, last_hash)) = last_sync {
             // Both files have been modified since last sync
-            let local_changed = local_meta.modified > last_modified || local_meta.hash != last_hash;
-            let remote_changed = remote_meta.modified > last_modified || remote_meta.hash != last_hash;
+            let local_changed = self.modified_since(local_meta.modified, last_modified) || local_meta.hash != last_hash;
+            let remote_changed = self.modified_since(remote_meta.modified, last_modified) || remote_meta.hash != last_hash;
 
             Ok(local_changed && remote_changed)
         } else {
@@ -90,6 +235,25 @@ impl ConflictResolver {
         }
     }
 
+    /// Whether a file changed locally since its last sync; without a record it counts as changed
+    pub fn changed_since_sync(&self, local_file: &Path) -> Result<bool> {
+        Ok(match self.recorded(local_file)? {
+            Some(record) => self.calculate_file_hash(local_file)? != record.hash,
+            None => true,
+        })
+    }
+
+    /// Whether a file changed both locally and on the NAS (given its hash there) since
+    /// its last sync; without a record any difference counts
+    pub fn changed_on_both_sides(&self, local_file: &Path, remote_hash: &str) -> Result<bool> {
+        let local_hash = self.calculate_file_hash(local_file)?;
+        Ok(match self.recorded(local_file)? {
+            Some(record) => local_hash != record.hash && remote_hash != record.hash,
+            None => local_hash != remote_hash,
+        })
+    }
+
+    #[allow(dead_code)]
     pub fn resolve_conflict(
         &self,
         local_file: &Path,
@@ -114,8 +278,9 @@ impl ConflictResolver {
                 info!("Keeping both versions");
                 let timestamp = Local::now().format("%Y%m%d-%H%M%S");
                 let conflict_name = format!(
-                    "{}.conflict.{}",
+                    "{}.conflict.{}-{}",
                     remote_file.display(),
+                    device::current().label(),
                     timestamp
                 );
                 fs::rename(remote_file, &conflict_name)
@@ -131,7 +296,7 @@ impl ConflictResolver {
                 let local_meta = self.get_file_metadata(local_file)?;
                 let remote_meta = self.get_file_metadata(remote_file)?;
 
-                if local_meta.modified >= remote_meta.modified {
+                if !self.modified_since(remote_meta.modified - self.clock_offset, local_meta.modified) {
                     info!("Keeping newest version (source is newer or same)");
                     fs::copy(local_file, remote_file)?;
                     self.update_sync_state(local_file)?;
@@ -174,27 +339,20 @@ impl ConflictResolver {
         let hash = self.calculate_file_hash(path)?;
 
         Ok(FileMetadata {
-            path: path.to_string_lossy().to_string(),
+            path: state_key(path),
             size: metadata.len(),
             modified,
             hash,
         })
     }
 
-    fn calculate_file_hash(&self, path: &Path) -> Result<String> {
-        let contents = fs::read(path)
-            .with_context(|| format!("Failed to read file for hashing: {}", path.display()))?;
-
-        let mut hasher = Sha256::new();
-        hasher.update(&contents);
-        let result = hasher.finalize();
-
-        Ok(format!("{:x}", result))
+    pub(crate) fn calculate_file_hash(&self, path: &Path) -> Result<String> {
+        hash_file(path)
     }
 
-    pub fn update_sync_state(&self, path: &Path) -> Result<()> {
+    pub fn update_sync_state(&self, path: &Path) -> Result<FileMetadata> {
         let meta = self.get_file_metadata(path)?;
-        let now = Local::now().timestamp();
+        let now = Utc::now().timestamp();
 
         let conn = Connection::open(&self.db_path)?;
         conn.execute(
@@ -204,8 +362,426 @@ impl ConflictResolver {
         )?;
 
         debug!("Updated sync state for: {}", path.display());
+        Ok(meta)
+    }
+
+    /// Record files known to be identical on both sides as synced, in one transaction
+    pub fn record_adopted(&self, files: &[FileMetadata]) -> Result<()> {
+        let now = Utc::now().timestamp();
+        let mut conn = Connection::open(&self.db_path)?;
+        let tx = conn.transaction()?;
+        for meta in files {
+            tx.execute(
+                "INSERT OR REPLACE INTO sync_state (path, size, modified, hash, last_sync)
+                 VALUES (?, ?, ?, ?, ?)",
+                params![meta.path, meta.size as i64, meta.modified, meta.hash, now],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn forget_sync_state(&self, path: &Path) -> Result<()> {
+        let conn = Connection::open(&self.db_path)?;
+        conn.execute(
+            "DELETE FROM sync_state WHERE path = ?",
+            params![state_key(path)],
+        )?;
+        Ok(())
+    }
+
+    /// State recorded for one file at its last successful sync
+    pub fn recorded(&self, path: &Path) -> Result<Option<FileMetadata>> {
+        let conn = Connection::open(&self.db_path)
+            .context("Failed to open sync state database")?;
+
+        conn.query_row(
+            "SELECT path, size, modified, hash FROM sync_state WHERE path = ?",
+            params![state_key(path)],
+            |row| {
+                Ok(FileMetadata {
+                    path: row.get(0)?,
+                    size: row.get::<_, i64>(1)? as u64,
+                    modified: row.get(2)?,
+                    hash: row.get(3)?,
+                })
+            },
+        )
+        .optional()
+        .context("Failed to read sync state")
+    }
+
+    /// Every file recorded at its last successful sync
+    pub fn recorded_files(&self) -> Result<Vec<FileMetadata>> {
+        let conn = Connection::open(&self.db_path)
+            .context("Failed to open sync state database")?;
+        let mut stmt = conn.prepare("SELECT path, size, modified, hash FROM sync_state")?;
+
+        let rows = stmt.query_map([], |row| {
+            Ok(FileMetadata {
+                path: row.get(0)?,
+                size: row.get::<_, i64>(1)? as u64,
+                modified: row.get(2)?,
+                hash: row.get(3)?,
+            })
+        })?;
+
+        rows.collect::<rusqlite::Result<Vec<_>>>()
+            .context("Failed to read sync state")
+    }
+
+    /// Add a sync's network traffic to its profile's total for the current month
+    pub fn record_transfer(&self, profile: &str, bytes: u64, files: u64) -> Result<()> {
+        let conn = Connection::open(&self.db_path)?;
+        conn.execute(
+            "INSERT INTO transfer_totals (month, profile, bytes, files) VALUES (?, ?, ?, ?)
+             ON CONFLICT (month, profile) DO UPDATE SET bytes = bytes + excluded.bytes, files = files + excluded.files",
+            params![current_month(), profile, bytes as i64, files as i64],
+        )?;
+        Ok(())
+    }
+
+    /// Bytes transferred by all profiles in the current month
+    pub fn month_transfer_bytes(&self) -> Result<u64> {
+        let conn = Connection::open(&self.db_path)?;
+        let bytes: i64 = conn.query_row(
+            "SELECT COALESCE(SUM(bytes), 0) FROM transfer_totals WHERE month = ?",
+            params![current_month()],
+            |row| row.get(0),
+        )?;
+        Ok(bytes as u64)
+    }
+
+    /// Per-profile monthly totals, newest month first
+    pub fn transfer_totals(&self) -> Result<Vec<TransferTotal>> {
+        let conn = Connection::open(&self.db_path)
+            .context("Failed to open sync state database")?;
+        let mut stmt = conn.prepare(
+            "SELECT month, profile, bytes, files FROM transfer_totals ORDER BY month DESC, profile",
+        )?;
+
+        let rows = stmt.query_map([], |row| {
+            Ok(TransferTotal {
+                month: row.get(0)?,
+                profile: row.get(1)?,
+                bytes: row.get::<_, i64>(2)? as u64,
+                files: row.get::<_, i64>(3)? as u64,
+            })
+        })?;
+
+        rows.collect::<rusqlite::Result<Vec<_>>>()
+            .context("Failed to read transfer totals")
+    }
+
+    /// Remember that a scheduled run of a profile completed
+    pub fn record_run(&self, profile: &str) -> Result<()> {
+        let conn = Connection::open(&self.db_path)?;
+        conn.execute(
+            "INSERT OR REPLACE INTO profile_runs (profile, last_run) VALUES (?, ?)",
+            params![profile, Utc::now().timestamp()],
+        )?;
+        Ok(())
+    }
+
+    /// When a scheduled run of a profile last completed
+    pub fn last_run(&self, profile: &str) -> Result<Option<DateTime<Local>>> {
+        let conn = Connection::open(&self.db_path)
+            .context("Failed to open sync state database")?;
+        let timestamp: Option<i64> = conn
+            .query_row("SELECT last_run FROM profile_runs WHERE profile = ?", params![profile], |row| row.get(0))
+            .optional()?;
+        Ok(timestamp.and_then(|timestamp| Local.timestamp_opt(timestamp, 0).single()))
+    }
+
+    /// Append one finished (or failed) run to the history kept for `stats export`
+    pub fn record_history(&self, run: &RunRecord) -> Result<()> {
+        let conn = Connection::open(&self.db_path)?;
+        conn.execute(
+            "INSERT INTO run_history
+             (run_id, profile, device, started_at, duration_secs, status, files, bytes, network_bytes, conflicts, error)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            params![
+                run.run_id,
+                run.profile,
+                run.device,
+                run.started_at.timestamp(),
+                run.duration_secs,
+                run.status,
+                run.files as i64,
+                run.bytes as i64,
+                run.network_bytes as i64,
+                run.conflicts as i64,
+                run.error,
+            ],
+        )?;
         Ok(())
     }
+
+    /// Recorded runs started at or after `since`, oldest first
+    pub fn run_history(&self, since: Option<DateTime<Local>>) -> Result<Vec<RunRecord>> {
+        let conn = Connection::open(&self.db_path)
+            .context("Failed to open sync state database")?;
+        let mut stmt = conn.prepare(
+            "SELECT profile, started_at, duration_secs, status, files, bytes, network_bytes, conflicts, error, run_id, device
+             FROM run_history WHERE started_at >= ? ORDER BY started_at, id",
+        )?;
+        let rows = stmt.query_map(params![since.map_or(0, |since| since.timestamp())], run_record)?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()
+            .context("Failed to read run history")
+    }
+
+    /// Delete runs started before `before` and all but the newest `max_per_profile` runs of
+    /// each profile from the history. `archive` gets the runs first; if it fails, they stay
+    pub fn prune_history(
+        &self,
+        before: Option<DateTime<Local>>,
+        max_per_profile: Option<usize>,
+        archive: impl FnOnce(&[RunRecord]) -> Result<()>,
+    ) -> Result<usize> {
+        let mut conn = Connection::open(&self.db_path)
+            .context("Failed to open sync state database")?;
+        let tx = conn.transaction()?;
+        tx.execute(
+            "CREATE TEMP TABLE expired_runs AS
+             SELECT id FROM run_history WHERE started_at < ?1
+             UNION
+             SELECT id FROM (SELECT id, ROW_NUMBER() OVER (PARTITION BY profile ORDER BY started_at DESC, id DESC) AS n
+                             FROM run_history)
+             WHERE n > ?2",
+            params![before.map_or(i64::MIN, |before| before.timestamp()), max_per_profile.map_or(i64::MAX, |max| max as i64)],
+        )?;
+        let expired = {
+            let mut stmt = tx.prepare(
+                "SELECT profile, started_at, duration_secs, status, files, bytes, network_bytes, conflicts, error, run_id, device
+                 FROM run_history WHERE id IN (SELECT id FROM expired_runs) ORDER BY started_at, id",
+            )?;
+            let rows = stmt.query_map([], run_record)?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()
+                .context("Failed to read run history")?
+        };
+        if !expired.is_empty() {
+            archive(&expired)?;
+            tx.execute("DELETE FROM run_history WHERE id IN (SELECT id FROM expired_runs)", [])
+                .context("Failed to prune run history")?;
+        }
+        tx.execute("DROP TABLE expired_runs", [])?;
+        tx.commit()?;
+        Ok(expired.len())
+    }
+
+    /// Give the space of deleted rows back to the file system
+    pub fn vacuum(&self) -> Result<()> {
+        let conn = Connection::open(&self.db_path)
+            .context("Failed to open sync state database")?;
+        conn.execute_batch("VACUUM")
+            .with_context(|| format!("Failed to vacuum {}", self.db_path.display()))
+    }
+
+    /// Duration and bytes of the latest runs of a profile that did not fail, newest first
+    pub fn recent_runs(&self, profile: &str, limit: usize) -> Result<Vec<(f64, u64)>> {
+        let conn = Connection::open(&self.db_path)
+            .context("Failed to open sync state database")?;
+        let mut stmt = conn.prepare(
+            "SELECT duration_secs, bytes FROM run_history WHERE profile = ? AND status != 'failed'
+             ORDER BY started_at DESC, id DESC LIMIT ?",
+        )?;
+        let rows = stmt.query_map(params![profile, limit as i64], |row| {
+            Ok((row.get(0)?, row.get::<_, i64>(1)? as u64))
+        })?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()
+            .context("Failed to read run history")
+    }
+
+    /// Failure streak of a profile as tracked for notification policies
+    pub fn alert_state(&self, profile: &str) -> Result<AlertState> {
+        let conn = Connection::open(&self.db_path)
+            .context("Failed to open sync state database")?;
+        let time = |timestamp: Option<i64>| timestamp.and_then(|timestamp| Local.timestamp_opt(timestamp, 0).single());
+        let state = conn
+            .query_row(
+                "SELECT failures, first_failure, last_success, alerted FROM alert_state WHERE profile = ?",
+                params![profile],
+                |row| {
+                    Ok(AlertState {
+                        failures: row.get::<_, i64>(0)? as u32,
+                        first_failure: time(row.get(1)?),
+                        last_success: time(row.get(2)?),
+                        alerted: row.get(3)?,
+                    })
+                },
+            )
+            .optional()?;
+        Ok(state.unwrap_or_default())
+    }
+
+    pub fn set_alert_state(&self, profile: &str, state: &AlertState) -> Result<()> {
+        let conn = Connection::open(&self.db_path)?;
+        conn.execute(
+            "INSERT OR REPLACE INTO alert_state (profile, failures, first_failure, last_success, alerted)
+             VALUES (?, ?, ?, ?, ?)",
+            params![
+                profile,
+                state.failures as i64,
+                state.first_failure.map(|at| at.timestamp()),
+                state.last_success.map(|at| at.timestamp()),
+                state.alerted,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Mark a subdirectory of a profile's remote path as kept on the NAS only, or sync it again
+    pub fn set_remote_only(&self, profile: &str, path: &str, remote_only: bool) -> Result<()> {
+        let conn = Connection::open(&self.db_path)?;
+        if remote_only {
+            conn.execute(
+                "INSERT OR IGNORE INTO remote_only (profile, path) VALUES (?, ?)",
+                params![profile, path],
+            )?;
+        } else {
+            conn.execute(
+                "DELETE FROM remote_only WHERE profile = ? AND path = ?",
+                params![profile, path],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Subdirectories of a profile's remote path that are not synced locally
+    pub fn remote_only_paths(&self, profile: &str) -> Result<Vec<String>> {
+        let conn = Connection::open(&self.db_path)
+            .context("Failed to open sync state database")?;
+        let mut stmt = conn.prepare("SELECT path FROM remote_only WHERE profile = ? ORDER BY path")?;
+        let rows = stmt.query_map(params![profile], |row| row.get(0))?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()
+            .context("Failed to read remote-only paths")
+    }
+}
+
+/// Bytes and files a profile transferred in one month (`YYYY-MM`)
+#[derive(Debug)]
+pub struct TransferTotal {
+    pub month: String,
+    pub profile: String,
+    pub bytes: u64,
+    pub files: u64,
+}
+
+/// Consecutive failures of a profile since its last successful sync
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AlertState {
+    pub failures: u32,
+    pub first_failure: Option<DateTime<Local>>,
+    pub last_success: Option<DateTime<Local>>,
+    /// Whether the current streak was already notified
+    pub alerted: bool,
+}
+
+/// One sync of a profile as kept in the run history
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RunRecord {
+    pub run_id: String,
+    pub profile: String,
+    /// Name of the machine that ran it
+    pub device: String,
+    pub started_at: DateTime<Local>,
+    pub duration_secs: f64,
+    /// `success`, `partial` or `failed`
+    pub status: String,
+    pub files: u64,
+    pub bytes: u64,
+    pub network_bytes: u64,
+    pub conflicts: u64,
+    pub error: Option<String>,
+}
+
+/// A run_history row selected in the column order of `run_history()`
+fn run_record(row: &rusqlite::Row) -> rusqlite::Result<RunRecord> {
+    let started_at: i64 = row.get(1)?;
+    Ok(RunRecord {
+        profile: row.get(0)?,
+        started_at: Local.timestamp_opt(started_at, 0).single().unwrap_or_default(),
+        duration_secs: row.get(2)?,
+        status: row.get(3)?,
+        files: row.get::<_, i64>(4)? as u64,
+        bytes: row.get::<_, i64>(5)? as u64,
+        network_bytes: row.get::<_, i64>(6)? as u64,
+        conflicts: row.get::<_, i64>(7)? as u64,
+        error: row.get(8)?,
+        run_id: row.get(9)?,
+        // Runs recorded before devices were named
+        device: row.get::<_, Option<String>>(10)?.unwrap_or_default(),
+    })
+}
+
+/// Bring an older state database up to SCHEMA_VERSION. Every time column holds UTC Unix
+/// seconds: databases without a version were written through chrono's `timestamp()`,
+/// which is UTC whatever the local time zone, so version 1 only stamps the version.
+/// Version 2 records the device of each run.
+fn migrate(conn: &Connection) -> Result<()> {
+    let version: i32 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    if version < SCHEMA_VERSION {
+        debug!("Migrating sync state database from schema version {} to {}", version, SCHEMA_VERSION);
+        if version < 2 {
+            conn.execute("ALTER TABLE run_history ADD COLUMN device TEXT", [])
+                .context("Failed to add the device column to run_history")?;
+        }
+        conn.pragma_update(None, "user_version", SCHEMA_VERSION)
+            .context("Failed to update the sync state schema version")?;
+    } else if version > SCHEMA_VERSION {
+        warn!(
+            "Sync state database has schema version {}, newer than this UGNasSync ({}); it may not be read correctly",
+            version, SCHEMA_VERSION
+        );
+    }
+    Ok(())
+}
+
+pub fn current_month() -> String {
+    Local::now().format("%Y-%m").to_string()
+}
+
+pub fn print_transfer_totals(totals: &[TransferTotal], profile: Option<&str>, cap_gb: Option<f64>) {
+    const GB: f64 = 1024.0 * 1024.0 * 1024.0;
+    let month = current_month();
+    // The cap applies to all profiles together
+    let month_bytes: u64 = totals.iter().filter(|t| t.month == month).map(|t| t.bytes).sum();
+
+    let totals: Vec<&TransferTotal> = totals
+        .iter()
+        .filter(|total| profile.is_none_or(|name| total.profile == name))
+        .collect();
+
+    if totals.is_empty() {
+        println!("No transfers recorded");
+        return;
+    }
+
+    println!("{:<8} {:<30} {:>10} {:>12}", "Month", "Profile", "Files", "Transferred");
+    for total in &totals {
+        println!(
+            "{:<8} {:<30} {:>10} {:>9.2} GB",
+            total.month,
+            total.profile,
+            total.files,
+            total.bytes as f64 / GB
+        );
+    }
+
+    if let Some(cap_gb) = cap_gb {
+        println!("{}: {:.2} of {} GB monthly cap used", month, month_bytes as f64 / GB, cap_gb);
+    }
+}
+
+/// The shared state database, `~/.ugnassync/sync_state.db`
+pub fn default_db_path() -> Result<PathBuf> {
+    let home = dirs::home_dir().context("Could not determine home directory")?;
+    Ok(home.join(".ugnassync").join("sync_state.db"))
+}
+
+/// Key of a file in sync_state; NFC so macOS (NFD) and Linux spellings of a name match
+pub fn state_key(path: &Path) -> String {
+    path.to_string_lossy().nfc().collect()
 }
 
 // Add dirs crate for home directory detection
@@ -220,6 +796,30 @@ mod dirs {
     }
 }
 
+/// SHA-256 of a file's contents, as stored in the sync state
+pub fn hash_file(path: &Path) -> Result<String> {
+    let mut file = fs::File::open(path)
+        .with_context(|| format!("Failed to read file for hashing: {}", path.display()))?;
+
+    // Stream through a fixed buffer so memory use does not grow with the file
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; HASH_BUFFER_BYTES];
+    loop {
+        let read = match file.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read file for hashing: {}", path.display()))
+            }
+        };
+        hasher.update(&buffer[..read]);
+    }
+    let result = hasher.finalize();
+
+    Ok(format!("{:x}", result))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -229,4 +829,136 @@ mod tests {
         let resolver = ConflictResolver::new();
         assert!(resolver.is_ok());
     }
+
+    #[test]
+    fn test_in_memory_state_persists_across_connections() {
+        let resolver = ConflictResolver::in_memory("test-persist").unwrap();
+        let file = std::env::temp_dir().join(format!("ugnassync-conflict-test-{}", std::process::id()));
+        fs::write(&file, "content").unwrap();
+
+        resolver.update_sync_state(&file).unwrap();
+        drop(resolver);
+        let again = ConflictResolver::in_memory("test-persist").unwrap();
+        assert_eq!(again.recorded_files().unwrap().len(), 1);
+
+        fs::remove_file(&file).unwrap();
+    }
+
+    #[test]
+    fn test_mtime_rounding_is_not_a_change() {
+        let resolver = ConflictResolver::in_memory("test-rounding").unwrap();
+        let conn = Connection::open(&resolver.db_path).unwrap();
+        let version: i32 = conn.query_row("PRAGMA user_version", [], |row| row.get(0)).unwrap();
+        assert_eq!(version, SCHEMA_VERSION);
+        let dir = std::env::temp_dir().join(format!("ugnassync-rounding-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (local, remote) = (dir.join("local.txt"), dir.join("remote.txt"));
+        fs::write(&local, "synced").unwrap();
+        fs::write(&remote, "synced").unwrap();
+        let synced_at = std::time::SystemTime::now() - std::time::Duration::from_secs(600);
+        fs::File::options().write(true).open(&local).unwrap().set_modified(synced_at).unwrap();
+        resolver.update_sync_state(&local).unwrap();
+
+        // The NAS copy is unchanged but its mtime was rounded up to an even second
+        fs::File::options()
+            .write(true)
+            .open(&remote)
+            .unwrap()
+            .set_modified(synced_at + std::time::Duration::from_secs(2))
+            .unwrap();
+        fs::write(&local, "edited locally").unwrap();
+        assert!(!resolver.detect_conflict(&local, &remote).unwrap());
+        // A target with exact mtimes sees the rounded copy as changed
+        assert!(resolver.clone().with_mtime_tolerance(0).detect_conflict(&local, &remote).unwrap());
+
+        fs::write(&remote, "edited on the NAS").unwrap();
+        assert!(resolver.detect_conflict(&local, &remote).unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_hash_file_spans_several_buffers() {
+        let file = std::env::temp_dir().join(format!("ugnassync-hash-test-{}", std::process::id()));
+        let contents: Vec<u8> = (0..HASH_BUFFER_BYTES * 3 + 17).map(|i| (i % 251) as u8).collect();
+        fs::write(&file, &contents).unwrap();
+
+        assert_eq!(hash_file(&file).unwrap(), format!("{:x}", Sha256::digest(&contents)));
+
+        let _ = fs::remove_file(&file);
+    }
+
+    #[test]
+    fn test_transfer_totals_accumulate() {
+        let resolver = ConflictResolver::in_memory("test-transfer").unwrap();
+        resolver.record_transfer("photos", 1000, 2).unwrap();
+        resolver.record_transfer("photos", 500, 1).unwrap();
+        resolver.record_transfer("docs", 10, 1).unwrap();
+
+        assert_eq!(resolver.month_transfer_bytes().unwrap(), 1510);
+        let totals = resolver.transfer_totals().unwrap();
+        assert_eq!(totals.len(), 2);
+        assert_eq!((totals[1].bytes, totals[1].files), (1500, 3));
+    }
+
+    #[test]
+    fn test_run_history_since() {
+        let resolver = ConflictResolver::in_memory("test-history").unwrap();
+        let run = |days_ago: i64| RunRecord {
+            run_id: format!("run-{}", days_ago),
+            profile: "docs".to_string(),
+            device: "laptop".to_string(),
+            started_at: Local.timestamp_opt(Local::now().timestamp() - days_ago * 86400, 0).unwrap(),
+            duration_secs: 2.0,
+            status: "success".to_string(),
+            files: 1,
+            bytes: 10,
+            network_bytes: 5,
+            conflicts: 0,
+            error: None,
+        };
+        resolver.record_history(&run(30)).unwrap();
+        resolver.record_history(&run(1)).unwrap();
+
+        assert_eq!(resolver.run_history(None).unwrap().len(), 2);
+        let recent = resolver.run_history(Some(run(7).started_at)).unwrap();
+        assert_eq!(recent, vec![run(1)]);
+    }
+
+    #[test]
+    fn test_prune_history_by_age_and_count() {
+        let resolver = ConflictResolver::in_memory("test-prune-history").unwrap();
+        let run = |profile: &str, days_ago: i64| RunRecord {
+            run_id: format!("{}-{}", profile, days_ago),
+            profile: profile.to_string(),
+            device: "laptop".to_string(),
+            started_at: Local.timestamp_opt(Local::now().timestamp() - days_ago * 86400, 0).unwrap(),
+            duration_secs: 2.0,
+            status: "success".to_string(),
+            files: 1,
+            bytes: 10,
+            network_bytes: 5,
+            conflicts: 0,
+            error: None,
+        };
+        for (profile, days_ago) in [("docs", 30), ("docs", 10), ("docs", 5), ("docs", 1), ("photos", 40), ("photos", 2)] {
+            resolver.record_history(&run(profile, days_ago)).unwrap();
+        }
+        let before = Some(run("docs", 20).started_at);
+
+        // A failed archive leaves the history alone
+        assert!(resolver.prune_history(before, Some(2), |_| anyhow::bail!("disk full")).is_err());
+        assert_eq!(resolver.run_history(None).unwrap().len(), 6);
+
+        let mut archived = Vec::new();
+        let pruned = resolver
+            .prune_history(before, Some(2), |runs| {
+                archived = runs.iter().map(|run| run.run_id.clone()).collect();
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(pruned, 3);
+        assert_eq!(archived, vec!["photos-40", "docs-30", "docs-10"]);
+        let kept: Vec<String> = resolver.run_history(None).unwrap().into_iter().map(|run| run.run_id).collect();
+        assert_eq!(kept, vec!["docs-5", "photos-2", "docs-1"]);
+    }
 }
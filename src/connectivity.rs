@@ -0,0 +1,118 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use crate::config::NasConfig;
+use crate::dsm;
+use crate::error::SyncError;
+use anyhow::Result;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::process::Command;
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Address chosen by the first successful probe, reused for the rest of the run
+static SELECTED_HOST: Mutex<Option<String>> = Mutex::new(None);
+
+/// Pick the first reachable NAS address, probing candidates only when there is a choice
+pub fn select_host(nas: &NasConfig) -> Result<String> {
+    let quickconnect_id = nas.dsm.as_ref().and_then(|dsm| dsm.quickconnect_id.as_deref());
+    if nas.fallback_hosts.is_empty() && nas.discovery_name.is_none() && quickconnect_id.is_none() {
+        return Ok(nas.host.clone());
+    }
+
+    if let Some(host) = SELECTED_HOST.lock().unwrap().clone() {
+        return Ok(host);
+    }
+
+    // QuickConnect is only asked once the configured addresses failed
+    let quickconnect = quickconnect_id.into_iter().flat_map(|id| {
+        dsm::resolve_quickconnect(id)
+            .inspect_err(|e| warn!("Could not resolve QuickConnect ID {}: {:#}", id, e))
+            .unwrap_or_default()
+    });
+    for candidate in candidates(nas).into_iter().chain(quickconnect) {
+        debug!("Probing NAS address {}:{}", candidate, nas.port);
+        if is_reachable(&candidate, nas.port) {
+            if candidate != nas.host {
+                info!("NAS reachable via alternate address {}", candidate);
+            }
+            *SELECTED_HOST.lock().unwrap() = Some(candidate.clone());
+            return Ok(candidate);
+        }
+    }
+
+    Err(SyncError::Unreachable(format!(
+        "NAS is not reachable on port {} at any configured address ({})",
+        nas.port,
+        candidates(nas).join(", ")
+    ))
+    .into())
+}
+
+/// Drop the cached address so the next run probes again (e.g. after a connection failure)
+pub fn forget_selected_host() {
+    SELECTED_HOST.lock().unwrap().take();
+}
+
+fn candidates(nas: &NasConfig) -> Vec<String> {
+    let mut candidates = vec![nas.host.clone()];
+
+    if let Some(name) = &nas.discovery_name {
+        match resolve_mdns(name) {
+            Some(address) => candidates.push(address),
+            None => warn!("Could not resolve NAS discovery name {}", name),
+        }
+    }
+
+    candidates.extend(nas.fallback_hosts.iter().cloned());
+    candidates.dedup();
+    candidates
+}
+
+pub fn is_reachable(host: &str, port: u16) -> bool {
+    let addrs = match (host, port).to_socket_addrs() {
+        Ok(addrs) => addrs,
+        Err(e) => {
+            debug!("Failed to resolve {}: {}", host, e);
+            return false;
+        }
+    };
+
+    addrs.into_iter().any(|addr| TcpStream::connect_timeout(&addr, PROBE_TIMEOUT).is_ok())
+}
+
+/// Resolve a `.local` name through Avahi
+fn resolve_mdns(name: &str) -> Option<String> {
+    let output = Command::new("avahi-resolve-host-name")
+        .arg("-4")
+        .arg(name)
+        .output()
+        .ok()?;
+
+    // Output format: "<name>\t<address>"
+    String::from_utf8_lossy(&output.stdout)
+        .split_whitespace()
+        .nth(1)
+        .map(|address| address.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_is_reachable() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        assert!(is_reachable("127.0.0.1", port));
+        drop(listener);
+        assert!(!is_reachable("127.0.0.1", port));
+    }
+}
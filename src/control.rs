@@ -0,0 +1,289 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use crate::lowchurn::Activity;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{Read, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::UnixListener;
+use tracing::{debug, info, warn};
+
+/// Runtime status of a watched profile
+#[derive(Debug, Clone, Default)]
+pub struct ProfileStatus {
+    pub paused: bool,
+    pub syncing: bool,
+    pub pending_changes: usize,
+    pub last_sync: Option<DateTime<Local>>,
+    pub last_result: Option<String>,
+    pub warnings: Vec<String>,
+    pub registered: Option<DateTime<Local>>,
+    pub last_success: Option<DateTime<Local>>,
+    /// Why the watcher died, until the supervisor has it running again
+    pub watcher_error: Option<String>,
+    /// Start of the running sync and how long the profile's history says it takes
+    pub sync_started: Option<DateTime<Local>>,
+    pub expected_secs: Option<f64>,
+}
+
+/// Claims older than this are forgotten
+const CLAIM_RETENTION: Duration = Duration::from_secs(3600);
+
+/// Daemon state shared between watchers and the control socket
+#[derive(Clone, Default)]
+pub struct DaemonState {
+    profiles: Arc<Mutex<BTreeMap<String, ProfileStatus>>>,
+    activity: Arc<Mutex<Activity>>,
+    /// When each profile last queued a change of each path for its next sync
+    claims: Arc<Mutex<HashMap<(String, PathBuf), Instant>>>,
+}
+
+impl DaemonState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Low-churn mode, reported in the status with the NAS wakeups it leads to
+    pub fn set_low_churn(&self, window_minutes: u64) {
+        *self.activity.lock().unwrap() = Activity::low_churn(window_minutes, Local::now());
+    }
+
+    /// A sync starting or finishing now
+    pub fn record_nas_access(&self) {
+        self.activity.lock().unwrap().record(Local::now());
+    }
+
+    pub fn register(&self, profile: &str) {
+        self.profiles
+            .lock()
+            .unwrap()
+            .entry(profile.to_string())
+            .or_default()
+            .registered = Some(Local::now());
+    }
+
+    /// Copy of every profile's status
+    pub fn snapshot(&self) -> BTreeMap<String, ProfileStatus> {
+        self.profiles.lock().unwrap().clone()
+    }
+
+    pub fn update<F: FnOnce(&mut ProfileStatus)>(&self, profile: &str, f: F) {
+        if let Some(status) = self.profiles.lock().unwrap().get_mut(profile) {
+            f(status);
+        }
+    }
+
+    pub fn is_paused(&self, profile: &str) -> bool {
+        self.profiles
+            .lock()
+            .unwrap()
+            .get(profile)
+            .map(|s| s.paused)
+            .unwrap_or(false)
+    }
+
+    /// `profile` queued a change of `path` for its next sync
+    pub fn claim(&self, profile: &str, path: &Path) {
+        let mut claims = self.claims.lock().unwrap();
+        claims.retain(|_, at| at.elapsed() < CLAIM_RETENTION);
+        claims.insert((profile.to_string(), path.to_path_buf()), Instant::now());
+    }
+
+    /// Whether `profile` queued a change of `path` within the last `window`
+    pub fn claimed_within(&self, profile: &str, path: &Path, window: Duration) -> bool {
+        self.claims
+            .lock()
+            .unwrap()
+            .get(&(profile.to_string(), path.to_path_buf()))
+            .is_some_and(|at| at.elapsed() < window)
+    }
+
+    /// Pause or resume a profile, returning false if it is unknown
+    pub fn set_paused(&self, profile: &str, paused: bool) -> bool {
+        match self.profiles.lock().unwrap().get_mut(profile) {
+            Some(status) => {
+                status.paused = paused;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn render_status(&self) -> String {
+        let profiles = self.profiles.lock().unwrap();
+        let mut out = String::new();
+
+        for (name, status) in profiles.iter() {
+            let state = match (status.syncing, status.sync_started, status.expected_secs) {
+                (true, Some(started), Some(expected)) => {
+                    let elapsed = (Local::now() - started).num_milliseconds() as f64 / 1000.0;
+                    format!("syncing ({})", crate::eta::remaining(expected, elapsed))
+                }
+                (true, _, _) => "syncing".to_string(),
+                _ if status.paused => "paused".to_string(),
+                _ => "watching".to_string(),
+            };
+            let last_sync = status
+                .last_sync
+                .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+                .unwrap_or_else(|| "never".to_string());
+
+            out.push_str(&format!(
+                "{}: {}, pending changes: {}, last sync: {}",
+                name, state, status.pending_changes, last_sync
+            ));
+            if let Some(result) = &status.last_result {
+                out.push_str(&format!(" ({})", result));
+            }
+            for warning in &status.warnings {
+                out.push_str(&format!("\n  warning: {}", warning));
+            }
+            out.push('\n');
+        }
+        if let Some(line) = self.activity.lock().unwrap().render(Local::now()) {
+            out.push_str(&line);
+            out.push('\n');
+        }
+
+        out
+    }
+}
+
+/// Path of the daemon control socket
+pub fn socket_path() -> Result<PathBuf> {
+    Ok(dirs::home_dir()
+        .context("Failed to get home directory")?
+        .join(".ugnassync")
+        .join("control.sock"))
+}
+
+/// Serve control commands for the lifetime of the daemon
+pub async fn serve(state: DaemonState) -> Result<()> {
+    let path = socket_path()?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).context("Failed to create state directory")?;
+    }
+
+    // A stale socket from a previous run would make bind fail
+    if path.exists() {
+        fs::remove_file(&path).context("Failed to remove stale control socket")?;
+    }
+
+    let listener = UnixListener::bind(&path)
+        .with_context(|| format!("Failed to bind control socket: {}", path.display()))?;
+
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o600))
+        .context("Failed to set control socket permissions")?;
+
+    info!("Control socket listening on {}", path.display());
+
+    loop {
+        let (stream, _) = listener.accept().await.context("Control socket accept failed")?;
+        let state = state.clone();
+
+        tokio::spawn(async move {
+            let (reader, mut writer) = stream.into_split();
+            let mut line = String::new();
+
+            if let Err(e) = BufReader::new(reader).read_line(&mut line).await {
+                warn!("Failed to read control command: {}", e);
+                return;
+            }
+
+            debug!("Control command: {}", line.trim());
+            let response = handle_command(&state, line.trim());
+            let _ = writer.write_all(response.as_bytes()).await;
+        });
+    }
+}
+
+fn handle_command(state: &DaemonState, line: &str) -> String {
+    let (command, arg) = match line.split_once(' ') {
+        Some((command, arg)) => (command, arg.trim()),
+        None => (line, ""),
+    };
+
+    match command {
+        "status" => state.render_status(),
+        "pause" | "resume" => {
+            let paused = command == "pause";
+            if state.set_paused(arg, paused) {
+                info!("Profile {} {} via control socket", arg, if paused { "paused" } else { "resumed" });
+                format!("Profile '{}' {}\n", arg, if paused { "paused" } else { "resumed" })
+            } else {
+                format!("error: unknown profile '{}'\n", arg)
+            }
+        }
+        "log-level" => match crate::logging::set_level(arg) {
+            Ok(message) => format!("{}\n", message),
+            Err(e) => format!("error: {:#}\n", e),
+        },
+        _ => format!("error: unknown command '{}'\n", command),
+    }
+}
+
+/// Send a command to a running daemon and return its response
+pub fn send_command(command: &str) -> Result<String> {
+    let path = socket_path()?;
+    let mut stream = std::os::unix::net::UnixStream::connect(&path).with_context(|| {
+        format!(
+            "Failed to connect to control socket {} (is watch mode running?)",
+            path.display()
+        )
+    })?;
+
+    writeln!(stream, "{}", command).context("Failed to send control command")?;
+    stream
+        .shutdown(std::net::Shutdown::Write)
+        .context("Failed to send control command")?;
+
+    let mut response = String::new();
+    stream
+        .read_to_string(&mut response)
+        .context("Failed to read control response")?;
+
+    if let Some(message) = response.strip_prefix("error: ") {
+        anyhow::bail!("{}", message.trim());
+    }
+
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pause_and_resume_commands() {
+        let state = DaemonState::new();
+        state.register("Photos");
+
+        assert!(handle_command(&state, "pause Photos").contains("paused"));
+        assert!(state.is_paused("Photos"));
+        assert!(handle_command(&state, "status").contains("Photos: paused"));
+
+        handle_command(&state, "resume Photos");
+        assert!(!state.is_paused("Photos"));
+        assert!(handle_command(&state, "pause Missing").starts_with("error:"));
+
+        state.update("Photos", |s| {
+            s.syncing = true;
+            s.sync_started = Some(Local::now() - chrono::Duration::minutes(5));
+            s.expected_secs = Some(2400.0);
+        });
+        assert!(handle_command(&state, "status").contains("Photos: syncing (about 35 min remaining)"));
+        assert!(!handle_command(&state, "status").contains("low-churn"));
+
+        state.set_low_churn(120);
+        assert!(handle_command(&state, "status").contains("low-churn: syncs batched every 120 min, at most 12 NAS wakeup(s)/day"));
+    }
+}
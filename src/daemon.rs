@@ -0,0 +1,197 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use crate::config::Config;
+use anyhow::{Context, Result};
+use std::ffi::OsString;
+use std::fs::{self, OpenOptions};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+use tokio::signal::unix::{signal, SignalKind};
+use tracing::{error, info};
+
+/// Set in the environment of the background process started by `--detach`
+pub const DETACHED_ENV: &str = "UGNASSYNC_DETACHED";
+
+/// How long the background process must survive to count as started
+const STARTUP_GRACE: Duration = Duration::from_secs(2);
+
+/// How long `stop` waits for the daemon to exit
+const STOP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Whether this process is the background daemon started by `--detach`
+pub fn is_detached() -> bool {
+    std::env::var_os(DETACHED_ENV).is_some()
+}
+
+/// The PID file given on the command line, or `~/.ugnassync/ugnassync.pid`
+pub fn pid_file_path(custom: Option<&Path>) -> Result<PathBuf> {
+    match custom {
+        Some(path) => Ok(path.to_path_buf()),
+        None => Ok(dirs::home_dir()
+            .context("Failed to get home directory")?
+            .join(".ugnassync")
+            .join("ugnassync.pid")),
+    }
+}
+
+/// Start this command again in the background, with output appended to `log_file`,
+/// and record its PID
+pub fn detach(pid_file: &Path, log_file: &str) -> Result<u32> {
+    if let Some(pid) = running_pid(pid_file)? {
+        anyhow::bail!("UGNasSync is already running in the background (PID {})", pid);
+    }
+
+    if let Some(dir) = Path::new(log_file).parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    let log = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_file)
+        .with_context(|| format!("Failed to open log file {}", log_file))?;
+
+    let args: Vec<OsString> = std::env::args_os().skip(1).filter(|arg| arg != "--detach").collect();
+    let mut child = Command::new(std::env::current_exe().context("Failed to locate the ugnassync binary")?)
+        .args(args)
+        .env(DETACHED_ENV, "1")
+        .stdin(Stdio::null())
+        .stdout(log.try_clone()?)
+        .stderr(log)
+        // Leave the terminal's process group so Ctrl+C and closing the shell do not reach it
+        .process_group(0)
+        .spawn()
+        .context("Failed to start background process")?;
+
+    let started = Instant::now();
+    while started.elapsed() < STARTUP_GRACE {
+        if let Some(status) = child.try_wait()? {
+            anyhow::bail!("Background process exited at startup ({}), see {}", status, log_file);
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+
+    if let Some(dir) = pid_file.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    fs::write(pid_file, format!("{}\n", child.id()))
+        .with_context(|| format!("Failed to write PID file {}", pid_file.display()))?;
+    Ok(child.id())
+}
+
+/// PID of the daemon recorded in `pid_file`, if that process is still alive
+pub fn running_pid(pid_file: &Path) -> Result<Option<u32>> {
+    let content = match fs::read_to_string(pid_file) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read PID file {}", pid_file.display())),
+    };
+    let pid: u32 = content
+        .trim()
+        .parse()
+        .with_context(|| format!("PID file {} does not contain a PID", pid_file.display()))?;
+
+    Ok(kill(pid, "0").then_some(pid))
+}
+
+/// Terminate the daemon and wait for it to exit
+pub fn stop(pid_file: &Path) -> Result<()> {
+    let Some(pid) = running_pid(pid_file)? else {
+        let _ = fs::remove_file(pid_file);
+        anyhow::bail!("UGNasSync is not running in the background ({})", pid_file.display());
+    };
+
+    if !kill(pid, "TERM") {
+        anyhow::bail!("Failed to signal PID {}", pid);
+    }
+
+    let started = Instant::now();
+    while kill(pid, "0") {
+        if started.elapsed() >= STOP_TIMEOUT {
+            anyhow::bail!("PID {} did not exit within {}s", pid, STOP_TIMEOUT.as_secs());
+        }
+        std::thread::sleep(Duration::from_millis(200));
+    }
+
+    fs::remove_file(pid_file).with_context(|| format!("Failed to remove PID file {}", pid_file.display()))?;
+    println!("Stopped UGNasSync (PID {})", pid);
+    Ok(())
+}
+
+/// Ask the daemon to re-read its configuration
+pub fn reload(pid_file: &Path) -> Result<()> {
+    let Some(pid) = running_pid(pid_file)? else {
+        anyhow::bail!("UGNasSync is not running in the background ({})", pid_file.display());
+    };
+
+    if !kill(pid, "HUP") {
+        anyhow::bail!("Failed to signal PID {}", pid);
+    }
+    println!("Asked UGNasSync (PID {}) to reload its configuration", pid);
+    Ok(())
+}
+
+/// On SIGHUP, restart the process in place (keeping its PID) once the config
+/// still loads; a broken config is logged and the daemon keeps running
+pub async fn reload_on_hangup(config_path: PathBuf) -> Result<()> {
+    let mut hangups = signal(SignalKind::hangup()).context("Failed to listen for SIGHUP")?;
+
+    while hangups.recv().await.is_some() {
+        if let Err(e) = Config::load(&config_path) {
+            error!("Not reloading, configuration is invalid: {:#}", e);
+            continue;
+        }
+
+        info!("Reloading configuration from {}", config_path.display());
+        let exe = std::env::current_exe().context("Failed to locate the ugnassync binary")?;
+        let error = Command::new(exe).args(std::env::args_os().skip(1)).exec();
+        error!("Failed to restart for reload: {}", error);
+    }
+    Ok(())
+}
+
+/// On SIGUSR1, switch between debug logging and the configured log levels
+pub async fn toggle_debug_on_usr1() -> Result<()> {
+    let mut signals = signal(SignalKind::user_defined1()).context("Failed to listen for SIGUSR1")?;
+    while signals.recv().await.is_some() {
+        if let Err(e) = crate::logging::toggle_debug() {
+            error!("Failed to toggle debug logging: {:#}", e);
+        }
+    }
+    Ok(())
+}
+
+/// Send a signal with kill(1); signal 0 only checks that the process exists
+fn kill(pid: u32, signal: &str) -> bool {
+    Command::new("kill")
+        .arg(format!("-{}", signal))
+        .arg(pid.to_string())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_running_pid_ignores_dead_processes() {
+        let pid_file = std::env::temp_dir().join(format!("ugnassync-pid-{}", std::process::id()));
+        assert_eq!(running_pid(&pid_file).unwrap(), None);
+
+        fs::write(&pid_file, format!("{}\n", std::process::id())).unwrap();
+        assert_eq!(running_pid(&pid_file).unwrap(), Some(std::process::id()));
+
+        let mut exited = Command::new("true").spawn().unwrap();
+        exited.wait().unwrap();
+        fs::write(&pid_file, exited.id().to_string()).unwrap();
+        assert_eq!(running_pid(&pid_file).unwrap(), None);
+
+        fs::remove_file(&pid_file).unwrap();
+    }
+}
@@ -0,0 +1,203 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use crate::capabilities;
+use crate::config::{Config, SyncProfile};
+use crate::conflict::{ConflictResolver, FileMetadata};
+use crate::remote::{shell_quote, RemoteShell};
+use anyhow::Result;
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+use tracing::{info, warn};
+
+/// One synced copy of a file
+#[derive(Debug)]
+pub struct StoredCopy {
+    pub profile: String,
+    pub local_path: String,
+    /// Path on the NAS; None for SMB profiles, whose remote_path is on the local mount
+    pub nas_path: Option<String>,
+}
+
+/// Files with identical content, as recorded in the sync state
+#[derive(Debug)]
+pub struct DuplicateGroup {
+    pub hash: String,
+    pub size: u64,
+    pub copies: Vec<StoredCopy>,
+}
+
+impl DuplicateGroup {
+    /// Space freed on the NAS if only one copy were stored
+    pub fn reclaimable(&self) -> u64 {
+        self.size * (self.copies.len() as u64 - 1)
+    }
+}
+
+/// Group the recorded files of all profiles by content hash
+pub fn find_duplicates(config: &Config) -> Result<Vec<DuplicateGroup>> {
+    let mut by_hash: BTreeMap<(String, u64), Vec<StoredCopy>> = BTreeMap::new();
+
+    for profile in &config.sync_profiles {
+        let resolver = match ConflictResolver::for_profile(profile) {
+            Ok(resolver) => resolver,
+            Err(e) => {
+                warn!("Skipping profile {}: {:#}", profile.name, e);
+                continue;
+            }
+        };
+
+        for meta in recorded_for(profile, &resolver)? {
+            by_hash
+                .entry((meta.hash, meta.size))
+                .or_default()
+                .push(copy_for(profile, meta.path));
+        }
+    }
+
+    let mut groups: Vec<DuplicateGroup> = by_hash
+        .into_iter()
+        .filter(|(_, copies)| copies.len() > 1)
+        .map(|((hash, size), copies)| DuplicateGroup { hash, size, copies })
+        .filter(|group| group.size > 0)
+        .collect();
+    groups.sort_by_key(|group| std::cmp::Reverse(group.reclaimable()));
+    Ok(groups)
+}
+
+fn recorded_for(profile: &SyncProfile, resolver: &ConflictResolver) -> Result<Vec<FileMetadata>> {
+    let roots: Vec<&Path> = profile.source_paths().into_iter().map(Path::new).collect();
+    Ok(resolver
+        .recorded_files()?
+        .into_iter()
+        .filter(|meta| roots.iter().any(|root| Path::new(&meta.path).starts_with(root)))
+        .collect())
+}
+
+fn copy_for(profile: &SyncProfile, local_path: String) -> StoredCopy {
+    let nas_path = (!profile.use_smb_mount)
+        .then(|| local_path.strip_prefix(&profile.transfer_root()))
+        .flatten()
+        .map(|relative| format!("{}/{}", profile.remote_path.trim_end_matches('/'), relative));
+
+    StoredCopy {
+        profile: profile.name.clone(),
+        local_path,
+        nas_path,
+    }
+}
+
+pub fn print_report(groups: &[DuplicateGroup]) {
+    if groups.is_empty() {
+        println!("No duplicate files found in the sync state");
+        return;
+    }
+
+    for group in groups {
+        println!(
+            "{} x {:.2} MB ({:.2} MB reclaimable), sha256 {}",
+            group.copies.len(),
+            group.size as f64 / (1024.0 * 1024.0),
+            group.reclaimable() as f64 / (1024.0 * 1024.0),
+            &group.hash[..group.hash.len().min(12)]
+        );
+        for copy in &group.copies {
+            println!(
+                "  [{}] {} -> {}",
+                copy.profile,
+                copy.local_path,
+                copy.nas_path.as_deref().unwrap_or("(SMB mount)")
+            );
+        }
+    }
+
+    let total: u64 = groups.iter().map(DuplicateGroup::reclaimable).sum();
+    println!(
+        "{} group(s) of duplicates, {:.2} MB reclaimable on the NAS",
+        groups.len(),
+        total as f64 / (1024.0 * 1024.0)
+    );
+}
+
+/// Replace duplicate NAS copies with hardlinks to the first one, after re-checking their hashes.
+/// rsync replaces files instead of writing into them, so later syncs simply break the link.
+pub fn hardlink(config: &Config, groups: &[DuplicateGroup], dry_run: bool) -> Result<u64> {
+    let shell = RemoteShell::new(&config.nas)?;
+    let capabilities = capabilities::of(&shell)?;
+    capabilities.require(capabilities.sha256sum, "sha256sum", "Hardlinking duplicates")?;
+    let mut reclaimed = 0;
+
+    for group in groups {
+        let mut seen = HashSet::new();
+        let paths: Vec<&str> = group
+            .copies
+            .iter()
+            .filter_map(|copy| copy.nas_path.as_deref())
+            .filter(|path| seen.insert(*path))
+            .collect();
+        let Some((first, duplicates)) = paths.split_first() else {
+            continue;
+        };
+
+        for duplicate in duplicates {
+            if dry_run {
+                println!("Would link {} -> {}", duplicate, first);
+                reclaimed += group.size;
+                continue;
+            }
+
+            let script = format!(
+                r#"a={}; b={}; h={}
+if [ "$a" -ef "$b" ]; then echo same
+elif [ "$(sha256sum "$a" | cut -d' ' -f1)" = "$h" ] && [ "$(sha256sum "$b" | cut -d' ' -f1)" = "$h" ]; then ln -f "$a" "$b" && echo linked
+else echo changed; fi"#,
+                shell_quote(first),
+                shell_quote(duplicate),
+                shell_quote(&group.hash)
+            );
+
+            match shell.run(&script).map(|out| out.trim().to_string()).as_deref() {
+                Ok("linked") => {
+                    info!("Linked {} -> {}", duplicate, first);
+                    reclaimed += group.size;
+                }
+                Ok("same") => {}
+                Ok(_) => warn!("Not linking {}: content changed since the last sync", duplicate),
+                Err(e) => warn!("Not linking {}: {:#}", duplicate, e),
+            }
+        }
+    }
+
+    Ok(reclaimed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_copy_for_maps_nas_path() {
+        let profile: SyncProfile = toml::from_str(
+            r#"
+            name = "photos"
+            local_path = "/home/user/Pictures"
+            remote_path = "/volume1/backups/"
+            sync_type = "one-way"
+            enabled = true
+            "#,
+        )
+        .unwrap();
+
+        let copy = copy_for(&profile, "/home/user/Pictures/2024/a.jpg".to_string());
+        assert_eq!(copy.nas_path.as_deref(), Some("/volume1/backups/Pictures/2024/a.jpg"));
+
+        let group = DuplicateGroup {
+            hash: "abc".to_string(),
+            size: 10,
+            copies: vec![copy, copy_for(&profile, "/home/user/Pictures/b.jpg".to_string())],
+        };
+        assert_eq!(group.reclaimable(), 10);
+    }
+}
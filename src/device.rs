@@ -0,0 +1,118 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use crate::conflict;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+use std::time::SystemTime;
+use tracing::{info, warn};
+
+/// This machine as run history, conflict copies, audit logs and other clients name it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Device {
+    /// `<hostname>-<random>`, kept when the machine or device is renamed
+    pub id: String,
+    /// Friendly name, the hostname unless `device_name` is configured
+    pub name: String,
+}
+
+impl Device {
+    /// The name as it can appear in file names, e.g. `Annas-Laptop` for "Anna's Laptop"
+    pub fn label(&self) -> String {
+        let label: String = self
+            .name
+            .chars()
+            .filter(|c| *c != '\'')
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '-' })
+            .collect();
+        let label = label.trim_matches('-');
+        if label.is_empty() {
+            self.id.clone()
+        } else {
+            label.to_string()
+        }
+    }
+}
+
+static CURRENT: OnceLock<Device> = OnceLock::new();
+
+/// Load this machine's identity from the state directory, creating it on first use;
+/// `name` (device_name in the config) renames the device
+pub fn init(name: Option<&str>) -> &'static Device {
+    CURRENT.get_or_init(|| {
+        // Tests leave the identity in the home directory alone
+        if cfg!(test) {
+            return generate("test");
+        }
+        let loaded = conflict::default_db_path().and_then(|db| load(&db.with_file_name("device.json"), name));
+        loaded.unwrap_or_else(|e| {
+            warn!("Could not load the device identity: {:#}", e);
+            let mut device = generate(&hostname());
+            device.name = name.map_or(device.name, str::to_string);
+            device
+        })
+    })
+}
+
+/// This machine, with the name `init` gave it
+pub fn current() -> &'static Device {
+    init(None)
+}
+
+fn load(path: &Path, name: Option<&str>) -> Result<Device> {
+    let stored: Option<Device> = fs::read_to_string(path).ok().and_then(|content| serde_json::from_str(&content).ok());
+    let mut device = stored.clone().unwrap_or_else(|| generate(&hostname()));
+    if let Some(name) = name.map(str::trim).filter(|name| !name.is_empty()) {
+        device.name = name.to_string();
+    }
+    if stored.as_ref() != Some(&device) {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        fs::write(path, serde_json::to_string_pretty(&device)?).with_context(|| format!("Failed to write {}", path.display()))?;
+        info!("This device is {} ({})", device.name, device.id);
+    }
+    Ok(device)
+}
+
+fn generate(host: &str) -> Device {
+    let seed = format!("{}:{}:{:?}", host, std::process::id(), SystemTime::now());
+    Device {
+        id: format!("{}-{}", host, &format!("{:x}", Sha256::digest(seed.as_bytes()))[..8]),
+        name: host.to_string(),
+    }
+}
+
+fn hostname() -> String {
+    fs::read_to_string("/etc/hostname")
+        .ok()
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .map(|host| host.trim().to_string())
+        .filter(|host| !host.is_empty())
+        .unwrap_or_else(|| "client".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_device_identity_survives_renames() {
+        let path = std::env::temp_dir().join(format!("ugnassync-device-test-{}", std::process::id())).join("device.json");
+        let first = load(&path, None).unwrap();
+        assert_eq!(load(&path, None).unwrap(), first);
+
+        let renamed = load(&path, Some("Anna's Laptop")).unwrap();
+        assert_eq!((renamed.id.as_str(), renamed.name.as_str()), (first.id.as_str(), "Anna's Laptop"));
+        assert_eq!(load(&path, None).unwrap().name, "Anna's Laptop");
+        assert_eq!(renamed.label(), "Annas-Laptop");
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,145 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use crate::config::{NasConfig, SyncProfile};
+use crate::conflict;
+use crate::remote::{shell_quote, RemoteShell};
+use crate::smb::SmbMount;
+use crate::versions;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Like git, a NUL byte near the start marks a file as binary
+const BINARY_PROBE: usize = 8000;
+
+/// Show how a local file of a profile differs from its copy on the NAS: a unified
+/// diff for text, size and hash for binary files. `path` is absolute or relative
+/// to the profile's local path.
+pub async fn diff(nas: &NasConfig, profile: &SyncProfile, path: &Path) -> Result<()> {
+    let local = local_file(profile, path);
+    if !local.is_file() {
+        anyhow::bail!("{} is not a local file", local.display());
+    }
+    let remote = format!(
+        "{}/{}",
+        profile.remote_path.trim_end_matches('/'),
+        versions::relative_path(profile, &local)?
+    );
+
+    let (copy, temporary) = match (&nas.smb, profile.use_smb_mount) {
+        (Some(smb_config), true) if smb_config.enabled => {
+            let mut mount = SmbMount::new(smb_config.clone());
+            mount.mount().await?;
+            let result = compare(&local, Path::new(&remote), &remote);
+            if mount.should_auto_unmount() {
+                mount.unmount().await?;
+            }
+            return result;
+        }
+        _ => {
+            let temporary = std::env::temp_dir().join(format!("ugnassync-diff-{}", std::process::id()));
+            (fetch(nas, profile, &remote, &temporary), temporary)
+        }
+    };
+    let result = copy.and_then(|()| compare(&local, &temporary, &remote));
+    let _ = fs::remove_file(&temporary);
+    result
+}
+
+/// Absolute paths are taken as they are, others relative to the profile's local path
+fn local_file(profile: &SyncProfile, path: &Path) -> PathBuf {
+    if path.is_absolute() {
+        return path.to_path_buf();
+    }
+    let base = profile.source_paths().first().map(PathBuf::from).unwrap_or_default();
+    base.join(path)
+}
+
+/// Copy a file from the NAS over SSH
+pub(crate) fn fetch(nas: &NasConfig, profile: &SyncProfile, remote: &str, target: &Path) -> Result<()> {
+    let shell = RemoteShell::new(nas)?.with_owner(profile.owner.as_ref());
+    let output = shell
+        .command("ssh")?
+        .args(shell.ssh_args())
+        .arg(shell.login())
+        .arg(format!("cat {}", shell_quote(remote)))
+        .output()
+        .context("Failed to execute ssh")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Cannot read {} on the NAS: {}",
+            remote,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    fs::write(target, &output.stdout).with_context(|| format!("Failed to write {}", target.display()))
+}
+
+fn compare(local: &Path, copy: &Path, remote: &str) -> Result<()> {
+    let local_bytes = fs::read(local).with_context(|| format!("Failed to read {}", local.display()))?;
+    let copy_bytes = fs::read(copy).with_context(|| format!("Cannot read {} on the NAS", remote))?;
+
+    if local_bytes == copy_bytes {
+        println!("{} is identical on the NAS", local.display());
+        return Ok(());
+    }
+
+    if is_binary(&local_bytes) || is_binary(&copy_bytes) {
+        println!("Binary files differ");
+        println!("  local  {:>12} bytes  sha256 {}", local_bytes.len(), conflict::hash_file(local)?);
+        println!("  NAS    {:>12} bytes  sha256 {}", copy_bytes.len(), conflict::hash_file(copy)?);
+        return Ok(());
+    }
+
+    let status = Command::new("diff")
+        .arg("-u")
+        .arg("--label")
+        .arg(format!("local/{}", local.display()))
+        .arg("--label")
+        .arg(format!("nas/{}", remote))
+        .arg(local)
+        .arg(copy)
+        .status()
+        .context("Failed to execute diff")?;
+    // diff exits with 1 when the files differ
+    if status.code() != Some(1) {
+        anyhow::bail!("diff failed ({})", status);
+    }
+    Ok(())
+}
+
+fn is_binary(bytes: &[u8]) -> bool {
+    bytes[..bytes.len().min(BINARY_PROBE)].contains(&0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_file_and_binary_detection() {
+        let profile: SyncProfile = toml::from_str(
+            r#"
+            name = "docs"
+            local_path = "/home/me/docs"
+            remote_path = "/volume1/docs"
+            sync_type = "one-way"
+            enabled = true
+            "#,
+        )
+        .unwrap();
+        assert_eq!(local_file(&profile, Path::new("notes/todo.md")), PathBuf::from("/home/me/docs/notes/todo.md"));
+        assert_eq!(local_file(&profile, Path::new("/etc/hosts")), PathBuf::from("/etc/hosts"));
+        assert_eq!(
+            versions::relative_path(&profile, &local_file(&profile, Path::new("notes/todo.md"))).unwrap(),
+            "docs/notes/todo.md"
+        );
+
+        assert!(!is_binary(b"- [ ] buy milk\n"));
+        assert!(is_binary(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"));
+    }
+}
@@ -0,0 +1,271 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use crate::config::{NasConfig, SmbAuth, SmbConfig};
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::process::Command;
+use tracing::{debug, warn};
+
+/// DNS-SD service types offered by NAS devices that UGNasSync can use
+const SERVICE_TYPES: &[&str] = &["_smb._tcp", "_sftp-ssh._tcp", "_rsync._tcp"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiscoveredService {
+    pub name: String,
+    pub service_type: String,
+    pub hostname: String,
+    pub address: String,
+    pub port: u16,
+}
+
+#[derive(Debug, Default)]
+pub struct NasDevice {
+    pub name: String,
+    pub hostname: String,
+    pub addresses: Vec<String>,
+    pub services: Vec<(String, u16)>,
+    pub shares: Vec<String>,
+}
+
+impl NasDevice {
+    fn port_for(&self, service_type: &str) -> Option<u16> {
+        self.services
+            .iter()
+            .find(|(t, _)| t == service_type)
+            .map(|(_, port)| *port)
+    }
+}
+
+/// Browse the LAN for NAS services via avahi-browse
+pub fn browse(list_shares: bool) -> Result<Vec<NasDevice>> {
+    let mut services = Vec::new();
+
+    for service_type in SERVICE_TYPES {
+        let output = Command::new("avahi-browse")
+            .arg("--resolve")
+            .arg("--parsable")
+            .arg("--terminate")
+            .arg(service_type)
+            .output()
+            .context("Failed to execute avahi-browse (is avahi-utils installed?)")?;
+
+        if !output.status.success() {
+            warn!(
+                "avahi-browse failed for {}: {}",
+                service_type,
+                String::from_utf8_lossy(&output.stderr)
+            );
+            continue;
+        }
+
+        services.extend(parse_avahi_output(&String::from_utf8_lossy(&output.stdout)));
+    }
+
+    let mut devices = group_by_host(services);
+
+    if list_shares {
+        for device in &mut devices {
+            if device.port_for("_smb._tcp").is_some() {
+                device.shares = list_smb_shares(&device.hostname);
+            }
+        }
+    }
+
+    Ok(devices)
+}
+
+/// Parse resolved entries (`=;iface;proto;name;type;domain;host;address;port;txt`)
+fn parse_avahi_output(output: &str) -> Vec<DiscoveredService> {
+    output
+        .lines()
+        .filter(|line| line.starts_with('='))
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split(';').collect();
+            if fields.len() < 9 {
+                return None;
+            }
+
+            Some(DiscoveredService {
+                name: unescape_avahi(fields[3]),
+                service_type: fields[4].to_string(),
+                hostname: fields[6].to_string(),
+                address: fields[7].to_string(),
+                port: fields[8].parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+/// avahi-browse escapes special characters in names as `\DDD` decimal codes
+fn unescape_avahi(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'\\' && i + 4 <= bytes.len() {
+            let digits = std::str::from_utf8(&bytes[i + 1..i + 4]).unwrap_or("");
+            if let Ok(code) = digits.parse::<u8>() {
+                out.push(code);
+                i += 4;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&out).to_string()
+}
+
+fn group_by_host(services: Vec<DiscoveredService>) -> Vec<NasDevice> {
+    let mut devices: BTreeMap<String, NasDevice> = BTreeMap::new();
+
+    for service in services {
+        let device = devices
+            .entry(service.hostname.clone())
+            .or_insert_with(|| NasDevice {
+                name: service.name.clone(),
+                hostname: service.hostname.clone(),
+                ..Default::default()
+            });
+
+        if !device.addresses.contains(&service.address) {
+            device.addresses.push(service.address.clone());
+        }
+
+        let entry = (service.service_type.clone(), service.port);
+        if !device.services.contains(&entry) {
+            device.services.push(entry);
+        }
+    }
+
+    devices.into_values().collect()
+}
+
+/// List disk shares with an anonymous smbclient query (best effort)
+fn list_smb_shares(host: &str) -> Vec<String> {
+    let output = match Command::new("smbclient")
+        .arg("-g")
+        .arg("-N")
+        .arg("-L")
+        .arg(format!("//{}", host))
+        .output()
+    {
+        Ok(output) => output,
+        Err(e) => {
+            debug!("smbclient not available: {}", e);
+            return Vec::new();
+        }
+    };
+
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.strip_prefix("Disk|"))
+        .filter_map(|rest| rest.split('|').next())
+        .map(|share| share.to_string())
+        .collect()
+}
+
+/// Print discovered devices in a human readable form
+pub fn print_devices(devices: &[NasDevice]) {
+    if devices.is_empty() {
+        println!("No NAS devices found");
+        return;
+    }
+
+    for device in devices {
+        println!("{} ({})", device.name, device.hostname);
+        println!("  Addresses: {}", device.addresses.join(", "));
+        for (service_type, port) in &device.services {
+            println!("  Service:   {} on port {}", service_type, port);
+        }
+        if !device.shares.is_empty() {
+            println!("  Shares:    {}", device.shares.join(", "));
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ScaffoldConfig {
+    nas: NasConfig,
+}
+
+/// Build a `[nas]` config entry for a discovered device
+pub fn scaffold(device: &NasDevice) -> Result<String> {
+    let smb = device.port_for("_smb._tcp").map(|_| SmbConfig {
+        enabled: true,
+        share_path: format!(
+            "//{}/{}",
+            device.hostname,
+            device.shares.first().map(String::as_str).unwrap_or("share")
+        ),
+        mount_point: "/mnt/nas".to_string(),
+        domain: String::new(),
+        username: "admin".to_string(),
+        password: "smb_password".to_string(),
+        auth: SmbAuth::Password,
+        keytab: None,
+        principal: None,
+        vers: None,
+        sec: None,
+        seal: false,
+        uid: None,
+        gid: None,
+        file_mode: None,
+        dir_mode: None,
+        mount_options: String::new(),
+        auto_unmount: true,
+        idle_unmount_minutes: None,
+        mount_timeout: 30,
+        probe_path: None,
+    });
+
+    let nas = NasConfig {
+        host: device.hostname.clone(),
+        fallback_hosts: device.addresses.clone(),
+        discovery_name: None,
+        port: device.port_for("_sftp-ssh._tcp").unwrap_or(22),
+        username: "admin".to_string(),
+        password: None,
+        key_path: Some("~/.ssh/id_ed25519".to_string()),
+        smb,
+        ftp: None,
+        dsm: None,
+        qnap: None,
+        truenas: None,
+        monthly_transfer_cap_gb: None,
+        rsync_path: None,
+        health_gate: false,
+        max_clock_skew_secs: 60,
+        correct_clock_skew: false,
+    };
+
+    toml::to_string(&ScaffoldConfig { nas }).context("Failed to render config entry")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_avahi_output() {
+        let output = "+;eth0;IPv4;DS920;_smb._tcp;local\n\
+            =;eth0;IPv4;Office\\032NAS;_smb._tcp;local;ds920.local;192.168.1.20;445;\"model=DS920+\"\n\
+            =;eth0;IPv4;Office\\032NAS;_sftp-ssh._tcp;local;ds920.local;192.168.1.20;22;\n";
+
+        let services = parse_avahi_output(output);
+        assert_eq!(services.len(), 2);
+        assert_eq!(services[0].name, "Office NAS");
+        assert_eq!(services[0].port, 445);
+
+        let devices = group_by_host(services);
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].addresses, vec!["192.168.1.20"]);
+        assert_eq!(devices[0].port_for("_sftp-ssh._tcp"), Some(22));
+    }
+}
@@ -0,0 +1,387 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use crate::config::{DsmConfig, NasConfig, SyncProfile};
+use crate::error::SyncError;
+use crate::remote::{shell_quote, RemoteShell};
+use crate::vendor::Vendor;
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use std::io::Write;
+use std::process::{Command, Stdio};
+use tracing::{debug, info, warn};
+
+const QUICKCONNECT_URL: &str = "https://global.quickconnect.to/Serv.php";
+
+/// A volume as reported by SYNO.Storage.CGI.Storage
+#[derive(Debug, PartialEq)]
+pub struct Volume {
+    /// e.g. /volume1
+    pub path: String,
+    /// normal, degraded, crashed, ...
+    pub status: String,
+    pub total_bytes: u64,
+    pub used_bytes: u64,
+}
+
+/// A logged-in DSM Web API session, logged out when dropped
+pub struct Dsm {
+    base: String,
+    insecure: bool,
+    sid: String,
+}
+
+impl Dsm {
+    /// Log in at [nas.dsm] url, or DSM's HTTPS port on `host`
+    pub fn login(config: &DsmConfig, host: &str) -> Result<Self> {
+        let base = config
+            .url
+            .clone()
+            .unwrap_or_else(|| format!("https://{}:5001", host))
+            .trim_end_matches('/')
+            .to_string();
+        let data = post(
+            &format!("{}/webapi/auth.cgi", base),
+            config.insecure,
+            &[
+                ("api", "SYNO.API.Auth"),
+                ("version", "3"),
+                ("method", "login"),
+                ("account", &config.username),
+                ("session", "UGNasSync"),
+                ("format", "sid"),
+            ],
+            ("passwd", &config.password),
+        )
+        .with_context(|| format!("Failed to log in to DSM at {}", base))?;
+        let sid = data["sid"].as_str().context("DSM login returned no session")?.to_string();
+        debug!("Logged in to DSM at {}", base);
+        Ok(Self {
+            base,
+            insecure: config.insecure,
+            sid,
+        })
+    }
+
+    fn call(&self, api: &str, version: &str, method: &str, params: &[(&str, &str)]) -> Result<Value> {
+        let mut all = vec![("api", api), ("version", version), ("method", method)];
+        all.extend_from_slice(params);
+        post(&format!("{}/webapi/entry.cgi", self.base), self.insecure, &all, ("_sid", &self.sid))
+            .with_context(|| format!("DSM request {}.{} failed", api, method))
+    }
+
+    pub fn volumes(&self) -> Result<Vec<Volume>> {
+        Ok(parse_volumes(&self.call("SYNO.Storage.CGI.Storage", "1", "load_info", &[])?))
+    }
+
+    /// Names of the shared folders
+    pub fn shares(&self) -> Result<Vec<String>> {
+        let data = self.call("SYNO.Core.Share", "1", "list", &[])?;
+        Ok(data["shares"]
+            .as_array()
+            .map(|shares| shares.iter().filter_map(|share| share["name"].as_str().map(str::to_string)).collect())
+            .unwrap_or_default())
+    }
+
+    pub fn create_share(&self, name: &str, volume_path: &str) -> Result<()> {
+        let info = json!({ "name": name, "vol_path": volume_path, "desc": "Created by UGNasSync" });
+        self.call(
+            "SYNO.Core.Share",
+            "1",
+            "create",
+            &[("name", &json!(name).to_string()), ("shareinfo", &info.to_string())],
+        )?;
+        Ok(())
+    }
+}
+
+impl Drop for Dsm {
+    fn drop(&mut self) {
+        let logout = post(
+            &format!("{}/webapi/auth.cgi", self.base),
+            self.insecure,
+            &[("api", "SYNO.API.Auth"), ("version", "3"), ("method", "logout"), ("session", "UGNasSync")],
+            ("_sid", &self.sid),
+        );
+        if let Err(e) = logout {
+            debug!("DSM logout failed: {:#}", e);
+        }
+    }
+}
+
+/// POST form parameters with curl; `secret` (password or session) goes in on stdin so it
+/// never shows up in the process list
+fn post(url: &str, insecure: bool, params: &[(&str, &str)], secret: (&str, &str)) -> Result<Value> {
+    let mut cmd = Command::new("curl");
+    cmd.arg("-fsS").arg("-m").arg("30");
+    if insecure {
+        cmd.arg("--insecure");
+    }
+    for (name, value) in params {
+        cmd.arg("--data-urlencode").arg(format!("{}={}", name, value));
+    }
+    let mut child = cmd
+        .arg("--data-urlencode")
+        .arg(format!("{}@-", secret.0))
+        .arg(url)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| SyncError::from_spawn("curl", e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(secret.1.as_bytes()).context("Failed to pass credentials to curl")?;
+    }
+    let output = child.wait_with_output().context("Failed to execute curl")?;
+    if !output.status.success() {
+        return Err(SyncError::Unreachable(format!(
+            "DSM at {} did not answer: {}",
+            url,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+        .into());
+    }
+    parse_response(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// `{"success": true, "data": {...}}` or `{"success": false, "error": {"code": 400}}`
+fn parse_response(body: &str) -> Result<Value> {
+    let response: Value = serde_json::from_str(body).context("DSM did not answer with JSON")?;
+    if response["success"].as_bool() == Some(true) {
+        return Ok(response["data"].clone());
+    }
+    let code = response["error"]["code"].as_i64().unwrap_or(0);
+    Err(match code {
+        400 | 401 => SyncError::AuthFailed(format!("DSM rejected the login (error {}): wrong password or disabled account", code)).into(),
+        403 | 404 | 406 => SyncError::AuthFailed(format!(
+            "DSM asks for 2-step verification (error {}); use an account without it",
+            code
+        ))
+        .into(),
+        105 | 402 => SyncError::PermissionDenied(format!(
+            "DSM denied the request (error {}); the account needs administrator rights",
+            code
+        ))
+        .into(),
+        102..=104 => anyhow::anyhow!("This DSM version does not offer the request (error {})", code),
+        _ => anyhow::anyhow!("DSM returned error {}", code),
+    })
+}
+
+/// Sizes are strings in some DSM versions and numbers in others
+fn number(value: &Value) -> u64 {
+    value.as_u64().or_else(|| value.as_str()?.parse().ok()).unwrap_or(0)
+}
+
+fn parse_volumes(data: &Value) -> Vec<Volume> {
+    let Some(volumes) = data["volumes"].as_array() else {
+        return Vec::new();
+    };
+    volumes
+        .iter()
+        .filter_map(|volume| {
+            // vol_path is missing on some models; the id is volume_1 for /volume1
+            let path = match volume["vol_path"].as_str() {
+                Some(path) => path.to_string(),
+                None => format!("/{}", volume["id"].as_str()?.replace('_', "")),
+            };
+            Some(Volume {
+                path,
+                status: volume["status"].as_str().unwrap_or("unknown").to_string(),
+                total_bytes: number(&volume["size"]["total"]),
+                used_bytes: number(&volume["size"]["used"]),
+            })
+        })
+        .collect()
+}
+
+/// `/volume1/backups/laptop` -> (`/volume1`, `backups`)
+fn share_of(remote_path: &str) -> Option<(String, &str)> {
+    let mut parts = remote_path.trim_start_matches('/').split('/');
+    let volume = parts.next().filter(|volume| volume.starts_with("volume"))?;
+    let share = parts.next().filter(|share| !share.is_empty())?;
+    Some((format!("/{}", volume), share))
+}
+
+/// A degraded volume is a warning; a crashed one, or one below min_free_gb, stops the sync
+fn check_volume(volume: &Volume, min_free_gb: Option<f64>) -> Result<Option<String>> {
+    if volume.status == "crashed" {
+        anyhow::bail!("Volume {} on the NAS has crashed; not syncing to it", volume.path);
+    }
+    if let Some(min_free_gb) = min_free_gb {
+        let free_gb = volume.total_bytes.saturating_sub(volume.used_bytes) as f64 / 1e9;
+        if free_gb < min_free_gb {
+            return Err(SyncError::DiskFull(format!(
+                "Volume {} on the NAS has {:.1} GB free, less than min_free_gb = {}",
+                volume.path, free_gb, min_free_gb
+            ))
+            .into());
+        }
+    }
+    Ok((volume.status != "normal").then(|| format!("Volume {} on the NAS is {}", volume.path, volume.status)))
+}
+
+/// [nas.dsm] as a vendor integration
+pub struct Synology {
+    config: DsmConfig,
+    host: String,
+}
+
+impl Synology {
+    pub fn new(config: &DsmConfig, host: &str) -> Self {
+        Self {
+            config: config.clone(),
+            host: host.to_string(),
+        }
+    }
+}
+
+impl Vendor for Synology {
+    fn name(&self) -> &'static str {
+        "Synology DSM"
+    }
+
+    /// Volume health and free space, and the shared folder with create_shares
+    fn prepare(&self, profile: &SyncProfile, dry_run: bool) -> Result<Vec<String>> {
+        let Some((volume_path, share)) = share_of(&profile.remote_path) else {
+            debug!("{} is not below a DSM volume, skipping DSM checks", profile.remote_path);
+            return Ok(Vec::new());
+        };
+        let dsm = Dsm::login(&self.config, &self.host)?;
+        let mut warnings = Vec::new();
+        match dsm.volumes()?.iter().find(|volume| volume.path == volume_path) {
+            Some(volume) => warnings.extend(check_volume(volume, self.config.min_free_gb)?),
+            None => warn!("DSM does not report volume {}", volume_path),
+        }
+
+        if self.config.create_shares && !dsm.shares()?.iter().any(|name| name == share) {
+            if dry_run {
+                info!("Would create shared folder {} on {}", share, volume_path);
+            } else {
+                info!("Creating shared folder {} on {}", share, volume_path);
+                dsm.create_share(share, &volume_path)?;
+            }
+        }
+        Ok(warnings)
+    }
+
+    /// Every volume that is not normal, e.g. degraded or repairing
+    fn health(&self, profile: &SyncProfile) -> Result<Vec<String>> {
+        if share_of(&profile.remote_path).is_none() {
+            return Ok(Vec::new());
+        }
+        let dsm = Dsm::login(&self.config, &self.host)?;
+        Ok(dsm
+            .volumes()?
+            .iter()
+            .filter(|volume| volume.status != "normal")
+            .map(|volume| format!("Volume {} on the NAS is {}", volume.path, volume.status))
+            .collect())
+    }
+}
+
+/// Addresses registered for a QuickConnect ID, LAN interfaces first
+pub fn resolve_quickconnect(id: &str) -> Result<Vec<String>> {
+    let request = json!({
+        "version": 1,
+        "command": "get_server_info",
+        "stop_when_error": false,
+        "stop_when_success": false,
+        "id": "dsm_portal_https",
+        "serverID": id,
+        "is_gofile": false,
+    });
+    let output = Command::new("curl")
+        .arg("-fsS")
+        .arg("-m")
+        .arg("10")
+        .arg("-H")
+        .arg("Content-Type: application/json")
+        .arg("-d")
+        .arg(request.to_string())
+        .arg(QUICKCONNECT_URL)
+        .output()
+        .map_err(|e| SyncError::from_spawn("curl", e))?;
+    if !output.status.success() {
+        anyhow::bail!("QuickConnect lookup failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    parse_quickconnect(id, &String::from_utf8_lossy(&output.stdout))
+}
+
+fn parse_quickconnect(id: &str, body: &str) -> Result<Vec<String>> {
+    let response: Value = serde_json::from_str(body).context("QuickConnect did not answer with JSON")?;
+    if let Some(errno) = response["errno"].as_i64().filter(|errno| *errno != 0) {
+        anyhow::bail!("QuickConnect does not know {} (errno {})", id, errno);
+    }
+    let server = &response["server"];
+    let mut addresses: Vec<String> = server["interface"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|interface| interface["ip"].as_str())
+        .chain([&server["external"]["ip"], &server["ddns"], &server["fqdn"]].into_iter().filter_map(Value::as_str))
+        .filter(|address| !address.is_empty() && *address != "NULL")
+        .map(str::to_string)
+        .collect();
+    addresses.dedup();
+    Ok(addresses)
+}
+
+/// Show a notification to DSM's administrators, beeping for critical ones. DSM's Web API
+/// has no request for either, so they run over SSH and need passwordless sudo
+pub fn alert(nas: &NasConfig, title: &str, message: &str, beep: bool) -> Result<()> {
+    let mut script = format!(
+        "sudo -n /usr/syno/bin/synodsmnotify @administrators {} {}",
+        shell_quote(title),
+        shell_quote(message)
+    );
+    if beep {
+        script.push_str(" && sudo -n sh -c 'echo 2 > /dev/ttyS1'");
+    }
+    RemoteShell::new(nas)?.run(&script)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_volumes_and_shares() {
+        let data = parse_response(
+            r#"{"success": true, "data": {"volumes": [
+                {"id": "volume_1", "vol_path": "/volume1", "status": "normal", "size": {"total": "4000000000000", "used": "3990000000000"}},
+                {"id": "volume_2", "status": "degraded", "size": {"total": 2000000000000, "used": 0}}
+            ]}}"#,
+        )
+        .unwrap();
+        let volumes = parse_volumes(&data);
+        assert_eq!(volumes[1].path, "/volume2");
+        assert_eq!(volumes[0].total_bytes - volumes[0].used_bytes, 10_000_000_000);
+
+        assert_eq!(check_volume(&volumes[0], Some(5.0)).unwrap(), None);
+        let full = check_volume(&volumes[0], Some(50.0)).unwrap_err();
+        assert!(matches!(crate::error::find(&full), Some(SyncError::DiskFull(_))));
+        assert_eq!(check_volume(&volumes[1], None).unwrap().as_deref(), Some("Volume /volume2 on the NAS is degraded"));
+
+        assert_eq!(share_of("/volume1/backups/laptop"), Some(("/volume1".to_string(), "backups")));
+        assert_eq!(share_of("/mnt/data/backups"), None);
+
+        let denied = parse_response(r#"{"success": false, "error": {"code": 400}}"#).unwrap_err();
+        assert!(matches!(crate::error::find(&denied), Some(SyncError::AuthFailed(_))));
+    }
+
+    #[test]
+    fn test_parse_quickconnect() {
+        let addresses = parse_quickconnect(
+            "mynas",
+            r#"{"errno": 0, "server": {"interface": [{"ip": "192.168.1.20", "name": "eth0"}],
+                "external": {"ip": "203.0.113.7"}, "ddns": "mynas.synology.me", "fqdn": "NULL"}}"#,
+        )
+        .unwrap();
+        assert_eq!(addresses, vec!["192.168.1.20", "203.0.113.7", "mynas.synology.me"]);
+        assert!(parse_quickconnect("missing", r#"{"errno": 4, "suberrno": 2}"#).is_err());
+    }
+}
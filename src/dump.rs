@@ -0,0 +1,104 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use crate::config::SyncProfile;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use tracing::info;
+
+/// Run the profile's `dump_cmd` with its stdout written to `dump_output`.
+/// Returns the dump size, or None if the profile has no dump command.
+pub fn run(profile: &SyncProfile) -> Result<Option<u64>> {
+    let (Some(dump_cmd), Some(output)) = (&profile.dump_cmd, &profile.dump_output) else {
+        return Ok(None);
+    };
+    let output = Path::new(output);
+
+    if let Some(dir) = output.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+
+    // Dump next to the target first so a failed run never replaces the last good dump
+    let partial = output.with_extension("partial");
+    let file = fs::File::create(&partial)
+        .with_context(|| format!("Failed to create {}", partial.display()))?;
+
+    info!("Running dump for profile {}: {}", profile.name, dump_cmd);
+    let result = Command::new("sh")
+        .arg("-c")
+        .arg(dump_cmd)
+        .stdout(file)
+        .stderr(Stdio::piped())
+        .output()
+        .context("Failed to execute dump_cmd");
+
+    match verify(result, &partial) {
+        Ok(size) => {
+            fs::rename(&partial, output)
+                .with_context(|| format!("Failed to move dump to {}", output.display()))?;
+            info!("Dump written to {} ({} bytes)", output.display(), size);
+            Ok(Some(size))
+        }
+        Err(e) => {
+            let _ = fs::remove_file(&partial);
+            Err(e.context(format!("Dump for profile '{}' failed", profile.name)))
+        }
+    }
+}
+
+/// The command must exit successfully and produce a non-empty dump
+fn verify(result: Result<std::process::Output>, partial: &Path) -> Result<u64> {
+    let result = result?;
+    if !result.status.success() {
+        anyhow::bail!(
+            "dump_cmd exited with {}: {}",
+            result.status,
+            String::from_utf8_lossy(&result.stderr).trim()
+        );
+    }
+
+    let size = fs::metadata(partial)?.len();
+    if size == 0 {
+        anyhow::bail!("dump_cmd produced no output");
+    }
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_failed_dump_keeps_previous() {
+        let dir = std::env::temp_dir().join(format!("ugnassync-dump-test-{}", std::process::id()));
+        let output = dir.join("dumps/app.sql");
+        let mut profile: SyncProfile = toml::from_str(&format!(
+            r#"
+            name = "db"
+            local_path = "{}"
+            remote_path = "/volume1/db"
+            sync_type = "one-way"
+            enabled = true
+            dump_cmd = "echo 'CREATE TABLE t;'"
+            dump_output = "{}"
+            "#,
+            dir.display(),
+            output.display()
+        ))
+        .unwrap();
+
+        assert_eq!(run(&profile).unwrap(), Some(16));
+        assert_eq!(fs::read_to_string(&output).unwrap(), "CREATE TABLE t;\n");
+
+        profile.dump_cmd = Some("echo partial; exit 1".to_string());
+        assert!(run(&profile).is_err());
+        assert_eq!(fs::read_to_string(&output).unwrap(), "CREATE TABLE t;\n");
+        assert!(!output.with_extension("partial").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
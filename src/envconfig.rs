@@ -0,0 +1,191 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use toml::{Table, Value};
+
+const PREFIX: &str = "UGNASSYNC_";
+
+/// Whole config file, base64-encoded
+pub const CONFIG_B64: &str = "UGNASSYNC_CONFIG_B64";
+
+/// Variables the tool sets for its own child processes, not configuration
+const INTERNAL: &[&str] = &[CONFIG_B64, crate::daemon::DETACHED_ENV];
+
+/// Variable prefixes (after `UGNASSYNC_`) and the config table they fill; longest first
+const SECTIONS: &[(&str, &[&str])] = &[
+    ("NAS_SMB_", &["nas", "smb"]),
+    ("NAS_", &["nas"]),
+    ("LOGGING_", &["logging"]),
+    ("NOTIFICATIONS_", &["notifications"]),
+    ("BASE_STORE_", &["base_store"]),
+    ("HEALTH_", &["health"]),
+    ("METRICS_", &["metrics"]),
+];
+
+/// Whether the environment carries any configuration
+pub fn present() -> bool {
+    std::env::var_os(CONFIG_B64).is_some() || std::env::vars().any(|(name, _)| is_config_var(&name))
+}
+
+/// A `UGNASSYNC_*` variable setting a single option
+fn is_config_var(name: &str) -> bool {
+    name.starts_with(PREFIX) && !INTERNAL.contains(&name)
+}
+
+/// The config file passed as `UGNASSYNC_CONFIG_B64`, if set
+pub fn encoded_config() -> Result<Option<String>> {
+    let Ok(encoded) = std::env::var(CONFIG_B64) else {
+        return Ok(None);
+    };
+    let bytes = decode_base64(&encoded).with_context(|| format!("{} is not valid base64", CONFIG_B64))?;
+    String::from_utf8(bytes)
+        .map(Some)
+        .with_context(|| format!("{} is not UTF-8 text", CONFIG_B64))
+}
+
+/// Overlay `UGNASSYNC_*` variables on a parsed config, e.g. `UGNASSYNC_NAS_HOST` sets
+/// `nas.host` and `UGNASSYNC_PROFILE_0_LOCAL_PATH` the first profile's `local_path`
+pub fn apply(config: &mut Table, vars: impl Iterator<Item = (String, String)>) -> Result<()> {
+    let mut profiles: BTreeMap<usize, Table> = BTreeMap::new();
+
+    for (name, raw) in vars {
+        if !is_config_var(&name) {
+            continue;
+        }
+        let name = &name[PREFIX.len()..];
+        let value = parse_value(&raw);
+
+        if let Some(rest) = name.strip_prefix("PROFILE_") {
+            let (index, key) = rest
+                .split_once('_')
+                .and_then(|(index, key)| Some((index.parse::<usize>().ok()?, key)))
+                .with_context(|| format!("{}{} should look like {}PROFILE_<n>_<OPTION>", PREFIX, name, PREFIX))?;
+            profiles.entry(index).or_default().insert(key.to_lowercase(), value);
+            continue;
+        }
+
+        let (path, key) = SECTIONS
+            .iter()
+            .find_map(|(prefix, path)| name.strip_prefix(prefix).map(|key| (*path, key)))
+            .unwrap_or((&[], name));
+        let mut table = &mut *config;
+        for section in path {
+            table = table
+                .entry(section.to_string())
+                .or_insert_with(|| Value::Table(Table::new()))
+                .as_table_mut()
+                .with_context(|| format!("{} is not a table", section))?;
+        }
+        table.insert(key.to_lowercase(), value);
+    }
+
+    if !profiles.is_empty() {
+        let list = config
+            .entry("sync_profiles")
+            .or_insert_with(|| Value::Array(Vec::new()))
+            .as_array_mut()
+            .context("sync_profiles is not a list")?;
+        for (index, options) in profiles {
+            if index > list.len() {
+                anyhow::bail!("{}PROFILE_{}_* skips profile {}", PREFIX, index, list.len());
+            }
+            if index == list.len() {
+                let mut profile = Table::new();
+                profile.insert("enabled".to_string(), Value::Boolean(true));
+                list.push(Value::Table(profile));
+            }
+            list[index]
+                .as_table_mut()
+                .with_context(|| format!("sync_profiles[{}] is not a table", index))?
+                .extend(options);
+        }
+    }
+
+    // A container logs to stdout unless told otherwise
+    config.entry("logging").or_insert_with(|| {
+        toml::toml! {
+            enabled = true
+            log_file = "/var/log/ugnassync/sync.log"
+            log_level = "info"
+            console_output = true
+            file_output = false
+            rotate_enabled = false
+            max_file_size_mb = 10
+            max_files = 5
+            compress_rotated = false
+        }
+        .into()
+    });
+    Ok(())
+}
+
+/// Numbers, booleans and `[...]` lists are taken as TOML, anything else as a string
+fn parse_value(raw: &str) -> Value {
+    match toml::from_str::<Table>(&format!("value = {}", raw)).ok().and_then(|mut table| table.remove("value")) {
+        Some(value @ (Value::Integer(_) | Value::Float(_) | Value::Boolean(_) | Value::Array(_))) => value,
+        _ => Value::String(raw.to_string()),
+    }
+}
+
+/// Standard base64, with or without padding and line breaks
+fn decode_base64(encoded: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(encoded.len() * 3 / 4);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in encoded.bytes().filter(|c| !c.is_ascii_whitespace() && *c != b'=') {
+        let sextet = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        buffer = (buffer << 6) | sextet as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+        }
+    }
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn test_config_from_environment() {
+        let vars = [
+            ("UGNASSYNC_NAS_HOST", "nas.lan"),
+            ("UGNASSYNC_NAS_PORT", "2222"),
+            ("UGNASSYNC_NAS_USERNAME", "admin"),
+            ("UGNASSYNC_NAS_KEY_PATH", "/run/secrets/nas_key"),
+            ("UGNASSYNC_PROFILE_0_NAME", "data"),
+            ("UGNASSYNC_PROFILE_0_LOCAL_PATH", "/data"),
+            ("UGNASSYNC_PROFILE_0_REMOTE_PATH", "/volume1/data"),
+            ("UGNASSYNC_PROFILE_0_SYNC_TYPE", "mirror"),
+            ("UGNASSYNC_PROFILE_0_EXCLUDE", r#"["*.tmp", "cache"]"#),
+            ("UGNASSYNC_DETACHED", "1"),
+            ("HOME", "/root"),
+        ];
+        let mut table = Table::new();
+        apply(&mut table, vars.iter().map(|(name, value)| (name.to_string(), value.to_string()))).unwrap();
+
+        let config: Config = table.try_into().unwrap();
+        assert_eq!(config.nas.host, "nas.lan");
+        assert_eq!(config.nas.port, 2222);
+        assert_eq!(config.sync_profiles[0].exclude, vec!["*.tmp", "cache"]);
+        assert!(config.sync_profiles[0].enabled);
+        assert!(config.logging.console_output);
+
+        assert_eq!(decode_base64("aG9zdCA9ICJuYXMi").unwrap(), b"host = \"nas\"");
+        assert_eq!(decode_base64("bmFz\n").unwrap(), b"nas");
+    }
+}
@@ -0,0 +1,193 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use crate::notification::Severity;
+use crate::ransomware::Quarantine;
+use crate::sync::TransferCapReached;
+use serde_json::json;
+use std::io;
+use thiserror::Error;
+
+/// Exit code for errors that fall into no category
+pub const EXIT_FAILURE: i32 = 1;
+
+/// Failures that automation needs to tell apart, carried inside `anyhow::Error`
+#[derive(Debug, Error)]
+pub enum SyncError {
+    /// rsync, ssh or another required program is not installed
+    #[error("{0}")]
+    ToolMissing(String),
+    #[error("{0}")]
+    AuthFailed(String),
+    #[error("{0}")]
+    Unreachable(String),
+    #[error("{0}")]
+    DiskFull(String),
+    #[error("{0}")]
+    PermissionDenied(String),
+    #[error("{0}")]
+    Mount(String),
+    #[error("{0}")]
+    StateDatabase(String),
+    #[error("{0}")]
+    Config(String),
+    /// rsync failed for a reason not covered above
+    #[error("{message}")]
+    Rsync { code: Option<i32>, message: String },
+    /// Some files could not be transferred (rsync exit codes 23, 24 and 25)
+    #[error("{0}")]
+    Partial(String),
+    /// The health gate found the NAS storage degraded, rebuilding or failing
+    #[error("{0}")]
+    Unhealthy(String),
+    /// The run exceeded a confirmation threshold and nobody confirmed it
+    #[error("{0}")]
+    NotConfirmed(String),
+}
+
+impl SyncError {
+    /// Categorize a failed rsync (or ssh) run by its exit code and stderr
+    pub fn from_rsync(code: Option<i32>, message: String) -> Self {
+        let stderr = message.to_lowercase();
+        let mentions = |patterns: &[&str]| patterns.iter().any(|pattern| stderr.contains(pattern));
+
+        if mentions(&["no space left on device", "disk quota exceeded"]) {
+            SyncError::DiskFull(message)
+        } else if mentions(&[
+            "permission denied (publickey",
+            "permission denied, please try again",
+            "authentication failed",
+            "auth failed",
+            "host key verification failed",
+        ]) {
+            SyncError::AuthFailed(message)
+        } else if mentions(&[
+            "connection refused",
+            "connection timed out",
+            "no route to host",
+            "network is unreachable",
+            "could not resolve hostname",
+        ]) || code == Some(255)
+        {
+            SyncError::Unreachable(message)
+        } else if code == Some(127) || mentions(&["rsync: command not found", "rsync: not found"]) {
+            SyncError::ToolMissing(message)
+        } else if mentions(&["permission denied"]) {
+            SyncError::PermissionDenied(message)
+        } else {
+            SyncError::Rsync { code, message }
+        }
+    }
+
+    /// Categorize a failure to start a program
+    pub fn from_spawn(program: &str, error: io::Error) -> anyhow::Error {
+        match error.kind() {
+            io::ErrorKind::NotFound => SyncError::ToolMissing(format!("{} is not installed or not in PATH", program)).into(),
+            _ => anyhow::Error::new(error).context(format!("Failed to execute {}", program)),
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            SyncError::ToolMissing(_) => "tool_missing",
+            SyncError::AuthFailed(_) => "auth_failed",
+            SyncError::Unreachable(_) => "unreachable",
+            SyncError::DiskFull(_) => "disk_full",
+            SyncError::PermissionDenied(_) => "permission_denied",
+            SyncError::Mount(_) => "mount_failed",
+            SyncError::StateDatabase(_) => "state_database",
+            SyncError::Config(_) => "config",
+            SyncError::Rsync { .. } => "rsync_failed",
+            SyncError::Partial(_) => "partial",
+            SyncError::Unhealthy(_) => "nas_unhealthy",
+            SyncError::NotConfirmed(_) => "not_confirmed",
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            SyncError::Config(_) => 3,
+            SyncError::ToolMissing(_) => 4,
+            SyncError::AuthFailed(_) => 5,
+            SyncError::Unreachable(_) => 6,
+            SyncError::DiskFull(_) => 7,
+            SyncError::PermissionDenied(_) => 8,
+            SyncError::Mount(_) => 9,
+            SyncError::StateDatabase(_) => 10,
+            SyncError::Rsync { .. } => 11,
+            SyncError::Partial(_) => 14,
+            SyncError::Unhealthy(_) => 15,
+            SyncError::NotConfirmed(_) => 16,
+        }
+    }
+
+    /// Problems that need someone to act are critical, ones that may clear up by themselves warnings
+    pub fn severity(&self) -> Severity {
+        match self {
+            SyncError::Unreachable(_) | SyncError::Mount(_) | SyncError::Rsync { .. } | SyncError::Partial(_) => {
+                Severity::Warning
+            }
+            _ => Severity::Critical,
+        }
+    }
+}
+
+/// The categorized error anywhere in an error's context chain
+pub fn find(error: &anyhow::Error) -> Option<&SyncError> {
+    error.chain().find_map(|cause| cause.downcast_ref::<SyncError>())
+}
+
+/// Category name of any error, as used in JSON output and reports
+pub fn kind(error: &anyhow::Error) -> &'static str {
+    if error.is::<Quarantine>() {
+        "quarantined"
+    } else if error.is::<TransferCapReached>() {
+        "transfer_cap"
+    } else {
+        find(error).map_or("other", SyncError::kind)
+    }
+}
+
+/// Process exit code for any error
+pub fn exit_code(error: &anyhow::Error) -> i32 {
+    if error.is::<Quarantine>() {
+        12
+    } else if error.is::<TransferCapReached>() {
+        13
+    } else {
+        find(error).map_or(EXIT_FAILURE, SyncError::exit_code)
+    }
+}
+
+/// One-line JSON description of an error for `--json-errors`
+pub fn to_json(profile: Option<&str>, error: &anyhow::Error) -> String {
+    json!({
+        "profile": profile,
+        "kind": kind(error),
+        "exit_code": exit_code(error),
+        "message": format!("{:#}", error),
+    })
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_rsync_failures_are_categorized_through_context() {
+        let full = SyncError::from_rsync(Some(11), "rsync: write failed on \"/volume1/a\": No space left on device (28)".into());
+        assert_eq!(full.kind(), "disk_full");
+        assert_eq!(SyncError::from_rsync(Some(255), "ssh: connect to host nas port 22: Connection refused".into()).kind(), "unreachable");
+        assert_eq!(SyncError::from_rsync(Some(255), "admin@nas: Permission denied (publickey).".into()).kind(), "auth_failed");
+        assert_eq!(SyncError::from_rsync(Some(23), "some files vanished".into()).kind(), "rsync_failed");
+
+        let error = Err::<(), _>(anyhow::Error::from(full)).context("Profile photos failed").unwrap_err();
+        assert_eq!(kind(&error), "disk_full");
+        assert_eq!(exit_code(&error), 7);
+        assert_eq!(exit_code(&anyhow::anyhow!("something else")), EXIT_FAILURE);
+    }
+}
@@ -0,0 +1,135 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use crate::conflict::ConflictResolver;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use tracing::{debug, info};
+
+/// Number of recent runs the estimates are based on
+const HISTORY_RUNS: usize = 10;
+
+/// How often a running sync logs its remaining time
+const REPORT_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Transfer rate and usual duration of a profile's recent runs
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct History {
+    pub bytes_per_sec: Option<f64>,
+    pub typical_secs: Option<f64>,
+}
+
+impl History {
+    /// From (duration, bytes) of successful runs; the rate only counts runs that moved data
+    pub fn from_runs(runs: &[(f64, u64)]) -> Self {
+        let (secs, bytes) = runs
+            .iter()
+            .filter(|(_, bytes)| *bytes > 0)
+            .fold((0.0, 0u64), |(secs, bytes), (run_secs, run_bytes)| (secs + run_secs, bytes + run_bytes));
+        let mut durations: Vec<f64> = runs.iter().map(|(secs, _)| *secs).collect();
+        durations.sort_by(f64::total_cmp);
+        Self {
+            bytes_per_sec: (secs > 0.0).then(|| bytes as f64 / secs),
+            typical_secs: durations.get(durations.len() / 2).copied(),
+        }
+    }
+
+    /// Seconds a whole run should take: moving `bytes` at the usual rate, or as long as
+    /// the median run when the amount of data is not known
+    pub fn expected_secs(&self, bytes: Option<u64>) -> Option<f64> {
+        match (bytes, self.bytes_per_sec) {
+            (Some(bytes), Some(rate)) => Some(bytes as f64 / rate),
+            _ => self.typical_secs,
+        }
+    }
+}
+
+/// History of a profile from the run history in the shared state database
+pub fn history(profile: &str) -> History {
+    match ConflictResolver::new().and_then(|resolver| resolver.recent_runs(profile, HISTORY_RUNS)) {
+        Ok(runs) => History::from_runs(&runs),
+        Err(e) => {
+            debug!("No run history for profile {}: {:#}", profile, e);
+            History::default()
+        }
+    }
+}
+
+/// Current size of the regular files among `paths`
+pub fn size_of(paths: &[PathBuf]) -> u64 {
+    paths
+        .iter()
+        .filter_map(|path| path.metadata().ok())
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+/// "about 35 min remaining" for a run expected to take `expected_secs`
+pub fn remaining(expected_secs: f64, elapsed_secs: f64) -> String {
+    let minutes = ((expected_secs - elapsed_secs) / 60.0).round();
+    if minutes < -1.0 {
+        "taking longer than usual".to_string()
+    } else if minutes < 1.0 {
+        "less than a minute remaining".to_string()
+    } else if minutes < 60.0 {
+        format!("about {} min remaining", minutes)
+    } else {
+        format!("about {} h {} min remaining", (minutes / 60.0).floor(), minutes % 60.0)
+    }
+}
+
+/// Logs the remaining time of a run every few minutes until dropped
+pub struct Reporter {
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Reporter {
+    pub fn start(profile: &str, expected_secs: f64, started: Instant) -> Self {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let profile = profile.to_string();
+        let thread = thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(REPORT_INTERVAL) {
+                info!("Profile {}: {}", profile, remaining(expected_secs, started.elapsed().as_secs_f64()));
+            }
+        });
+        Self {
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for Reporter {
+    fn drop(&mut self) {
+        self.stop.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expected_and_remaining() {
+        // 3 GB in 500 s plus runs without changes: 6 MB/s, median run 300 s
+        let history = History::from_runs(&[(200.0, 2_000_000_000), (20.0, 0), (300.0, 1_000_000_000), (400.0, 0)]);
+        assert_eq!(history.bytes_per_sec, Some(6_000_000.0));
+        assert_eq!(history.expected_secs(Some(600_000_000)), Some(100.0));
+        assert_eq!(history.expected_secs(None), Some(300.0));
+        assert_eq!(History::from_runs(&[]).expected_secs(Some(1)), None);
+
+        assert_eq!(remaining(2400.0, 300.0), "about 35 min remaining");
+        assert_eq!(remaining(6000.0, 0.0), "about 1 h 40 min remaining");
+        assert_eq!(remaining(60.0, 40.0), "less than a minute remaining");
+        assert_eq!(remaining(60.0, 400.0), "taking longer than usual");
+    }
+}
@@ -0,0 +1,216 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use crate::config::{NasConfig, SyncProfile};
+use crate::listing;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tracing::info;
+
+/// Fall back to a full sync this often to pick up changes the watcher missed
+const FULL_SYNC_INTERVAL: Duration = Duration::from_secs(6 * 60 * 60);
+
+/// Beyond this many changed paths a full sync is cheaper than a file list
+const MAX_LISTED_PATHS: usize = 10_000;
+
+/// What the NAS holds at one path, as printed by `rsync --list-only`
+#[derive(Debug, PartialEq)]
+struct Entry {
+    size: u64,
+    /// `YYYY/MM/DD HH:MM:SS`
+    modified: String,
+    is_dir: bool,
+}
+
+/// The NAS side of a watched profile, kept between syncs so that rsync only
+/// has to look at the paths the watcher reported
+pub struct FileListCache {
+    /// Keyed by path relative to the profile's transfer root
+    entries: HashMap<String, Entry>,
+    built: Instant,
+}
+
+impl FileListCache {
+    /// List the profile's remote path, right after a full sync
+    pub async fn build(nas: &NasConfig, profile: &SyncProfile) -> Result<Self> {
+        let started = Instant::now();
+        let entries: HashMap<String, Entry> = listing::list(nas, profile, None, true)
+            .await?
+            .into_iter()
+            .filter(|entry| entry.path != ".")
+            .map(|entry| {
+                let is_dir = entry.is_dir();
+                let path = entry.path.trim_end_matches('/').to_string();
+                (
+                    path,
+                    Entry {
+                        size: entry.size,
+                        modified: entry.modified,
+                        is_dir,
+                    },
+                )
+            })
+            .collect();
+
+        info!(
+            "Cached {} NAS entries of profile {} in {:.1}s",
+            entries.len(),
+            profile.name,
+            started.elapsed().as_secs_f64()
+        );
+        Ok(Self {
+            entries,
+            built: Instant::now(),
+        })
+    }
+
+    /// Whether the next sync should cover the whole tree again
+    pub fn is_stale(&self) -> bool {
+        self.built.elapsed() >= FULL_SYNC_INTERVAL
+    }
+
+    /// Paths relative to the transfer root that differ from the NAS, or None
+    /// if only a full sync can bring the NAS up to date
+    pub fn plan(&self, profile: &SyncProfile, changed: &[PathBuf]) -> Option<Vec<String>> {
+        if changed.len() > MAX_LISTED_PATHS {
+            return None;
+        }
+
+        let root = profile.transfer_root();
+        let mut paths = Vec::new();
+        for path in changed {
+            let relative = path.strip_prefix(&root).ok()?.to_string_lossy().to_string();
+            let cached = self.entries.get(&relative);
+            match local_entry(path) {
+                // Existing directories change through the files in them, which have their own events
+                Some(local) if local.is_dir && (relative.is_empty() || cached.is_some_and(|entry| entry.is_dir)) => {
+                    continue
+                }
+                Some(local) if cached == Some(&local) => continue,
+                Some(_) => {}
+                // Created and removed again between two syncs
+                None if cached.is_none() => continue,
+                None => {}
+            }
+            paths.push(relative);
+        }
+
+        paths.sort();
+        paths.dedup();
+        Some(paths)
+    }
+
+    /// Record that `paths` were synced and now match the local side
+    pub fn update(&mut self, profile: &SyncProfile, paths: &[String]) {
+        let root = PathBuf::from(profile.transfer_root());
+        for relative in paths {
+            // Deleted or replaced directories take their contents with them
+            let prefix = format!("{}/", relative);
+            self.entries.retain(|path, _| !path.starts_with(&prefix));
+            self.entries.remove(relative);
+            self.insert(&root, relative);
+        }
+    }
+
+    fn insert(&mut self, root: &Path, relative: &str) {
+        let path = root.join(relative);
+        let Some(entry) = local_entry(&path) else {
+            return;
+        };
+
+        // New directories were transferred recursively
+        if entry.is_dir {
+            if let Ok(dir) = fs::read_dir(&path) {
+                for child in dir.flatten() {
+                    let name = child.file_name().to_string_lossy().to_string();
+                    self.insert(root, &format!("{}/{}", relative, name));
+                }
+            }
+        }
+        self.entries.insert(relative.to_string(), entry);
+    }
+}
+
+/// Write `paths` as the rsync `--files-from` list of a watch-mode sync
+pub fn write_list(profile: &SyncProfile, paths: &[String]) -> Result<PathBuf> {
+    let name: String = profile
+        .name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    let list = std::env::temp_dir().join(format!("ugnassync-watch-{}.list", name));
+
+    let mut content = paths.join("\n");
+    content.push('\n');
+    fs::write(&list, content).with_context(|| format!("Failed to write {}", list.display()))?;
+    Ok(list)
+}
+
+/// The local side of a path in the form rsync lists it (symlinks are not followed)
+fn local_entry(path: &Path) -> Option<Entry> {
+    let metadata = fs::symlink_metadata(path).ok()?;
+    let modified: DateTime<Local> = metadata.modified().ok()?.into();
+    Some(Entry {
+        size: metadata.len(),
+        modified: modified.format("%Y/%m/%d %H:%M:%S").to_string(),
+        is_dir: metadata.is_dir(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan_skips_paths_matching_the_nas() {
+        let dir = std::env::temp_dir().join(format!("ugnassync-filelist-{}", std::process::id()));
+        fs::create_dir_all(dir.join("tree/old")).unwrap();
+        fs::write(dir.join("tree/same.txt"), "same").unwrap();
+        fs::write(dir.join("tree/edited.txt"), "edited").unwrap();
+        fs::create_dir_all(dir.join("tree/new")).unwrap();
+
+        let profile: SyncProfile = toml::from_str(&format!(
+            "name = \"docs\"\nlocal_path = \"{}\"\nremote_path = \"/volume1/docs\"\nsync_type = \"mirror\"\nenabled = true",
+            dir.join("tree").display()
+        ))
+        .unwrap();
+
+        let mut cache = FileListCache {
+            entries: HashMap::new(),
+            built: Instant::now(),
+        };
+        cache.update(&profile, &["tree".to_string()]);
+        cache.entries.get_mut("tree/edited.txt").unwrap().size = 1;
+        cache.entries.remove("tree/new");
+        cache.entries.insert(
+            "tree/gone.txt".to_string(),
+            Entry {
+                size: 1,
+                modified: "2025/01/01 00:00:00".to_string(),
+                is_dir: false,
+            },
+        );
+
+        let changed: Vec<PathBuf> = ["same.txt", "edited.txt", "old", "new", "gone.txt", "temp.txt"]
+            .iter()
+            .map(|name| dir.join("tree").join(name))
+            .collect();
+        assert_eq!(
+            cache.plan(&profile, &changed),
+            Some(vec![
+                "tree/edited.txt".to_string(),
+                "tree/gone.txt".to_string(),
+                "tree/new".to_string()
+            ])
+        );
+        assert_eq!(cache.plan(&profile, &[PathBuf::from("/elsewhere/file")]), None);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,299 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use crate::config::{FtpTls, NasConfig, COARSE_MTIME_SECS};
+use crate::error::SyncError;
+use crate::push::{Backend, LocalFile, RemoteFile};
+use crate::sync::FileError;
+use anyhow::{Context, Result};
+use chrono::{Local, NaiveDateTime, TimeZone, Utc};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+use tracing::{debug, info, warn};
+
+/// curl exit code when the server refuses to enter a directory (it does not exist)
+const CURLE_REMOTE_ACCESS_DENIED: i32 = 9;
+
+/// Transfers with curl, which speaks FTP and FTPS, so the NAS needs no extra software
+pub struct FtpClient {
+    /// `ftp://host:port` or `ftps://host:port`
+    base_url: String,
+    /// remote_path, URL-encoded, without leading or trailing slash
+    remote_dir: String,
+    tls: FtpTls,
+    insecure: bool,
+    /// `user = "name:password"` for curl's config on stdin, never in argv or a file
+    credentials: String,
+}
+
+impl FtpClient {
+    pub fn new(nas: &NasConfig, remote_path: &str) -> Result<Self> {
+        let ftp = nas.ftp.as_ref().context("FTP transfers need [nas.ftp]")?;
+        let host = ftp.host.as_deref().unwrap_or(&nas.host);
+        let (scheme, default_port) = match ftp.tls {
+            FtpTls::Implicit => ("ftps", 990),
+            FtpTls::None | FtpTls::Explicit => ("ftp", 21),
+        };
+        if ftp.tls == FtpTls::None {
+            warn!("FTP without TLS sends the password and files unencrypted");
+        }
+
+        // A leading slash is kept as %2F so the path starts at the server's root
+        let mut remote_dir = if remote_path.starts_with('/') { "%2F".to_string() } else { String::new() };
+        remote_dir.push_str(&encode_path(remote_path.trim_matches('/')));
+
+        let escape = |value: &str| value.replace('\\', "\\\\").replace('"', "\\\"");
+        Ok(Self {
+            base_url: format!("{}://{}:{}", scheme, host, ftp.port.unwrap_or(default_port)),
+            remote_dir: remote_dir.trim_end_matches('/').to_string(),
+            tls: ftp.tls,
+            insecure: ftp.insecure,
+            credentials: format!("user = \"{}:{}\"\n", escape(&ftp.username), escape(&ftp.password)),
+        })
+    }
+
+    fn curl(&self) -> Command {
+        let mut cmd = Command::new("curl");
+        cmd.arg("--silent").arg("--show-error").arg("--config").arg("-");
+        cmd.arg("--connect-timeout").arg("30");
+        if self.tls == FtpTls::Explicit {
+            cmd.arg("--ssl-reqd");
+        }
+        if self.insecure {
+            cmd.arg("--insecure");
+        }
+        cmd
+    }
+
+    /// Run curl with the credentials written to its stdin
+    fn run(&self, mut cmd: Command) -> Result<Output> {
+        debug!("curl command: {:?}", cmd);
+        let mut child = cmd
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| SyncError::from_spawn("curl", e))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(self.credentials.as_bytes())?;
+        }
+        Ok(child.wait_with_output()?)
+    }
+
+    /// URL of `path` (relative to remote_path, not encoded yet)
+    fn url(&self, path: &str) -> String {
+        let mut url = format!("{}/", self.base_url);
+        if !self.remote_dir.is_empty() {
+            url.push_str(&self.remote_dir);
+            url.push('/');
+        }
+        url.push_str(&encode_path(path));
+        url
+    }
+
+    fn list_dir(&self, dir: &str, files: &mut Vec<RemoteFile>) -> Result<()> {
+        let mut url = self.url(dir);
+        if !url.ends_with('/') {
+            url.push('/');
+        }
+        let mut cmd = self.curl();
+        cmd.arg("--request").arg("MLSD").arg(&url);
+        let output = self.run(cmd)?;
+        if !output.status.success() {
+            if dir.is_empty() && output.status.code() == Some(CURLE_REMOTE_ACCESS_DENIED) {
+                debug!("{} does not exist on the FTP server yet", self.remote_dir);
+                return Ok(());
+            }
+            return Err(error("list the FTP directory", &output));
+        }
+
+        for (name, entry) in parse_mlsd(&String::from_utf8_lossy(&output.stdout)) {
+            let path = if dir.is_empty() { name } else { format!("{}/{}", dir, name) };
+            match entry {
+                MlsdEntry::Dir => self.list_dir(&path, files)?,
+                MlsdEntry::File { size, modified } => files.push(RemoteFile { path, size, modified }),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Backend for FtpClient {
+    fn name(&self) -> &'static str {
+        "FTP"
+    }
+
+    fn list(&self) -> Result<Vec<RemoteFile>> {
+        let mut files = Vec::new();
+        self.list_dir("", &mut files)?;
+        Ok(files)
+    }
+
+    /// One curl per file, creating directories, resuming uploads that were cut off and
+    /// setting the modification time with MFMT where the server supports it
+    fn upload(&self, root: &Path, files: &[LocalFile], remote: &[RemoteFile]) -> Result<Vec<FileError>> {
+        let on_server: BTreeMap<&str, &RemoteFile> = remote.iter().map(|file| (file.path.as_str(), file)).collect();
+        let mut failed = Vec::new();
+        if !files.is_empty() {
+            info!("Uploading {} file(s) to {}/{}", files.len(), self.base_url, self.remote_dir);
+        }
+
+        for file in files {
+            let mut cmd = self.curl();
+            cmd.arg("--ftp-create-dirs").arg("--upload-file").arg(root.join(&file.path));
+            if on_server.get(file.path.as_str()).is_some_and(|theirs| resumable(file, theirs)) {
+                info!("Resuming upload of {}", file.path);
+                cmd.arg("--continue-at").arg("-");
+            }
+            // After the transfer curl is in the file's directory; `*` ignores servers without MFMT
+            let name = file.path.rsplit('/').next().unwrap_or(&file.path);
+            cmd.arg("--quote").arg(format!("-*MFMT {} {}", mfmt_time(file.modified), name));
+            cmd.arg(self.url(&file.path));
+
+            let output = self.run(cmd)?;
+            if output.status.success() {
+                continue;
+            }
+            // Problems with the connection or login would fail every file
+            match output.status.code() {
+                Some(6 | 7 | 28 | 35 | 60 | 67) => return Err(error("upload files", &output)),
+                _ => failed.push(FileError {
+                    path: file.path.clone(),
+                    reason: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+                }),
+            }
+        }
+        Ok(failed)
+    }
+
+    fn delete(&self, paths: &[String]) -> Result<Vec<String>> {
+        let (deletable, skipped): (Vec<&String>, Vec<&String>) = paths.iter().partition(|path| !path.contains(['\r', '\n']));
+        for path in &skipped {
+            warn!("Not deleting {} over FTP: the name cannot be sent", path);
+        }
+        // DELE runs after listing remote_path, relative to it
+        for batch in deletable.chunks(50) {
+            let mut cmd = self.curl();
+            cmd.arg("--list-only");
+            for path in batch {
+                cmd.arg("--quote").arg(format!("-DELE {}", path));
+            }
+            cmd.arg(format!("{}/", self.url("").trim_end_matches('/')));
+            let output = self.run(cmd)?;
+            if !output.status.success() {
+                return Err(error("delete files", &output));
+            }
+        }
+        Ok(deletable.into_iter().cloned().collect())
+    }
+}
+
+/// A partial upload: smaller on the server and without the local mtime MFMT sets once
+/// an upload is complete
+fn resumable(local: &LocalFile, remote: &RemoteFile) -> bool {
+    remote.size < local.size && (local.modified - remote.modified).num_seconds().unsigned_abs() > COARSE_MTIME_SECS
+}
+
+/// MFMT and MLSD times are UTC, `YYYYMMDDHHMMSS`
+fn mfmt_time(local: NaiveDateTime) -> String {
+    let utc = Local.from_local_datetime(&local).earliest().map_or(local, |time| time.naive_utc());
+    utc.format("%Y%m%d%H%M%S").to_string()
+}
+
+#[derive(Debug, PartialEq)]
+enum MlsdEntry {
+    Dir,
+    File { size: u64, modified: NaiveDateTime },
+}
+
+/// `type=file;size=1234;modify=20240101120000.000; name with spaces` per line; the
+/// `.` and `..` entries (cdir, pdir) and links are skipped
+fn parse_mlsd(output: &str) -> Vec<(String, MlsdEntry)> {
+    let mut entries = Vec::new();
+    for line in output.lines().map(|line| line.trim_end_matches('\r')) {
+        let Some((facts, name)) = line.split_once(' ') else {
+            continue;
+        };
+        let facts: BTreeMap<String, &str> = facts
+            .split(';')
+            .filter_map(|fact| fact.split_once('='))
+            .map(|(key, value)| (key.to_ascii_lowercase(), value))
+            .collect();
+        let entry = match facts.get("type").map(|kind| kind.to_ascii_lowercase()).as_deref() {
+            Some("dir") => MlsdEntry::Dir,
+            Some("file") => {
+                let modified = facts
+                    .get("modify")
+                    .and_then(|modify| NaiveDateTime::parse_from_str(&modify[..modify.len().min(14)], "%Y%m%d%H%M%S").ok())
+                    .map(|utc| Utc.from_utc_datetime(&utc).with_timezone(&Local).naive_local());
+                match (facts.get("size").and_then(|size| size.parse().ok()), modified) {
+                    (Some(size), Some(modified)) => MlsdEntry::File { size, modified },
+                    _ => continue,
+                }
+            }
+            _ => continue,
+        };
+        entries.push((name.to_string(), entry));
+    }
+    entries
+}
+
+/// Percent-encode each segment of a `/`-separated path for a URL
+fn encode_path(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+fn error(what: &str, output: &Output) -> anyhow::Error {
+    let message = format!("curl could not {}: {}", what, String::from_utf8_lossy(&output.stderr).trim());
+    match output.status.code() {
+        Some(6 | 7 | 28) => SyncError::Unreachable(message).into(),
+        Some(67) => SyncError::AuthFailed(message).into(),
+        Some(9) => SyncError::PermissionDenied(message).into(),
+        _ if message.contains("552") => SyncError::DiskFull(message).into(),
+        _ => anyhow::anyhow!(message),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mlsd_and_urls() {
+        let entries = parse_mlsd(
+            "type=cdir;modify=20240101120000; .\r\n\
+             type=pdir;modify=20240101120000; ..\r\n\
+             type=file;size=1234;modify=20240213093005.250;UNIX.mode=0644; notes 2024.txt\r\n\
+             Type=dir;Modify=20240101120000; Photos\r\n\
+             type=OS.unix=slink:/x;modify=20240101120000; link\r\n",
+        );
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].0, "notes 2024.txt");
+        assert!(matches!(entries[0].1, MlsdEntry::File { size: 1234, .. }));
+        assert_eq!(entries[1], ("Photos".to_string(), MlsdEntry::Dir));
+
+        assert_eq!(encode_path("Photos/2024 #1/ä.jpg"), "Photos/2024%20%231/%C3%A4.jpg");
+    }
+
+    #[test]
+    fn test_resumable() {
+        let at = |s: &str| NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap();
+        let local = LocalFile { path: "big.iso".to_string(), size: 1000, modified: at("2024-05-01 10:00:00") };
+        let cut_off = RemoteFile { path: "big.iso".to_string(), size: 400, modified: at("2024-06-01 08:00:00") };
+        let older_copy = RemoteFile { size: 400, modified: at("2024-05-01 10:00:01"), ..cut_off.clone() };
+        assert!(resumable(&local, &cut_off));
+        assert!(!resumable(&local, &older_copy));
+        assert!(!resumable(&local, &RemoteFile { size: 1000, ..cut_off }));
+    }
+}
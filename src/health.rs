@@ -0,0 +1,144 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use crate::config::{HealthConfig, NasConfig};
+use crate::connectivity;
+use crate::control::{DaemonState, ProfileStatus};
+use anyhow::{Context, Result};
+use chrono::Local;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use tracing::{debug, info, warn};
+
+/// Serve `/healthz` (liveness) and `/readyz` (readiness) over HTTP for orchestrators
+/// Without `probe_nas` (low-churn mode) readiness does not touch the NAS
+pub async fn serve(config: HealthConfig, nas: NasConfig, probe_nas: bool, state: DaemonState) -> Result<()> {
+    let listener = TcpListener::bind(&config.listen)
+        .await
+        .with_context(|| format!("Failed to listen on {}", config.listen))?;
+    info!("Health endpoints listening on http://{}/healthz and /readyz", config.listen);
+
+    loop {
+        let (stream, peer) = listener.accept().await.context("Health endpoint accept failed")?;
+        let config = config.clone();
+        let nas = nas.clone();
+        let state = state.clone();
+
+        tokio::spawn(async move {
+            let (reader, mut writer) = stream.into_split();
+            let mut request = String::new();
+            if let Err(e) = BufReader::new(reader).read_line(&mut request).await {
+                warn!("Health request from {} failed: {}", peer, e);
+                return;
+            }
+            debug!("Health request from {}: {}", peer, request.trim());
+
+            // `GET /readyz HTTP/1.1`
+            let (healthy, body) = match request.split_whitespace().nth(1) {
+                Some("/healthz") => liveness(&state.snapshot()),
+                Some("/readyz") if !probe_nas => readiness(&state.snapshot(), None, config.max_sync_age_minutes),
+                Some("/readyz") => {
+                    let reachable = tokio::task::spawn_blocking(move || {
+                        connectivity::select_host(&nas).is_ok_and(|host| connectivity::is_reachable(&host, nas.port))
+                    })
+                    .await
+                    .unwrap_or(false);
+                    let reachable = Some(reachable);
+                    readiness(&state.snapshot(), reachable, config.max_sync_age_minutes)
+                }
+                _ => {
+                    let _ = writer.write_all(response("404 Not Found", "{}").as_bytes()).await;
+                    return;
+                }
+            };
+
+            let status = if healthy { "200 OK" } else { "503 Service Unavailable" };
+            let _ = writer.write_all(response(status, &body.to_string()).as_bytes()).await;
+        });
+    }
+}
+
+fn response(status: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+/// Alive while every watcher runs; a watcher stuck in restart backoff makes the daemon wedged
+fn liveness(profiles: &BTreeMap<String, ProfileStatus>) -> (bool, Value) {
+    let failed: BTreeMap<&String, &String> = profiles
+        .iter()
+        .filter_map(|(name, status)| status.watcher_error.as_ref().map(|error| (name, error)))
+        .collect();
+    (failed.is_empty(), json!({ "alive": failed.is_empty(), "failed_watchers": failed }))
+}
+
+/// Ready while the NAS is reachable (when probed) and no profile has been failing for longer
+/// than `max_sync_age_minutes` since its last successful sync (or since watching started)
+fn readiness(profiles: &BTreeMap<String, ProfileStatus>, nas_reachable: Option<bool>, max_sync_age_minutes: u64) -> (bool, Value) {
+    let now = Local::now();
+    let mut ready = nas_reachable != Some(false);
+    let mut details = serde_json::Map::new();
+
+    for (name, status) in profiles {
+        let last_success_age = status.last_success.map(|at| (now - at).num_seconds());
+        let failing = status.last_result.as_deref().is_some_and(|result| result.starts_with("failed"));
+        let since = status.last_success.or(status.registered).unwrap_or(now);
+        let stale = failing && (now - since).num_minutes() >= max_sync_age_minutes as i64;
+        ready &= !stale;
+
+        details.insert(
+            name.clone(),
+            json!({
+                "stale": stale,
+                "last_success_age_secs": last_success_age,
+                "last_result": status.last_result,
+            }),
+        );
+    }
+
+    (ready, json!({ "ready": ready, "nas_reachable": nas_reachable, "profiles": details }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_readiness_fails_for_long_failing_profiles() {
+        let mut profiles = BTreeMap::new();
+        profiles.insert(
+            "docs".to_string(),
+            ProfileStatus {
+                last_success: Some(Local::now() - Duration::minutes(90)),
+                last_result: Some("failed (unreachable): connection refused".to_string()),
+                ..Default::default()
+            },
+        );
+        profiles.insert(
+            "photos".to_string(),
+            ProfileStatus {
+                last_success: Some(Local::now() - Duration::days(3)),
+                last_result: Some("ok, 2 file(s)".to_string()),
+                ..Default::default()
+            },
+        );
+
+        assert!(!readiness(&profiles, Some(true), 60).0);
+        assert!(readiness(&profiles, Some(true), 120).0);
+        assert!(!readiness(&profiles, Some(false), 120).0);
+        assert!(readiness(&profiles, None, 120).0);
+
+        assert!(liveness(&profiles).0);
+        profiles.get_mut("docs").unwrap().watcher_error = Some("watcher failed".to_string());
+        assert!(!liveness(&profiles).0);
+    }
+}
@@ -0,0 +1,418 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use crate::configedit::NewProfile;
+use anyhow::{Context, Result};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// rsnapshot's configuration when no file is given
+const RSNAPSHOT_CONF: &str = "/etc/rsnapshot.conf";
+
+/// Syncthing's configuration below the home directory, newest layout first
+const SYNCTHING_CONFIGS: [&str; 2] = [".local/state/syncthing/config.xml", ".config/syncthing/config.xml"];
+
+/// Profiles translated from an existing backup setup, and what could not be translated
+#[derive(Debug, Default)]
+pub struct Import {
+    pub profiles: Vec<NewProfile>,
+    pub skipped: Vec<String>,
+}
+
+/// Read `path` (or the tool's default location) as `from` (`rsnapshot`, `crontab` or
+/// `syncthing`); NAS paths are placed below `remote_root` where the source has none.
+/// Cron jobs copying to a host other than `nas_hosts` are skipped when it is not empty.
+pub fn read(from: &str, path: Option<&Path>, remote_root: Option<&str>, nas_hosts: &[String]) -> Result<Import> {
+    let read = |path: &Path| fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()));
+    match from {
+        "rsnapshot" => {
+            // snapshot_root is a directory of the machine running rsnapshot, not of the NAS
+            let remote_root = remote_root.context("rsnapshot's snapshot_root is a local directory; pass --remote-root")?;
+            Ok(rsnapshot(&read(path.unwrap_or(Path::new(RSNAPSHOT_CONF)))?, remote_root))
+        }
+        "crontab" => match path {
+            Some(path) => Ok(crontab(&read(path)?, nas_hosts)),
+            None => {
+                let output = Command::new("crontab").arg("-l").output().context("Failed to run crontab -l")?;
+                if !output.status.success() {
+                    anyhow::bail!("crontab -l failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+                }
+                Ok(crontab(&String::from_utf8_lossy(&output.stdout), nas_hosts))
+            }
+        },
+        "syncthing" => {
+            let remote_root = remote_root.context("Syncthing folders have no NAS path; pass --remote-root")?;
+            let path = match path {
+                Some(path) => path.to_path_buf(),
+                None => syncthing_config().context("No Syncthing config.xml found; pass its path")?,
+            };
+            Ok(syncthing(&read(&path)?, remote_root, |folder| fs::read_to_string(folder.join(".stignore")).ok()))
+        }
+        other => anyhow::bail!("Cannot import from '{}'", other),
+    }
+}
+
+/// Each `backup` of a local folder becomes a mirror profile keeping as many versions as
+/// the first `retain` level; NAS paths follow rsnapshot's `<dest>/<source>` layout below `remote_root`
+fn rsnapshot(content: &str, remote_root: &str) -> Import {
+    let mut import = Import::default();
+    let mut names = BTreeSet::new();
+    let (mut keep_versions, mut excludes) = (None, Vec::new());
+    let mut backups = Vec::new();
+
+    for line in content.lines().filter(|line| !line.trim_start().starts_with('#')) {
+        // rsnapshot separates fields with tabs, so paths may contain spaces
+        let fields: Vec<&str> = line.split('\t').map(str::trim).filter(|field| !field.is_empty()).collect();
+        match fields.as_slice() {
+            ["retain" | "interval", _, count, ..] => {
+                keep_versions = keep_versions.or(count.parse().ok());
+            }
+            ["exclude", pattern, ..] => excludes.push(pattern.to_string()),
+            ["backup", source, dest, rest @ ..] => backups.push((*source, *dest, rest.first().copied())),
+            [kind @ ("backup_script" | "backup_exec"), ..] => {
+                import.skipped.push(format!("{}: {} runs a command", line.trim(), kind));
+            }
+            _ => {}
+        }
+    }
+
+    let root = remote_root.trim_end_matches('/');
+    for (source, dest, options) in backups {
+        if is_remote(source) {
+            import.skipped.push(format!("backup {}: pulls from another host; UGNasSync syncs local folders", source));
+            continue;
+        }
+        let mut exclude = excludes.clone();
+        for option in options.into_iter().flat_map(|options| options.split(',')) {
+            if let Some(pattern) = option.strip_prefix("exclude=") {
+                exclude.push(pattern.to_string());
+            }
+        }
+        let local_path = trim_slash(source);
+        let relative = local_path.trim_start_matches('/');
+        let remote_path = [root, dest.trim_matches('/'), relative]
+            .iter()
+            .filter(|part| !part.is_empty())
+            .copied()
+            .collect::<Vec<_>>()
+            .join("/");
+        import.profiles.push(NewProfile {
+            name: unique_name(&mut names, &local_path),
+            local_path,
+            remote_path: format!("{}{}", if root.starts_with('/') { "" } else { "/" }, remote_path),
+            sync_type: "mirror".to_string(),
+            enabled: true,
+            exclude,
+            watch_mode: false,
+            interval_minutes: None,
+            allowed_window: None,
+            keep_versions,
+        });
+    }
+    import
+}
+
+/// Each cron job running rsync from a local folder to `host:path` becomes a profile,
+/// scheduled by interval_minutes (and allowed_window for jobs at a fixed time)
+fn crontab(content: &str, nas_hosts: &[String]) -> Import {
+    let mut import = Import::default();
+    let mut names = BTreeSet::new();
+
+    for line in content.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+        let words = shell_words(line);
+        // Variable assignments like MAILTO=me
+        if words.first().is_some_and(|word| word.contains('=') && !word.starts_with('@')) {
+            continue;
+        }
+        let fields = if line.starts_with('@') { 1 } else { 5 };
+        if words.len() <= fields {
+            continue;
+        }
+        let (schedule, command) = words.split_at(fields);
+        let Some(start) = command.iter().position(|word| word == "rsync" || word.ends_with("/rsync")) else {
+            continue;
+        };
+        let args = command[start + 1..]
+            .iter()
+            .take_while(|word| !matches!(word.as_str(), ";" | "&&" | "||" | "|") && !word.starts_with('>') && !word.starts_with("2>"));
+
+        let (mut delete, mut exclude, mut operands) = (false, Vec::new(), Vec::new());
+        let mut args = args.peekable();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--exclude" => exclude.extend(args.next().cloned()),
+                "-e" | "--rsh" | "--exclude-from" | "--include" | "--filter" | "-f" => {
+                    args.next();
+                }
+                _ if arg.starts_with("--delete") => delete = true,
+                _ if arg.starts_with("--exclude=") => exclude.push(arg["--exclude=".len()..].to_string()),
+                _ if arg.starts_with('-') => {}
+                _ => operands.push(arg.as_str()),
+            }
+        }
+
+        let job = line.to_string();
+        let [source, dest] = operands.as_slice() else {
+            import.skipped.push(format!("{}: expected one source and one destination", job));
+            continue;
+        };
+        if is_remote(source) {
+            import.skipped.push(format!("{}: pulls from another host; UGNasSync syncs local folders", job));
+            continue;
+        }
+        let Some((login, dest_path)) = dest.split_once(':').filter(|_| is_remote(dest) && !dest.contains("::")) else {
+            import.skipped.push(format!("{}: the destination is not a host:path over SSH", job));
+            continue;
+        };
+        let host = login.rsplit('@').next().unwrap_or(login);
+        if !nas_hosts.is_empty() && !nas_hosts.iter().any(|nas| nas.eq_ignore_ascii_case(host)) {
+            import.skipped.push(format!("{}: copies to {}, not the configured NAS", job, host));
+            continue;
+        }
+
+        // Without a trailing slash rsync copies the folder itself into the destination
+        let local_path = trim_slash(source);
+        let remote_path = match source.ends_with('/') {
+            true => trim_slash(dest_path),
+            false => format!("{}/{}", dest_path.trim_end_matches('/'), basename(&local_path)),
+        };
+        let (interval_minutes, allowed_window) = cron_interval(schedule);
+        if interval_minutes.is_none() {
+            import.skipped.push(format!("{}: schedule has no interval equivalent; set interval_minutes by hand", job));
+        }
+        import.profiles.push(NewProfile {
+            name: unique_name(&mut names, &local_path),
+            local_path,
+            remote_path,
+            sync_type: if delete { "mirror" } else { "one-way" }.to_string(),
+            enabled: true,
+            exclude,
+            watch_mode: false,
+            interval_minutes,
+            allowed_window,
+            keep_versions: None,
+        });
+    }
+    import
+}
+
+/// interval_minutes of a cron schedule, and an hour-long allowed_window when it runs at a
+/// fixed time of day; None for schedules without a fixed interval
+fn cron_interval(schedule: &[String]) -> (Option<u64>, Option<String>) {
+    let fields: Vec<&str> = schedule.iter().map(String::as_str).collect();
+    let window = |minute: &str, hour: &str| {
+        let (minute, hour): (u32, u32) = (minute.parse().ok()?, hour.parse().ok()?);
+        Some(format!("{:02}:{:02}-{:02}:{:02}", hour, minute, (hour + 1) % 24, minute))
+    };
+    let every = |field: &str| field.strip_prefix("*/").and_then(|n| n.parse::<u64>().ok());
+    let numeric = |field: &str| field.parse::<u32>().is_ok();
+    match fields.as_slice() {
+        ["@hourly"] => (Some(60), None),
+        ["@daily" | "@midnight"] => (Some(1440), window("0", "0")),
+        ["@weekly"] => (Some(10080), window("0", "0")),
+        [minute, "*", "*", "*", "*"] if every(minute).is_some() => (every(minute), None),
+        [minute, "*", "*", "*", "*"] if numeric(minute) => (Some(60), None),
+        [minute, hour, "*", "*", "*"] if numeric(minute) && every(hour).is_some() => (every(hour).map(|n| n * 60), None),
+        [minute, hour, "*", "*", "*"] => (window(minute, hour).map(|_| 1440), window(minute, hour)),
+        [minute, hour, "*", "*", day] if numeric(day) => (window(minute, hour).map(|_| 10080), window(minute, hour)),
+        _ => (None, None),
+    }
+}
+
+/// Each send-receive or send-only folder becomes a watched two-way or one-way profile in
+/// `<remote_root>/<label>`, with the plain patterns of its `.stignore`
+fn syncthing(content: &str, remote_root: &str, stignore: impl Fn(&Path) -> Option<String>) -> Import {
+    let mut import = Import::default();
+    let mut names = BTreeSet::new();
+
+    for tag in content.split("<folder ").skip(1).filter_map(|rest| rest.split_once('>').map(|(tag, _)| tag)) {
+        let attr = |name: &str| attribute(tag, name);
+        let (Some(id), Some(path)) = (attr("id"), attr("path")) else {
+            continue;
+        };
+        let label = attr("label").filter(|label| !label.is_empty()).unwrap_or_else(|| id.clone());
+        let sync_type = match attr("type").as_deref().unwrap_or("sendreceive") {
+            "sendreceive" => "two-way",
+            "sendonly" => "one-way",
+            other => {
+                import.skipped.push(format!("folder {}: {} folders only receive", label, other));
+                continue;
+            }
+        };
+
+        let mut exclude = Vec::new();
+        for line in stignore(Path::new(&path)).unwrap_or_default().lines().map(str::trim) {
+            let pattern = line.trim_start_matches("(?i)").trim_start_matches("(?d)");
+            if pattern.is_empty() || pattern.starts_with("//") {
+                continue;
+            }
+            if pattern.starts_with('!') || pattern.starts_with("#include") {
+                import.skipped.push(format!("folder {}: .stignore line '{}' has no exclude equivalent", label, line));
+                continue;
+            }
+            exclude.push(pattern.to_string());
+        }
+
+        let name = unique_name(&mut names, &label);
+        import.profiles.push(NewProfile {
+            remote_path: format!("{}/{}", remote_root.trim_end_matches('/'), name),
+            name,
+            local_path: trim_slash(&path),
+            sync_type: sync_type.to_string(),
+            enabled: true,
+            exclude,
+            watch_mode: attr("fsWatcherEnabled").as_deref() != Some("false"),
+            interval_minutes: None,
+            allowed_window: None,
+            keep_versions: None,
+        });
+    }
+    import
+}
+
+fn syncthing_config() -> Option<PathBuf> {
+    let home = PathBuf::from(std::env::var_os("HOME")?);
+    SYNCTHING_CONFIGS.iter().map(|path| home.join(path)).find(|path| path.is_file())
+}
+
+/// Value of `name="..."` in the attributes of an XML tag, with entities decoded
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let start = tag.match_indices(&format!("{}=\"", name)).find(|(i, _)| *i == 0 || tag[..*i].ends_with(char::is_whitespace))?.0;
+    let value = &tag[start + name.len() + 2..];
+    let value = &value[..value.find('"')?];
+    Some(
+        value
+            .replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&quot;", "\"")
+            .replace("&apos;", "'")
+            .replace("&amp;", "&"),
+    )
+}
+
+/// Words of a shell command line with quotes and backslash escapes removed
+fn shell_words(line: &str) -> Vec<String> {
+    let (mut words, mut word, mut in_word, mut quote) = (Vec::new(), String::new(), false, None);
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('"'), '\\') | (None, '\\') => {
+                word.extend(chars.next());
+                in_word = true;
+            }
+            (Some(_), c) => word.push(c),
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                in_word = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            (None, c) => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    words
+}
+
+/// `host:path`, `user@host:path`, `host::module` or `rsync://` rather than a local path
+fn is_remote(path: &str) -> bool {
+    path.starts_with("rsync://") || path.split_once(':').is_some_and(|(host, _)| !host.is_empty() && !host.contains('/'))
+}
+
+fn trim_slash(path: &str) -> String {
+    match path.trim_end_matches('/') {
+        "" => "/".to_string(),
+        trimmed => trimmed.to_string(),
+    }
+}
+
+fn basename(path: &str) -> &str {
+    path.rsplit('/').find(|part| !part.is_empty()).unwrap_or("root")
+}
+
+/// A profile name from a folder or label, numbered when an earlier job took it
+fn unique_name(names: &mut BTreeSet<String>, path: &str) -> String {
+    let base = basename(path).to_string();
+    let mut name = base.clone();
+    let mut n = 1;
+    while !names.insert(name.clone()) {
+        n += 1;
+        name = format!("{} {}", base, n);
+    }
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rsnapshot_and_crontab_jobs() {
+        let conf = "config_version\t1.2\nsnapshot_root\t/mnt/nas/snapshots/\n\
+                    retain\tdaily\t7\nretain\tweekly\t4\nexclude\t*.tmp\n\
+                    backup\t/home/anna/\tlocalhost/\texclude=.cache\n\
+                    backup\troot@web:/etc/\tweb/\nbackup_exec\t/bin/date\n";
+        let import = rsnapshot(conf, "/volume1/backup/");
+        assert_eq!(import.profiles.len(), 1);
+        let anna = &import.profiles[0];
+        assert_eq!((anna.name.as_str(), anna.local_path.as_str()), ("anna", "/home/anna"));
+        assert_eq!(anna.remote_path, "/volume1/backup/localhost/home/anna");
+        assert_eq!((anna.keep_versions, anna.exclude.clone()), (Some(7), vec!["*.tmp".to_string(), ".cache".to_string()]));
+        assert_eq!(import.skipped.len(), 2);
+
+        let tab = "MAILTO=anna\n\
+                   30 2 * * * /usr/bin/rsync -a --delete --exclude '*.iso' /home/anna/Photos nas:/volume1/photos >/dev/null 2>&1\n\
+                   */15 * * * * rsync -az \"/home/anna/My Docs/\" anna@nas:/volume1/docs/\n\
+                   0 3 1 * * rsync -a /srv/db/ nas:/volume1/db\n\
+                   @reboot rsync -a /home/anna/ /mnt/usb/\n\
+                   0 4 * * * rsync -a /home/anna/ offsite.example.com:/backup/anna/\n";
+        let import = crontab(tab, &["NAS".to_string()]);
+        let photos = &import.profiles[0];
+        assert_eq!((photos.sync_type.as_str(), photos.remote_path.as_str()), ("mirror", "/volume1/photos/Photos"));
+        assert_eq!((photos.interval_minutes, photos.allowed_window.as_deref()), (Some(1440), Some("02:30-03:30")));
+        assert_eq!(photos.exclude, vec!["*.iso"]);
+        let docs = &import.profiles[1];
+        assert_eq!((docs.name.as_str(), docs.local_path.as_str()), ("My Docs", "/home/anna/My Docs"));
+        assert_eq!((docs.sync_type.as_str(), docs.remote_path.as_str(), docs.interval_minutes), ("one-way", "/volume1/docs", Some(15)));
+        // The monthly job is imported unscheduled; the USB and offsite copies are not NAS jobs
+        assert_eq!(import.profiles[2].interval_minutes, None);
+        assert_eq!(import.profiles.len(), 3);
+        assert_eq!(import.skipped.len(), 3);
+        assert!(import.skipped[2].ends_with("copies to offsite.example.com, not the configured NAS"));
+    }
+
+    #[test]
+    fn test_syncthing_folders() {
+        let xml = r#"<configuration version="37">
+            <folder id="abcd-1234" label="Work &amp; Notes" path="/home/anna/Work/" type="sendreceive" fsWatcherEnabled="true">
+                <device id="XYZ"></device>
+            </folder>
+            <folder id="phone-photos" label="" path="/home/anna/Phone" type="receiveonly"></folder>
+            <folder id="music" label="Music" path="/home/anna/Music" type="sendonly" fsWatcherEnabled="false"></folder>
+        </configuration>"#;
+        let import = syncthing(xml, "/volume1/sync/", |path| {
+            (path == Path::new("/home/anna/Work/")).then(|| "// build output\n(?d).DS_Store\n/build\n!keep.txt\n".to_string())
+        });
+        let names: Vec<(&str, &str, bool)> =
+            import.profiles.iter().map(|p| (p.name.as_str(), p.sync_type.as_str(), p.watch_mode)).collect();
+        assert_eq!(names, vec![("Work & Notes", "two-way", true), ("Music", "one-way", false)]);
+        assert_eq!(import.profiles[0].remote_path, "/volume1/sync/Work & Notes");
+        assert_eq!(import.profiles[0].local_path, "/home/anna/Work");
+        assert_eq!(import.profiles[0].exclude, vec![".DS_Store", "/build"]);
+        assert_eq!(import.skipped.len(), 2);
+    }
+}
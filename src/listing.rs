@@ -0,0 +1,156 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use crate::config::{NasConfig, SyncProfile};
+use crate::remote::RemoteShell;
+use crate::smb::SmbMount;
+use crate::sync::stable_number_format;
+use anyhow::{Context, Result};
+use std::path::{Component, Path};
+use std::process::Command;
+
+/// One entry of an `rsync --list-only` listing
+#[derive(Debug, PartialEq)]
+pub struct RemoteEntry {
+    pub permissions: String,
+    pub size: u64,
+    /// `YYYY/MM/DD HH:MM:SS` as printed by rsync
+    pub modified: String,
+    pub path: String,
+}
+
+impl RemoteEntry {
+    pub fn is_dir(&self) -> bool {
+        self.permissions.starts_with('d')
+    }
+}
+
+/// List the NAS side of a profile, optionally below `subpath` of its remote path
+pub async fn list(nas: &NasConfig, profile: &SyncProfile, subpath: Option<&str>, recursive: bool) -> Result<Vec<RemoteEntry>> {
+    let mut remote = profile.remote_path.trim_end_matches('/').to_string();
+    if let Some(subpath) = subpath {
+        if Path::new(subpath).components().any(|c| c == Component::ParentDir) {
+            anyhow::bail!("Subpath must stay inside the profile's remote path: {}", subpath);
+        }
+        remote = format!("{}/{}", remote, subpath.trim_matches('/'));
+    }
+    // List a directory's contents rather than the directory itself
+    remote.push('/');
+
+    let mut cmd = Command::new(nas.rsync_binary());
+    stable_number_format(&mut cmd);
+    cmd.arg("--list-only");
+    if recursive {
+        cmd.arg("--recursive");
+    }
+
+    let mut smb_mount = None;
+    match (&nas.smb, profile.use_smb_mount) {
+        (Some(smb_config), true) if smb_config.enabled => {
+            let mut mount = SmbMount::new(smb_config.clone());
+            mount.mount().await?;
+            smb_mount = Some(mount);
+            cmd.arg(&remote);
+        }
+        _ => {
+            let shell = RemoteShell::new(nas)?.with_owner(profile.owner.as_ref());
+            cmd.arg("-e").arg(shell.rsync_rsh());
+            shell.authenticate(&mut cmd)?;
+            if let Some(remote_rsync) = profile.remote_rsync_command() {
+                cmd.arg(format!("--rsync-path={}", remote_rsync));
+            }
+            cmd.arg(shell.destination(&remote));
+        }
+    }
+
+    let output = cmd.output().context("Failed to execute rsync");
+
+    if let Some(mut mount) = smb_mount {
+        if mount.should_auto_unmount() {
+            mount.unmount().await?;
+        }
+    }
+
+    let output = output?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Listing {} failed: {}",
+            remote,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(parse_line)
+        .filter(|entry| entry.path != ".")
+        .collect())
+}
+
+pub fn print_entries(profile: &SyncProfile, entries: &[RemoteEntry]) {
+    for entry in entries {
+        let path = if entry.is_dir() {
+            format!("{}/", entry.path)
+        } else {
+            entry.path.clone()
+        };
+        println!("{} {:>15} {}  {}", entry.permissions, entry.size, entry.modified, path);
+    }
+
+    let files: Vec<&RemoteEntry> = entries.iter().filter(|e| !e.is_dir()).collect();
+    let bytes: u64 = files.iter().map(|e| e.size).sum();
+    println!(
+        "{} file(s), {:.2} MB in {}",
+        files.len(),
+        bytes as f64 / (1024.0 * 1024.0),
+        profile.remote_path
+    );
+}
+
+/// `drwxr-xr-x          4,096 2025/01/02 10:00:00 photos` (older rsync omits the commas)
+fn parse_line(line: &str) -> Option<RemoteEntry> {
+    let line = line.trim_start();
+    let permissions = line.split_whitespace().next()?;
+    if permissions.len() != 10 {
+        return None;
+    }
+    let mut rest = line[permissions.len()..].trim_start();
+
+    let mut next = || {
+        let end = rest.find(char::is_whitespace)?;
+        let field = &rest[..end];
+        rest = rest[end..].trim_start();
+        Some(field)
+    };
+    let size = next()?.replace([',', '.'], "").parse().ok()?;
+    let date = next()?;
+    let time = next()?;
+
+    Some(RemoteEntry {
+        permissions: permissions.to_string(),
+        size,
+        modified: format!("{} {}", date, time),
+        path: rest.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_list_only_output() {
+        let entry = parse_line("-rw-r--r--      1,234,567 2025/03/04 05:06:07 holiday photos/IMG 01.jpg").unwrap();
+
+        assert_eq!(entry.permissions, "-rw-r--r--");
+        assert_eq!(entry.size, 1_234_567);
+        assert_eq!(entry.modified, "2025/03/04 05:06:07");
+        assert_eq!(entry.path, "holiday photos/IMG 01.jpg");
+        assert!(!entry.is_dir());
+
+        assert!(parse_line("drwxr-xr-x          4096 2025/03/04 05:06:07 .").unwrap().is_dir());
+        assert!(parse_line("receiving incremental file list").is_none());
+    }
+}
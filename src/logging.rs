@@ -3,14 +3,32 @@
 // Author: Immanuel Jeyaraj <irj@sefier.com>
 // License: GPL-3.0
 
-use crate::config::LoggingConfig;
+use crate::config::{LoggingConfig, SyncProfile};
+use crate::output::Output;
 use anyhow::{Context, Result};
 use std::path::Path;
-use tracing::Level;
+use std::sync::{Mutex, OnceLock};
+use tracing::level_filters::LevelFilter;
+use tracing::{Level, Subscriber};
 use tracing_appender::rolling::{RollingFileAppender, Rotation};
-use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::layer::{Layer, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{fmt, reload, util::SubscriberInitExt, EnvFilter, Registry};
 
-pub fn init_logging(config: &LoggingConfig, verbose: bool) -> Result<()> {
+/// Span every profile's sync runs in, so a profile's `log_level` can apply to it
+pub const PROFILE_SPAN: &str = "sync";
+
+/// Swaps the filter of the running process, see [`set_level`]
+static FILTER: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
+/// Directives from the config, restored by `reset`
+static CONFIGURED: OnceLock<Vec<String>> = OnceLock::new();
+
+/// Change made at runtime on top of the configured levels, if any
+static OVERRIDE: Mutex<Option<String>> = Mutex::new(None);
+
+pub fn init_logging(config: &LoggingConfig, profiles: &[SyncProfile], verbose: bool, output: Output) -> Result<()> {
     if !config.enabled {
         return Ok(());
     }
@@ -22,9 +40,10 @@ pub fn init_logging(config: &LoggingConfig, verbose: bool) -> Result<()> {
         parse_log_level(&config.log_level)?
     };
 
-    let filter = EnvFilter::from_default_env()
-        .add_directive(level.into())
-        .add_directive("ugnassync=trace".parse().unwrap());
+    let configured = directives(level, config, profiles)?;
+    let (filter, handle) = reload::Layer::new(build_filter(&configured)?);
+    let _ = CONFIGURED.set(configured);
+    let _ = FILTER.set(handle);
 
     let registry = tracing_subscriber::registry().with(filter);
 
@@ -60,13 +79,9 @@ pub fn init_logging(config: &LoggingConfig, verbose: bool) -> Result<()> {
             .with_target(false);
 
         if config.console_output {
-            let console_layer = fmt::layer()
-                .with_writer(std::io::stdout)
-                .with_target(false);
-
             registry
                 .with(file_layer)
-                .with(console_layer)
+                .with(console_layer(output))
                 .init();
         } else {
             registry
@@ -74,12 +89,8 @@ pub fn init_logging(config: &LoggingConfig, verbose: bool) -> Result<()> {
                 .init();
         }
     } else if config.console_output {
-        let console_layer = fmt::layer()
-            .with_writer(std::io::stdout)
-            .with_target(false);
-
         registry
-            .with(console_layer)
+            .with(console_layer(output))
             .init();
     } else {
         // At least one output should be enabled
@@ -89,7 +100,116 @@ pub fn init_logging(config: &LoggingConfig, verbose: bool) -> Result<()> {
     Ok(())
 }
 
-fn parse_log_level(level_str: &str) -> Result<Level> {
+/// Console log lines on stdout, or only errors on stderr with `--quiet`; colored
+/// only when the output is
+fn console_layer<S>(output: Output) -> impl Layer<S>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    let (writer, level) = if output.quiet {
+        (BoxMakeWriter::new(std::io::stderr), LevelFilter::ERROR)
+    } else {
+        (BoxMakeWriter::new(std::io::stdout), LevelFilter::TRACE)
+    };
+    fmt::layer()
+        .with_writer(writer)
+        .with_ansi(output.color)
+        .with_target(false)
+        .with_filter(level)
+}
+
+/// `RUST_LOG` plus the given directives; later ones win for the same target
+fn build_filter(directives: &[String]) -> Result<EnvFilter> {
+    let mut filter = EnvFilter::from_default_env();
+    for directive in directives {
+        filter = filter.add_directive(
+            directive
+                .parse()
+                .with_context(|| format!("Invalid log filter '{}'", directive))?,
+        );
+    }
+    Ok(filter)
+}
+
+/// Change the log level of the running process without a restart: a level such as
+/// `debug`, module levels like `watch=debug, sync=info` on top of the configured
+/// ones, or `reset` for the configured levels
+pub fn set_level(spec: &str) -> Result<String> {
+    let handle = FILTER.get().context("Logging is disabled")?;
+    let mut directives = CONFIGURED.get().cloned().unwrap_or_default();
+    let spec = spec.trim();
+
+    let applied = if spec.is_empty() || spec == "reset" {
+        None
+    } else {
+        directives.extend(runtime_directives(spec)?);
+        Some(spec.to_string())
+    };
+    handle
+        .reload(build_filter(&directives)?)
+        .context("Failed to change the log level")?;
+
+    let message = match &applied {
+        Some(spec) => format!("Log level set to {}", spec),
+        None => "Log level reset to the configured levels".to_string(),
+    };
+    *OVERRIDE.lock().unwrap() = applied;
+    tracing::info!("{}", message);
+    Ok(message)
+}
+
+/// Switch between debug logging and the configured levels (SIGUSR1)
+pub fn toggle_debug() -> Result<String> {
+    let debugging = OVERRIDE.lock().unwrap().as_deref() == Some("debug");
+    set_level(if debugging { "reset" } else { "debug" })
+}
+
+/// A bare level applies to everything, anything else is read like `module_levels`
+fn runtime_directives(spec: &str) -> Result<Vec<String>> {
+    if !spec.contains('=') {
+        return Ok(vec![parse_log_level(spec)?.to_string().to_lowercase()]);
+    }
+    module_directives(spec)
+}
+
+/// `watch=debug, sync=info` as directives for modules of this crate
+fn module_directives(spec: &str) -> Result<Vec<String>> {
+    let mut directives = Vec::new();
+    for entry in spec.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+        let (module, module_level) = entry
+            .split_once('=')
+            .with_context(|| format!("module_levels entry '{}' should look like watch=debug", entry))?;
+        let module = module.trim();
+        let target = if module == "ugnassync" || module.starts_with("ugnassync::") {
+            module.to_string()
+        } else {
+            format!("ugnassync::{}", module)
+        };
+        directives.push(format!("{}={}", target, parse_log_level(module_level.trim())?.to_string().to_lowercase()));
+    }
+    Ok(directives)
+}
+
+/// Filter directives: the global level, `module_levels` for modules of this crate,
+/// and the `log_level` of profiles for everything logged while they sync
+fn directives(level: Level, config: &LoggingConfig, profiles: &[SyncProfile]) -> Result<Vec<String>> {
+    let mut directives = vec![level.to_string().to_lowercase()];
+    directives.extend(module_directives(&config.module_levels)?);
+
+    for profile in profiles {
+        if let Some(profile_level) = &profile.log_level {
+            directives.push(format!(
+                "[{}{{profile={}}}]={}",
+                PROFILE_SPAN,
+                profile.name,
+                parse_log_level(profile_level)?.to_string().to_lowercase()
+            ));
+        }
+    }
+    Ok(directives)
+}
+
+pub(crate) fn parse_log_level(level_str: &str) -> Result<Level> {
     match level_str.to_lowercase().as_str() {
         "trace" => Ok(Level::TRACE),
         "debug" => Ok(Level::DEBUG),
@@ -111,4 +231,93 @@ mod tests {
         assert!(matches!(parse_log_level("warn").unwrap(), Level::WARN));
         assert!(parse_log_level("invalid").is_err());
     }
+
+    #[test]
+    fn test_module_and_profile_levels() {
+        use std::sync::{Arc, Mutex};
+
+        let mut config: LoggingConfig = toml::from_str(
+            r#"
+            enabled = true
+            log_file = "/tmp/ugnassync.log"
+            log_level = "info"
+            console_output = true
+            file_output = false
+            rotate_enabled = false
+            max_file_size_mb = 10
+            max_files = 5
+            compress_rotated = false
+            module_levels = "watch=debug, ugnassync::sync=warn"
+            "#,
+        )
+        .unwrap();
+        let profile = |name: &str, level: Option<&str>| {
+            let mut profile: SyncProfile = toml::from_str(&format!(
+                "name = '{}'\nlocal_path = '/a'\nremote_path = '/b'\nsync_type = 'one-way'\nenabled = true",
+                name
+            ))
+            .unwrap();
+            profile.log_level = level.map(str::to_string);
+            profile
+        };
+        let profiles = vec![profile("photos", Some("debug")), profile("docs", None)];
+
+        let directives = directives(Level::INFO, &config, &profiles).unwrap();
+        assert_eq!(
+            directives,
+            vec!["info", "ugnassync::watch=debug", "ugnassync::sync=warn", "[sync{profile=photos}]=debug"]
+        );
+
+        // Only the verbose profile's debug lines get through
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let writer = output.clone();
+        let mut filter = EnvFilter::new("");
+        for directive in &directives {
+            filter = filter.add_directive(directive.parse().unwrap());
+        }
+        let subscriber = tracing_subscriber::registry().with(filter).with(
+            fmt::layer()
+                .with_ansi(false)
+                .with_writer(move || WriteTo(writer.clone())),
+        );
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!(PROFILE_SPAN, profile = "photos").in_scope(|| tracing::debug!("photos detail"));
+            tracing::info_span!(PROFILE_SPAN, profile = "docs").in_scope(|| tracing::debug!("docs detail"));
+        });
+        let logged = String::from_utf8(output.lock().unwrap().clone()).unwrap();
+        assert!(logged.contains("photos detail"));
+        assert!(!logged.contains("docs detail"));
+
+        config.module_levels = "watch".to_string();
+        assert!(super::directives(Level::INFO, &config, &profiles).is_err());
+    }
+
+    #[test]
+    fn test_runtime_level_overrides_configured() {
+        use tracing::level_filters::LevelFilter;
+
+        let configured = vec!["info".to_string(), "ugnassync::sync=warn".to_string()];
+        let base = build_filter(&configured).unwrap();
+        assert_eq!(base.max_level_hint(), Some(LevelFilter::INFO));
+
+        let mut debugging = configured.clone();
+        debugging.extend(runtime_directives("debug").unwrap());
+        assert_eq!(build_filter(&debugging).unwrap().max_level_hint(), Some(LevelFilter::DEBUG));
+
+        assert_eq!(runtime_directives("watch=trace").unwrap(), vec!["ugnassync::watch=trace"]);
+        assert!(runtime_directives("loud").is_err());
+    }
+
+    struct WriteTo(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for WriteTo {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
 }
@@ -0,0 +1,102 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use chrono::{DateTime, Local};
+use std::collections::VecDeque;
+
+/// NAS disks usually spin down after 10-20 minutes without access; a sync after a
+/// longer quiet period than this is counted as waking them
+const SPIN_DOWN_MINUTES: i64 = 15;
+
+const MINUTES_PER_DAY: i64 = 24 * 60;
+
+/// Wall-clock window `now` falls in; every profile uses the same windows, so their
+/// batched syncs wake the NAS together
+pub fn window(now: DateTime<Local>, window_minutes: u64) -> i64 {
+    now.timestamp() / (window_minutes as i64 * 60)
+}
+
+/// When the daemon's syncs accessed the NAS, to estimate how often its disks wake up
+#[derive(Debug, Default)]
+pub struct Activity {
+    /// Set in low-churn mode
+    window_minutes: Option<u64>,
+    started: Option<DateTime<Local>>,
+    last: Option<DateTime<Local>>,
+    /// Wakeups of the last 24 hours
+    wakeups: VecDeque<DateTime<Local>>,
+}
+
+impl Activity {
+    pub fn low_churn(window_minutes: u64, started: DateTime<Local>) -> Self {
+        Self {
+            window_minutes: Some(window_minutes),
+            started: Some(started),
+            ..Default::default()
+        }
+    }
+
+    /// A sync starting or finishing at `at`
+    pub fn record(&mut self, at: DateTime<Local>) {
+        if self.last.is_none_or(|last| (at - last).num_minutes() >= SPIN_DOWN_MINUTES) {
+            self.wakeups.push_back(at);
+        }
+        self.last = Some(at);
+        while self.wakeups.front().is_some_and(|first| (at - *first).num_minutes() >= MINUTES_PER_DAY) {
+            self.wakeups.pop_front();
+        }
+    }
+
+    /// Wakeups of the last 24 hours, extrapolated while the daemon has been running for
+    /// less; None until a first window has passed
+    pub fn wakeups_per_day(&self, now: DateTime<Local>) -> Option<f64> {
+        let running = (now - self.started?).num_minutes();
+        if running < self.window_minutes? as i64 {
+            return None;
+        }
+        let recent = self.wakeups.iter().filter(|at| (now - **at).num_minutes() < MINUTES_PER_DAY).count();
+        Some(recent as f64 * MINUTES_PER_DAY as f64 / running.min(MINUTES_PER_DAY) as f64)
+    }
+
+    /// Status line of low-churn mode
+    pub fn render(&self, now: DateTime<Local>) -> Option<String> {
+        let window_minutes = self.window_minutes?;
+        let estimate = match self.wakeups_per_day(now) {
+            Some(wakeups) => format!("about {:.0}", wakeups),
+            None => format!("at most {}", MINUTES_PER_DAY / window_minutes as i64),
+        };
+        Some(format!(
+            "low-churn: syncs batched every {} min, {} NAS wakeup(s)/day",
+            window_minutes, estimate
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    #[test]
+    fn test_windows_and_wakeups() {
+        let start = Local.with_ymd_and_hms(2025, 3, 1, 8, 0, 0).unwrap();
+        assert_eq!(window(start + Duration::minutes(59), 60), window(start, 60));
+        assert_eq!(window(start + Duration::minutes(60), 60), window(start, 60) + 1);
+
+        let mut activity = Activity::low_churn(60, start);
+        assert_eq!(activity.wakeups_per_day(start + Duration::minutes(30)), None);
+        assert!(activity.render(start).unwrap().contains("at most 24 NAS wakeup(s)/day"));
+
+        // Two profiles syncing in the same window wake the NAS once
+        for minutes in [60, 61, 62, 64, 180, 181] {
+            activity.record(start + Duration::minutes(minutes));
+        }
+        assert_eq!(activity.wakeups_per_day(start + Duration::hours(6)), Some(8.0));
+
+        let next_day = start + Duration::hours(30);
+        activity.record(next_day);
+        assert_eq!(activity.wakeups_per_day(next_day), Some(1.0));
+    }
+}
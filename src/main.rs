@@ -3,20 +3,84 @@
 // Author: Immanuel Jeyaraj <irj@sefier.com>
 // License: GPL-3.0
 
+mod adopt;
+mod archive;
+mod basestore;
+mod bench;
+mod capabilities;
+mod cascade;
+mod changes;
+mod clock;
 mod config;
+mod configedit;
 mod conflict;
+mod connectivity;
+mod control;
+mod daemon;
+mod dedup;
+mod device;
+mod diff;
+mod discover;
+mod dsm;
+mod dump;
+mod envconfig;
+mod error;
+mod eta;
+mod filelist;
+mod ftp;
+mod health;
+mod import;
+mod listing;
 mod logging;
+mod lowchurn;
+mod maintenance;
+mod markers;
+mod migrate;
+mod mounts;
+mod names;
+mod nashealth;
+mod notification;
+mod origin;
+mod output;
+mod preflight;
+mod push;
+mod qnap;
+mod quota;
+mod ransomware;
+mod remote;
+mod report;
+mod resolve;
+mod rsync;
+mod schedule;
+mod service;
 mod smb;
+mod smbclient;
+mod snapshot;
+mod stats;
 mod sync;
+mod truenas;
+mod vendor;
+mod versions;
 mod watch;
+mod window;
 
-use anyhow::Result;
-use clap::Parser;
-use config::Config;
-use std::path::PathBuf;
-use sync::SyncEngine;
-use tracing::{error, info};
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+use basestore::BaseStore;
+use config::{Config, InsecureKey};
+use conflict::ConflictResolver;
+use error::SyncError;
+use maintenance::Maintenance;
+use notification::{Notifier, Severity};
+use ransomware::Quarantine;
+use remote::RemoteShell;
+use report::RunReport;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use sync::{SyncEngine, TransferCapReached};
+use tracing::{error, info, warn};
 use watch::WatchManager;
+use window::WindowCheck;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 const AUTHORS: &str = "Immanuel Jeyaraj <irj@sefier.com>";
@@ -40,7 +104,7 @@ struct Cli {
     config: PathBuf,
 
     /// Run only the specified sync profile
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     profile: Option<String>,
 
     /// Simulate sync without making changes
@@ -54,35 +118,853 @@ struct Cli {
     /// Enable watch mode for real-time sync (runs as daemon)
     #[arg(short, long)]
     watch: bool,
+
+    /// Run watch mode in the background, with output in the configured log file
+    #[arg(long, requires = "watch")]
+    detach: bool,
+
+    /// PID file of the background daemon (default: ~/.ugnassync/ugnassync.pid)
+    #[arg(long, global = true)]
+    pid_file: Option<PathBuf>,
+
+    /// Never delete or overwrite anything on the destination
+    #[arg(long)]
+    read_only: bool,
+
+    /// Stop syncing further profiles after the first failure
+    #[arg(long)]
+    fail_fast: bool,
+
+    /// Compare files by checksum instead of mtime/size for this run
+    #[arg(long)]
+    checksum: bool,
+
+    /// Ignore incremental shortcuts and re-evaluate the whole tree for this run
+    #[arg(long)]
+    force_full: bool,
+
+    /// Run mirror and archive syncs even if the NAS health gate finds degraded storage
+    #[arg(long)]
+    ignore_health: bool,
+
+    /// Print how long each sync phase took
+    #[arg(long)]
+    timings: bool,
+
+    /// Only errors on stderr, and the run summary as one JSON line on stdout (for cron and scripts)
+    #[arg(short, long, global = true)]
+    quiet: bool,
+
+    /// Never color output (also with the NO_COLOR environment variable)
+    #[arg(long, global = true)]
+    no_color: bool,
+
+    /// Print errors as one JSON object per line on stderr (kind, exit code, message)
+    #[arg(long, global = true)]
+    json_errors: bool,
+
+    /// Exit 0 if the NAS is reachable and the watch daemon (if any) answers, else 1 (for Docker HEALTHCHECK)
+    #[arg(long)]
+    healthcheck: bool,
+
+    #[command(subcommand)]
+    command: Option<Commands>,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Show the status of profiles in a running watch daemon
+    Status,
+    /// Pause a profile in a running watch daemon (changes stay queued)
+    Pause {
+        /// Profile name
+        name: String,
+    },
+    /// Resume a paused profile in a running watch daemon
+    Resume {
+        /// Profile name
+        name: String,
+    },
+    /// Change the log level of a running watch daemon without restarting it
+    LogLevel {
+        /// A level (debug), module levels (watch=debug, sync=info), or reset
+        level: String,
+    },
+    /// Stop the daemon started with --watch --detach
+    Stop,
+    /// Make the daemon started with --watch --detach re-read its config file
+    Reload,
+    /// Change profiles in the config file, keeping its comments and layout
+    Profile {
+        #[command(subcommand)]
+        action: ProfileCommand,
+    },
+    /// Add profiles for the jobs of an existing rsnapshot config, crontab (rsync jobs)
+    /// or Syncthing config.xml to the config file (--dry-run prints them instead)
+    Import {
+        #[arg(long, value_parser = ["rsnapshot", "crontab", "syncthing"])]
+        from: String,
+
+        /// File to read (default: /etc/rsnapshot.conf, `crontab -l`, or Syncthing's config.xml)
+        path: Option<PathBuf>,
+
+        /// NAS directory for the profiles; required for rsnapshot and Syncthing
+        #[arg(long)]
+        remote_root: Option<String>,
+
+        /// Add the profiles switched off
+        #[arg(long)]
+        disabled: bool,
+    },
+    /// Discover NAS devices on the LAN via mDNS/Avahi
+    Discover {
+        /// Print a [nas] config entry for the device with this hostname or address
+        #[arg(long)]
+        scaffold: Option<String>,
+
+        /// Skip listing SMB shares with smbclient
+        #[arg(long)]
+        no_shares: bool,
+    },
+    /// List local files that changed since the last sync of --profile (works offline)
+    Changes,
+    /// List files on the NAS side of --profile with sizes and modification times
+    Ls {
+        /// Directory below the profile's remote path
+        subpath: Option<String>,
+
+        /// List subdirectories too
+        #[arg(short, long)]
+        recursive: bool,
+    },
+    /// Show how a local file of --profile differs from its NAS copy (unified diff, or size and hash for binaries)
+    Diff {
+        /// Local file, absolute or relative to the profile's local path
+        path: PathBuf,
+    },
+    /// Fetch files moved to the NAS by an archive profile back (a file, its stub, or a directory)
+    Hydrate {
+        paths: Vec<PathBuf>,
+    },
+    /// Record files already identical locally and on the NAS as synced (first sync of existing data, needs --profile)
+    Adopt,
+    /// Find identical files synced more than once, using the sync state hashes
+    DedupReport {
+        /// Replace duplicate copies on the NAS with hardlinks (SSH profiles only)
+        #[arg(long)]
+        hardlink: bool,
+    },
+    /// Prune old run history, compress and expire reports and logs, vacuum the state
+    /// databases and remove leftovers of dead runs (the watch daemon does this daily)
+    Maintenance,
+    /// Show bytes transferred per profile and month (all profiles, or --profile)
+    Stats {
+        #[command(subcommand)]
+        action: Option<StatsCommand>,
+    },
+    /// Move sync state between machines
+    State {
+        #[command(subcommand)]
+        action: StateCommand,
+    },
+    /// Choose NAS subdirectories of --profile that are not synced locally
+    Selective {
+        #[command(subcommand)]
+        action: SelectiveCommand,
+    },
+    /// Older copies of files kept on the NAS by keep_versions (uses --profile)
+    Versions {
+        #[command(subcommand)]
+        action: VersionsCommand,
+    },
+    /// Inspect data kept for conflict handling
+    Conflicts {
+        #[command(subcommand)]
+        action: ConflictsCommand,
+    },
+    /// Show what the NAS offers over SSH (rsync, sftp, sha256sum, btrfs/zfs, userland), probed once and cached
+    Capabilities {
+        /// Probe the NAS again instead of using the cached result
+        #[arg(long)]
+        refresh: bool,
+    },
+    /// Mount a configured share for browsing and leave it mounted
+    Mount {
+        /// smb, a profile name, or a profile's requires_mount path
+        target: String,
+    },
+    /// Unmount a share mounted with `mount` or left mounted by syncs
+    Umount {
+        /// smb, a profile name, or a profile's requires_mount path
+        target: String,
+    },
+    /// Measure NAS throughput and latency over each available transport
+    Bench {
+        /// Writable directory on the NAS for the test file
+        #[arg(long)]
+        remote_dir: String,
+
+        /// Amount of test data in MB
+        #[arg(long, default_value_t = 64)]
+        size_mb: u64,
+    },
+}
+
+#[derive(Subcommand)]
+enum ProfileCommand {
+    /// Set enabled = true for a profile
+    Enable {
+        name: String,
+    },
+    /// Set enabled = false for a profile
+    Disable {
+        name: String,
+    },
+    /// Append a new [[sync_profiles]] entry
+    Add {
+        name: String,
+
+        #[arg(long)]
+        local_path: String,
+
+        #[arg(long)]
+        remote_path: String,
+
+        #[arg(long, value_parser = ["mirror", "one-way", "two-way", "incremental", "backup", "archive"], default_value = "mirror")]
+        sync_type: String,
+
+        /// Exclude pattern (repeatable)
+        #[arg(long)]
+        exclude: Vec<String>,
+
+        /// Sync on file changes in watch mode
+        #[arg(long, conflicts_with = "interval_minutes")]
+        watch_mode: bool,
+
+        /// Sync every N minutes in watch mode
+        #[arg(long)]
+        interval_minutes: Option<u64>,
+
+        /// Add the profile switched off
+        #[arg(long)]
+        disabled: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum StatsCommand {
+    /// Print the run history and per-profile aggregates for analysis (all profiles, or --profile)
+    Export {
+        #[arg(long, value_parser = ["csv", "json"], default_value = "csv")]
+        format: String,
+
+        /// Only runs started on or after this date (YYYY-MM-DD)
+        #[arg(long)]
+        since: Option<String>,
+
+        /// CSV: one row per profile instead of one per run
+        #[arg(long)]
+        aggregates: bool,
+
+        /// Also push the aggregates to the [metrics] endpoint
+        #[arg(long)]
+        push: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum StateCommand {
+    /// Export sync state, archive logs, conflict bases and reports to a bundle, or import one
+    Migrate {
+        /// Write a .tar.zst bundle
+        #[arg(long, conflicts_with = "import", required_unless_present = "import")]
+        export: Option<PathBuf>,
+
+        /// Read a bundle written by --export on the old machine
+        #[arg(long)]
+        import: Option<PathBuf>,
+
+        /// Rewrite local paths on import, e.g. /home/alice=/Users/alice (repeatable)
+        #[arg(long = "map", value_name = "OLD=NEW", requires = "import")]
+        mappings: Vec<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum SelectiveCommand {
+    /// Keep a subdirectory on the NAS only; syncs neither transfer nor delete it
+    Exclude {
+        /// Directory relative to the profile's remote path
+        path: String,
+    },
+    /// Sync a previously excluded subdirectory again
+    Include {
+        /// Directory relative to the profile's remote path
+        path: String,
+    },
+    /// Show the subdirectories kept on the NAS only
+    List,
+}
+
+#[derive(Subcommand)]
+enum VersionsCommand {
+    /// Show the kept versions, of one local file or of all files
+    List {
+        path: Option<PathBuf>,
+    },
+    /// Copy a kept version of a local file back; the current file is moved to *.bak
+    Restore {
+        path: PathBuf,
+
+        /// Run directory of the version, as shown by `versions list` (default: newest)
+        #[arg(long)]
+        version: Option<String>,
+
+        /// Write the copy here instead of over the local file
+        #[arg(long)]
+        to: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConflictsCommand {
+    /// Print the version of a file as it was last synced (uses --profile's state database)
+    ShowBase {
+        /// Local path of the file
+        path: PathBuf,
+    },
+    /// Settle a conflict of --profile by keeping the local copy, the NAS copy, or both
+    Resolve {
+        /// Local path of the file
+        path: PathBuf,
+
+        /// local, nas, or both (the NAS copy is kept locally as *.conflict.<device>-<time>)
+        #[arg(long, value_parser = ["local", "nas", "both"])]
+        keep: String,
+    },
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() {
     let cli = Cli::parse();
+    let json_errors = cli.json_errors;
+    let healthcheck = cli.healthcheck;
+
+    if let Err(e) = run(cli).await {
+        if json_errors {
+            eprintln!("{}", error::to_json(None, &e));
+        } else {
+            eprintln!("Error: {:?}", e);
+        }
+        // Docker treats only 1 as unhealthy
+        std::process::exit(if healthcheck { 1 } else { error::exit_code(&e) });
+    }
+}
+
+/// The NAS is reachable and, with watch or scheduled profiles configured, the watch daemon answers
+fn healthcheck(config: &Config) -> Result<()> {
+    if !config.get_watch_profiles().is_empty() || !config.get_scheduled_profiles().is_empty() {
+        control::send_command("status").context("Watch daemon is not responding")?;
+    }
+
+    let host = connectivity::select_host(&config.nas)?;
+    if !connectivity::is_reachable(&host, config.nas.port) {
+        return Err(SyncError::Unreachable(format!("NAS {}:{} is not reachable", host, config.nas.port)).into());
+    }
+
+    println!("healthy");
+    Ok(())
+}
+
+async fn run(cli: Cli) -> Result<()> {
+    // These commands need no config file
+    match &cli.command {
+        Some(Commands::Status) => {
+            print!("{}", control::send_command("status")?);
+            return Ok(());
+        }
+        Some(Commands::Pause { name }) => {
+            print!("{}", control::send_command(&format!("pause {}", name))?);
+            return Ok(());
+        }
+        Some(Commands::Resume { name }) => {
+            print!("{}", control::send_command(&format!("resume {}", name))?);
+            return Ok(());
+        }
+        Some(Commands::LogLevel { level }) => {
+            print!("{}", control::send_command(&format!("log-level {}", level))?);
+            return Ok(());
+        }
+        Some(Commands::Stop) => {
+            return daemon::stop(&daemon::pid_file_path(cli.pid_file.as_deref())?);
+        }
+        Some(Commands::Reload) => {
+            return daemon::reload(&daemon::pid_file_path(cli.pid_file.as_deref())?);
+        }
+        Some(Commands::Profile { action }) => {
+            match action {
+                ProfileCommand::Enable { name } | ProfileCommand::Disable { name } => {
+                    let enabled = matches!(action, ProfileCommand::Enable { .. });
+                    let changed = configedit::edit(&cli.config, |doc| configedit::set_enabled(doc, name, enabled))?;
+                    let state = if enabled { "enabled" } else { "disabled" };
+                    if changed {
+                        println!("Profile {} {} in {}", name, state, cli.config.display());
+                    } else {
+                        println!("Profile {} is already {}", name, state);
+                    }
+                }
+                ProfileCommand::Add {
+                    name,
+                    local_path,
+                    remote_path,
+                    sync_type,
+                    exclude,
+                    watch_mode,
+                    interval_minutes,
+                    disabled,
+                } => {
+                    let new = configedit::NewProfile {
+                        name: name.clone(),
+                        local_path: local_path.clone(),
+                        remote_path: remote_path.clone(),
+                        sync_type: sync_type.clone(),
+                        enabled: !disabled,
+                        exclude: exclude.clone(),
+                        watch_mode: *watch_mode,
+                        interval_minutes: *interval_minutes,
+                        allowed_window: None,
+                        keep_versions: None,
+                    };
+                    configedit::edit(&cli.config, |doc| configedit::add_profile(doc, &new))?;
+                    println!("Profile {} added to {}", name, cli.config.display());
+                }
+            }
+            if daemon::pid_file_path(cli.pid_file.as_deref()).is_ok_and(|pid_file| pid_file.exists()) {
+                println!("Run `ugnassync reload` to apply it to the running daemon");
+            }
+            return Ok(());
+        }
+        Some(Commands::Import { from, path, remote_root, disabled }) => {
+            let nas_hosts: Vec<String> = Config::load(&cli.config)
+                .map(|config| std::iter::once(config.nas.host).chain(config.nas.fallback_hosts).collect())
+                .unwrap_or_default();
+            let mut import = import::read(from, path.as_deref(), remote_root.as_deref(), &nas_hosts)?;
+            for skipped in &import.skipped {
+                eprintln!("Skipped {}", skipped);
+            }
+            for profile in &mut import.profiles {
+                profile.enabled = !disabled;
+            }
+            if cli.dry_run {
+                let mut doc = toml_edit::DocumentMut::new();
+                for profile in &import.profiles {
+                    configedit::add_profile(&mut doc, profile)?;
+                }
+                print!("{}", doc);
+                return Ok(());
+            }
+            let added = configedit::edit(&cli.config, |doc| {
+                let mut added = 0;
+                for profile in &import.profiles {
+                    match configedit::add_profile(doc, profile) {
+                        Ok(()) => added += 1,
+                        Err(e) => eprintln!("Skipped {}: {:#}", profile.name, e),
+                    }
+                }
+                Ok(added)
+            })?;
+            println!("Imported {} profile(s) into {}", added, cli.config.display());
+            if added > 0 {
+                println!("Remove the original jobs so they do not run alongside UGNasSync");
+            }
+            return Ok(());
+        }
+        Some(Commands::Discover { scaffold, no_shares }) => {
+            let devices = discover::browse(!no_shares)?;
+            match scaffold {
+                Some(host) => {
+                    let device = devices
+                        .iter()
+                        .find(|d| &d.hostname == host || d.addresses.contains(host))
+                        .with_context(|| format!("No discovered device matches '{}'", host))?;
+                    print!("{}", discover::scaffold(device)?);
+                }
+                None => discover::print_devices(&devices),
+            }
+            return Ok(());
+        }
+        _ => {}
+    }
 
     // Load configuration
-    let config = Config::from_file(&cli.config)?;
+    let mut config = match Config::load(&cli.config) {
+        // Offer to fix a key that ssh would refuse rather than fail mid-run
+        Err(e) if std::io::stdin().is_terminal() && e.is::<InsecureKey>() => {
+            let key = e.downcast_ref::<InsecureKey>().unwrap();
+            if sync::confirm(&format!("{}. Restrict it to 600 now?", key))? {
+                key.fix()?;
+                Config::load(&cli.config)
+            } else {
+                Err(e)
+            }
+        }
+        result => result,
+    }
+    .map_err(|e| SyncError::Config(format!("{:#}", e)))?;
+
+    if cli.read_only {
+        for profile in &mut config.sync_profiles {
+            profile.read_only = true;
+        }
+    }
+
+    if cli.healthcheck {
+        return healthcheck(&config);
+    }
+
+    if cli.detach {
+        let pid = daemon::detach(&daemon::pid_file_path(cli.pid_file.as_deref())?, &config.logging.log_file)?;
+        println!("Watching in the background (PID {}), stop with `ugnassync stop`", pid);
+        return Ok(());
+    }
+
+    // Console output of the background daemon already ends up in the log file
+    if daemon::is_detached() && config.logging.file_output {
+        config.logging.console_output = false;
+    }
 
     // Initialize logging
-    logging::init_logging(&config.logging, cli.verbose)?;
+    output::init(output::Output::new(cli.quiet, cli.no_color));
+    logging::init_logging(&config.logging, &config.sync_profiles, cli.verbose, output::get())?;
+    device::init(config.device_name.as_deref());
 
     info!("UGNasSync v{}", VERSION);
     info!("{}", COPYRIGHT);
     info!("License: {}", LICENSE);
     info!("Starting UGNasSync...");
 
+    let rsync_binary = config.nas.rsync_binary();
+    match rsync::detect(rsync_binary) {
+        Some(version) if !version.supports_3_1() => warn!(
+            "Using {} ({}); options needing rsync 3.1 or later are ignored",
+            version, rsync_binary
+        ),
+        Some(version) => info!("Using {} ({})", version, rsync_binary),
+        None => warn!("Could not determine the version of {}, is it installed?", rsync_binary),
+    }
+
+    if let Some(Commands::Capabilities { refresh }) = &cli.command {
+        let shell = RemoteShell::new(&config.nas)?;
+        let capabilities = if *refresh { capabilities::refresh(&shell)? } else { capabilities::of(&shell)? };
+        capabilities.print();
+        return Ok(());
+    }
+
+    if let Some(Commands::Mount { target }) = &cli.command {
+        for target in mounts::targets(&config.nas, &config.sync_profiles, target)? {
+            target.mount().await?;
+            println!("{} is mounted", target.mount_point().display());
+        }
+        return Ok(());
+    }
+
+    if let Some(Commands::Umount { target }) = &cli.command {
+        for target in mounts::targets(&config.nas, &config.sync_profiles, target)? {
+            target.unmount()?;
+        }
+        return Ok(());
+    }
+
+    if let Some(Commands::Bench { remote_dir, size_mb }) = &cli.command {
+        let results = bench::run(&config.nas, remote_dir, *size_mb).await?;
+        bench::print_results(&results);
+        return Ok(());
+    }
+
+    if let Some(Commands::Changes) = &cli.command {
+        let profile_name = cli.profile.as_ref().context("changes requires --profile")?;
+        let profile = config
+            .sync_profiles
+            .iter()
+            .find(|p| &p.name == profile_name)
+            .with_context(|| format!("Profile not found: {}", profile_name))?;
+
+        let resolver = ConflictResolver::for_profile(profile)?;
+        let pending = changes::pending_changes(profile, &resolver)?;
+        changes::print_changes(profile, &pending);
+        return Ok(());
+    }
+
+    if let Some(Commands::Ls { subpath, recursive }) = &cli.command {
+        let profile_name = cli.profile.as_ref().context("ls requires --profile")?;
+        let profile = config
+            .sync_profiles
+            .iter()
+            .find(|p| &p.name == profile_name)
+            .with_context(|| format!("Profile not found: {}", profile_name))?;
+
+        let entries = listing::list(&config.nas, profile, subpath.as_deref(), *recursive).await?;
+        listing::print_entries(profile, &entries);
+        return Ok(());
+    }
+
+    if let Some(Commands::Adopt) = &cli.command {
+        let profile_name = cli.profile.as_ref().context("adopt requires --profile")?;
+        let profile = config
+            .sync_profiles
+            .iter()
+            .find(|p| &p.name == profile_name)
+            .with_context(|| format!("Profile not found: {}", profile_name))?;
+
+        let resolver = ConflictResolver::for_profile(profile)?;
+        let report = adopt::adopt(&config.nas, profile, &resolver, cli.dry_run).await?;
+        adopt::print_report(profile, &report, cli.dry_run);
+        return Ok(());
+    }
+
+    if let Some(Commands::Diff { path }) = &cli.command {
+        let profile_name = cli.profile.as_ref().context("diff requires --profile")?;
+        let profile = config
+            .sync_profiles
+            .iter()
+            .find(|p| &p.name == profile_name)
+            .with_context(|| format!("Profile not found: {}", profile_name))?;
+
+        diff::diff(&config.nas, profile, path).await?;
+        return Ok(());
+    }
+
+    if let Some(Commands::Hydrate { paths }) = &cli.command {
+        for path in paths {
+            let hydrated = archive::hydrate(&config, path).await?;
+            println!("Hydrated {} file(s) under {}", hydrated.len(), path.display());
+        }
+        return Ok(());
+    }
+
+    if let Some(Commands::DedupReport { hardlink }) = &cli.command {
+        let groups = dedup::find_duplicates(&config)?;
+        dedup::print_report(&groups);
+        if *hardlink && !groups.is_empty() {
+            let reclaimed = dedup::hardlink(&config, &groups, cli.dry_run)?;
+            println!("{:.2} MB reclaimed by hardlinking", reclaimed as f64 / (1024.0 * 1024.0));
+        }
+        return Ok(());
+    }
+
+    if let Some(Commands::Maintenance) = &cli.command {
+        // Like the daemon's own maintenance, wait for its syncs instead of vacuuming under them
+        if control::send_command("status").is_ok_and(|status| status.contains(": syncing")) {
+            anyhow::bail!("Profiles are syncing in the watch daemon, run maintenance once they are done");
+        }
+        let summary = Maintenance::new(&config, cli.pid_file.as_deref()).run()?;
+        println!("Maintenance: {}", summary);
+        return Ok(());
+    }
+
+    if let Some(Commands::Stats { action }) = &cli.command {
+        let resolver = ConflictResolver::new()?;
+        match action {
+            None => {
+                let totals = resolver.transfer_totals()?;
+                conflict::print_transfer_totals(&totals, cli.profile.as_deref(), config.nas.monthly_transfer_cap_gb);
+            }
+            Some(StatsCommand::Export { format, since, aggregates, push }) => {
+                let since = since.as_deref().map(stats::parse_since).transpose()?;
+                let mut runs = resolver.run_history(since)?;
+                runs.retain(|run| cli.profile.as_ref().is_none_or(|name| &run.profile == name));
+                let totals = stats::aggregate(&runs);
+
+                match (format.as_str(), aggregates) {
+                    ("json", _) => println!("{}", stats::to_json(&runs, &totals)?),
+                    (_, true) => print!("{}", stats::aggregates_csv(&totals)),
+                    (_, false) => print!("{}", stats::runs_csv(&runs)),
+                }
+                if *push {
+                    let metrics = config.metrics.as_ref().context("--push needs a [metrics] section")?;
+                    stats::push(metrics, &totals)?;
+                    eprintln!("Pushed {} profile aggregate(s) to {}", totals.len(), metrics.push_url);
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    if let Some(Commands::State { action: StateCommand::Migrate { export, import, mappings } }) = &cli.command {
+        match (export, import) {
+            (Some(bundle), _) => migrate::export(&config, bundle)?,
+            (_, Some(bundle)) => {
+                let mappings = mappings
+                    .iter()
+                    .map(|mapping| migrate::parse_mapping(mapping))
+                    .collect::<Result<Vec<_>>>()?;
+                migrate::import(&config, bundle, &mappings)?;
+            }
+            (None, None) => unreachable!("clap requires --export or --import"),
+        }
+        return Ok(());
+    }
+
+    if let Some(Commands::Selective { action }) = &cli.command {
+        let profile_name = cli.profile.as_ref().context("selective requires --profile")?;
+        let profile = config
+            .sync_profiles
+            .iter()
+            .find(|p| &p.name == profile_name)
+            .with_context(|| format!("Profile not found: {}", profile_name))?;
+        let resolver = ConflictResolver::for_profile(profile)?;
+
+        match action {
+            SelectiveCommand::Exclude { path } | SelectiveCommand::Include { path } => {
+                if Path::new(path).components().any(|c| c == std::path::Component::ParentDir) {
+                    anyhow::bail!("Path must stay inside the profile's remote path: {}", path);
+                }
+                let path = path.trim_matches('/');
+                if path.is_empty() {
+                    anyhow::bail!("Name a subdirectory of the profile's remote path");
+                }
+                let exclude = matches!(action, SelectiveCommand::Exclude { .. });
+                resolver.set_remote_only(&profile.name, path, exclude)?;
+                if exclude {
+                    println!("{}/{} is now kept on the NAS only; a local copy is left in place", profile.remote_path.trim_end_matches('/'), path);
+                } else {
+                    println!("{}/{} is synced again from the next run", profile.remote_path.trim_end_matches('/'), path);
+                }
+            }
+            SelectiveCommand::List => {
+                let paths = resolver.remote_only_paths(&profile.name)?;
+                if paths.is_empty() {
+                    println!("Profile {} syncs its whole remote path", profile.name);
+                }
+                for path in paths {
+                    println!("{}", path);
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    if let Some(Commands::Versions { action }) = &cli.command {
+        let profile_name = cli.profile.as_ref().context("versions requires --profile")?;
+        let profile = config
+            .sync_profiles
+            .iter()
+            .find(|p| &p.name == profile_name)
+            .with_context(|| format!("Profile not found: {}", profile_name))?;
+
+        let (side, mount) = versions::open(&config.nas, profile).await?;
+        let result = (|| -> Result<()> {
+            let all = versions::list(&side, profile)?;
+            match action {
+                VersionsCommand::List { path } => {
+                    let relative = path
+                        .as_ref()
+                        .map(|path| versions::relative_path(profile, &std::path::absolute(path)?))
+                        .transpose()?;
+                    let shown: Vec<_> = all.iter().filter(|v| relative.as_ref().is_none_or(|r| &v.path == r)).collect();
+                    if shown.is_empty() {
+                        println!("No versions kept");
+                    }
+                    for version in shown {
+                        println!("{}  {}", version.stamp, version.path);
+                    }
+                }
+                VersionsCommand::Restore { path, version, to } => {
+                    let path = std::path::absolute(path)?;
+                    let relative = versions::relative_path(profile, &path)?;
+                    let chosen = all
+                        .iter()
+                        .rev()
+                        .filter(|v| v.path == relative)
+                        .find(|v| version.as_ref().is_none_or(|stamp| &v.stamp == stamp))
+                        .with_context(|| format!("No kept version of {}", path.display()))?;
+                    let target = to.clone().unwrap_or(path);
+                    versions::restore(&config.nas, &side, profile, chosen, &target)?;
+                    println!("Restored version {} to {}", chosen.stamp, target.display());
+                }
+            }
+            Ok(())
+        })();
+
+        if let Some(mut mount) = mount {
+            if mount.should_auto_unmount() {
+                mount.unmount().await?;
+            }
+        }
+        return result;
+    }
+
+    if let Some(Commands::Conflicts { action }) = &cli.command {
+        match action {
+            ConflictsCommand::ShowBase { path } => {
+                let profile = match &cli.profile {
+                    Some(name) => Some(
+                        config
+                            .sync_profiles
+                            .iter()
+                            .find(|p| &p.name == name)
+                            .with_context(|| format!("Profile not found: {}", name))?,
+                    ),
+                    None => None,
+                };
+                let resolver = match profile {
+                    Some(profile) => ConflictResolver::for_profile(profile)?,
+                    None => ConflictResolver::new()?,
+                };
+                let store = BaseStore::new(config.base_store.as_ref().context("No [base_store] configured")?)?;
+
+                let path = std::path::absolute(path)?;
+                let state = resolver
+                    .recorded(&path)?
+                    .with_context(|| format!("No synced version recorded for {}", path.display()))?;
+                let base = store
+                    .get(&state.hash)
+                    .with_context(|| format!("Base version of {} is not in the store", path.display()))?;
+
+                std::io::copy(&mut std::fs::File::open(base)?, &mut std::io::stdout())?;
+            }
+            ConflictsCommand::Resolve { path, keep } => {
+                let profile_name = cli.profile.as_ref().context("conflicts resolve requires --profile")?;
+                let profile = config
+                    .sync_profiles
+                    .iter()
+                    .find(|p| &p.name == profile_name)
+                    .with_context(|| format!("Profile not found: {}", profile_name))?;
+
+                let path = std::path::absolute(path)?;
+                resolve::resolve(&config.nas, profile, &path, keep).await?;
+                println!("Resolved {} keeping {}", path.display(), keep);
+            }
+        }
+        return Ok(());
+    }
+
     if cli.watch {
         // Watch mode
         let watch_profiles = config.get_watch_profiles();
+        let scheduled_profiles = config.get_scheduled_profiles();
 
-        if watch_profiles.is_empty() {
-            error!("No profiles with watch_mode enabled found in config");
-            anyhow::bail!("No watch-enabled profiles configured");
+        if watch_profiles.is_empty() && scheduled_profiles.is_empty() {
+            error!("No profiles with watch_mode or interval_minutes found in config");
+            anyhow::bail!("No watch-enabled or scheduled profiles configured");
         }
 
         info!("Running in watch mode");
-        let watch_manager = WatchManager::new(config.nas.clone());
-        watch_manager.start_watching(watch_profiles).await?;
+        tokio::spawn(async {
+            if let Err(e) = daemon::toggle_debug_on_usr1().await {
+                error!("SIGUSR1 handler failed: {:#}", e);
+            }
+        });
+        if daemon::is_detached() {
+            let config_path = cli.config.clone();
+            tokio::spawn(async move {
+                if let Err(e) = daemon::reload_on_hangup(config_path).await {
+                    error!("Reload handler failed: {:#}", e);
+                }
+            });
+        }
+        let watch_manager = WatchManager::new(&config);
+        watch_manager.start_watching(watch_profiles, scheduled_profiles).await?;
     } else {
         // One-time sync mode
         let profiles = if let Some(profile_name) = &cli.profile {
@@ -104,43 +986,120 @@ async fn main() -> Result<()> {
 
         info!("Found {} profile(s) to sync", profiles.len());
 
-        let sync_engine = SyncEngine::new(config.nas.clone());
+        let sync_engine = SyncEngine::new(config.nas.clone())
+            .with_checksum(cli.checksum)
+            .with_force_full(cli.force_full)
+            .with_ignore_health(cli.ignore_health)
+            .with_interactive(std::io::stdin().is_terminal())
+            .with_base_store(config.base_store.as_ref());
+        let mut report = RunReport::new(cli.dry_run);
+        let fail_fast = cli.fail_fast || config.fail_fast;
+        let mut aborted_by: Option<String> = None;
+        // Decides the exit code once all profiles ran
+        let mut first_failure: Option<anyhow::Error> = None;
+        let notifier = Notifier::new(config.notifications.clone()).with_dsm_alerts(&config.nas);
+        let single_profile = profiles.len() == 1;
+        let held_mounts = mounts::hold(&config.nas, &profiles).await;
 
         for profile in profiles {
+            if let Some(failed) = &aborted_by {
+                warn!("Skipping profile {} (fail-fast after {} failed)", profile.name, failed);
+                report.add_skipped(&profile.name, &format!("fail-fast after {} failed", failed));
+                continue;
+            }
+
+            // Scheduled runs start inside the profile's allowed window, once per window
+            if profile.allowed_window.is_some() && !cli.dry_run {
+                let resolver = ConflictResolver::for_profile(profile)
+                    .inspect_err(|e| warn!("Sync state unavailable, not tracking window runs: {:#}", e))
+                    .ok();
+                match window::check(profile, resolver.as_ref(), chrono::Local::now())? {
+                    WindowCheck::Run => {}
+                    // Naming the profile asks for another run in the same window
+                    WindowCheck::Done(_) if cli.profile.is_some() => {}
+                    WindowCheck::Deferred(reason) | WindowCheck::Done(reason) => {
+                        info!("Skipping profile {}: {}", profile.name, reason);
+                        report.add_skipped(&profile.name, &reason);
+                        continue;
+                    }
+                }
+            }
+
             info!("Processing profile: {}", profile.name);
 
             match sync_engine.sync_profile(profile, cli.dry_run).await {
                 Ok(stats) => {
-                    println!("\nSync Summary:");
-                    println!("Profile: {}", profile.name);
-                    println!("Files transferred: {}", stats.files_transferred);
-                    println!(
-                        "Bytes transferred: {:.2} MB",
-                        stats.bytes_transferred as f64 / (1024.0 * 1024.0)
-                    );
-
-                    if stats.conflicts_detected > 0 {
-                        println!("Conflicts detected: {}", stats.conflicts_detected);
-                        println!("  - Skipped: {}", stats.conflicts_skipped);
-                        println!("  - Resolved: {}", stats.conflicts_resolved);
+                    report.add_success(&profile.name, &stats);
+                    if !cli.dry_run {
+                        notifier.send_recovery(&profile.name);
                     }
-
-                    println!("Duration: {:.2}s", stats.duration_secs);
-                    println!(
-                        "Status: {}",
-                        if stats.conflicts_skipped > 0 {
-                            "Completed with warnings"
-                        } else {
-                            "Completed successfully"
+                    if profile.allowed_window.is_some() && !cli.dry_run {
+                        if let Err(e) = ConflictResolver::for_profile(profile).and_then(|resolver| resolver.record_run(&profile.name)) {
+                            warn!("Failed to record run of profile {}: {:#}", profile.name, e);
                         }
-                    );
+                    }
+
+                    output::profile_result(&profile.name, &stats, single_profile, cli.dry_run, cli.timings);
+                }
+                Err(e) if e.is::<TransferCapReached>() => {
+                    warn!("Skipping profile {}: {}", profile.name, e);
+                    report.add_skipped(&profile.name, &e.to_string());
                 }
                 Err(e) => {
                     error!("Failed to sync profile {}: {}", profile.name, e);
+                    let run_id = sync_engine.run_id(&profile.name);
+                    report.add_failure(&profile.name, &e, run_id.clone());
+                    let notifier = notifier.for_run(run_id);
+                    if cli.json_errors {
+                        eprintln!("{}", error::to_json(Some(&profile.name), &e));
+                    }
+
+                    if let Some(quarantine) = e.downcast_ref::<Quarantine>() {
+                        notifier.send_failure(
+                            &profile.name,
+                            Severity::Critical,
+                            &format!("Profile {} quarantined", profile.name),
+                            &quarantine.reason,
+                        );
+                    } else if let Some(sync_error) = error::find(&e) {
+                        notifier.send_failure(
+                            &profile.name,
+                            sync_error.severity(),
+                            &format!("Profile {} failed ({})", profile.name, sync_error.kind()),
+                            &sync_error.to_string(),
+                        );
+                    }
+
+                    if fail_fast {
+                        // No pre-sync snapshot exists to roll back to, so earlier profiles stay synced
+                        error!("Fail-fast enabled, not scheduling remaining profiles");
+                        aborted_by = Some(profile.name.clone());
+                    }
+                    first_failure.get_or_insert(e);
                 }
             }
         }
 
+        held_mounts.release().await;
+        output::run_summary(&report, single_profile);
+
+        if let Some(report_path) = &config.logging.report_path {
+            if let Err(e) = report.write(Path::new(report_path), config.logging.report_html) {
+                error!("Failed to write sync report: {:#}", e);
+            }
+        }
+
+        if let Some(failed) = aborted_by {
+            let e = first_failure.unwrap_or_else(|| anyhow::anyhow!("profile failed"));
+            return Err(e.context(format!("Sync aborted after profile {} failed (fail-fast)", failed)));
+        }
+        if let Some(e) = first_failure {
+            return Err(e.context(format!("{} profile(s) failed", report.failed_count())));
+        }
+        if report.partial_count() > 0 {
+            return Err(SyncError::Partial(format!("{} profile(s) synced only partially", report.partial_count())).into());
+        }
+
         info!("All sync operations completed");
     }
 
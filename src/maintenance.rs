@@ -0,0 +1,375 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use crate::config::{Config, LoggingConfig, MaintenanceConfig};
+use crate::conflict::{self, ConflictResolver, RunRecord};
+use crate::control::{self, DaemonState};
+use crate::daemon;
+use crate::mounts;
+use anyhow::{Context, Result};
+use chrono::Local;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, SystemTime};
+use tracing::{debug, info, warn};
+
+/// Temporary files of the tool untouched this long belong to runs that died
+const STALE_TEMP_AFTER: Duration = Duration::from_secs(24 * 3600);
+
+/// The daemon's first maintenance waits for the startup syncs to settle
+const FIRST_RUN_DELAY: Duration = Duration::from_secs(15 * 60);
+
+/// Maintenance due while a profile syncs is retried after this long
+const BUSY_RETRY: Duration = Duration::from_secs(10 * 60);
+
+/// Directory next to the shared state database receiving pruned runs with archive_pruned_runs
+const HISTORY_ARCHIVE_DIR: &str = "history";
+
+/// What a maintenance run cleaned up
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub runs_pruned: usize,
+    pub databases_vacuumed: usize,
+    pub reports_compressed: usize,
+    pub reports_deleted: usize,
+    pub logs_compressed: usize,
+    pub logs_deleted: usize,
+    pub temp_removed: usize,
+    pub locks_removed: usize,
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "pruned {} run(s) from history, vacuumed {} database(s), compressed {} and deleted {} report(s), \
+             compressed {} and deleted {} log file(s), removed {} temporary file(s) and {} stale lock(s)",
+            self.runs_pruned,
+            self.databases_vacuumed,
+            self.reports_compressed,
+            self.reports_deleted,
+            self.logs_compressed,
+            self.logs_deleted,
+            self.temp_removed,
+            self.locks_removed
+        )
+    }
+}
+
+/// Housekeeping of the state databases, reports, logs and leftovers of dead runs
+#[derive(Debug, Clone)]
+pub struct Maintenance {
+    settings: MaintenanceConfig,
+    logging: LoggingConfig,
+    /// `state_db` of the profiles, besides the shared database
+    state_dbs: Vec<PathBuf>,
+    pid_file: Option<PathBuf>,
+}
+
+impl Maintenance {
+    pub fn new(config: &Config, pid_file: Option<&Path>) -> Self {
+        Self {
+            settings: config.maintenance.clone(),
+            logging: config.logging.clone(),
+            state_dbs: config.sync_profiles.iter().filter_map(|profile| profile.state_db.as_ref().map(PathBuf::from)).collect(),
+            pid_file: pid_file.map(Path::to_path_buf),
+        }
+    }
+
+    pub fn run(&self) -> Result<Summary> {
+        let mut summary = Summary::default();
+
+        let retention = self.settings.history_retention_days;
+        let before = (retention > 0).then(|| Local::now() - chrono::Duration::days(retention as i64));
+        let default_db = conflict::default_db_path()?;
+        let archive_dir = default_db.with_file_name(HISTORY_ARCHIVE_DIR);
+        let databases: BTreeSet<PathBuf> = std::iter::once(default_db).chain(self.state_dbs.clone()).collect();
+        for db in databases.iter().filter(|db| db.exists()) {
+            let resolver = ConflictResolver::open(db)?;
+            summary.runs_pruned += resolver.prune_history(before, self.settings.max_runs_per_profile, |runs| {
+                if self.settings.archive_pruned_runs {
+                    archive_runs(&archive_dir, db, runs)?;
+                }
+                Ok(())
+            })?;
+            resolver.vacuum()?;
+            summary.databases_vacuumed += 1;
+        }
+
+        if let Some(dir) = self.logging.report_path.as_deref().map(Path::new).filter(|dir| dir.is_dir()) {
+            self.age_reports(dir, &mut summary)?;
+        }
+        if self.logging.file_output && self.logging.rotate_enabled {
+            self.rotate_logs(&mut summary)?;
+        }
+
+        summary.temp_removed = remove_stale_temp(&std::env::temp_dir(), STALE_TEMP_AFTER)?;
+        summary.locks_removed = self.expire_locks()?;
+        Ok(summary)
+    }
+
+    /// Gzip reports older than compress_reports_days and delete those older than keep_reports_days
+    fn age_reports(&self, dir: &Path, summary: &mut Summary) -> Result<()> {
+        let days = |days: u64| Duration::from_secs(days * 24 * 3600);
+        for entry in fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))? {
+            let path = entry?.path();
+            let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+            let Some(age) = age(&path).filter(|_| name.starts_with("report-")) else {
+                continue;
+            };
+            if self.settings.keep_reports_days > 0 && age >= days(self.settings.keep_reports_days) {
+                fs::remove_file(&path).with_context(|| format!("Failed to delete {}", path.display()))?;
+                summary.reports_deleted += 1;
+            } else if age >= days(self.settings.compress_reports_days) && !name.ends_with(".gz") {
+                gzip(&path)?;
+                summary.reports_compressed += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply max_files and compress_rotated to the daily log files next to log_file
+    fn rotate_logs(&self, summary: &mut Summary) -> Result<()> {
+        let log_path = Path::new(&self.logging.log_file);
+        let (Some(dir), Some(file_name)) = (log_path.parent(), log_path.file_name()) else {
+            return Ok(());
+        };
+        let Ok(entries) = fs::read_dir(dir) else {
+            return Ok(());
+        };
+        let prefix = format!("{}.", file_name.to_string_lossy());
+        let names: Vec<String> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .filter(|name| name.starts_with(&prefix))
+            .collect();
+
+        let (compress, delete) = rotated_logs(names, self.logging.max_files, self.logging.compress_rotated);
+        for name in delete {
+            let path = dir.join(name);
+            fs::remove_file(&path).with_context(|| format!("Failed to delete {}", path.display()))?;
+            summary.logs_deleted += 1;
+        }
+        for name in compress {
+            gzip(&dir.join(name))?;
+            summary.logs_compressed += 1;
+        }
+        Ok(())
+    }
+
+    /// Remove the PID file of a daemon that is gone and a control socket nobody listens on
+    fn expire_locks(&self) -> Result<usize> {
+        let mut removed = 0;
+        let pid_file = daemon::pid_file_path(self.pid_file.as_deref())?;
+        if pid_file.exists() && !matches!(daemon::running_pid(&pid_file), Ok(Some(_))) {
+            fs::remove_file(&pid_file).with_context(|| format!("Failed to remove {}", pid_file.display()))?;
+            info!("Removed stale PID file {}", pid_file.display());
+            removed += 1;
+        }
+        let socket = control::socket_path()?;
+        if socket.exists() && UnixStream::connect(&socket).is_err() {
+            fs::remove_file(&socket).with_context(|| format!("Failed to remove {}", socket.display()))?;
+            info!("Removed stale control socket {}", socket.display());
+            removed += 1;
+        }
+        Ok(removed)
+    }
+
+    /// Run maintenance every `interval_hours` inside the watch daemon, postponed while
+    /// a profile syncs so vacuuming does not compete with it for the state database
+    pub async fn run_periodically(self, state: DaemonState) {
+        let interval = Duration::from_secs(self.settings.interval_hours * 3600);
+        info!("Running maintenance every {} hour(s)", self.settings.interval_hours);
+        let mut delay = FIRST_RUN_DELAY;
+        loop {
+            tokio::time::sleep(delay).await;
+            if state.snapshot().values().any(|status| status.syncing) {
+                debug!("Postponing maintenance while profiles sync");
+                delay = BUSY_RETRY;
+                continue;
+            }
+            let maintenance = self.clone();
+            match tokio::task::spawn_blocking(move || maintenance.run()).await {
+                Ok(Ok(summary)) => info!("Maintenance: {}", summary),
+                Ok(Err(e)) => warn!("Maintenance failed: {:#}", e),
+                Err(e) => warn!("Maintenance failed: {}", e),
+            }
+            delay = interval;
+        }
+    }
+}
+
+/// Save runs about to be pruned from `db` as `runs-<db path>-<time>.json.gz` in `dir`,
+/// never replacing an earlier archive
+fn archive_runs(dir: &Path, db: &Path, runs: &[RunRecord]) -> Result<()> {
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    // The shared and per-user databases are all called sync_state.db, so name by the whole path
+    let source: Vec<String> = db
+        .with_extension("")
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part.to_string_lossy().to_string()),
+            _ => None,
+        })
+        .collect();
+    let name = format!("runs-{}-{}", source.join("-"), Local::now().format("%Y%m%d-%H%M%S"));
+
+    let mut attempt = 0;
+    let (path, file) = loop {
+        let path = match attempt {
+            0 => dir.join(format!("{}.json.gz", name)),
+            n => dir.join(format!("{}-{}.json.gz", name, n)),
+        };
+        match fs::OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => break (path, file),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => attempt += 1,
+            Err(e) => return Err(e).with_context(|| format!("Failed to create {}", path.display())),
+        }
+    };
+
+    let mut child = Command::new("gzip")
+        .arg("-c")
+        .stdin(Stdio::piped())
+        .stdout(file)
+        .spawn()
+        .context("Failed to run gzip")?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(serde_json::to_string_pretty(runs)?.as_bytes())?;
+    }
+    if !child.wait().context("Failed to run gzip")?.success() {
+        let _ = fs::remove_file(&path);
+        anyhow::bail!("gzip failed on {}", path.display());
+    }
+    info!("Archived {} pruned run(s) to {}", runs.len(), path.display());
+    Ok(())
+}
+
+/// Rotated logs (`<log_file>.<date>`, newest last by name) to compress and to delete:
+/// all but the newest `max_files` go, and all but the one being written get compressed
+fn rotated_logs(mut names: Vec<String>, max_files: usize, compress: bool) -> (Vec<String>, Vec<String>) {
+    names.sort_by(|a, b| b.cmp(a));
+    let keep = max_files.max(1).min(names.len());
+    let delete = names.split_off(keep);
+    let compress = match compress {
+        true => names.into_iter().skip(1).filter(|name| !name.ends_with(".gz")).collect(),
+        false => Vec::new(),
+    };
+    (compress, delete)
+}
+
+/// Delete `ugnassync-<kind>-<pid>` files and directories in `dir` whose process is gone
+/// and that were not modified for `older_than`. Names without a PID (watch lists) and
+/// mount points (LVM snapshots) are kept.
+fn remove_stale_temp(dir: &Path, older_than: Duration) -> Result<usize> {
+    let mut removed = 0;
+    for entry in fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))? {
+        let path = entry?.path();
+        let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        if owner_pid(&name).is_none_or(process_alive)
+            || age(&path).is_none_or(|age| age < older_than)
+            || mounts::is_mount_point(&path)
+        {
+            continue;
+        }
+        let result = match path.is_dir() {
+            true => fs::remove_dir_all(&path),
+            false => fs::remove_file(&path),
+        };
+        match result {
+            Ok(()) => {
+                debug!("Removed stale temporary file {}", path.display());
+                removed += 1;
+            }
+            // Another user's files in a shared /tmp
+            Err(e) => debug!("Could not remove {}: {}", path.display(), e),
+        }
+    }
+    Ok(removed)
+}
+
+/// PID embedded in a temporary file name, as in `ugnassync-filelist-1234` or `ugnassync-bench-1234.bin`
+fn owner_pid(name: &str) -> Option<u32> {
+    let stem = name.strip_prefix("ugnassync-")?.split('.').next()?;
+    stem.rsplit_once('-')?.1.parse().ok()
+}
+
+fn process_alive(pid: u32) -> bool {
+    Path::new("/proc").join(pid.to_string()).exists()
+}
+
+fn age(path: &Path) -> Option<Duration> {
+    let modified = fs::symlink_metadata(path).ok()?.modified().ok()?;
+    Some(SystemTime::now().duration_since(modified).unwrap_or_default())
+}
+
+fn gzip(path: &Path) -> Result<()> {
+    let status = Command::new("gzip")
+        .arg("-f")
+        .arg("--")
+        .arg(path)
+        .status()
+        .context("Failed to run gzip")?;
+    if !status.success() {
+        anyhow::bail!("gzip failed on {}", path.display());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotated_logs_keep_newest() {
+        let names = ["sync.log.2025-03-08.gz", "sync.log.2025-03-10", "sync.log.2025-03-09", "sync.log.2025-03-07.gz"];
+        let (compress, delete) = rotated_logs(names.map(str::to_string).to_vec(), 3, true);
+        assert_eq!(compress, vec!["sync.log.2025-03-09"]);
+        assert_eq!(delete, vec!["sync.log.2025-03-07.gz"]);
+
+        let (compress, delete) = rotated_logs(names.map(str::to_string).to_vec(), 5, false);
+        assert!(compress.is_empty() && delete.is_empty());
+    }
+
+    #[test]
+    fn test_archive_runs_never_overwrite() {
+        let dir = std::env::temp_dir().join(format!("ugnassync-archive-test-{}", std::process::id()));
+        archive_runs(&dir, Path::new("/home/alice/.ugnassync/sync_state.db"), &[]).unwrap();
+        archive_runs(&dir, Path::new("/home/alice/.ugnassync/sync_state.db"), &[]).unwrap();
+        archive_runs(&dir, Path::new("/home/bob/.ugnassync/sync_state.db"), &[]).unwrap();
+
+        let names: Vec<String> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(names.len(), 3);
+        assert_eq!(names.iter().filter(|name| name.starts_with("runs-home-alice-.ugnassync-sync_state-")).count(), 2);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_remove_stale_temp_only_touches_own_files() {
+        let dir = std::env::temp_dir().join(format!("ugnassync-maintenance-{}", std::process::id()));
+        // PIDs above the kernel's pid_max are never running
+        fs::create_dir_all(dir.join("ugnassync-filelist-999999999/tree")).unwrap();
+        fs::write(dir.join("ugnassync-smbclient-999999999.list"), "a.txt\n").unwrap();
+        fs::write(dir.join(format!("ugnassync-bench-{}.bin", std::process::id())), "in use").unwrap();
+        fs::write(dir.join("ugnassync-watch-docs.list"), "a.txt\n").unwrap();
+        fs::write(dir.join("other.txt"), "keep").unwrap();
+
+        assert_eq!(remove_stale_temp(&dir, STALE_TEMP_AFTER).unwrap(), 0);
+        assert_eq!(remove_stale_temp(&dir, Duration::ZERO).unwrap(), 2);
+        let mut left: Vec<_> = fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+        left.sort();
+        assert_eq!(left, vec!["other.txt", format!("ugnassync-bench-{}.bin", std::process::id()).as_str(), "ugnassync-watch-docs.list"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
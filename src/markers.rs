@@ -0,0 +1,108 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use crate::config::SyncProfile;
+use crate::watch::WatchManager;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::{debug, warn};
+
+/// First bytes of a valid CACHEDIR.TAG (https://bford.info/cachedir/)
+const CACHEDIR_SIGNATURE: &[u8] = b"Signature: 8a477f597d28d172789f06886806bc55";
+
+/// Directories tagged with CACHEDIR.TAG and files or directories flagged nodump
+/// (`chattr +d`) below the profile's local paths, which backup tools leave out
+pub fn scan(profile: &SyncProfile) -> Vec<PathBuf> {
+    let excludes = profile.effective_excludes();
+    let mut marked = Vec::new();
+    for root in profile.source_paths() {
+        cache_dirs(Path::new(root), &excludes, &mut marked);
+        marked.extend(nodump_paths(Path::new(root)));
+    }
+    marked
+}
+
+fn cache_dirs(dir: &Path, excludes: &[String], marked: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("Cannot read {}: {}", dir.display(), e);
+            return;
+        }
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        // Symlinks are synced as links, so their targets are not looked at
+        if !entry.file_type().is_ok_and(|t| t.is_dir()) || WatchManager::is_excluded(&path, excludes) {
+            continue;
+        }
+        if is_cache_dir(&path) {
+            debug!("Leaving out cache directory {}", path.display());
+            marked.push(path);
+        } else {
+            cache_dirs(&path, excludes, marked);
+        }
+    }
+}
+
+fn is_cache_dir(dir: &Path) -> bool {
+    let mut signature = [0u8; CACHEDIR_SIGNATURE.len()];
+    File::open(dir.join("CACHEDIR.TAG"))
+        .and_then(|mut tag| tag.read_exact(&mut signature))
+        .is_ok_and(|()| signature == CACHEDIR_SIGNATURE)
+}
+
+/// nodump flags as listed by `lsattr` (Linux, e2fsprogs); none where it is missing
+fn nodump_paths(root: &Path) -> Vec<PathBuf> {
+    match Command::new("lsattr").arg("-R").arg("-a").arg(root).output() {
+        Ok(output) => parse_lsattr(&String::from_utf8_lossy(&output.stdout)),
+        Err(e) => {
+            debug!("Cannot run lsattr, nodump flags are not checked: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// Entries look like `------d------e------- /home/me/docs/big.iso`; the directory
+/// headers of -R and the `.`/`..` entries of -a are skipped
+fn parse_lsattr(output: &str) -> Vec<PathBuf> {
+    output
+        .lines()
+        .filter_map(|line| line.split_once(' '))
+        .filter(|(flags, _)| flags.contains('-') && flags.chars().all(|c| c == '-' || c.is_ascii_alphabetic()))
+        .filter(|(flags, _)| flags.contains('d'))
+        .filter(|(_, path)| !path.ends_with("/.") && !path.ends_with("/.."))
+        .map(|(_, path)| PathBuf::from(path))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_dirs_and_nodump() {
+        let root = std::env::temp_dir().join(format!("ugnassync-markers-{}", std::process::id()));
+        fs::create_dir_all(root.join("project/target/debug")).unwrap();
+        fs::create_dir_all(root.join("notes")).unwrap();
+        fs::write(root.join("project/target/CACHEDIR.TAG"), b"Signature: 8a477f597d28d172789f06886806bc55\n# cargo").unwrap();
+        fs::write(root.join("notes/CACHEDIR.TAG"), b"not a cache").unwrap();
+
+        let mut marked = Vec::new();
+        cache_dirs(&root, &[], &mut marked);
+        assert_eq!(marked, vec![root.join("project/target")]);
+        fs::remove_dir_all(&root).unwrap();
+
+        let listed = parse_lsattr(
+            "--------------e------- /home/me/docs/a.txt\n\
+             ------d-------e------- /home/me/docs/big file.iso\n\
+             \n/home/me/docs/sub:\n\
+             ------d-------e------- /home/me/docs/sub/.\n",
+        );
+        assert_eq!(listed, vec![PathBuf::from("/home/me/docs/big file.iso")]);
+    }
+}
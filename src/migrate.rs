@@ -0,0 +1,321 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use crate::archive::{self, ArchiveRecord};
+use crate::basestore::BaseStore;
+use crate::config::{Config, SyncProfile};
+use crate::conflict;
+use crate::error::SyncError;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::{info, warn};
+
+const MANIFEST: &str = "manifest.json";
+const SHARED_STATE: &str = "sync_state.db";
+const BASES: &str = "bases";
+const REPORTS: &str = "reports";
+
+/// What a migration bundle holds, relative to its root
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Manifest {
+    created: Option<DateTime<Local>>,
+    /// State databases of profiles with their own `state_db`, by profile name
+    profile_states: BTreeMap<String, String>,
+    /// Archive audit logs, each with one of the profiles writing to it
+    archive_logs: BTreeMap<String, String>,
+}
+
+/// `OLD=NEW` path prefix mapping for `--map`
+pub fn parse_mapping(mapping: &str) -> Result<(String, String)> {
+    let (old, new) = mapping
+        .split_once('=')
+        .with_context(|| format!("Path mapping '{}' should look like OLD=NEW", mapping))?;
+    Ok((old.trim_end_matches('/').to_string(), new.trim_end_matches('/').to_string()))
+}
+
+/// Pack sync state, archive logs, conflict bases and run reports into a `.tar.zst` bundle
+pub fn export(config: &Config, bundle: &Path) -> Result<()> {
+    let staging = staging_dir("export")?;
+    let mut manifest = Manifest {
+        created: Some(Local::now()),
+        ..Default::default()
+    };
+
+    let shared = conflict::default_db_path()?;
+    if shared.exists() {
+        snapshot_db(&shared, &staging.join(SHARED_STATE))?;
+    }
+    for profile in &config.sync_profiles {
+        let name = file_name(&profile.name);
+        if let Some(db) = profile.state_db.as_deref().map(Path::new).filter(|db| db.exists()) {
+            let entry = format!("profile-states/{}.db", name);
+            snapshot_db(db, &staging.join(&entry))?;
+            manifest.profile_states.insert(profile.name.clone(), entry);
+        }
+
+        let log = archive::audit_log_path(profile)?;
+        let entry = log_entry(profile);
+        if log.exists() && !manifest.archive_logs.contains_key(&entry) {
+            copy(&log, &staging.join(&entry))?;
+            manifest.archive_logs.insert(entry, profile.name.clone());
+        }
+    }
+
+    let bases = BaseStore::default_dir()?;
+    if bases.is_dir() {
+        copy_dir(&bases, &staging.join(BASES))?;
+    }
+    if let Some(reports) = config.logging.report_path.as_deref().map(Path::new).filter(|dir| dir.is_dir()) {
+        copy_dir(reports, &staging.join(REPORTS))?;
+    }
+
+    fs::write(staging.join(MANIFEST), serde_json::to_string_pretty(&manifest)?)?;
+    let result = tar(&["--zstd", "-cf"], bundle, &staging);
+    let _ = fs::remove_dir_all(&staging);
+    result?;
+
+    println!(
+        "Exported sync state of {} profile(s) to {}",
+        config.sync_profiles.len(),
+        bundle.display()
+    );
+    Ok(())
+}
+
+/// Unpack a bundle into this machine's state locations, moving existing files to `*.bak`
+/// and rewriting local path prefixes as given by `mappings`
+pub fn import(config: &Config, bundle: &Path, mappings: &[(String, String)]) -> Result<()> {
+    let staging = staging_dir("import")?;
+    let result = import_from(config, bundle, &staging, mappings);
+    let _ = fs::remove_dir_all(&staging);
+    result
+}
+
+fn import_from(config: &Config, bundle: &Path, staging: &Path, mappings: &[(String, String)]) -> Result<()> {
+    tar(&["--zstd", "-xf"], bundle, staging)?;
+    let manifest: Manifest = serde_json::from_str(
+        &fs::read_to_string(staging.join(MANIFEST)).context("Bundle has no manifest, is it a UGNasSync state export?")?,
+    )?;
+
+    let mut databases = Vec::new();
+    if staging.join(SHARED_STATE).exists() {
+        let target = conflict::default_db_path()?;
+        replace(&staging.join(SHARED_STATE), &target)?;
+        databases.push(target);
+    }
+    for (name, entry) in &manifest.profile_states {
+        match config.sync_profiles.iter().find(|p| &p.name == name).and_then(|p| p.state_db.as_ref()) {
+            Some(target) => {
+                replace(&staging.join(entry), Path::new(target))?;
+                databases.push(PathBuf::from(target));
+            }
+            None => warn!("Skipping state of profile {}: it has no state_db in this config", name),
+        }
+    }
+    for database in &databases {
+        let remapped = remap_state(database, mappings)?;
+        info!("Imported {} ({} path(s) remapped)", database.display(), remapped);
+    }
+
+    for (entry, name) in &manifest.archive_logs {
+        let Some(profile) = config.sync_profiles.iter().find(|p| &p.name == name) else {
+            warn!("Skipping archive log of profile {}: not in this config", name);
+            continue;
+        };
+        let target = archive::audit_log_path(profile)?;
+        replace(&staging.join(entry), &target)?;
+        remap_archive_log(&target, mappings)?;
+    }
+
+    if staging.join(BASES).is_dir() {
+        copy_dir(&staging.join(BASES), &BaseStore::default_dir()?)?;
+    }
+    if let Some(reports) = &config.logging.report_path {
+        if staging.join(REPORTS).is_dir() {
+            copy_dir(&staging.join(REPORTS), Path::new(reports))?;
+        }
+    }
+
+    println!(
+        "Imported sync state from {} ({} database(s), {} archive log(s))",
+        bundle.display(),
+        databases.len(),
+        manifest.archive_logs.len()
+    );
+    Ok(())
+}
+
+/// The first mapping whose prefix `path` is equal to or below, applied
+fn remap_path(path: &str, mappings: &[(String, String)]) -> Option<String> {
+    mappings.iter().find_map(|(old, new)| {
+        let rest = path.strip_prefix(old.as_str())?;
+        (rest.is_empty() || rest.starts_with('/')).then(|| format!("{}{}", new, rest))
+    })
+}
+
+fn remap_state(db: &Path, mappings: &[(String, String)]) -> Result<usize> {
+    if mappings.is_empty() {
+        return Ok(0);
+    }
+
+    let mut conn = Connection::open(db).with_context(|| format!("Failed to open {}", db.display()))?;
+    let tx = conn.transaction()?;
+    let paths: Vec<String> = tx
+        .prepare("SELECT path FROM sync_state")?
+        .query_map([], |row| row.get(0))?
+        .collect::<rusqlite::Result<_>>()?;
+
+    let mut remapped = 0;
+    for path in paths {
+        if let Some(new_path) = remap_path(&path, mappings) {
+            tx.execute("UPDATE OR REPLACE sync_state SET path = ?1 WHERE path = ?2", params![new_path, path])?;
+            remapped += 1;
+        }
+    }
+    tx.commit()?;
+    Ok(remapped)
+}
+
+fn remap_archive_log(log: &Path, mappings: &[(String, String)]) -> Result<()> {
+    if mappings.is_empty() {
+        return Ok(());
+    }
+
+    let mut content = String::new();
+    for line in fs::read_to_string(log)?.lines().filter(|line| !line.trim().is_empty()) {
+        let mut record: ArchiveRecord =
+            serde_json::from_str(line).with_context(|| format!("Unexpected line in {}", log.display()))?;
+        if let Some(local_path) = remap_path(&record.local_path, mappings) {
+            record.local_path = local_path;
+        }
+        content.push_str(&serde_json::to_string(&record)?);
+        content.push('\n');
+    }
+    fs::write(log, content).with_context(|| format!("Failed to write {}", log.display()))
+}
+
+/// Consistent copy of a database that may be in use
+fn snapshot_db(db: &Path, target: &Path) -> Result<()> {
+    if let Some(dir) = target.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    Connection::open(db)
+        .and_then(|conn| conn.execute("VACUUM INTO ?1", [target.to_string_lossy()]))
+        .with_context(|| format!("Failed to copy {}", db.display()))?;
+    Ok(())
+}
+
+/// Move `target` aside to `*.bak` and put `source` in its place
+fn replace(source: &Path, target: &Path) -> Result<()> {
+    if target.exists() {
+        let mut backup = target.as_os_str().to_owned();
+        backup.push(".bak");
+        fs::rename(target, &backup).with_context(|| format!("Failed to back up {}", target.display()))?;
+    }
+    copy(source, target)
+}
+
+fn copy(source: &Path, target: &Path) -> Result<()> {
+    if let Some(dir) = target.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::copy(source, target).with_context(|| format!("Failed to copy {} to {}", source.display(), target.display()))?;
+    Ok(())
+}
+
+/// Copy a flat or nested directory, keeping files already in `target`
+fn copy_dir(source: &Path, target: &Path) -> Result<()> {
+    fs::create_dir_all(target)?;
+    for entry in fs::read_dir(source)?.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            copy_dir(&path, &target.join(entry.file_name()))?;
+        } else {
+            copy(&path, &target.join(entry.file_name()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Bundle entry of a profile's archive log; profiles sharing the default log share the entry
+fn log_entry(profile: &SyncProfile) -> String {
+    match &profile.archive_log {
+        Some(_) => format!("archive/{}.log", file_name(&profile.name)),
+        None => "archive.log".to_string(),
+    }
+}
+
+fn file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect()
+}
+
+fn staging_dir(purpose: &str) -> Result<PathBuf> {
+    let dir = std::env::temp_dir().join(format!("ugnassync-{}-{}", purpose, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    Ok(dir)
+}
+
+fn tar(mode: &[&str], bundle: &Path, dir: &Path) -> Result<()> {
+    let mut cmd = Command::new("tar");
+    cmd.args(mode).arg(bundle).arg("-C").arg(dir);
+    if mode.contains(&"-cf") {
+        cmd.arg(".");
+    }
+    let output = cmd.output().map_err(|e| SyncError::from_spawn("tar", e))?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "tar failed for {} (needs GNU tar and zstd): {}",
+            bundle.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remap_state_rewrites_path_prefixes() {
+        let mappings = vec![parse_mapping("/home/old=/Users/new/").unwrap()];
+        assert_eq!(remap_path("/home/old/docs/a.txt", &mappings), Some("/Users/new/docs/a.txt".to_string()));
+        assert_eq!(remap_path("/home/older/a.txt", &mappings), None);
+
+        let db = std::env::temp_dir().join(format!("ugnassync-migrate-test-{}.db", std::process::id()));
+        let _ = fs::remove_file(&db);
+        let resolver = conflict::ConflictResolver::open(&db).unwrap();
+        drop(resolver);
+        let conn = Connection::open(&db).unwrap();
+        for path in ["/home/old/a.txt", "/srv/b.txt"] {
+            conn.execute(
+                "INSERT INTO sync_state (path, size, modified, hash, last_sync) VALUES (?1, 1, 1, 'h', 1)",
+                [path],
+            )
+            .unwrap();
+        }
+
+        assert_eq!(remap_state(&db, &mappings).unwrap(), 1);
+        let paths: Vec<String> = conn
+            .prepare("SELECT path FROM sync_state ORDER BY path")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .map(|path| path.unwrap())
+            .collect();
+        assert_eq!(paths, vec!["/Users/new/a.txt", "/srv/b.txt"]);
+
+        fs::remove_file(&db).unwrap();
+    }
+}
@@ -0,0 +1,642 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use crate::config::{NasConfig, SmbConfig, SyncProfile, COARSE_MTIME_SECS};
+use crate::error::SyncError;
+use crate::smb::SmbMount;
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::mpsc;
+use std::sync::{Mutex, OnceLock, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+#[derive(Debug, Clone, Copy)]
+struct Users {
+    count: usize,
+    /// Whether this process mounted it rather than finding it mounted
+    mounted_here: bool,
+}
+
+/// Filesystem types served over the network
+const NETWORK_FS: &[&str] = &["nfs", "nfs4", "cifs", "smb3", "smbfs", "fuse.sshfs", "fuse.rclone", "9p", "ceph", "glusterfs", "afs"];
+
+/// Filesystem types that round mtimes, as named in mountinfo and by `stat -f -c %T`
+const COARSE_MTIME_FS: &[&str] = &["vfat", "msdos", "exfat", "cifs", "smb2", "smb3", "smbfs"];
+
+/// One entry of /proc/self/mountinfo
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mount {
+    pub mount_point: PathBuf,
+    pub fs_type: String,
+    pub source: String,
+    /// Directory of the mounted filesystem shown here; not `/` for bind mounts
+    pub root: String,
+}
+
+impl Mount {
+    pub fn is_network(&self) -> bool {
+        NETWORK_FS.contains(&self.fs_type.as_str())
+    }
+
+    /// btrfs subvolume mounts also have a root other than `/`, so they do not count
+    pub fn is_bind(&self) -> bool {
+        self.root != "/" && self.fs_type != "btrfs"
+    }
+}
+
+/// Mounts of this system; empty where /proc is not available (macOS)
+pub fn mounts() -> Vec<Mount> {
+    match fs::read_to_string("/proc/self/mountinfo") {
+        Ok(content) => parse_mountinfo(&content),
+        Err(e) => {
+            debug!("Cannot read the mount table: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// `36 35 98:0 /mnt1 /mnt/parent rw,noatime master:1 - ext3 /dev/root rw`: root and
+/// mount point come before the optional fields, type and source after the ` - `
+fn parse_mountinfo(content: &str) -> Vec<Mount> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let (mount, fs) = line.split_once(" - ")?;
+            let mount: Vec<&str> = mount.split(' ').collect();
+            let mut fs = fs.split(' ');
+            Some(Mount {
+                root: unescape(mount.get(3)?),
+                mount_point: PathBuf::from(unescape(mount.get(4)?)),
+                fs_type: fs.next()?.to_string(),
+                source: unescape(fs.next()?),
+            })
+        })
+        .collect()
+}
+
+/// Spaces, tabs, newlines and backslashes are written as `\040`-style octal escapes
+fn unescape(field: &str) -> String {
+    let mut out = String::with_capacity(field.len());
+    let mut rest = field;
+    while let Some(pos) = rest.find('\\') {
+        out.push_str(&rest[..pos]);
+        match rest.get(pos + 1..pos + 4).and_then(|octal| u8::from_str_radix(octal, 8).ok()) {
+            Some(byte) => {
+                out.push(byte as char);
+                rest = &rest[pos + 4..];
+            }
+            None => {
+                out.push('\\');
+                rest = &rest[pos + 1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Profiles and runs of this process using each mount point, so a share several of
+/// them depend on is mounted once and unmounted after the last of them. Mounting and
+/// unmounting happen under its lock, so concurrent profiles never race on a share.
+#[derive(Debug, Default)]
+pub struct MountManager {
+    registry: Mutex<Registry>,
+}
+
+#[derive(Debug, Default)]
+struct Registry {
+    users: BTreeMap<PathBuf, Users>,
+    /// Since when mount points nobody uses have been idle
+    idle_since: BTreeMap<PathBuf, Instant>,
+}
+
+impl MountManager {
+    /// The manager shared by all profiles of this process
+    pub fn global() -> &'static MountManager {
+        static MANAGER: OnceLock<MountManager> = OnceLock::new();
+        MANAGER.get_or_init(MountManager::default)
+    }
+
+    /// Count a user of `mount_point`, first calling `mount` (true when it mounted, false
+    /// when it was already mounted) if nothing in this process uses it yet
+    pub fn acquire(&self, mount_point: &Path, mount: impl FnOnce() -> Result<bool>) -> Result<()> {
+        let mut registry = self.registry.lock().unwrap_or_else(PoisonError::into_inner);
+        let Registry { users, idle_since } = &mut *registry;
+        idle_since.remove(mount_point);
+        match users.get_mut(mount_point) {
+            Some(entry) => {
+                entry.count += 1;
+                debug!("{} is already in use by {} other(s)", mount_point.display(), entry.count - 1);
+            }
+            None => {
+                let mounted_here = mount()?;
+                users.insert(mount_point.to_path_buf(), Users { count: 1, mounted_here });
+            }
+        }
+        Ok(())
+    }
+
+    /// Drop a user of `mount_point`; the last one calls `unmount` with whether this
+    /// process mounted it, still under the lock so nobody mounts it again halfway
+    pub fn release(&self, mount_point: &Path, unmount: impl FnOnce(bool)) {
+        let mut registry = self.registry.lock().unwrap_or_else(PoisonError::into_inner);
+        let Registry { users, idle_since } = &mut *registry;
+        let Some(entry) = users.get_mut(mount_point) else {
+            return;
+        };
+        entry.count -= 1;
+        if entry.count > 0 {
+            debug!("Keeping {} mounted for {} other user(s)", mount_point.display(), entry.count);
+            return;
+        }
+        let mounted_here = entry.mounted_here;
+        users.remove(mount_point);
+        idle_since.insert(mount_point.to_path_buf(), Instant::now());
+        unmount(mounted_here);
+    }
+
+    /// Run `f` while no mount or unmount can happen
+    pub fn exclusive<T>(&self, f: impl FnOnce() -> T) -> T {
+        let _registry = self.registry.lock().unwrap_or_else(PoisonError::into_inner);
+        f()
+    }
+
+    /// Call `unmount` when nobody has used `mount_point` for `idle`; the first check of a
+    /// mount point not used yet starts its clock
+    pub fn unmount_if_idle(&self, mount_point: &Path, idle: Duration, unmount: impl FnOnce()) {
+        let mut registry = self.registry.lock().unwrap_or_else(PoisonError::into_inner);
+        if registry.users.contains_key(mount_point) {
+            return;
+        }
+        match registry.idle_since.get(mount_point) {
+            Some(since) if since.elapsed() >= idle => {
+                registry.idle_since.remove(mount_point);
+                unmount();
+            }
+            Some(_) => {}
+            None => {
+                registry.idle_since.insert(mount_point.to_path_buf(), Instant::now());
+            }
+        }
+    }
+}
+
+/// Why a network mount is stale (server rebooted, handle gone), found by reading `path`
+/// on it; reads of a stale CIFS or NFS mount can hang, so this gives up after `timeout`
+/// and leaves the stuck thread behind
+pub fn probe(path: &Path, timeout: Duration) -> Option<String> {
+    let (done, result) = mpsc::channel();
+    let target = path.to_path_buf();
+    thread::spawn(move || {
+        // A listing needs the server, a stat of the mount point may come from cache
+        let read = if target.is_dir() { fs::read_dir(&target).map(|_| ()) } else { fs::metadata(&target).map(|_| ()) };
+        let _ = done.send(read);
+    });
+    match result.recv_timeout(timeout) {
+        Ok(Ok(())) => None,
+        Ok(Err(e)) => Some(format!("reading {} failed: {}", path.display(), e)),
+        Err(_) => Some(format!("reading {} did not answer within {}s", path.display(), timeout.as_secs())),
+    }
+}
+
+/// Whether something is mounted at `path`
+pub fn is_mount_point(path: &Path) -> bool {
+    mounts().iter().any(|mount| mount.mount_point == path)
+}
+
+/// The `requires_mount` of a profile: an NFS/SMB (or any) /etc/fstab entry mounted with
+/// `mount <path>` unless it is already up, and unmounted when dropped by its last user
+/// if it was mounted here
+pub struct RequiredMount {
+    /// None once released
+    path: Option<PathBuf>,
+}
+
+impl RequiredMount {
+    /// mount(8) of a slow NFS server, and waiting for other profiles mounting under the
+    /// manager's lock, happen off the runtime's workers
+    pub async fn acquire(path: &str) -> Result<Self> {
+        let path = PathBuf::from(path);
+        let mount_point = path.clone();
+        tokio::task::spawn_blocking(move || MountManager::global().acquire(&mount_point, || mount_fstab(&mount_point)))
+            .await
+            .context("Mount task failed")??;
+        Ok(Self { path: Some(path) })
+    }
+
+    /// Give up this use and wait for the unmount, if it was the last
+    pub async fn release(mut self) {
+        if let Some(path) = self.path.take() {
+            if let Err(e) = tokio::task::spawn_blocking(move || release_fstab(&path)).await {
+                warn!("Unmount task failed: {}", e);
+            }
+        }
+    }
+}
+
+impl Drop for RequiredMount {
+    /// Dropped on an early return: the unmount runs in the background
+    fn drop(&mut self) {
+        let Some(path) = self.path.take() else {
+            return;
+        };
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => drop(runtime.spawn_blocking(move || release_fstab(&path))),
+            Err(_) => release_fstab(&path),
+        }
+    }
+}
+
+fn release_fstab(path: &Path) {
+    MountManager::global().release(path, |mounted_here| {
+        if mounted_here {
+            if let Err(e) = unmount_fstab(path) {
+                warn!("{:#}", e);
+            }
+        }
+    })
+}
+
+/// `mount <path>` of an /etc/fstab entry unless it is already up; true when this mounted it
+fn mount_fstab(path: &Path) -> Result<bool> {
+    if is_mount_point(path) {
+        debug!("{} is already mounted", path.display());
+        return Ok(false);
+    }
+    info!("Mounting {}", path.display());
+    let output = Command::new("mount").arg(path).output().map_err(|e| SyncError::from_spawn("mount", e))?;
+    if !output.status.success() || !is_mount_point(path) {
+        return Err(SyncError::Mount(format!(
+            "Could not mount {}: {}",
+            path.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+        .into());
+    }
+    Ok(true)
+}
+
+fn unmount_fstab(path: &Path) -> Result<()> {
+    info!("Unmounting {}", path.display());
+    let output = Command::new("umount").arg(path).output().map_err(|e| SyncError::from_spawn("umount", e))?;
+    if !output.status.success() {
+        return Err(SyncError::Mount(format!(
+            "Failed to unmount {}: {}",
+            path.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+/// A configured share `ugnassync mount` and `umount` act on
+#[derive(Debug)]
+pub enum MountTarget {
+    Smb(Box<SmbConfig>),
+    /// A requires_mount path, mounted through its /etc/fstab entry
+    Fstab(PathBuf),
+}
+
+impl MountTarget {
+    pub fn mount_point(&self) -> &Path {
+        match self {
+            MountTarget::Smb(smb) => Path::new(&smb.mount_point),
+            MountTarget::Fstab(path) => path,
+        }
+    }
+
+    /// Mount and leave mounted after this process exits
+    pub async fn mount(&self) -> Result<()> {
+        match self {
+            MountTarget::Smb(smb) => {
+                let mut config = smb.as_ref().clone();
+                config.auto_unmount = false;
+                SmbMount::new(config).mount().await
+            }
+            MountTarget::Fstab(path) => mount_fstab(path).map(|_| ()),
+        }
+    }
+
+    pub fn unmount(&self) -> Result<()> {
+        if !is_mount_point(self.mount_point()) {
+            info!("{} is not mounted", self.mount_point().display());
+            return Ok(());
+        }
+        match self {
+            MountTarget::Smb(smb) => SmbMount::new(smb.as_ref().clone()).unmount_share(),
+            MountTarget::Fstab(path) => unmount_fstab(path),
+        }
+    }
+}
+
+/// `smb` or the [nas.smb] mount point, a requires_mount path, or a profile name for
+/// all mounts that profile uses
+pub fn targets(nas: &NasConfig, profiles: &[SyncProfile], target: &str) -> Result<Vec<MountTarget>> {
+    let smb = nas.smb.as_ref();
+    if target == "smb" || smb.is_some_and(|smb| Path::new(&smb.mount_point) == Path::new(target)) {
+        let smb = smb.ok_or_else(|| SyncError::Config("No [nas.smb] share is configured".to_string()))?;
+        return Ok(vec![MountTarget::Smb(Box::new(smb.clone()))]);
+    }
+    if let Some(profile) = profiles.iter().find(|profile| profile.name == target) {
+        let mut found = Vec::new();
+        if let Some(smb) = smb.filter(|_| profile.use_smb_mount) {
+            found.push(MountTarget::Smb(Box::new(smb.clone())));
+        }
+        found.extend(profile.requires_mount.as_ref().map(|path| MountTarget::Fstab(PathBuf::from(path))));
+        if found.is_empty() {
+            return Err(SyncError::Config(format!("Profile '{}' uses no mount", target)).into());
+        }
+        return Ok(found);
+    }
+    if profiles.iter().any(|profile| profile.requires_mount.as_deref().is_some_and(|path| Path::new(path) == Path::new(target))) {
+        return Ok(vec![MountTarget::Fstab(PathBuf::from(target))]);
+    }
+    Err(SyncError::Config(format!(
+        "Unknown mount target '{}'; use smb, a profile name or a profile's requires_mount path",
+        target
+    ))
+    .into())
+}
+
+/// Mounts a group of profiles depends on, taken before the first of them syncs and kept
+/// until the last one is done, however the syncs in between mount and unmount
+#[derive(Default)]
+pub struct MountHold {
+    smb: Option<SmbMount>,
+    required: Vec<RequiredMount>,
+}
+
+/// Failures are only logged; the profile's own sync then reports them
+pub async fn hold(nas: &NasConfig, profiles: &[&SyncProfile]) -> MountHold {
+    let smb = match &nas.smb {
+        // With idle_unmount_minutes the share comes and goes between syncs instead
+        Some(smb) if smb.enabled && smb.idle_unmount_minutes.is_none() && profiles.iter().any(|p| p.use_smb_mount) => {
+            let mut mount = SmbMount::new(smb.clone());
+            match mount.mount().await {
+                Ok(()) => Some(mount),
+                Err(e) => {
+                    warn!("Could not mount {} ahead of the profiles using it: {:#}", smb.mount_point, e);
+                    None
+                }
+            }
+        }
+        _ => None,
+    };
+
+    let mut paths: Vec<&str> = profiles.iter().filter_map(|p| p.requires_mount.as_deref()).collect();
+    paths.sort_unstable();
+    paths.dedup();
+    let mut required = Vec::new();
+    for path in paths {
+        match RequiredMount::acquire(path).await {
+            Ok(mount) => required.push(mount),
+            Err(e) => warn!("Could not mount {} ahead of the profiles using it: {:#}", path, e),
+        }
+    }
+
+    MountHold { smb, required }
+}
+
+impl MountHold {
+    /// Unmounts what no other profile still uses; the SMB share only with auto_unmount
+    pub async fn release(mut self) {
+        if let Some(mut mount) = self.smb.take() {
+            if mount.should_auto_unmount() {
+                if let Err(e) = mount.unmount().await {
+                    warn!("Failed to unmount {}: {:#}", mount.mount_point(), e);
+                }
+            }
+        }
+        for required in self.required.drain(..) {
+            required.release().await;
+        }
+    }
+}
+
+/// Network and bind mounts inside the profile's local paths that a sync would descend
+/// into: those below `nas_mount_point` or not excluded, unless one_file_system is set
+pub fn crossed_mounts<'a>(profile: &SyncProfile, mounts: &'a [Mount], nas_mount_point: Option<&Path>) -> Vec<&'a Mount> {
+    if profile.one_file_system || profile.source_host.is_some() {
+        return Vec::new();
+    }
+    mounts
+        .iter()
+        .filter(|mount| mount.is_network() || mount.is_bind() || nas_mount_point == Some(mount.mount_point.as_path()))
+        .filter(|mount| {
+            profile.source_paths().iter().any(|source| {
+                mount
+                    .mount_point
+                    .strip_prefix(source)
+                    .is_ok_and(|relative| !relative.as_os_str().is_empty() && !profile.excludes(relative))
+            })
+        })
+        .collect()
+}
+
+/// The mount `path` is on: the deepest mount point above it
+pub fn mount_of<'a>(mounts: &'a [Mount], path: &Path) -> Option<&'a Mount> {
+    mounts
+        .iter()
+        .filter(|mount| path.starts_with(&mount.mount_point))
+        .max_by_key(|mount| mount.mount_point.components().count())
+}
+
+/// Tolerance mtime comparisons need on a filesystem of this type, if it rounds them
+pub fn mtime_tolerance(fs_type: &str) -> Option<u64> {
+    COARSE_MTIME_FS.contains(&fs_type).then_some(COARSE_MTIME_SECS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn users(manager: &MountManager, mount_point: &Path) -> usize {
+        manager.registry.lock().unwrap().users.get(mount_point).map_or(0, |entry| entry.count)
+    }
+
+    #[test]
+    fn test_crossed_mounts() {
+        let mounts = parse_mountinfo(
+            "22 1 8:2 / / rw,relatime shared:1 - ext4 /dev/sda2 rw\n\
+             40 22 8:3 / /home rw,relatime shared:2 - ext4 /dev/sda3 rw\n\
+             51 40 0:52 / /home/me/nas\\040share rw,relatime shared:30 - nfs4 nas:/volume1/share rw\n\
+             52 40 8:3 /me/Music /home/me/Music\\040copy rw,relatime shared:2 - ext4 /dev/sda3 rw\n\
+             53 40 0:40 /@snapshots /home/.snapshots rw,relatime shared:5 - btrfs /dev/sda4 rw\n\
+             54 40 0:60 / /home/me/cache rw,relatime shared:31 - fuse.sshfs me@host: rw\n",
+        );
+        assert_eq!(mounts.len(), 6);
+        assert_eq!(mounts[2].mount_point, PathBuf::from("/home/me/nas share"));
+        assert!(mounts[2].is_network() && mounts[3].is_bind() && !mounts[4].is_bind());
+
+        let mut profile: SyncProfile = toml::from_str(
+            r#"
+            name = "home"
+            local_path = "/home"
+            remote_path = "/volume1/backup/home"
+            sync_type = "backup"
+            enabled = true
+            exclude = ["cache"]
+            "#,
+        )
+        .unwrap();
+        let crossed: Vec<_> = crossed_mounts(&profile, &mounts, None).iter().map(|m| m.mount_point.clone()).collect();
+        assert_eq!(crossed, vec![PathBuf::from("/home/me/nas share"), PathBuf::from("/home/me/Music copy")]);
+
+        profile.one_file_system = true;
+        assert!(crossed_mounts(&profile, &mounts, None).is_empty());
+    }
+
+    #[test]
+    fn test_mtime_tolerance_of_target() {
+        let mounts = parse_mountinfo(
+            "22 1 8:2 / / rw,relatime shared:1 - ext4 /dev/sda2 rw\n\
+             60 22 8:17 / /media/usb rw,relatime shared:40 - exfat /dev/sdb1 rw\n",
+        );
+        let usb = mount_of(&mounts, Path::new("/media/usb/backup")).unwrap();
+        assert_eq!(mtime_tolerance(&usb.fs_type), Some(2));
+        let root = mount_of(&mounts, Path::new("/media/usbstick")).unwrap();
+        assert_eq!(mtime_tolerance(&root.fs_type), None);
+
+        let mut profile: SyncProfile = toml::from_str(
+            r#"
+            name = "usb"
+            local_path = "/home"
+            remote_path = "/media/usb/backup"
+            sync_type = "backup"
+            enabled = true
+            "#,
+        )
+        .unwrap();
+        assert_eq!(profile.mtime_tolerance(), 0);
+        profile.use_smb_mount = true;
+        assert_eq!(profile.mtime_tolerance(), 2);
+        profile.mtime_tolerance_secs = Some(0);
+        assert_eq!(profile.mtime_tolerance(), 0);
+    }
+
+    #[test]
+    fn test_shared_mount_is_mounted_once() {
+        let manager = MountManager::default();
+        let path = Path::new("/mnt/nas");
+        let (mut mounted, mut unmounted) = (0, Vec::new());
+        for _ in 0..3 {
+            manager
+                .acquire(path, || {
+                    mounted += 1;
+                    Ok(true)
+                })
+                .unwrap();
+        }
+        assert_eq!((mounted, users(&manager, path)), (1, 3));
+
+        manager.release(path, |here| unmounted.push(here));
+        manager.release(path, |here| unmounted.push(here));
+        assert!(unmounted.is_empty());
+        manager.release(path, |here| unmounted.push(here));
+        assert_eq!(unmounted, vec![true]);
+
+        // A failed mount leaves nobody counted
+        assert!(manager.acquire(path, || anyhow::bail!("no route to host")).is_err());
+        assert_eq!(users(&manager, path), 0);
+        manager.release(path, |_| panic!("not in use"));
+
+        // Idle unmounts wait for the last user and the idle period
+        let mut idle_unmounts = 0;
+        manager.acquire(path, || Ok(false)).unwrap();
+        manager.unmount_if_idle(path, Duration::ZERO, || idle_unmounts += 1);
+        manager.release(path, |_| {});
+        manager.unmount_if_idle(path, Duration::from_secs(3600), || idle_unmounts += 1);
+        assert_eq!(idle_unmounts, 0);
+        manager.unmount_if_idle(path, Duration::ZERO, || idle_unmounts += 1);
+        assert_eq!(idle_unmounts, 1);
+    }
+
+    #[test]
+    fn test_mount_targets() {
+        let nas: NasConfig = toml::from_str(
+            r#"
+            host = "nas"
+            port = 22
+            username = "admin"
+            [smb]
+            enabled = true
+            share_path = "//nas/backups"
+            mount_point = "/mnt/nas"
+            username = "admin"
+            "#,
+        )
+        .unwrap();
+        let profiles: Vec<SyncProfile> = [
+            r#"name = "photos"
+            local_path = "/mnt/nas/photos"
+            remote_path = "/volume1/photos"
+            sync_type = "mirror"
+            enabled = true
+            use_smb_mount = true
+            requires_mount = "/mnt/media""#,
+            r#"name = "docs"
+            local_path = "/home/me/docs"
+            remote_path = "/volume1/docs"
+            sync_type = "mirror"
+            enabled = true"#,
+        ]
+        .iter()
+        .map(|profile| toml::from_str(profile).unwrap())
+        .collect();
+
+        let points = |target: &str| -> Vec<PathBuf> {
+            targets(&nas, &profiles, target).unwrap().iter().map(|t| t.mount_point().to_path_buf()).collect()
+        };
+        assert_eq!(points("smb"), vec![PathBuf::from("/mnt/nas")]);
+        assert_eq!(points("/mnt/nas/"), vec![PathBuf::from("/mnt/nas")]);
+        assert_eq!(points("photos"), vec![PathBuf::from("/mnt/nas"), PathBuf::from("/mnt/media")]);
+        assert_eq!(points("/mnt/media"), vec![PathBuf::from("/mnt/media")]);
+        assert!(targets(&nas, &profiles, "docs").is_err());
+        assert!(targets(&nas, &profiles, "/mnt/other").is_err());
+    }
+
+    #[test]
+    fn test_probe() {
+        let dir = std::env::temp_dir();
+        assert_eq!(probe(&dir, Duration::from_secs(5)), None);
+        let missing = dir.join(format!("ugnassync-probe-{}", std::process::id()));
+        assert!(probe(&missing, Duration::from_secs(5)).is_some_and(|reason| reason.starts_with("reading ")));
+    }
+
+    #[test]
+    fn test_concurrent_profiles_mount_once() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        let manager = MountManager::default();
+        let path = Path::new("/mnt/nas");
+        let (mounts, unmounts) = (AtomicUsize::new(0), AtomicUsize::new(0));
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    manager
+                        .acquire(path, || {
+                            mounts.fetch_add(1, Ordering::SeqCst);
+                            std::thread::sleep(std::time::Duration::from_millis(20));
+                            Ok(true)
+                        })
+                        .unwrap();
+                    std::thread::sleep(std::time::Duration::from_millis(5));
+                    manager.release(path, |_| {
+                        unmounts.fetch_add(1, Ordering::SeqCst);
+                    });
+                });
+            }
+        });
+        // Late threads may find the share already unmounted and mount it again, but
+        // every mount is paired with exactly one unmount
+        assert_eq!(mounts.load(Ordering::SeqCst), unmounts.load(Ordering::SeqCst));
+        assert_eq!(users(&manager, path), 0);
+    }
+}
@@ -0,0 +1,300 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use crate::config::{SyncProfile, TargetFs, UnicodeForm};
+use crate::watch::WatchManager;
+use anyhow::Result;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+use unicode_normalization::UnicodeNormalization;
+
+/// Characters SMB/NTFS does not allow in file names
+const SMB_ILLEGAL_CHARS: &[char] = &['<', '>', ':', '"', '\\', '|', '?', '*'];
+
+/// Longest file name most NAS filesystems accept, in bytes
+const MAX_COMPONENT_BYTES: usize = 255;
+/// Linux PATH_MAX, in bytes
+const MAX_PATH_BYTES: usize = 4096;
+/// Legacy Windows MAX_PATH, in characters
+const LEGACY_SMB_PATH_CHARS: usize = 260;
+
+/// Device names Windows reserves regardless of extension
+const SMB_RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9", "LPT1",
+    "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+#[derive(Debug, PartialEq)]
+pub enum IssueKind {
+    /// Differs only in case from another name in the same directory
+    CaseCollision(String),
+    IllegalName,
+    NameTooLong,
+    PathTooLong,
+    NonUtf8,
+    /// Not in the profile's Unicode normalization form
+    NotNormalized,
+}
+
+/// A local file or directory whose name the target filesystem cannot store as-is
+#[derive(Debug)]
+pub struct NameIssue {
+    pub path: PathBuf,
+    pub kind: IssueKind,
+    /// Name to use on the NAS when the rename policy applies; None if renaming cannot help
+    pub new_name: Option<String>,
+}
+
+impl fmt::Display for NameIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            IssueKind::CaseCollision(other) => {
+                write!(f, "{} (case collision with {})", self.path.display(), other)
+            }
+            IssueKind::IllegalName => write!(f, "{} (illegal name on target)", self.path.display()),
+            IssueKind::NameTooLong => write!(f, "{} (name over {} bytes)", self.path.display(), MAX_COMPONENT_BYTES),
+            IssueKind::PathTooLong => write!(f, "{} (path too long on target)", self.path.display()),
+            IssueKind::NonUtf8 => write!(f, "{} (name is not valid UTF-8)", self.path.display()),
+            IssueKind::NotNormalized => write!(f, "{} (not Unicode-normalized)", self.path.display()),
+        }
+    }
+}
+
+/// Walk the profile's sources and report names the target filesystem would reject
+pub fn scan(profile: &SyncProfile) -> Result<Vec<NameIssue>> {
+    let mut issues = Vec::new();
+    if !profile.validates_names() {
+        return Ok(issues);
+    }
+
+    let excludes = profile.effective_excludes();
+    for root in profile.source_paths() {
+        scan_dir(Path::new(root), profile, &excludes, &mut issues)?;
+    }
+
+    Ok(issues)
+}
+
+fn scan_dir(dir: &Path, profile: &SyncProfile, excludes: &[String], issues: &mut Vec<NameIssue>) -> Result<()> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("Cannot read {}: {}", dir.display(), e);
+            return Ok(());
+        }
+    };
+
+    let target = profile.target_fs();
+    let smb = target != TargetFs::Posix;
+    let iconv = profile.effective_iconv();
+
+    let mut names: Vec<(String, PathBuf, bool)> = Vec::new();
+    // What cannot be read here is skipped; the transfer reports it
+    for entry in entries {
+        let Ok(entry) = entry.inspect_err(|e| warn!("Cannot read {}: {}", dir.display(), e)) else {
+            continue;
+        };
+        let path = entry.path();
+        if WatchManager::is_excluded(&path, excludes) {
+            continue;
+        }
+        let Ok(file_type) = entry.file_type().inspect_err(|e| warn!("Cannot read {}: {}", path.display(), e)) else {
+            continue;
+        };
+        let name = entry.file_name().to_string_lossy().to_string();
+        names.push((name, path, file_type.is_dir()));
+    }
+    names.sort();
+
+    // Names that fold to the same lowercase form; the first one keeps its name
+    let mut seen: HashMap<String, String> = HashMap::new();
+    for (name, path, is_dir) in names {
+        let raw_name = path.file_name().unwrap_or_default();
+        let remote_path = WatchManager::anchored_exclude(profile, &path)
+            .map(|anchored| format!("{}{}", profile.remote_path.trim_end_matches('/'), anchored))
+            .unwrap_or_default();
+
+        let normalized = iconv.is_none().then(|| normalize(&name, profile.unicode_normalization)).flatten();
+
+        let issue = if raw_name.to_str().is_none() && iconv.is_none() {
+            Some((IssueKind::NonUtf8, Some(transliterate(&name, smb, &profile.name_mapping))))
+        } else if smb && is_illegal(&name) {
+            Some((IssueKind::IllegalName, Some(map_name(&name, &profile.name_mapping))))
+        } else if raw_name.len() > MAX_COMPONENT_BYTES {
+            Some((IssueKind::NameTooLong, Some(truncate_name(&name))))
+        } else if remote_path.len() > MAX_PATH_BYTES
+            || (target == TargetFs::SmbLegacy && remote_path.chars().count() > LEGACY_SMB_PATH_CHARS)
+        {
+            Some((IssueKind::PathTooLong, None))
+        } else if normalized.as_ref().is_some_and(|normalized| normalized != &name) {
+            Some((IssueKind::NotNormalized, normalized))
+        } else if smb {
+            seen.get(&fold(&name))
+                .map(|first| (IssueKind::CaseCollision(first.clone()), Some(dedupe_name(&name, &seen))))
+        } else {
+            None
+        };
+
+        match issue {
+            Some((kind, new_name)) => {
+                if let Some(new_name) = &new_name {
+                    seen.insert(fold(new_name), new_name.clone());
+                }
+                issues.push(NameIssue { path, kind, new_name });
+            }
+            None => {
+                seen.insert(fold(&name), name);
+                if is_dir {
+                    scan_dir(&path, profile, excludes, issues)?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn is_illegal(name: &str) -> bool {
+    let stem = name.split('.').next().unwrap_or_default().to_uppercase();
+
+    name.chars().any(|c| SMB_ILLEGAL_CHARS.contains(&c) || c.is_control())
+        || name.ends_with('.')
+        || name.ends_with(' ')
+        || SMB_RESERVED_NAMES.contains(&stem.as_str())
+}
+
+/// Replace illegal characters using the profile's mapping (default `_`)
+fn map_name(name: &str, mapping: &BTreeMap<String, String>) -> String {
+    let mut mapped = String::new();
+    for c in name.chars() {
+        if SMB_ILLEGAL_CHARS.contains(&c) || c.is_control() {
+            mapped.push_str(mapping.get(&c.to_string()).map(String::as_str).unwrap_or("_"));
+        } else {
+            mapped.push(c);
+        }
+    }
+
+    let mut mapped = mapped.trim_end_matches(['.', ' ']).to_string();
+    let stem = mapped.split('.').next().unwrap_or_default().to_uppercase();
+    if mapped.is_empty() || SMB_RESERVED_NAMES.contains(&stem.as_str()) {
+        mapped.insert(0, '_');
+    }
+    mapped
+}
+
+/// Case- and normalization-insensitive form of a name, for collision checks
+fn fold(name: &str) -> String {
+    name.nfc().collect::<String>().to_lowercase()
+}
+
+fn normalize(name: &str, form: Option<UnicodeForm>) -> Option<String> {
+    match form? {
+        UnicodeForm::Nfc => Some(name.nfc().collect()),
+        UnicodeForm::Nfd => Some(name.nfd().collect()),
+    }
+}
+
+/// Replace undecodable bytes (shown as U+FFFD) with `_`
+fn transliterate(lossy_name: &str, smb: bool, mapping: &BTreeMap<String, String>) -> String {
+    let name = lossy_name.replace('\u{FFFD}', "_");
+    if smb {
+        map_name(&name, mapping)
+    } else {
+        name
+    }
+}
+
+/// Shorten a name to the component limit, keeping its extension
+fn truncate_name(name: &str) -> String {
+    let (stem, ext) = match name.rfind('.') {
+        Some(i) if i > 0 && name.len() - i <= 16 => (&name[..i], &name[i..]),
+        _ => (name, ""),
+    };
+
+    let mut end = MAX_COMPONENT_BYTES - ext.len();
+    while !stem.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}{}", &stem[..end], ext)
+}
+
+/// `File.TXT` -> `File (2).TXT`, skipping numbers already taken
+fn dedupe_name(name: &str, seen: &HashMap<String, String>) -> String {
+    let (stem, ext) = match name.rfind('.') {
+        Some(i) if i > 0 => (&name[..i], &name[i..]),
+        _ => (name, ""),
+    };
+
+    (2..)
+        .map(|n| format!("{} ({}){}", stem, n, ext))
+        .find(|candidate| !seen.contains_key(&fold(candidate)))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_illegal_names_are_mapped() {
+        let mapping = BTreeMap::from([(":".to_string(), "-".to_string())]);
+
+        assert!(is_illegal("notes: draft?.txt"));
+        assert!(is_illegal("aux.log"));
+        assert!(is_illegal("trailing."));
+        assert!(!is_illegal("report.pdf"));
+
+        assert_eq!(map_name("notes: draft?.txt", &mapping), "notes- draft_.txt");
+        assert_eq!(map_name("aux.log", &mapping), "_aux.log");
+    }
+
+    #[test]
+    fn test_normalization() {
+        let nfd = "Cafe\u{301}.txt";
+
+        assert_eq!(normalize(nfd, Some(UnicodeForm::Nfc)).unwrap(), "Caf\u{e9}.txt");
+        assert_eq!(fold(nfd), fold("CAF\u{c9}.TXT"));
+    }
+
+    #[test]
+    fn test_truncate_name_keeps_extension() {
+        let name = format!("{}.jpg", "é".repeat(200));
+        let truncated = truncate_name(&name);
+
+        assert!(truncated.len() <= MAX_COMPONENT_BYTES);
+        assert!(truncated.ends_with("é.jpg"));
+    }
+
+    #[test]
+    fn test_case_collision_detected() {
+        let dir = std::env::temp_dir().join(format!("ugnassync-names-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("file.txt"), "a").unwrap();
+        fs::write(dir.join("File.TXT"), "b").unwrap();
+
+        let profile: SyncProfile = toml::from_str(&format!(
+            r#"
+            name = "test"
+            local_path = "{}"
+            remote_path = "/volume1/test"
+            sync_type = "one-way"
+            enabled = true
+            target_fs = "smb"
+            "#,
+            dir.display()
+        ))
+        .unwrap();
+
+        let issues = scan(&profile).unwrap();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].kind, IssueKind::CaseCollision("File.TXT".to_string()));
+        assert_eq!(issues[0].new_name.as_deref(), Some("file (2).txt"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
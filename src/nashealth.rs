@@ -0,0 +1,195 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use crate::config::{SyncProfile, SyncType};
+use crate::error::SyncError;
+use crate::remote::RemoteShell;
+use crate::vendor::Vendor;
+use anyhow::{Context, Result};
+use tracing::{debug, warn};
+
+/// Prints sections headed `== <name>`; smartctl needs root on most systems, so it runs
+/// through `sudo -n` when that is allowed
+const PROBE_SCRIPT: &str = r#"PATH="$PATH:/sbin:/usr/sbin:/usr/local/sbin:/opt/bin"
+if [ -r /proc/mdstat ]; then echo "== mdstat"; cat /proc/mdstat; fi
+if command -v zpool >/dev/null 2>&1; then echo "== zpool"; zpool status -x 2>&1; fi
+if command -v smartctl >/dev/null 2>&1; then
+  if sudo -n true 2>/dev/null; then smart="sudo -n smartctl"; else smart=smartctl; fi
+  for disk in /dev/sd? /dev/sata? /dev/nvme?n1; do
+    [ -e "$disk" ] || continue
+    echo "== smart $disk"; $smart -H "$disk" 2>&1
+  done
+fi
+true"#;
+
+/// raid1 arrays with more slots than this are system partitions mirrored across every
+/// bay (Synology md0/md1, QNAP md9/md13) and show empty bays as missing members
+const SYSTEM_ARRAY_SLOTS: usize = 4;
+
+/// Sync types that delete data whose only other copy may then be the degraded array
+pub fn is_destructive(profile: &SyncProfile) -> bool {
+    !profile.read_only && matches!(profile.sync_type, SyncType::Mirror | SyncType::Archive)
+}
+
+/// Refuse a destructive sync while the NAS storage is degraded, rebuilding or has a
+/// failing disk, as reported by the vendor API and over SSH
+pub fn gate(shell: &RemoteShell, vendor: Option<&dyn Vendor>, profile: &SyncProfile) -> Result<()> {
+    let mut problems = Vec::new();
+    if let Some(vendor) = vendor {
+        problems.extend(vendor.health(profile).with_context(|| format!("Failed to read NAS health from {}", vendor.name()))?);
+    }
+    match shell.run(PROBE_SCRIPT) {
+        Ok(output) => problems.extend(parse_probe(&output)),
+        // Without the SSH checks the vendor API may still have answered
+        Err(e) if vendor.is_some() => warn!("Could not check disk health over SSH: {:#}", e),
+        Err(e) => return Err(e.context("Failed to check NAS health")),
+    }
+    problems.dedup();
+    if problems.is_empty() {
+        debug!("NAS health gate passed for {}", profile.name);
+        return Ok(());
+    }
+    Err(SyncError::Unhealthy(format!(
+        "Refusing to run {} sync of {} while the NAS storage is unhealthy: {}; \
+         run with --ignore-health to sync anyway",
+        sync_type_name(&profile.sync_type),
+        profile.name,
+        problems.join("; ")
+    ))
+    .into())
+}
+
+fn sync_type_name(sync_type: &SyncType) -> &'static str {
+    match sync_type {
+        SyncType::Archive => "an archive",
+        _ => "a mirror",
+    }
+}
+
+/// Problems in the `== <name>` sections printed by the probe script
+fn parse_probe(output: &str) -> Vec<String> {
+    let mut sections: Vec<(&str, String)> = Vec::new();
+    for line in output.lines() {
+        match (line.strip_prefix("== "), sections.last_mut()) {
+            (Some(name), _) => sections.push((name.trim(), String::new())),
+            (None, Some((_, body))) => {
+                body.push_str(line);
+                body.push('\n');
+            }
+            (None, None) => {}
+        }
+    }
+    let mut problems = Vec::new();
+    for (name, body) in &sections {
+        match *name {
+            "mdstat" => problems.extend(mdstat_problems(body)),
+            "zpool" => problems.extend(zpool_problems(body)),
+            smart => {
+                let disk = smart.strip_prefix("smart ").unwrap_or(smart);
+                if body.lines().any(|line| line.contains("self-assessment test result: FAILED")) {
+                    problems.push(format!("Disk {} on the NAS fails its SMART health check", disk));
+                }
+            }
+        }
+    }
+    problems
+}
+
+/// Arrays in /proc/mdstat missing a member or resyncing; each array starts with
+/// `mdN : active raidL ...` followed by indented detail lines
+fn mdstat_problems(mdstat: &str) -> Vec<String> {
+    let mut problems = Vec::new();
+    let mut lines = mdstat.lines().peekable();
+    while let Some(line) = lines.next() {
+        let Some((array, description)) = line.split_once(" : ").filter(|(array, _)| array.starts_with("md")) else {
+            continue;
+        };
+        let mut details = Vec::new();
+        while let Some(detail) = lines.next_if(|next| next.starts_with(char::is_whitespace) && !next.trim().is_empty()) {
+            details.push(detail.trim());
+        }
+        let raid1 = description.split_whitespace().any(|word| word == "raid1");
+        // `[4/3] [UUU_]`: slots in the array and which of them are up
+        let members = details.iter().find_map(|detail| {
+            let status = detail.rsplit_once(" [")?.1.strip_suffix(']')?;
+            status.chars().all(|c| c == 'U' || c == '_').then_some(status)
+        });
+        if let Some(members) = members {
+            let system = raid1 && members.len() > SYSTEM_ARRAY_SLOTS;
+            if members.contains('_') && !system {
+                problems.push(format!("RAID array {} on the NAS is degraded [{}]", array.trim(), members));
+            }
+        }
+        if description.contains("(F)") {
+            problems.push(format!("RAID array {} on the NAS has a failed disk", array.trim()));
+        }
+        for action in ["recovery", "resync", "reshape"] {
+            if details.iter().any(|detail| detail.contains(&format!("{} =", action))) {
+                problems.push(format!("RAID array {} on the NAS is in {}", array.trim(), action));
+            }
+        }
+    }
+    problems
+}
+
+/// `zpool status -x` lists only pools with problems, under `pool:` with `state:` and `scan:`
+fn zpool_problems(status: &str) -> Vec<String> {
+    let mut problems = Vec::new();
+    let mut pool = None;
+    for line in status.lines().map(str::trim) {
+        if let Some(name) = line.strip_prefix("pool:") {
+            pool = Some(name.trim());
+        } else if let (Some(name), Some(state)) = (pool, line.strip_prefix("state:")) {
+            if state.trim() != "ONLINE" {
+                problems.push(format!("ZFS pool {} on the NAS is {}", name, state.trim()));
+            }
+        } else if let (Some(name), Some(scan)) = (pool, line.strip_prefix("scan:")) {
+            if scan.contains("resilver in progress") {
+                problems.push(format!("ZFS pool {} on the NAS is resilvering", name));
+            }
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_probe_output() {
+        let output = "== mdstat\n\
+            Personalities : [raid1] [raid6] [raid5] [raid4]\n\
+            md2 : active raid5 sda3[0] sdc3[2] sdb3[1]\n      \
+            7804374912 blocks super 1.2 level 5, 64k chunk, algorithm 2 [4/3] [UUU_]\n      \
+            [==>..................]  recovery = 12.6% (328401152/2601458304) finish=220.1min\n\
+            \n\
+            md0 : active raid1 sda1[0] sdb1[1] sdc1[2]\n      \
+            2490176 blocks [16/3] [UUU_____________]\n\
+            \n\
+            unused devices: <none>\n\
+            == zpool\n\
+            all pools are healthy\n\
+            == smart /dev/sda\n\
+            SMART overall-health self-assessment test result: PASSED\n\
+            == smart /dev/sdb\n\
+            SMART overall-health self-assessment test result: FAILED!\n";
+        assert_eq!(
+            parse_probe(output),
+            vec![
+                "RAID array md2 on the NAS is degraded [UUU_]",
+                "RAID array md2 on the NAS is in recovery",
+                "Disk /dev/sdb on the NAS fails its SMART health check",
+            ]
+        );
+
+        let zpool = "  pool: tank\n state: DEGRADED\n  scan: resilver in progress since Sun Mar  9 02:00:01 2025\n";
+        assert_eq!(
+            zpool_problems(zpool),
+            vec!["ZFS pool tank on the NAS is DEGRADED", "ZFS pool tank on the NAS is resilvering"]
+        );
+        assert!(parse_probe("== mdstat\nmd1 : active raid1 sda2[0] sdb2[1]\n      2097088 blocks [2/2] [UU]\n").is_empty());
+    }
+}
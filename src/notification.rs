@@ -0,0 +1,340 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use crate::config::{NasConfig, NotificationConfig};
+use crate::conflict::{AlertState, ConflictResolver};
+use crate::dsm;
+use crate::remote::shell_quote;
+use chrono::{DateTime, Duration, Local};
+use serde::Serialize;
+use serde_json::json;
+use std::path::PathBuf;
+use std::process::Command;
+use tracing::{debug, warn};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Info,
+    Warning,
+    Critical,
+}
+
+impl Severity {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Critical => "critical",
+        }
+    }
+
+    fn urgency(&self) -> &'static str {
+        match self {
+            Severity::Info => "low",
+            Severity::Warning => "normal",
+            Severity::Critical => "critical",
+        }
+    }
+}
+
+/// A command that acts on what a notification reports
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Action {
+    pub label: String,
+    pub command: String,
+}
+
+/// Files conflicting in one notification get resolution commands; the rest are counted
+const MAX_CONFLICT_ACTIONS: usize = 5;
+
+/// Best-effort delivery of notifications to the desktop and/or a webhook
+#[derive(Clone)]
+pub struct Notifier {
+    config: NotificationConfig,
+    /// Sync run the notifications are about, sent to webhooks as `run_id`
+    run_id: Option<String>,
+    /// NAS that shows warnings in DSM ([nas.dsm] alerts)
+    dsm_alerts: Option<NasConfig>,
+}
+
+impl Notifier {
+    pub fn new(config: Option<NotificationConfig>) -> Self {
+        Self {
+            config: config.unwrap_or_default(),
+            run_id: None,
+            dsm_alerts: None,
+        }
+    }
+
+    /// Also raise warnings on the NAS itself when [nas.dsm] alerts is set
+    pub fn with_dsm_alerts(mut self, nas: &NasConfig) -> Self {
+        self.dsm_alerts = nas.dsm.as_ref().filter(|dsm| dsm.alerts).map(|_| nas.clone());
+        self
+    }
+
+    /// A notifier tagging its notifications with the ID of a sync run
+    pub fn for_run(&self, run_id: Option<String>) -> Self {
+        Self {
+            config: self.config.clone(),
+            run_id,
+            dsm_alerts: self.dsm_alerts.clone(),
+        }
+    }
+
+    /// Send a notification; delivery failures are logged but never fail the caller
+    pub fn send(&self, severity: Severity, title: &str, message: &str) {
+        self.send_with_actions(severity, title, message, &[]);
+    }
+
+    /// Send a notification listing commands to act on it; webhooks get them as `actions`
+    pub fn send_with_actions(&self, severity: Severity, title: &str, message: &str, actions: &[Action]) {
+        if self.config.desktop {
+            let mut body = message.to_string();
+            for action in actions {
+                body.push_str(&format!("\n{}: {}", action.label, action.command));
+            }
+            self.send_desktop(severity, title, &body);
+        }
+
+        if let Some(url) = &self.config.webhook_url {
+            self.send_webhook(url, severity, title, message, actions);
+        }
+
+        if let Some(nas) = self.dsm_alerts.as_ref().filter(|_| severity != Severity::Info) {
+            if let Err(e) = dsm::alert(nas, title, message, severity == Severity::Critical) {
+                warn!("DSM notification failed: {:#}", e);
+            }
+        }
+    }
+
+    /// Report a failed sync of a profile as the notification policy says: once its
+    /// failure streak reaches `failure_threshold` or `max_hours_without_success`, or
+    /// every time without either. Critical failures are always reported.
+    pub fn send_failure(&self, profile: &str, severity: Severity, title: &str, message: &str) {
+        if !self.is_enabled() {
+            return;
+        }
+        let now = Local::now();
+        let critical = severity == Severity::Critical;
+        let notify = self
+            .update_alert_state(profile, |state| failure_alert(&self.config, state, now, critical))
+            .unwrap_or(true);
+        if notify {
+            self.send(severity, title, message);
+        } else {
+            debug!("Not reporting failure of profile {} yet (notification policy)", profile);
+        }
+    }
+
+    /// Report that a profile syncs again after a reported failure streak
+    pub fn send_recovery(&self, profile: &str) {
+        if !self.is_enabled() {
+            return;
+        }
+        let recovered = self.update_alert_state(profile, |state| recovery(state, Local::now()));
+        if let (Some(Some(failures)), true) = (recovered, self.config.notify_recovery) {
+            self.send(
+                Severity::Info,
+                &format!("Profile {} recovered", profile),
+                &format!("Synced successfully again after {} failed run(s)", failures),
+            );
+        }
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.config.desktop || self.config.webhook_url.is_some() || self.dsm_alerts.is_some()
+    }
+
+    /// Apply `update` to a profile's persisted failure streak; None if the state database is unavailable
+    fn update_alert_state<T>(&self, profile: &str, update: impl FnOnce(&mut AlertState) -> T) -> Option<T> {
+        let result = ConflictResolver::new().and_then(|resolver| {
+            let mut state = resolver.alert_state(profile)?;
+            let value = update(&mut state);
+            resolver.set_alert_state(profile, &state)?;
+            Ok(value)
+        });
+        match result {
+            Ok(value) => Some(value),
+            Err(e) => {
+                warn!("Failed to track failures of profile {}: {:#}", profile, e);
+                None
+            }
+        }
+    }
+
+    /// Report files a two-way run left alone because they changed on both sides,
+    /// with the commands that resolve each of them
+    pub fn send_conflicts(&self, profile: &str, local_paths: &[PathBuf]) {
+        let actions = conflict_actions(profile, local_paths);
+        let mut message = local_paths
+            .iter()
+            .take(MAX_CONFLICT_ACTIONS)
+            .map(|path| path.display().to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        if local_paths.len() > MAX_CONFLICT_ACTIONS {
+            message.push_str(&format!("\n... and {} more", local_paths.len() - MAX_CONFLICT_ACTIONS));
+        }
+        self.send_with_actions(
+            Severity::Warning,
+            &format!("{} conflict(s) in profile {}", local_paths.len(), profile),
+            &message,
+            &actions,
+        );
+    }
+
+    fn send_desktop(&self, severity: Severity, title: &str, message: &str) {
+        let result = Command::new("notify-send")
+            .arg("-u")
+            .arg(severity.urgency())
+            .arg("-a")
+            .arg("UGNasSync")
+            .arg(title)
+            .arg(message)
+            .output();
+
+        match result {
+            Ok(output) if output.status.success() => debug!("Desktop notification sent: {}", title),
+            Ok(output) => warn!(
+                "Desktop notification failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            ),
+            Err(e) => warn!("Failed to execute notify-send: {}", e),
+        }
+    }
+
+    fn send_webhook(&self, url: &str, severity: Severity, title: &str, message: &str, actions: &[Action]) {
+        let mut payload = json!({
+            "source": "ugnassync",
+            "severity": severity.as_str(),
+            "title": title,
+            "message": message,
+            "timestamp": Local::now().to_rfc3339(),
+        });
+        if !actions.is_empty() {
+            payload["actions"] = json!(actions);
+        }
+        if let Some(run_id) = &self.run_id {
+            payload["run_id"] = json!(run_id);
+        }
+
+        let result = Command::new("curl")
+            .arg("-fsS")
+            .arg("-m")
+            .arg("10")
+            .arg("-H")
+            .arg("Content-Type: application/json")
+            .arg("-d")
+            .arg(payload.to_string())
+            .arg(url)
+            .output();
+
+        match result {
+            Ok(output) if output.status.success() => debug!("Webhook notification sent: {}", title),
+            Ok(output) => warn!(
+                "Webhook notification failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            ),
+            Err(e) => warn!("Failed to execute curl for webhook: {}", e),
+        }
+    }
+}
+
+/// Count a failure and decide whether it is reported
+fn failure_alert(config: &NotificationConfig, state: &mut AlertState, now: DateTime<Local>, critical: bool) -> bool {
+    state.failures += 1;
+    let streak_start = *state.first_failure.get_or_insert(now);
+    let policy = config.failure_threshold.is_some() || config.max_hours_without_success.is_some();
+    if state.alerted {
+        // Without a policy every failure is reported, as before
+        return !policy || critical;
+    }
+
+    let due = !policy
+        || config.failure_threshold.is_some_and(|threshold| state.failures >= threshold)
+        || config.max_hours_without_success.is_some_and(|hours| {
+            now - state.last_success.unwrap_or(streak_start) >= Duration::hours(hours as i64)
+        });
+    state.alerted = due || critical;
+    state.alerted
+}
+
+/// End a failure streak; the number of failures if it had been reported
+fn recovery(state: &mut AlertState, now: DateTime<Local>) -> Option<u32> {
+    let reported = state.alerted.then_some(state.failures);
+    *state = AlertState {
+        last_success: Some(now),
+        ..Default::default()
+    };
+    reported
+}
+
+/// `diff` and `conflicts resolve` commands for the first conflicting files
+fn conflict_actions(profile: &str, local_paths: &[PathBuf]) -> Vec<Action> {
+    let base = format!("ugnassync --profile {}", shell_quote(profile));
+    local_paths
+        .iter()
+        .take(MAX_CONFLICT_ACTIONS)
+        .flat_map(|local_path| {
+            let path = shell_quote(&local_path.to_string_lossy());
+            [
+                ("Compare", format!("{} diff {}", base, path)),
+                ("Keep local", format!("{} conflicts resolve {} --keep local", base, path)),
+                ("Keep NAS", format!("{} conflicts resolve {} --keep nas", base, path)),
+                ("Keep both", format!("{} conflicts resolve {} --keep both", base, path)),
+            ]
+            .map(|(label, command)| Action {
+                label: format!("{} {}", label, local_path.display()),
+                command,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_severity_urgency() {
+        assert_eq!(Severity::Critical.urgency(), "critical");
+        assert_eq!(Severity::Warning.as_str(), "warning");
+    }
+
+    #[test]
+    fn test_conflict_actions() {
+        let paths: Vec<PathBuf> = (0..7).map(|i| PathBuf::from(format!("/home/me/docs/file {}.txt", i))).collect();
+        let actions = conflict_actions("docs", &paths);
+        assert_eq!(actions.len(), MAX_CONFLICT_ACTIONS * 4);
+        assert_eq!(
+            actions[1].command,
+            "ugnassync --profile 'docs' conflicts resolve '/home/me/docs/file 0.txt' --keep local"
+        );
+        assert_eq!(actions[1].label, "Keep local /home/me/docs/file 0.txt");
+    }
+
+    #[test]
+    fn test_failure_threshold_and_recovery() {
+        let config = NotificationConfig {
+            failure_threshold: Some(3),
+            max_hours_without_success: Some(24),
+            ..Default::default()
+        };
+        let start = Local::now();
+        let mut state = AlertState::default();
+
+        let reported: Vec<bool> = (0..4).map(|i| failure_alert(&config, &mut state, start, i == 3)).collect();
+        assert_eq!(reported, vec![false, false, true, true]);
+        assert_eq!(recovery(&mut state, start), Some(4));
+        assert_eq!(recovery(&mut state, start), None);
+
+        // A single failure a day after the last success is reported right away
+        assert!(failure_alert(&config, &mut state, start + Duration::hours(25), false));
+
+        let mut every_time = AlertState::default();
+        let default = NotificationConfig::default();
+        assert!((0..3).all(|_| failure_alert(&default, &mut every_time, start, false)));
+    }
+}
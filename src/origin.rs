@@ -0,0 +1,158 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use crate::config::SyncProfile;
+use crate::device::Device;
+use crate::remote::shell_quote;
+use crate::versions::Side;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+/// File in a two-way profile's remote path recording which client last changed each file
+pub const LEDGER_FILE: &str = ".ugnassync-origins.json";
+
+/// Who wrote the NAS copy of a file, with which of their changes
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Origin {
+    /// Device ID of the client
+    pub client: String,
+    /// Its device name at the time
+    #[serde(default)]
+    pub name: String,
+    /// Change sequence number of the client's run that pushed it
+    pub seq: u64,
+    /// SHA-256 of what was pushed, telling whether the NAS copy is still that one
+    pub hash: String,
+}
+
+/// Origins shared by all clients two-way syncing the same folder
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Ledger {
+    /// Last change sequence number of each client, by device ID
+    #[serde(default)]
+    clients: BTreeMap<String, u64>,
+    #[serde(default)]
+    files: BTreeMap<String, Origin>,
+}
+
+impl Ledger {
+    /// The profile's ledger on the NAS; empty before the first two-way run records one
+    pub fn read(side: &Side, profile: &SyncProfile) -> Result<Self> {
+        let path = ledger_path(profile);
+        let content = match side {
+            Side::Ssh(shell) => shell.run(&format!("cat {} 2>/dev/null; true", shell_quote(&path)))?,
+            Side::Mounted => match fs::read_to_string(&path) {
+                Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+                read => read.with_context(|| format!("Failed to read {}", path))?,
+            },
+        };
+        if content.trim().is_empty() {
+            return Ok(Self::default());
+        }
+        serde_json::from_str(&content).with_context(|| format!("Invalid origin ledger {}", path))
+    }
+
+    /// Replace the ledger on the NAS, through a temporary file so readers never see half of it
+    fn write(&self, side: &Side, profile: &SyncProfile) -> Result<()> {
+        let path = ledger_path(profile);
+        let content = serde_json::to_string_pretty(self)?;
+        match side {
+            Side::Ssh(shell) => {
+                let quoted = shell_quote(&path);
+                shell.run_with_input(&format!("cat > {quoted}.tmp && mv {quoted}.tmp {quoted}"), &content)?;
+            }
+            Side::Mounted => {
+                let tmp = format!("{}.tmp", path);
+                fs::write(&tmp, content).with_context(|| format!("Failed to write {}", tmp))?;
+                fs::rename(&tmp, &path).with_context(|| format!("Failed to replace {}", path))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Replaced files (with their NAS hash) whose NAS copy another client pushed and this
+    /// one has not changed since its last sync: pushing the local copy would revert that
+    /// change, and the other client would push it again on its next run
+    pub fn propagated(
+        &self,
+        me: &str,
+        remote_hashes: &HashMap<String, String>,
+        changed_here: impl Fn(&str) -> bool,
+    ) -> Vec<(&str, &Origin)> {
+        self.files
+            .iter()
+            .filter(|(path, origin)| origin.client != me && remote_hashes.get(*path) == Some(&origin.hash))
+            .filter(|(path, _)| !changed_here(path))
+            .map(|(path, origin)| (path.as_str(), origin))
+            .collect()
+    }
+
+    /// Record the files (with their hash) this client pushed as its next change
+    pub fn record(&mut self, me: &Device, pushed: &[(String, String)]) -> u64 {
+        let seq = self.clients.get(&me.id).copied().unwrap_or(0) + 1;
+        for (path, hash) in pushed {
+            let origin = Origin {
+                client: me.id.clone(),
+                name: me.name.clone(),
+                seq,
+                hash: hash.clone(),
+            };
+            self.files.insert(path.clone(), origin);
+        }
+        self.clients.insert(me.id.clone(), seq);
+        seq
+    }
+}
+
+/// Add a run's pushed files to the ledger, reading it again first so entries other
+/// clients wrote during the run are kept
+pub fn update(side: &Side, profile: &SyncProfile, me: &Device, pushed: &[(String, String)]) -> Result<u64> {
+    let mut ledger = Ledger::read(side, profile)?;
+    let seq = ledger.record(me, pushed);
+    ledger.write(side, profile)?;
+    Ok(seq)
+}
+
+fn ledger_path(profile: &SyncProfile) -> String {
+    Path::new(&profile.remote_path).join(LEDGER_FILE).to_string_lossy().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_propagated_changes_are_not_pushed_back() {
+        let device = |id: &str| Device {
+            id: id.to_string(),
+            name: id.split('-').next().unwrap().to_string(),
+        };
+        let (laptop, desktop) = (device("laptop-1a2b3c4d"), device("desktop-5e6f7a8b"));
+        let mut ledger = Ledger::default();
+        assert_eq!(ledger.record(&laptop, &[("docs/a.txt".to_string(), "h2".to_string())]), 1);
+        assert_eq!(ledger.record(&desktop, &[("docs/b.txt".to_string(), "h3".to_string())]), 1);
+        assert_eq!(ledger.record(&laptop, &[("docs/c.txt".to_string(), "h4".to_string())]), 2);
+
+        // The desktop still holds the older a.txt it synced last time
+        let on_nas: HashMap<String, String> =
+            [("docs/a.txt", "h2"), ("docs/b.txt", "h3"), ("docs/c.txt", "h9")].map(|(p, h)| (p.to_string(), h.to_string())).into();
+        let propagated = ledger.propagated("desktop-5e6f7a8b", &on_nas, |_| false);
+        let paths: Vec<(&str, &str, u64)> =
+            propagated.iter().map(|(path, origin)| (*path, origin.name.as_str(), origin.seq)).collect();
+        // b.txt is the desktop's own change; c.txt was changed on the NAS since the laptop pushed it
+        assert_eq!(paths, vec![("docs/a.txt", "laptop", 1)]);
+
+        // A local edit of the file is a conflict for the conflict scan instead
+        assert!(ledger.propagated("desktop-5e6f7a8b", &on_nas, |path| path == "docs/a.txt").is_empty());
+
+        let json = serde_json::to_string(&ledger).unwrap();
+        let read: Ledger = serde_json::from_str(&json).unwrap();
+        assert_eq!(read.files["docs/c.txt"].seq, 2);
+    }
+}
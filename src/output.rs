@@ -0,0 +1,169 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use crate::report::RunReport;
+use crate::sync::{RunStatus, SyncStats};
+use serde_json::json;
+use std::io::IsTerminal;
+use std::sync::OnceLock;
+
+static OUTPUT: OnceLock<Output> = OnceLock::new();
+
+/// How results of a one-time run are printed: for people, or with `--quiet` as a
+/// single JSON line for scripts while the console log shows errors only
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Output {
+    pub quiet: bool,
+    pub color: bool,
+}
+
+impl Output {
+    /// Colors only on a terminal, and never with `--no-color` or `NO_COLOR` set
+    pub fn new(quiet: bool, no_color: bool) -> Self {
+        let color = !no_color && std::env::var_os("NO_COLOR").is_none() && std::io::stdout().is_terminal();
+        Self { quiet, color }
+    }
+}
+
+pub fn init(output: Output) {
+    let _ = OUTPUT.set(output);
+}
+
+pub fn get() -> Output {
+    OUTPUT.get().copied().unwrap_or(Output {
+        quiet: false,
+        color: false,
+    })
+}
+
+/// Result of one profile: the full summary when it is the only one, otherwise just
+/// what does not fit in the table printed at the end
+pub fn profile_result(name: &str, stats: &SyncStats, single_profile: bool, dry_run: bool, timings: bool) {
+    if get().quiet {
+        return;
+    }
+
+    if single_profile {
+        println!("\nSync Summary:");
+        println!("Profile: {}", name);
+        println!("Files transferred: {}", stats.files_transferred);
+        println!(
+            "Bytes transferred: {:.2} MB",
+            stats.bytes_transferred as f64 / (1024.0 * 1024.0)
+        );
+    } else if has_details(stats, dry_run, timings) {
+        println!("\n{}:", name);
+    }
+
+    if dry_run {
+        if !stats.deleted_files.is_empty() {
+            println!("Would delete: {} file(s)", stats.deleted_files.len());
+            for file in &stats.deleted_files {
+                println!("  - {}", file);
+            }
+        }
+        if !stats.planned_conflicts.is_empty() {
+            println!("Would conflict: {} file(s)", stats.planned_conflicts.len());
+            for conflict in &stats.planned_conflicts {
+                println!(
+                    "  - {} -> {:?}{}",
+                    conflict.path,
+                    conflict.strategy,
+                    if conflict.confirmed { "" } else { " (possible, remote not inspected)" }
+                );
+            }
+        }
+    }
+
+    if stats.conflicts_detected > 0 && single_profile {
+        println!("Conflicts detected: {}", stats.conflicts_detected);
+        println!("  - Skipped: {}", stats.conflicts_skipped);
+        println!("  - Resolved: {}", stats.conflicts_resolved);
+    }
+
+    if single_profile {
+        println!("Duration: {:.2}s", stats.duration_secs);
+    }
+
+    for warning in &stats.warnings {
+        println!("Warning: {}", warning);
+    }
+
+    if timings {
+        println!("Timings:");
+        for timing in &stats.phase_timings {
+            println!("  {:<20} {:>8.2}s", timing.phase, timing.secs);
+        }
+    }
+    if !stats.file_errors.is_empty() {
+        println!("Not transferred: {} file(s)", stats.file_errors.len());
+        for file_error in &stats.file_errors {
+            println!("  - {}: {}", file_error.path, file_error.reason);
+        }
+    }
+    if single_profile {
+        println!(
+            "Status: {}",
+            if stats.status == RunStatus::Partial {
+                "Completed partially"
+            } else if stats.conflicts_skipped > 0 {
+                "Completed with warnings"
+            } else {
+                "Completed successfully"
+            }
+        );
+    }
+}
+
+/// End of a run: the summary table after several profiles, or the JSON line with `--quiet`
+pub fn run_summary(report: &RunReport, single_profile: bool) {
+    let output = get();
+    if output.quiet {
+        println!("{}", summary_json(report));
+    } else if !single_profile && !report.profiles.is_empty() {
+        println!();
+        print!("{}", report.summary_table(output.color));
+    }
+}
+
+fn summary_json(report: &RunReport) -> String {
+    let summary = report.summary();
+    json!({
+        "dry_run": report.dry_run,
+        "failed": report.failed_count(),
+        "profiles": summary.profiles,
+        "total": summary.total,
+    })
+    .to_string()
+}
+
+/// Whether a profile's result has more to show than its line in the summary table
+fn has_details(stats: &SyncStats, dry_run: bool, timings: bool) -> bool {
+    (dry_run && !(stats.deleted_files.is_empty() && stats.planned_conflicts.is_empty()))
+        || !stats.warnings.is_empty()
+        || !stats.file_errors.is_empty()
+        || (timings && !stats.phase_timings.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quiet_summary_is_one_json_line() {
+        let mut report = RunReport::new(true);
+        report.add_success("Documents", &SyncStats::default());
+        report.add_failure("Music", &anyhow::anyhow!("NAS unreachable"), None);
+
+        let line = summary_json(&report);
+        assert!(!line.contains('\n'));
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["failed"], 1);
+        assert_eq!(value["profiles"][1]["status"], "failed");
+        assert_eq!(value["total"]["status"], "1/2 ok");
+
+        assert!(!Output::new(false, true).color);
+    }
+}
@@ -0,0 +1,147 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use crate::config::SyncProfile;
+use crate::error::SyncError;
+use crate::remote::{shell_quote, RemoteShell};
+use anyhow::Result;
+use std::fs;
+use std::path::Path;
+use tracing::info;
+
+/// Name of the file written and deleted to prove the destination is writable
+const PROBE_FILE: &str = ".ugnassync-probe";
+
+/// Result of checking the destination, shared by the SSH and SMB checks
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    Ok,
+    Missing,
+    CreateFailed,
+    NotWritable,
+}
+
+/// Check the profile's remote path over SSH, creating it when the profile allows
+pub fn check_remote(shell: &RemoteShell, profile: &SyncProfile, dry_run: bool) -> Result<()> {
+    let path = shell_quote(&profile.remote_path);
+    let create = profile.create_remote_path && !dry_run;
+
+    // rsync creates the last path component itself, so an existing parent is enough
+    let script = format!(
+        r#"p={path}; d="$p"
+if [ ! -d "$p" ]; then
+  if {create}; then mkdir -p "$p" || exit 3
+  else d=$(dirname "$p"); [ -d "$d" ] || exit 2; fi
+fi
+if {dry_run}; then [ -w "$d" ] || exit 4
+else f="$d/{PROBE_FILE}-$$"; touch "$f" 2>/dev/null || exit 4; rm -f "$f"; fi"#
+    );
+
+    let outcome = match shell.exit_code(&script)? {
+        0 => Outcome::Ok,
+        2 => Outcome::Missing,
+        3 => Outcome::CreateFailed,
+        4 => Outcome::NotWritable,
+        code => anyhow::bail!("Preflight check of {} on {} exited with {}", profile.remote_path, shell.host(), code),
+    };
+    report(outcome, profile, &shell.login())
+}
+
+/// Same checks for a destination on a mounted SMB share
+pub fn check_local(profile: &SyncProfile, dry_run: bool) -> Result<()> {
+    let user = std::env::var("USER").unwrap_or_else(|_| "current user".to_string());
+    report(local_outcome(profile, dry_run), profile, &user)
+}
+
+fn local_outcome(profile: &SyncProfile, dry_run: bool) -> Outcome {
+    let path = Path::new(&profile.remote_path);
+    let mut dir = path;
+
+    if !path.is_dir() {
+        if profile.create_remote_path && !dry_run {
+            if fs::create_dir_all(path).is_err() {
+                return Outcome::CreateFailed;
+            }
+        } else {
+            match path.parent() {
+                Some(parent) if parent.is_dir() => dir = parent,
+                _ => return Outcome::Missing,
+            }
+        }
+    }
+
+    if dry_run {
+        return match fs::metadata(dir) {
+            Ok(metadata) if !metadata.permissions().readonly() => Outcome::Ok,
+            _ => Outcome::NotWritable,
+        };
+    }
+
+    let probe = dir.join(format!("{}-{}", PROBE_FILE, std::process::id()));
+    match fs::write(&probe, b"") {
+        Ok(()) => {
+            let _ = fs::remove_file(&probe);
+            Outcome::Ok
+        }
+        Err(_) => Outcome::NotWritable,
+    }
+}
+
+fn report(outcome: Outcome, profile: &SyncProfile, user: &str) -> Result<()> {
+    let path = &profile.remote_path;
+    let (category, error): (fn(String) -> SyncError, String) = match outcome {
+        Outcome::Ok => {
+            info!("Remote path {} is writable by {}", path, user);
+            return Ok(());
+        }
+        Outcome::Missing => (
+            SyncError::Config,
+            format!(
+                "Remote path {} does not exist (as {}); create it or set create_remote_path = true",
+                path, user
+            ),
+        ),
+        Outcome::CreateFailed => (
+            SyncError::PermissionDenied,
+            format!("Cannot create remote path {} as {}", path, user),
+        ),
+        Outcome::NotWritable => (
+            SyncError::PermissionDenied,
+            format!("Remote path {} is not writable by {}", path, user),
+        ),
+    };
+    Err(category(format!("Preflight check failed for profile '{}': {}", profile.name, error)).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_outcome_creates_when_allowed() {
+        let dir = std::env::temp_dir().join(format!("ugnassync-preflight-test-{}", std::process::id()));
+        let mut profile: SyncProfile = toml::from_str(&format!(
+            r#"
+            name = "test"
+            local_path = "/home/user/Documents"
+            remote_path = "{}/nested/Documents"
+            sync_type = "one-way"
+            enabled = true
+            "#,
+            dir.display()
+        ))
+        .unwrap();
+
+        assert_eq!(local_outcome(&profile, false), Outcome::Missing);
+
+        profile.create_remote_path = true;
+        assert_eq!(local_outcome(&profile, true), Outcome::Missing);
+        assert_eq!(local_outcome(&profile, false), Outcome::Ok);
+        assert!(dir.join("nested/Documents").is_dir());
+        assert_eq!(fs::read_dir(dir.join("nested/Documents")).unwrap().count(), 0);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
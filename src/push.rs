@@ -0,0 +1,219 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use crate::config::{SyncProfile, SyncType};
+use crate::sync::FileError;
+use crate::watch::WatchManager;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, NaiveDateTime};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+
+/// A transfer program that pushes file by file where rsync cannot run (smbclient, FTP)
+pub trait Backend {
+    /// For log messages
+    fn name(&self) -> &'static str;
+
+    /// Files below the profile's remote_path
+    fn list(&self) -> Result<Vec<RemoteFile>>;
+
+    /// Upload `files`, relative to `root`; `remote` is the listing they were planned
+    /// from. Returns the files that could not be uploaded
+    fn upload(&self, root: &Path, files: &[LocalFile], remote: &[RemoteFile]) -> Result<Vec<FileError>>;
+
+    /// Delete files below remote_path, returning those deleted
+    fn delete(&self, paths: &[String]) -> Result<Vec<String>>;
+}
+
+/// The sync types a file-by-file push can do
+pub fn supports(sync_type: &SyncType) -> bool {
+    matches!(sync_type, SyncType::Mirror | SyncType::OneWay | SyncType::Incremental)
+}
+
+/// A file on the NAS, relative to the profile's remote_path
+#[derive(Debug, Clone, PartialEq)]
+pub struct RemoteFile {
+    pub path: String,
+    pub size: u64,
+    pub modified: NaiveDateTime,
+}
+
+/// A local file to push, relative to the profile's transfer root
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocalFile {
+    pub path: String,
+    pub size: u64,
+    pub modified: NaiveDateTime,
+}
+
+/// What a push changes on the NAS
+#[derive(Debug, Default, PartialEq)]
+pub struct Plan {
+    pub upload: Vec<LocalFile>,
+    /// Uploads that replace a file already on the NAS
+    pub replaced: Vec<String>,
+    pub delete: Vec<String>,
+}
+
+impl Plan {
+    pub fn bytes(&self) -> u64 {
+        self.upload.iter().map(|file| file.size).sum()
+    }
+}
+
+/// Which local files to upload and which remote ones to delete: new files, files that
+/// changed size or are newer locally by more than `mtime_tolerance` seconds (only newer
+/// with `update_only`), and with `delete` the remote files not present locally that no
+/// exclude protects
+pub fn plan(
+    local: &[LocalFile],
+    remote: &[RemoteFile],
+    excludes: &[String],
+    delete: bool,
+    update_only: bool,
+    mtime_tolerance: u64,
+    clock_offset: i64,
+) -> Plan {
+    let on_share: BTreeMap<&str, &RemoteFile> = remote.iter().map(|file| (file.path.as_str(), file)).collect();
+    let mut plan = Plan::default();
+
+    for file in local {
+        // NAS mtimes on this machine's clock
+        let newer = |theirs: &RemoteFile| (file.modified - theirs.modified).num_seconds() + clock_offset > mtime_tolerance as i64;
+        match on_share.get(file.path.as_str()) {
+            None => plan.upload.push(file.clone()),
+            Some(theirs) if newer(theirs) || (!update_only && theirs.size != file.size) => {
+                plan.replaced.push(file.path.clone());
+                plan.upload.push(file.clone());
+            }
+            Some(_) => {}
+        }
+    }
+
+    if delete {
+        let here: Vec<&str> = local.iter().map(|file| file.path.as_str()).collect();
+        plan.delete = remote
+            .iter()
+            .filter(|file| !here.contains(&file.path.as_str()))
+            .filter(|file| !WatchManager::is_excluded(Path::new(&file.path), excludes))
+            .map(|file| file.path.clone())
+            .collect();
+    }
+    plan
+}
+
+/// Files below the profile's local paths, leaving out excluded ones, and those that could
+/// not be read; only an unreadable local path itself fails
+pub fn local_files(profile: &SyncProfile) -> Result<(Vec<LocalFile>, Vec<FileError>)> {
+    let root = PathBuf::from(profile.transfer_root());
+    let excludes = profile.effective_excludes();
+    let (mut files, mut unreadable) = (Vec::new(), Vec::new());
+    for source in profile.source_paths() {
+        fs::symlink_metadata(source).with_context(|| format!("Cannot read {}", source))?;
+        walk(Path::new(source), &root, &excludes, &mut files, &mut unreadable);
+    }
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok((files, unreadable))
+}
+
+fn walk(path: &Path, root: &Path, excludes: &[String], files: &mut Vec<LocalFile>, unreadable: &mut Vec<FileError>) {
+    let mut skip = |path: &Path, e: io::Error| {
+        warn!("Cannot read {}: {}", path.display(), e);
+        unreadable.push(FileError::unreadable(path, &e));
+    };
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) => return skip(path, e),
+    };
+    if metadata.is_dir() {
+        let entries = match fs::read_dir(path) {
+            Ok(entries) => entries,
+            Err(e) => return skip(path, e),
+        };
+        let mut children = Vec::new();
+        for entry in entries {
+            match entry {
+                Ok(entry) if !WatchManager::is_excluded(&entry.path(), excludes) => children.push(entry.path()),
+                Ok(_) => {}
+                Err(e) => skip(path, e),
+            }
+        }
+        for child in children {
+            walk(&child, root, excludes, files, unreadable);
+        }
+    } else if metadata.is_file() {
+        let modified: DateTime<Local> = match metadata.modified() {
+            Ok(modified) => modified.into(),
+            Err(e) => return skip(path, e),
+        };
+        files.push(LocalFile {
+            path: path.strip_prefix(root).unwrap_or(path).to_string_lossy().to_string(),
+            size: metadata.len(),
+            modified: modified.naive_local(),
+        });
+    } else {
+        debug!("Only regular files are pushed, skipping {}", path.display());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan() {
+        let at = |s: &str| NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap();
+        let local = |path: &str, size: u64, modified: &str| LocalFile { path: path.to_string(), size, modified: at(modified) };
+        let remote = |path: &str, size: u64, modified: &str| RemoteFile { path: path.to_string(), size, modified: at(modified) };
+
+        let here = vec![
+            local("docs/new.txt", 10, "2024-05-01 10:00:00"),
+            local("docs/same.txt", 20, "2024-05-01 10:00:01"),
+            local("docs/edited.txt", 30, "2024-05-02 10:00:00"),
+            local("docs/shrunk.txt", 5, "2024-04-01 10:00:00"),
+        ];
+        let there = vec![
+            remote("docs/same.txt", 20, "2024-05-01 10:00:00"),
+            remote("docs/edited.txt", 30, "2024-05-01 10:00:00"),
+            remote("docs/shrunk.txt", 50, "2024-05-01 10:00:00"),
+            remote("docs/gone.txt", 1, "2024-05-01 10:00:00"),
+            remote("docs/cache/x.tmp", 1, "2024-05-01 10:00:00"),
+        ];
+        let excludes = vec!["cache".to_string()];
+
+        let mirror = plan(&here, &there, &excludes, true, false, 2, 0);
+        let uploads: Vec<&str> = mirror.upload.iter().map(|file| file.path.as_str()).collect();
+        assert_eq!(uploads, vec!["docs/new.txt", "docs/edited.txt", "docs/shrunk.txt"]);
+        assert_eq!(mirror.replaced, vec!["docs/edited.txt", "docs/shrunk.txt"]);
+        assert_eq!(mirror.delete, vec!["docs/gone.txt"]);
+        assert_eq!(mirror.bytes(), 45);
+
+        // Incremental leaves files that are newer on the NAS alone
+        let incremental = plan(&here, &there, &excludes, false, true, 2, 0);
+        assert_eq!(incremental.replaced, vec!["docs/edited.txt"]);
+        assert!(incremental.delete.is_empty());
+    }
+
+    #[test]
+    fn test_plan_corrects_clock_skew() {
+        let at = |s: &str| NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap();
+        let here = vec![LocalFile { path: "a.txt".to_string(), size: 10, modified: at("2024-05-01 10:01:40") }];
+        let plan = |remote: &str, offset| {
+            let there = vec![RemoteFile { path: "a.txt".to_string(), size: 10, modified: at(remote) }];
+            plan(&here, &there, &[], false, true, 2, offset).replaced
+        };
+
+        // NAS 300s ahead: its copy from 10:00 local time says 10:05, the local edit is newer
+        assert!(plan("2024-05-01 10:05:00", 0).is_empty());
+        assert_eq!(plan("2024-05-01 10:05:00", 300), vec!["a.txt"]);
+
+        // NAS 300s behind: its copy from 10:03 local time says 09:58, it is newer than the local file
+        assert_eq!(plan("2024-05-01 09:58:00", 0), vec!["a.txt"]);
+        assert!(plan("2024-05-01 09:58:00", -300).is_empty());
+    }
+}
@@ -0,0 +1,257 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use crate::config::{QnapConfig, SyncProfile};
+use crate::error::SyncError;
+use crate::vendor::Vendor;
+use anyhow::{Context, Result};
+use std::io::Write;
+use std::process::{Command, Stdio};
+use tracing::{debug, warn};
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// A volume's space as reported by chartReq.cgi
+#[derive(Debug)]
+struct Volume {
+    shares: Vec<String>,
+    free_bytes: u64,
+}
+
+/// [nas.qnap], talking to the QTS CGI endpoints. QTS offers no public API to create
+/// shared folders or snapshots, so this integration only checks storage health.
+pub struct Qnap {
+    config: QnapConfig,
+    base: String,
+}
+
+impl Qnap {
+    pub fn new(config: &QnapConfig, host: &str) -> Self {
+        let base = config.url.clone().unwrap_or_else(|| format!("https://{}", host));
+        Self {
+            config: config.clone(),
+            base: base.trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// GET a CGI endpoint; `secret` (password or session) reaches curl on stdin
+    fn get(&self, cgi: &str, params: &[(&str, &str)], secret: (&str, &str)) -> Result<String> {
+        let url = format!("{}/cgi-bin/{}", self.base, cgi);
+        let mut cmd = Command::new("curl");
+        cmd.arg("-fsS").arg("-m").arg("30").arg("-G");
+        if self.config.insecure {
+            cmd.arg("--insecure");
+        }
+        for (name, value) in params {
+            cmd.arg("--data-urlencode").arg(format!("{}={}", name, value));
+        }
+        let mut child = cmd
+            .arg("--data-urlencode")
+            .arg(format!("{}@-", secret.0))
+            .arg(&url)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| SyncError::from_spawn("curl", e))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(secret.1.as_bytes()).context("Failed to pass credentials to curl")?;
+        }
+        let output = child.wait_with_output().context("Failed to execute curl")?;
+        if !output.status.success() {
+            return Err(SyncError::Unreachable(format!(
+                "QTS at {} did not answer: {}",
+                url,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ))
+            .into());
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+
+    /// Session ID for the other requests
+    fn login(&self) -> Result<String> {
+        let response = self.get(
+            "authLogin.cgi",
+            &[("user", &self.config.username), ("serviceKey", "1")],
+            ("pwd", &base64(self.config.password.as_bytes())),
+        )?;
+        if tag(&response, "authPassed") != Some("1") {
+            return Err(SyncError::AuthFailed(format!(
+                "QTS at {} rejected the login of {} (wrong password, or 2-step verification is on)",
+                self.base, self.config.username
+            ))
+            .into());
+        }
+        tag(&response, "authSid").map(str::to_string).context("QTS login returned no session")
+    }
+}
+
+impl Vendor for Qnap {
+    fn name(&self) -> &'static str {
+        "QNAP QTS"
+    }
+
+    /// SMART health of every disk, and the free space of the volume holding the share
+    fn prepare(&self, profile: &SyncProfile, _dry_run: bool) -> Result<Vec<String>> {
+        let sid = self.login()?;
+        let mut warnings = smart_warnings(&self.get("disk/qsmart.cgi", &[("func", "all_hd_data")], ("sid", &sid))?);
+
+        match (share_of(&profile.remote_path), self.config.min_free_gb) {
+            (Some(share), Some(min_free_gb)) => {
+                let usage = self.get(
+                    "management/chartReq.cgi",
+                    &[("chart_func", "disk_usage"), ("disk_select", "all"), ("include", "all")],
+                    ("sid", &sid),
+                )?;
+                match parse_volumes(&usage).iter().find(|volume| volume.shares.iter().any(|name| name == share)) {
+                    Some(volume) => check_space(volume, share, min_free_gb)?,
+                    None => warn!("QTS does not report a volume holding share {}", share),
+                }
+            }
+            (None, Some(_)) => debug!("{} is not below /share, skipping the QTS space check", profile.remote_path),
+            _ => {}
+        }
+
+        if let Err(e) = self.get("authLogout.cgi", &[], ("sid", &sid)) {
+            debug!("QTS logout failed: {:#}", e);
+        }
+        warnings.dedup();
+        Ok(warnings)
+    }
+
+    /// Disks whose SMART health is not OK; QTS reports RAID state only in its web UI
+    fn health(&self, _profile: &SyncProfile) -> Result<Vec<String>> {
+        let sid = self.login()?;
+        let problems = smart_warnings(&self.get("disk/qsmart.cgi", &[("func", "all_hd_data")], ("sid", &sid))?);
+        if let Err(e) = self.get("authLogout.cgi", &[], ("sid", &sid)) {
+            debug!("QTS logout failed: {:#}", e);
+        }
+        Ok(problems)
+    }
+}
+
+fn check_space(volume: &Volume, share: &str, min_free_gb: f64) -> Result<()> {
+    let free_gb = volume.free_bytes as f64 / 1e9;
+    if free_gb < min_free_gb {
+        return Err(SyncError::DiskFull(format!(
+            "The volume holding share {} has {:.1} GB free, less than min_free_gb = {}",
+            share, free_gb, min_free_gb
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+/// `/share/Backups/laptop` or `/share/CACHEDEV1_DATA/Backups/laptop` -> `Backups`
+fn share_of(remote_path: &str) -> Option<&str> {
+    let mut parts = remote_path.strip_prefix("/share/")?.split('/').filter(|part| !part.is_empty());
+    let first = parts.next()?;
+    if first.starts_with("CACHEDEV") || first.ends_with("_DATA") {
+        parts.next()
+    } else {
+        Some(first)
+    }
+}
+
+/// Disks whose SMART health QTS does not report as OK
+fn smart_warnings(xml: &str) -> Vec<String> {
+    elements(xml, "entry")
+        .into_iter()
+        .filter_map(|entry| {
+            let health = tag(entry, "Health")?;
+            (!health.eq_ignore_ascii_case("ok") && !health.eq_ignore_ascii_case("good")).then(|| {
+                format!(
+                    "Disk {} ({}) on the NAS reports SMART health {}",
+                    tag(entry, "HDNo").unwrap_or("?"),
+                    tag(entry, "Model").unwrap_or("unknown model"),
+                    health
+                )
+            })
+        })
+        .collect()
+}
+
+fn parse_volumes(xml: &str) -> Vec<Volume> {
+    elements(xml, "volumeUse")
+        .into_iter()
+        .map(|usage| Volume {
+            shares: elements(usage, "folder_element")
+                .into_iter()
+                .filter_map(|folder| tag(folder, "sharename").map(str::to_string))
+                .collect(),
+            free_bytes: tag(usage, "free_size").and_then(|size| size.parse().ok()).unwrap_or(0),
+        })
+        .collect()
+}
+
+/// Bodies of every `<name>...</name>` in `xml`
+fn elements<'a>(xml: &'a str, name: &str) -> Vec<&'a str> {
+    let (open, close) = (format!("<{}>", name), format!("</{}>", name));
+    let mut found = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find(&open) {
+        let body = &rest[start + open.len()..];
+        let Some(end) = body.find(&close) else {
+            break;
+        };
+        found.push(&body[..end]);
+        rest = &body[end + close.len()..];
+    }
+    found
+}
+
+/// Text of the first `<name>` element, without its CDATA wrapper
+fn tag<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let body = elements(xml, name).into_iter().next()?.trim();
+    Some(body.strip_prefix("<![CDATA[").and_then(|text| text.strip_suffix("]]>")).unwrap_or(body))
+}
+
+/// QTS expects the password base64-encoded ("ezEncode")
+fn base64(bytes: &[u8]) -> String {
+    let mut encoded = String::new();
+    for chunk in bytes.chunks(3) {
+        let value = chunk.iter().enumerate().fold(0u32, |value, (i, byte)| value | (*byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(BASE64[(value >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_qts_responses() {
+        assert_eq!(base64(b"secret"), "c2VjcmV0");
+        assert_eq!(base64(b"pass1"), "cGFzczE=");
+        assert_eq!(share_of("/share/CACHEDEV1_DATA/Backups/laptop"), Some("Backups"));
+        assert_eq!(share_of("/share/Backups"), Some("Backups"));
+        assert_eq!(tag("<authPassed><![CDATA[1]]></authPassed>", "authPassed"), Some("1"));
+
+        let volumes = parse_volumes(
+            "<QDocRoot><volumeUseList><volumeUse><volumeValue><![CDATA[1]]></volumeValue>\
+             <total_size><![CDATA[4000000000000]]></total_size><free_size><![CDATA[20000000000]]></free_size>\
+             <folder_element><sharename><![CDATA[Public]]></sharename></folder_element>\
+             <folder_element><sharename><![CDATA[Backups]]></sharename></folder_element>\
+             </volumeUse></volumeUseList></QDocRoot>",
+        );
+        assert_eq!(volumes[0].shares, vec!["Public", "Backups"]);
+        assert!(check_space(&volumes[0], "Backups", 10.0).is_ok());
+        assert!(check_space(&volumes[0], "Backups", 50.0).is_err());
+
+        let warnings = smart_warnings(
+            "<Disk_Info><entry><HDNo><![CDATA[1]]></HDNo><Model><![CDATA[WD40EFRX]]></Model><Health><![CDATA[OK]]></Health></entry>\
+             <entry><HDNo><![CDATA[2]]></HDNo><Model><![CDATA[ST4000VN]]></Model><Health><![CDATA[Warning]]></Health></entry></Disk_Info>",
+        );
+        assert_eq!(warnings, vec!["Disk 2 (ST4000VN) on the NAS reports SMART health Warning"]);
+    }
+}
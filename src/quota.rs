@@ -0,0 +1,137 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use crate::remote::{shell_quote, RemoteShell};
+use anyhow::{Context, Result};
+use std::path::Path;
+use std::process::Command;
+
+/// Bytes that can still be written to a share, and which limit decides it
+#[derive(Debug, PartialEq)]
+pub struct Headroom {
+    pub bytes: u64,
+    pub limit: &'static str,
+}
+
+/// Free space, user quota and btrfs qgroup limit (as used by Synology shares) over SSH
+pub fn remote_headroom(shell: &RemoteShell, path: &str) -> Result<Option<Headroom>> {
+    // The remote path may not exist before the first sync
+    let script = format!(
+        r#"p={}; while [ ! -e "$p" ]; do p=$(dirname "$p"); done
+df -Pk "$p"; echo ---; quota -w 2>/dev/null; echo ---
+btrfs inspect-internal rootid "$p" 2>/dev/null; echo ---; btrfs qgroup show -re --raw "$p" 2>/dev/null; true"#,
+        shell_quote(path)
+    );
+    Ok(parse(&shell.run(&script)?))
+}
+
+/// Free space of a destination on a mounted share
+pub fn local_headroom(path: &str) -> Result<Option<Headroom>> {
+    let existing = Path::new(path).ancestors().find(|p| p.exists()).unwrap_or(Path::new("/"));
+    let output = Command::new("df")
+        .arg("-Pk")
+        .arg(existing)
+        .output()
+        .context("Failed to execute df")?;
+    Ok(parse(&String::from_utf8_lossy(&output.stdout)))
+}
+
+fn parse(output: &str) -> Option<Headroom> {
+    // Sections are separated by lines of exactly `---` (qgroup headers contain dashes too)
+    let mut sections: Vec<Vec<&str>> = vec![Vec::new()];
+    for line in output.lines() {
+        match line {
+            "---" => sections.push(Vec::new()),
+            line => sections.last_mut().unwrap().push(line),
+        }
+    }
+    sections.resize(4, Vec::new());
+    let (df, quota, qgroups) = (&sections[0], &sections[1], &sections[3]);
+    let rootid = sections[2].first().map(|id| id.trim()).unwrap_or_default();
+
+    let mut limits = Vec::new();
+
+    // Filesystem 1024-blocks Used Available Capacity Mounted-on
+    let df_fields: Vec<&str> = df.get(1).copied().unwrap_or_default().split_whitespace().collect();
+    let filesystem = df_fields.first().copied();
+    if let Some(available) = df_fields.get(3).and_then(|kb| kb.parse::<u64>().ok()) {
+        limits.push(Headroom {
+            bytes: available * 1024,
+            limit: "free space",
+        });
+    }
+
+    // Filesystem blocks quota limit grace ...; blocks gets a '*' suffix once over quota
+    for line in quota {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 4 || Some(fields[0]) != filesystem {
+            continue;
+        }
+        let kb = |field: &str| field.trim_end_matches('*').parse::<u64>().ok();
+        let (Some(used), Some(soft), Some(hard)) = (kb(fields[1]), kb(fields[2]), kb(fields[3])) else {
+            continue;
+        };
+        if let Some(limit) = [hard, soft].into_iter().filter(|&l| l > 0).min() {
+            limits.push(Headroom {
+                bytes: limit.saturating_sub(used) * 1024,
+                limit: "user quota",
+            });
+        }
+    }
+
+    // qgroupid rfer excl max_rfer max_excl, in bytes
+    if !rootid.is_empty() {
+        let qgroup = format!("0/{}", rootid);
+        for line in qgroups {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 4 || fields[0] != qgroup {
+                continue;
+            }
+            if let (Ok(used), Ok(max)) = (fields[1].parse::<u64>(), fields[3].parse::<u64>()) {
+                limits.push(Headroom {
+                    bytes: max.saturating_sub(used),
+                    limit: "btrfs qgroup",
+                });
+            }
+        }
+    }
+
+    limits.into_iter().min_by_key(|headroom| headroom.bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_picks_tightest_limit() {
+        let output = "\
+Filesystem     1024-blocks      Used Available Capacity Mounted on
+/dev/md2        3748434600 100000000 3648434600       3% /volume1
+---
+Disk quotas for user admin (uid 1024):
+     Filesystem  blocks   quota   limit   grace   files   quota   limit   grace
+       /dev/md2  900000       0 1000000               10       0       0
+---
+257
+---
+qgroupid         rfer         excl     max_rfer     max_excl
+--------         ----         ----     --------     --------
+0/5             16384        16384         none         none
+0/257       900000000    900000000    950000000         none
+";
+
+        assert_eq!(
+            parse(output),
+            Some(Headroom {
+                bytes: 50_000_000,
+                limit: "btrfs qgroup"
+            })
+        );
+
+        let df_only = output.split("---\n").next().unwrap();
+        assert_eq!(parse(df_only).unwrap().limit, "free space");
+    }
+}
@@ -0,0 +1,150 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+/// Minimum number of suspicious files before a profile is quarantined
+const MIN_SUSPICIOUS_FILES: usize = 20;
+/// Bytes sampled from the start of each file for the entropy check
+const ENTROPY_SAMPLE_BYTES: usize = 64 * 1024;
+/// Bits per byte above which content looks encrypted
+const HIGH_ENTROPY: f64 = 7.9;
+
+/// Formats that are compressed anyway, so high entropy says nothing about them
+const COMPRESSED_EXTENSIONS: &[&str] = &[
+    "7z", "avi", "bz2", "docx", "flac", "gif", "gz", "heic", "jpeg", "jpg", "m4a", "mkv", "mov", "mp3", "mp4",
+    "odp", "ods", "odt", "pdf", "png", "pptx", "rar", "webm", "webp", "xlsx", "xz", "zip", "zst",
+];
+
+/// A sync was stopped because its changes look like ransomware damage
+#[derive(Debug)]
+pub struct Quarantine {
+    pub profile: String,
+    pub reason: String,
+}
+
+impl fmt::Display for Quarantine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Possible ransomware activity in profile {}: {}; profile quarantined",
+            self.profile, self.reason
+        )
+    }
+}
+
+impl std::error::Error for Quarantine {}
+
+/// Look for mass renames to unknown extensions and entropy spikes among files about to be synced
+pub fn inspect(new_files: &[PathBuf], replaced_files: &[PathBuf], recorded: &HashSet<PathBuf>) -> Option<String> {
+    let renamed = renamed_to_unknown_extension(new_files, recorded);
+    if renamed >= MIN_SUSPICIOUS_FILES {
+        return Some(format!("{} files renamed to unknown extensions", renamed));
+    }
+
+    let checked: Vec<&PathBuf> = replaced_files
+        .iter()
+        .filter(|path| !extension(path).is_some_and(|ext| COMPRESSED_EXTENSIONS.contains(&ext.as_str())))
+        .collect();
+    let encrypted = checked
+        .iter()
+        .filter(|path| sample_entropy(path).is_some_and(|entropy| entropy > HIGH_ENTROPY))
+        .count();
+    debug!("{} of {} replaced files have high entropy", encrypted, checked.len());
+
+    if encrypted >= MIN_SUSPICIOUS_FILES && encrypted * 2 >= checked.len() {
+        return Some(format!("{} of {} modified files look encrypted", encrypted, checked.len()));
+    }
+
+    None
+}
+
+/// Count new files that replace a vanished synced file of the same base name with a never-seen extension
+fn renamed_to_unknown_extension(new_files: &[PathBuf], recorded: &HashSet<PathBuf>) -> usize {
+    let known_extensions: HashSet<String> = recorded.iter().filter_map(|path| extension(path)).collect();
+
+    let mut vanished: HashMap<(PathBuf, String), usize> = HashMap::new();
+    for path in recorded.iter().filter(|path| !path.exists()) {
+        if let Some(key) = base_key(path) {
+            *vanished.entry(key).or_default() += 1;
+        }
+    }
+
+    new_files
+        .iter()
+        .filter(|path| !extension(path).is_some_and(|ext| known_extensions.contains(&ext)))
+        .filter(|path| base_key(path).is_some_and(|key| vanished.contains_key(&key)))
+        .count()
+}
+
+/// Directory and file name up to the first dot (`report.pdf.locked` -> `report`)
+fn base_key(path: &Path) -> Option<(PathBuf, String)> {
+    let name = path.file_name()?.to_string_lossy();
+    let base = name.split('.').next()?.to_string();
+    Some((path.parent()?.to_path_buf(), base))
+}
+
+fn extension(path: &Path) -> Option<String> {
+    path.extension().map(|ext| ext.to_string_lossy().to_lowercase())
+}
+
+/// Shannon entropy in bits per byte of the start of a file
+fn sample_entropy(path: &Path) -> Option<f64> {
+    let mut buffer = Vec::with_capacity(ENTROPY_SAMPLE_BYTES);
+    File::open(path)
+        .ok()?
+        .take(ENTROPY_SAMPLE_BYTES as u64)
+        .read_to_end(&mut buffer)
+        .ok()?;
+    if buffer.is_empty() {
+        return None;
+    }
+    Some(entropy(&buffer))
+}
+
+fn entropy(data: &[u8]) -> f64 {
+    let mut counts = [0usize; 256];
+    for &byte in data {
+        counts[byte as usize] += 1;
+    }
+
+    let len = data.len() as f64;
+    counts
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entropy() {
+        assert_eq!(entropy(b"aaaaaaaa"), 0.0);
+        let all_bytes: Vec<u8> = (0..=255).collect();
+        assert!((entropy(&all_bytes) - 8.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_mass_rename_detected() {
+        let dir = PathBuf::from("/nonexistent/ugnassync-ransomware-test");
+        let recorded: HashSet<PathBuf> = (0..25).map(|i| dir.join(format!("doc{}.txt", i))).collect();
+        let new_files: Vec<PathBuf> = (0..25).map(|i| dir.join(format!("doc{}.txt.locked", i))).collect();
+
+        assert_eq!(renamed_to_unknown_extension(&new_files, &recorded), 25);
+        assert!(inspect(&new_files, &[], &recorded).is_some());
+        assert!(inspect(&new_files[..5], &[], &recorded).is_none());
+    }
+}
@@ -0,0 +1,360 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use crate::config::NasConfig;
+use crate::connectivity;
+use crate::service::ProfileOwner;
+use anyhow::{Context, Result};
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+use tracing::debug;
+
+/// Environment variable the askpass helper reads the password from
+const ASKPASS_PASSWORD_VAR: &str = "UGNASSYNC_SSH_PASSWORD";
+
+const ASKPASS_SCRIPT: &str = "#!/bin/sh\nprintf '%s\\n' \"$UGNASSYNC_SSH_PASSWORD\"\n";
+
+static PASSWORD_METHOD: OnceLock<Option<PasswordMethod>> = OnceLock::new();
+
+/// How ssh is given the NAS password when no key is configured; the password
+/// itself always travels in the environment, never on a command line
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PasswordMethod {
+    /// `sshpass -e`, reading `SSHPASS`
+    Sshpass,
+    /// A helper script as `SSH_ASKPASS` (`SSH_ASKPASS_REQUIRE` needs OpenSSH 8.4)
+    Askpass,
+}
+
+/// The password method available on this host, preferring sshpass
+pub fn password_method() -> Option<PasswordMethod> {
+    *PASSWORD_METHOD.get_or_init(|| {
+        let method = if Command::new("sshpass").arg("-V").output().is_ok_and(|output| output.status.success()) {
+            Some(PasswordMethod::Sshpass)
+        } else {
+            Command::new("ssh")
+                .arg("-V")
+                .output()
+                .ok()
+                .and_then(|output| openssh_version(&String::from_utf8_lossy(&output.stderr)))
+                .filter(|&version| version >= (8, 4))
+                .map(|_| PasswordMethod::Askpass)
+        };
+        debug!("SSH password method: {:?}", method);
+        method
+    })
+}
+
+/// `OpenSSH_9.2p1 Debian-2, OpenSSL 3.0.11` -> (9, 2)
+fn openssh_version(output: &str) -> Option<(u32, u32)> {
+    let version = output.trim().strip_prefix("OpenSSH_")?;
+    let (major, rest) = version.split_once('.')?;
+    let minor: String = rest.chars().take_while(|c| c.is_ascii_digit()).collect();
+    Some((major.parse().ok()?, minor.parse().ok()?))
+}
+
+/// Write the askpass helper, which prints the password from its environment
+fn askpass_helper() -> Result<PathBuf> {
+    let dir = dirs::home_dir()
+        .context("Failed to get home directory")?
+        .join(".ugnassync");
+    fs::create_dir_all(&dir).context("Failed to create ~/.ugnassync")?;
+
+    let helper = dir.join("askpass.sh");
+    if fs::read_to_string(&helper).ok().as_deref() != Some(ASKPASS_SCRIPT) {
+        fs::write(&helper, ASKPASS_SCRIPT).context("Failed to write askpass helper")?;
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&helper, fs::Permissions::from_mode(0o700))
+            .context("Failed to set askpass helper permissions")?;
+    }
+    Ok(helper)
+}
+
+/// SSH access to the NAS, shared by rsync and direct remote commands
+pub struct RemoteShell {
+    host: String,
+    port: u16,
+    username: String,
+    key_path: Option<String>,
+    /// Only set when there is no key to log in with
+    password: Option<String>,
+    /// Run ssh as this user instead of the service's own
+    owner: Option<ProfileOwner>,
+}
+
+impl RemoteShell {
+    /// Connect to the first reachable NAS address
+    pub fn new(nas: &NasConfig) -> Result<Self> {
+        Ok(Self::with_host(nas, connectivity::select_host(nas)?))
+    }
+
+    pub fn with_host(nas: &NasConfig, host: String) -> Self {
+        Self {
+            host,
+            port: nas.port,
+            username: nas.username.clone(),
+            key_path: nas.key_path.clone(),
+            password: nas.password.clone().filter(|_| nas.key_path.is_none()),
+            owner: None,
+        }
+    }
+
+    /// Log in as the owner of a profile from the service's config directory; the
+    /// service's password is never handed to the owner's processes
+    pub fn with_owner(mut self, owner: Option<&ProfileOwner>) -> Self {
+        if owner.is_some() {
+            self.password = None;
+        }
+        self.owner = owner.cloned();
+        self
+    }
+
+    /// SSH access to a host other than the NAS
+    pub fn to(host: &str, port: u16, username: &str, key_path: Option<String>) -> Self {
+        Self {
+            host: host.to_string(),
+            port,
+            username: username.to_string(),
+            key_path,
+            password: None,
+            owner: None,
+        }
+    }
+
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    /// Arguments for ssh (port and identity)
+    pub fn ssh_args(&self) -> Vec<String> {
+        let mut args = vec!["-p".to_string(), self.port.to_string()];
+        if let Some(key_path) = &self.key_path {
+            args.push("-i".to_string());
+            args.push(key_path.clone());
+        }
+        if self.password.is_some() {
+            // A wrong password fails once instead of being retried
+            args.push("-o".to_string());
+            args.push("NumberOfPasswordPrompts=1".to_string());
+        }
+        args
+    }
+
+    fn password_method(&self) -> Option<PasswordMethod> {
+        self.password.as_ref().and_then(|_| password_method())
+    }
+
+    /// Arguments for sftp, which spells the port option differently
+    pub fn sftp_args(&self) -> Vec<String> {
+        let mut args = self.ssh_args();
+        args[0] = "-P".to_string();
+        args
+    }
+
+    /// Remote shell string for rsync's `-e` option
+    pub fn rsync_rsh(&self) -> String {
+        let ssh = format!("ssh {}", self.ssh_args().join(" "));
+        match self.password_method() {
+            Some(PasswordMethod::Sshpass) => format!("sshpass -e {}", ssh),
+            _ => ssh,
+        }
+    }
+
+    /// Hand the password to the ssh started by `cmd` (directly or through rsync)
+    pub fn authenticate(&self, cmd: &mut Command) -> Result<()> {
+        if let Some(owner) = &self.owner {
+            // After dropping to the owner only a key in their own home is readable
+            if !self.key_path.as_deref().is_some_and(|key| key.starts_with("~/")) {
+                anyhow::bail!(
+                    "Profiles of {} log in with their own SSH key; set key_path = \"~/.ssh/<key>\" in [nas]",
+                    owner.name
+                );
+            }
+            owner.apply(cmd);
+            return Ok(());
+        }
+        let Some(password) = &self.password else {
+            return Ok(());
+        };
+        match self.password_method() {
+            Some(PasswordMethod::Sshpass) => {
+                cmd.env("SSHPASS", password);
+            }
+            Some(PasswordMethod::Askpass) => {
+                cmd.env("SSH_ASKPASS", askpass_helper()?)
+                    .env("SSH_ASKPASS_REQUIRE", "force")
+                    .env(ASKPASS_PASSWORD_VAR, password);
+            }
+            None => anyhow::bail!("Password authentication needs sshpass or OpenSSH 8.4 or later"),
+        }
+        Ok(())
+    }
+
+    /// `program` (ssh or sftp) set up to log in to this host
+    pub fn command(&self, program: &str) -> Result<Command> {
+        let mut cmd = match self.password_method() {
+            Some(PasswordMethod::Sshpass) => {
+                let mut cmd = Command::new("sshpass");
+                cmd.arg("-e").arg(program);
+                cmd
+            }
+            _ => Command::new(program),
+        };
+        self.authenticate(&mut cmd)?;
+        Ok(cmd)
+    }
+
+    /// `user@host`
+    pub fn login(&self) -> String {
+        format!("{}@{}", self.username, self.host)
+    }
+
+    /// rsync destination `user@host:path`
+    pub fn destination(&self, path: &str) -> String {
+        format!("{}:{}", self.login(), path)
+    }
+
+    /// Run a shell command on the NAS and return its stdout
+    pub fn run(&self, command: &str) -> Result<String> {
+        debug!("Running remote command on {}: {}", self.host, command);
+
+        let output = self
+            .command("ssh")?
+            .args(self.ssh_args())
+            .arg(self.login())
+            .arg(command)
+            .output()
+            .context("Failed to execute ssh")?;
+
+        if !output.status.success() {
+            anyhow::bail!(
+                "Remote command '{}' failed on {}: {}",
+                command,
+                self.host,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+
+    /// Run a shell command on the NAS with `input` on its stdin and return its stdout
+    pub fn run_with_input(&self, command: &str, input: &str) -> Result<String> {
+        debug!("Running remote command on {}: {}", self.host, command);
+
+        let mut child = self
+            .command("ssh")?
+            .args(self.ssh_args())
+            .arg(self.login())
+            .arg(command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context("Failed to execute ssh")?;
+        child.stdin.take().context("Failed to open ssh stdin")?.write_all(input.as_bytes())?;
+        let output = child.wait_with_output().context("Failed to execute ssh")?;
+
+        if !output.status.success() {
+            anyhow::bail!(
+                "Remote command '{}' failed on {}: {}",
+                command,
+                self.host,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+
+    /// Run a shell command on the NAS and return its raw output, whatever the exit status
+    pub fn output(&self, command: &str) -> Result<std::process::Output> {
+        debug!("Running remote command on {}: {}", self.host, command);
+
+        self.command("ssh")?
+            .args(self.ssh_args())
+            .arg(self.login())
+            .arg(command)
+            .output()
+            .context("Failed to execute ssh")
+    }
+
+    /// Run a shell command on the NAS and return its exit code; fails only if ssh itself does
+    pub fn exit_code(&self, command: &str) -> Result<i32> {
+        debug!("Running remote command on {}: {}", self.host, command);
+
+        let output = self
+            .command("ssh")?
+            .args(self.ssh_args())
+            .arg(self.login())
+            .arg(command)
+            .output()
+            .context("Failed to execute ssh")?;
+
+        match output.status.code() {
+            Some(255) | None => anyhow::bail!(
+                "ssh to {} failed: {}",
+                self.host,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+            Some(code) => Ok(code),
+        }
+    }
+}
+
+/// Quote a value for a POSIX shell on the remote side
+pub fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("/volume1/my files"), "'/volume1/my files'");
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+    }
+
+    #[test]
+    fn test_openssh_version() {
+        assert_eq!(openssh_version("OpenSSH_9.2p1 Debian-2+deb12u3, OpenSSL 3.0.15 3 Sep 2024\n"), Some((9, 2)));
+        assert_eq!(openssh_version("OpenSSH_8.4p1, LibreSSL 3.3.6"), Some((8, 4)));
+        assert_eq!(openssh_version("ssh: command not found"), None);
+    }
+
+    #[test]
+    fn test_owner_never_gets_service_credentials() {
+        let owner = ProfileOwner {
+            name: "alice".to_string(),
+            uid: 1000,
+            gid: 1000,
+            home: PathBuf::from("/home/alice"),
+        };
+        let mut nas: NasConfig = toml::from_str("host = \"nas\"\nport = 22\nusername = \"admin\"\npassword = \"secret\"").unwrap();
+        let shell = RemoteShell::with_host(&nas, "nas".to_string()).with_owner(Some(&owner));
+        let mut cmd = Command::new("ssh");
+        assert!(shell.authenticate(&mut cmd).is_err());
+        assert_eq!(cmd.get_envs().count(), 0);
+
+        // A root-only key is unreadable once ssh runs as the owner
+        nas.key_path = Some("/root/.ssh/id_ed25519".to_string());
+        assert!(RemoteShell::with_host(&nas, "nas".to_string()).with_owner(Some(&owner)).authenticate(&mut cmd).is_err());
+
+        nas.key_path = Some("~/.ssh/id_ed25519".to_string());
+        let mut cmd = Command::new("ssh");
+        RemoteShell::with_host(&nas, "nas".to_string()).with_owner(Some(&owner)).authenticate(&mut cmd).unwrap();
+        let envs: Vec<_> = cmd.get_envs().map(|(name, _)| name.to_string_lossy().to_string()).collect();
+        assert!(!envs.iter().any(|name| name == "SSHPASS" || name == ASKPASS_PASSWORD_VAR));
+    }
+}
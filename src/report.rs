@@ -0,0 +1,355 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use crate::error;
+use crate::sync::{RunStatus, SyncStats};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+/// Outcome of one profile within a run
+#[derive(Debug, Serialize)]
+pub struct ProfileReport {
+    pub name: String,
+    pub status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub run_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stats: Option<SyncStats>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Error category for automation, e.g. `auth_failed` or `disk_full`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_kind: Option<String>,
+}
+
+/// A profile's line in the summary table; numbers are missing for failed or skipped profiles
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SummaryRow {
+    pub profile: String,
+    pub status: String,
+    pub files: Option<u64>,
+    pub mb: Option<f64>,
+    pub duration_secs: Option<f64>,
+    pub conflicts: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Summary {
+    pub profiles: Vec<SummaryRow>,
+    pub total: SummaryRow,
+}
+
+/// Archivable summary of a complete sync run
+#[derive(Debug, Serialize)]
+pub struct RunReport {
+    pub started_at: DateTime<Local>,
+    pub finished_at: Option<DateTime<Local>>,
+    pub dry_run: bool,
+    pub profiles: Vec<ProfileReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary: Option<Summary>,
+}
+
+impl RunReport {
+    pub fn new(dry_run: bool) -> Self {
+        Self {
+            started_at: Local::now(),
+            finished_at: None,
+            dry_run,
+            profiles: Vec::new(),
+            summary: None,
+        }
+    }
+
+    pub fn add_success(&mut self, name: &str, stats: &SyncStats) {
+        let status = if stats.status == RunStatus::Partial {
+            "partial"
+        } else if stats.conflicts_skipped > 0 {
+            "warning"
+        } else {
+            "success"
+        };
+
+        self.profiles.push(ProfileReport {
+            name: name.to_string(),
+            status: status.to_string(),
+            run_id: Some(stats.run_id.clone()).filter(|run_id| !run_id.is_empty()),
+            stats: Some(stats.clone()),
+            error: None,
+            error_kind: None,
+        });
+    }
+
+    pub fn add_failure(&mut self, name: &str, error: &anyhow::Error, run_id: Option<String>) {
+        self.profiles.push(ProfileReport {
+            name: name.to_string(),
+            status: "failed".to_string(),
+            run_id,
+            stats: None,
+            error: Some(format!("{:#}", error)),
+            error_kind: Some(error::kind(error).to_string()),
+        });
+    }
+
+    pub fn failed_count(&self) -> usize {
+        self.profiles.iter().filter(|profile| profile.status == "failed").count()
+    }
+
+    pub fn partial_count(&self) -> usize {
+        self.profiles.iter().filter(|profile| profile.status == "partial").count()
+    }
+
+    pub fn add_skipped(&mut self, name: &str, reason: &str) {
+        self.profiles.push(ProfileReport {
+            name: name.to_string(),
+            status: "skipped".to_string(),
+            run_id: None,
+            stats: None,
+            error: Some(reason.to_string()),
+            error_kind: None,
+        });
+    }
+
+    /// Write the report as JSON (and optionally HTML) into the report directory
+    pub fn write(&mut self, dir: &Path, html: bool) -> Result<PathBuf> {
+        self.finished_at = Some(Local::now());
+        self.summary = Some(self.summary());
+
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create report directory: {}", dir.display()))?;
+
+        let base = format!("report-{}", self.started_at.format("%Y%m%d-%H%M%S"));
+        let json_path = dir.join(format!("{}.json", base));
+
+        let json = serde_json::to_string_pretty(self).context("Failed to serialize report")?;
+        fs::write(&json_path, json)
+            .with_context(|| format!("Failed to write report: {}", json_path.display()))?;
+        info!("Wrote sync report: {}", json_path.display());
+
+        if html {
+            let html_path = dir.join(format!("{}.html", base));
+            fs::write(&html_path, self.render_html())
+                .with_context(|| format!("Failed to write report: {}", html_path.display()))?;
+            info!("Wrote HTML sync report: {}", html_path.display());
+        }
+
+        Ok(json_path)
+    }
+
+    /// One row per profile plus a total row, as printed at the end of a run
+    pub fn summary(&self) -> Summary {
+        let rows: Vec<SummaryRow> = self
+            .profiles
+            .iter()
+            .map(|profile| {
+                let stats = profile.stats.as_ref();
+                SummaryRow {
+                    profile: profile.name.clone(),
+                    status: profile.status.clone(),
+                    files: stats.map(|stats| stats.files_transferred),
+                    mb: stats.map(|stats| stats.bytes_transferred as f64 / (1024.0 * 1024.0)),
+                    duration_secs: stats.map(|stats| stats.duration_secs),
+                    conflicts: stats.map(|stats| stats.conflicts_detected),
+                }
+            })
+            .collect();
+
+        let ok = rows.iter().filter(|row| row.status == "success").count();
+        let sum_u64 = |value: fn(&SummaryRow) -> Option<u64>| Some(rows.iter().filter_map(value).sum());
+        let sum_f64 = |value: fn(&SummaryRow) -> Option<f64>| Some(rows.iter().filter_map(value).sum());
+        let total = SummaryRow {
+            profile: "Total".to_string(),
+            status: format!("{}/{} ok", ok, rows.len()),
+            files: sum_u64(|row| row.files),
+            mb: sum_f64(|row| row.mb),
+            duration_secs: sum_f64(|row| row.duration_secs),
+            conflicts: sum_u64(|row| row.conflicts),
+        };
+        Summary { profiles: rows, total }
+    }
+
+    /// The summary as an aligned text table; `color` marks statuses with ANSI colors
+    pub fn summary_table(&self, color: bool) -> String {
+        let summary = self.summary();
+        let width = summary
+            .profiles
+            .iter()
+            .map(|row| row.profile.chars().count())
+            .chain([summary.total.profile.len(), "Profile".len()])
+            .max()
+            .unwrap_or_default();
+
+        let mut table = format!(
+            "{:<width$}  {:<10}  {:>8}  {:>10}  {:>9}  {:>9}\n",
+            "Profile", "Status", "Files", "MB", "Duration", "Conflicts"
+        );
+        for row in summary.profiles.iter().chain([&summary.total]) {
+            let status = format!("{:<10}", row.status);
+            let status = match (color, row.status.as_str()) {
+                (false, _) => status,
+                (true, "success") => format!("\x1b[32m{}\x1b[0m", status),
+                (true, "warning" | "partial") => format!("\x1b[33m{}\x1b[0m", status),
+                (true, "failed") => format!("\x1b[31m{}\x1b[0m", status),
+                (true, "skipped") => format!("\x1b[90m{}\x1b[0m", status),
+                (true, _) => format!("\x1b[1m{}\x1b[0m", status),
+            };
+            table.push_str(&format!(
+                "{:<width$}  {}  {:>8}  {:>10}  {:>9}  {:>9}\n",
+                row.profile,
+                status,
+                or_dash(row.files.map(|files| files.to_string())),
+                or_dash(row.mb.map(|mb| format!("{:.2}", mb))),
+                or_dash(row.duration_secs.map(|secs| format!("{:.2}s", secs))),
+                or_dash(row.conflicts.map(|conflicts| conflicts.to_string())),
+            ));
+        }
+        table
+    }
+
+    fn render_html(&self) -> String {
+        let mut rows = String::new();
+        let mut details = String::new();
+
+        let summary = self.summary();
+        for (row, class) in summary
+            .profiles
+            .iter()
+            .map(|row| (row, row.status.as_str()))
+            .chain([(&summary.total, "total")])
+        {
+            rows.push_str(&format!(
+                "<tr class=\"{}\"><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                class,
+                escape_html(&row.profile),
+                row.status,
+                or_dash(row.files.map(|files| files.to_string())),
+                or_dash(row.mb.map(|mb| format!("{:.2}", mb))),
+                or_dash(row.duration_secs.map(|secs| format!("{:.2}s", secs))),
+                or_dash(row.conflicts.map(|conflicts| conflicts.to_string()))
+            ));
+        }
+
+        for profile in &self.profiles {
+            if let Some(error) = &profile.error {
+                details.push_str(&format!(
+                    "<h3>{}</h3>\n<pre class=\"failed\">{}</pre>\n",
+                    escape_html(&profile.name),
+                    escape_html(error)
+                ));
+            } else if let Some(stats) = &profile.stats {
+                if !stats.file_errors.is_empty() {
+                    details.push_str(&format!("<h3>{}: not transferred</h3>\n<ul class=\"failed\">\n", escape_html(&profile.name)));
+                    for file_error in &stats.file_errors {
+                        details.push_str(&format!(
+                            "<li>{}: {}</li>\n",
+                            escape_html(&file_error.path),
+                            escape_html(&file_error.reason)
+                        ));
+                    }
+                    details.push_str("</ul>\n");
+                }
+                if !stats.changed_files.is_empty() || !stats.deleted_files.is_empty() {
+                    details.push_str(&format!("<h3>{}</h3>\n<ul>\n", escape_html(&profile.name)));
+                    for file in &stats.changed_files {
+                        details.push_str(&format!("<li>updated {}</li>\n", escape_html(file)));
+                    }
+                    for file in &stats.deleted_files {
+                        details.push_str(&format!("<li>deleted {}</li>\n", escape_html(file)));
+                    }
+                    details.push_str("</ul>\n");
+                }
+            }
+        }
+
+        format!(
+            r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>UGNasSync report {started}</title>
+<style>
+body {{ font-family: sans-serif; margin: 2em; }}
+table {{ border-collapse: collapse; }}
+th, td {{ border: 1px solid #ccc; padding: 4px 10px; text-align: left; }}
+tr.success td:nth-child(2) {{ color: #2e7d32; }}
+tr.warning td:nth-child(2) {{ color: #ef6c00; }}
+tr.partial td:nth-child(2) {{ color: #ef6c00; }}
+tr.failed td:nth-child(2), pre.failed, ul.failed {{ color: #c62828; }}
+tr.skipped td:nth-child(2) {{ color: #757575; }}
+tr.total td {{ font-weight: bold; }}
+</style>
+</head>
+<body>
+<h1>UGNasSync sync report</h1>
+<p>Started {started}{dry_run}</p>
+<table>
+<tr><th>Profile</th><th>Status</th><th>Files</th><th>MB</th><th>Duration</th><th>Conflicts</th></tr>
+{rows}</table>
+{details}</body>
+</html>
+"#,
+            started = self.started_at.format("%Y-%m-%d %H:%M:%S"),
+            dry_run = if self.dry_run { " (dry run)" } else { "" },
+            rows = rows,
+            details = details
+        )
+    }
+}
+
+fn or_dash(value: Option<String>) -> String {
+    value.unwrap_or_else(|| "-".to_string())
+}
+
+fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_html_report_escapes_names() {
+        let mut report = RunReport::new(false);
+        report.add_failure("<Docs>", &anyhow::anyhow!("rsync failed"), None);
+
+        let html = report.render_html();
+        assert!(html.contains("&lt;Docs&gt;"));
+        assert!(html.contains("rsync failed"));
+    }
+
+    #[test]
+    fn test_summary_table_with_total_row() {
+        let mut report = RunReport::new(false);
+        let stats = SyncStats {
+            files_transferred: 12,
+            bytes_transferred: 3 * 1024 * 1024,
+            duration_secs: 1.5,
+            ..Default::default()
+        };
+        report.add_success("Documents", &stats);
+        report.add_success("Photos Backup", &stats);
+        report.add_failure("Music", &anyhow::anyhow!("NAS unreachable"), None);
+
+        let summary = report.summary();
+        assert_eq!(summary.total.status, "2/3 ok");
+        assert_eq!((summary.total.files, summary.total.mb), (Some(24), Some(6.0)));
+
+        let table = report.summary_table(false);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert!(lines[3].starts_with("Music          failed             -"));
+        assert!(lines[4].starts_with("Total          2/3 ok            24        6.00      3.00s"));
+        assert!(report.summary_table(true).contains("\x1b[31mfailed"));
+    }
+}
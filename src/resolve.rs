@@ -0,0 +1,120 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use crate::config::{NasConfig, SyncProfile};
+use crate::conflict::ConflictResolver;
+use crate::device;
+use crate::diff;
+use crate::remote::RemoteShell;
+use crate::smb::SmbMount;
+use crate::versions;
+use anyhow::{Context, Result};
+use chrono::Local;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::info;
+
+/// Settle a conflict of a two-way profile by hand: `local` copies the local file to
+/// the NAS, `nas` the NAS copy over the local file, and `both` keeps the NAS copy next
+/// to the local file as `*.conflict.<timestamp>` before copying the local file up
+pub async fn resolve(nas: &NasConfig, profile: &SyncProfile, local: &Path, keep: &str) -> Result<()> {
+    if !local.is_file() {
+        anyhow::bail!("{} is not a local file", local.display());
+    }
+    let remote = format!(
+        "{}/{}",
+        profile.remote_path.trim_end_matches('/'),
+        versions::relative_path(profile, local)?
+    );
+    let resolver = ConflictResolver::for_profile(profile)?;
+
+    let mut smb_mount = None;
+    let shell = match (&nas.smb, profile.use_smb_mount) {
+        (Some(smb_config), true) if smb_config.enabled => {
+            let mut mount = SmbMount::new(smb_config.clone());
+            mount.mount().await?;
+            smb_mount = Some(mount);
+            None
+        }
+        _ => Some(RemoteShell::new(nas)?.with_owner(profile.owner.as_ref())),
+    };
+
+    let result = (|| -> Result<()> {
+        match keep {
+            "local" => push(nas, profile, shell.as_ref(), local, &remote)?,
+            "nas" => fetch(nas, profile, shell.as_ref(), &remote, local)?,
+            "both" => {
+                let copy = conflict_copy(local);
+                fetch(nas, profile, shell.as_ref(), &remote, &copy)?;
+                info!("Kept the NAS version as {}", copy.display());
+                push(nas, profile, shell.as_ref(), local, &remote)?;
+            }
+            other => anyhow::bail!("Unknown choice '{}', use local, nas or both", other),
+        }
+        resolver.update_sync_state(local)?;
+        Ok(())
+    })();
+
+    if let Some(mut mount) = smb_mount {
+        if mount.should_auto_unmount() {
+            mount.unmount().await?;
+        }
+    }
+    result
+}
+
+/// `report.odt` -> `report.odt.conflict.laptop-20250310-021500`, named after this device
+/// as the `keep` strategy names them
+fn conflict_copy(local: &Path) -> PathBuf {
+    let mut name = local.as_os_str().to_owned();
+    name.push(format!(".conflict.{}-{}", device::current().label(), Local::now().format("%Y%m%d-%H%M%S")));
+    PathBuf::from(name)
+}
+
+fn push(nas: &NasConfig, profile: &SyncProfile, shell: Option<&RemoteShell>, local: &Path, remote: &str) -> Result<()> {
+    let Some(shell) = shell else {
+        fs::copy(local, remote).with_context(|| format!("Failed to copy {} to {}", local.display(), remote))?;
+        return Ok(());
+    };
+
+    let mut cmd = Command::new(nas.rsync_binary());
+    cmd.arg("-t").arg("-e").arg(shell.rsync_rsh());
+    shell.authenticate(&mut cmd)?;
+    if let Some(remote_rsync) = profile.remote_rsync_command() {
+        cmd.arg(format!("--rsync-path={}", remote_rsync));
+    }
+    let output = cmd
+        .arg(local)
+        .arg(shell.destination(remote))
+        .output()
+        .context("Failed to execute rsync")?;
+    if !output.status.success() {
+        anyhow::bail!("Copying {} to the NAS failed: {}", local.display(), String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
+
+fn fetch(nas: &NasConfig, profile: &SyncProfile, shell: Option<&RemoteShell>, remote: &str, target: &Path) -> Result<()> {
+    match shell {
+        Some(_) => diff::fetch(nas, profile, remote, target),
+        None => fs::copy(remote, target)
+            .map(|_| ())
+            .with_context(|| format!("Failed to copy {} to {}", remote, target.display())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conflict_copy_name() {
+        let copy = conflict_copy(Path::new("/home/me/docs/report.odt"));
+        let name = copy.file_name().unwrap().to_string_lossy().to_string();
+        assert!(name.starts_with("report.odt.conflict."));
+        assert_eq!(copy.parent(), Some(Path::new("/home/me/docs")));
+    }
+}
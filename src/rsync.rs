@@ -0,0 +1,123 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::process::Command;
+use std::sync::Mutex;
+use tracing::debug;
+
+/// Versions detected per binary, so `--version` runs once per process
+static DETECTED: Mutex<BTreeMap<String, Option<RsyncVersion>>> = Mutex::new(BTreeMap::new());
+
+/// Version of the local rsync binary, deciding which options it understands
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RsyncVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+    /// OpenBSD's reimplementation (macOS), which lacks many options whatever version it claims
+    pub openrsync: bool,
+}
+
+impl RsyncVersion {
+    /// Parse `rsync --version` output, e.g. `rsync  version 3.2.7  protocol version 31`
+    /// or openrsync's `rsync version 2.6.9 compatible`
+    pub fn parse(output: &str) -> Option<Self> {
+        let openrsync = output.contains("openrsync");
+        let words: Vec<&str> = output.split_whitespace().collect();
+        let version = words
+            .windows(2)
+            .filter(|pair| pair[0] == "version" && pair[1].contains('.'))
+            .map(|pair| pair[1])
+            .next()?;
+
+        let mut numbers = version.split('.').map(|part| {
+            let digits: String = part.chars().take_while(|c| c.is_ascii_digit()).collect();
+            digits.parse::<u32>().unwrap_or(0)
+        });
+        Some(Self {
+            major: numbers.next()?,
+            minor: numbers.next().unwrap_or(0),
+            patch: numbers.next().unwrap_or(0),
+            openrsync,
+        })
+    }
+
+    fn at_least(&self, major: u32, minor: u32, patch: u32) -> bool {
+        !self.openrsync && (self.major, self.minor, self.patch) >= (major, minor, patch)
+    }
+
+    /// `--iconv`
+    pub fn supports_iconv(&self) -> bool {
+        self.at_least(3, 0, 0)
+    }
+
+    /// `--preallocate`, `--delete-missing-args` and `--ignore-missing-args`
+    pub fn supports_3_1(&self) -> bool {
+        self.at_least(3, 1, 0)
+    }
+
+    /// `--compress-choice`
+    pub fn supports_compress_choice(&self) -> bool {
+        self.at_least(3, 2, 0)
+    }
+
+    /// `--stop-after`
+    pub fn supports_stop_after(&self) -> bool {
+        self.at_least(3, 2, 3)
+    }
+}
+
+impl fmt::Display for RsyncVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.openrsync {
+            write!(f, "openrsync ({}.{}.{} compatible)", self.major, self.minor, self.patch)
+        } else {
+            write!(f, "rsync {}.{}.{}", self.major, self.minor, self.patch)
+        }
+    }
+}
+
+/// Run `binary --version` once; None if it cannot be run or its output is not understood
+pub fn detect(binary: &str) -> Option<RsyncVersion> {
+    let mut detected = DETECTED.lock().unwrap();
+    if let Some(version) = detected.get(binary) {
+        return *version;
+    }
+
+    let version = Command::new(binary)
+        .arg("--version")
+        .output()
+        .ok()
+        .and_then(|output| RsyncVersion::parse(&String::from_utf8_lossy(&output.stdout)));
+    debug!("Detected {:?} for {}", version, binary);
+    detected.insert(binary.to_string(), version);
+    version
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_version_output() {
+        let rsync = RsyncVersion::parse(
+            "rsync  version 3.2.7  protocol version 31\nCopyright (C) 1996-2022 by Andrew Tridgell, Wayne Davison, and others.\n",
+        )
+        .unwrap();
+        assert_eq!((rsync.major, rsync.minor, rsync.patch, rsync.openrsync), (3, 2, 7, false));
+        assert!(rsync.supports_compress_choice());
+
+        let old = RsyncVersion::parse("rsync  version 2.6.9  protocol version 29\n").unwrap();
+        assert!(!old.supports_iconv() && !old.supports_3_1());
+
+        let openrsync = RsyncVersion::parse("openrsync: protocol version 29\nrsync version 2.6.9 compatible\n").unwrap();
+        assert!(openrsync.openrsync);
+        assert_eq!(openrsync.to_string(), "openrsync (2.6.9 compatible)");
+
+        assert_eq!(RsyncVersion::parse("command not found"), None);
+    }
+}
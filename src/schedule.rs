@@ -0,0 +1,191 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use crate::config::{BaseStoreConfig, NasConfig, SyncProfile};
+use crate::conflict::ConflictResolver;
+use crate::control::DaemonState;
+use crate::eta;
+use crate::notification::Notifier;
+use crate::sync::{RunStatus, SyncEngine};
+use crate::window::TimeWindow;
+use chrono::{DateTime, Local};
+use std::collections::HashMap;
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{error, info, warn};
+
+/// How often the scheduler looks for due profiles
+const TICK: Duration = Duration::from_secs(30);
+
+/// Wait after the daemon starts or the machine resumes, so the network is back up
+const SETTLE_DELAY: Duration = Duration::from_secs(60);
+
+/// The wall clock getting this far ahead of the monotonic clock means the machine slept
+const CLOCK_JUMP: Duration = Duration::from_secs(60);
+
+/// Wait before retrying a failed scheduled run
+const RETRY_DELAY: Duration = Duration::from_secs(300);
+
+/// Run profiles with `interval_minutes` from the daemon. Last runs are kept in the
+/// state database, so runs missed while the daemon was stopped or the machine was
+/// asleep happen shortly after startup or resume instead of at the next slot.
+pub async fn run(
+    profiles: Vec<SyncProfile>,
+    nas_config: NasConfig,
+    base_store: Option<BaseStoreConfig>,
+    notifier: Notifier,
+    state: DaemonState,
+) {
+    info!("Scheduling {} profile(s)", profiles.len());
+    let engine = SyncEngine::new(nas_config).with_base_store(base_store.as_ref());
+    let mut failed_at: HashMap<String, Instant> = HashMap::new();
+    tokio::time::sleep(SETTLE_DELAY).await;
+
+    loop {
+        for profile in &profiles {
+            if state.is_paused(&profile.name)
+                || failed_at.get(&profile.name).is_some_and(|at| at.elapsed() < RETRY_DELAY)
+            {
+                continue;
+            }
+            let resolver = match ConflictResolver::for_profile(profile) {
+                Ok(resolver) => resolver,
+                Err(e) => {
+                    warn!("Sync state unavailable for scheduled profile {}: {:#}", profile.name, e);
+                    continue;
+                }
+            };
+            let last_run = resolver.last_run(&profile.name).unwrap_or_else(|e| {
+                warn!("Failed to read last run of {}: {:#}", profile.name, e);
+                None
+            });
+            let now = Local::now();
+            if !is_due(profile, last_run, now) {
+                continue;
+            }
+            if let (Some(last), Some(interval)) = (last_run, profile.interval_minutes) {
+                let missed = (now - last).num_minutes() / interval as i64 - 1;
+                if missed > 0 {
+                    info!("Profile {} missed {} scheduled run(s), catching up", profile.name, missed);
+                }
+            }
+
+            let expected_secs = eta::history(&profile.name).expected_secs(None);
+            state.record_nas_access();
+            state.update(&profile.name, |s| {
+                s.syncing = true;
+                s.sync_started = Some(Local::now());
+                s.expected_secs = expected_secs;
+            });
+            let result = engine.sync_profile(profile, false).await;
+            let notifier = notifier.for_run(engine.run_id(&profile.name));
+            let result = match result {
+                Ok(stats) => {
+                    failed_at.remove(&profile.name);
+                    if let Err(e) = resolver.record_run(&profile.name) {
+                        warn!("Failed to record run of profile {}: {:#}", profile.name, e);
+                    }
+                    state.update(&profile.name, |s| {
+                        s.warnings = stats.warnings.clone();
+                        s.last_success = Some(Local::now());
+                    });
+                    notifier.send_recovery(&profile.name);
+                    if !stats.planned_conflicts.is_empty() {
+                        notifier.send_conflicts(&profile.name, &stats.conflicting_local_paths(profile));
+                    }
+                    if stats.status == RunStatus::Partial {
+                        format!("partial, {} file(s), {} not transferred", stats.files_transferred, stats.file_errors.len())
+                    } else {
+                        format!("ok, {} file(s)", stats.files_transferred)
+                    }
+                }
+                Err(e) => {
+                    error!("Scheduled sync of {} failed: {}", profile.name, e);
+                    failed_at.insert(profile.name.clone(), Instant::now());
+                    match crate::error::find(&e) {
+                        Some(sync_error) => {
+                            notifier.send_failure(
+                                &profile.name,
+                                sync_error.severity(),
+                                &format!("Profile {} failed ({})", profile.name, sync_error.kind()),
+                                &sync_error.to_string(),
+                            );
+                            format!("failed ({}): {}", sync_error.kind(), e)
+                        }
+                        None => format!("failed: {}", e),
+                    }
+                }
+            };
+            state.record_nas_access();
+            state.update(&profile.name, |s| {
+                s.syncing = false;
+                s.last_sync = Some(Local::now());
+                s.last_result = Some(result);
+            });
+        }
+
+        let (wall, monotonic) = (SystemTime::now(), Instant::now());
+        tokio::time::sleep(TICK).await;
+        if let Some(slept) = clock_jump(wall, monotonic, SystemTime::now(), Instant::now()) {
+            info!(
+                "Resumed after about {} minute(s) of sleep, checking for missed runs",
+                slept.as_secs() / 60
+            );
+            tokio::time::sleep(SETTLE_DELAY).await;
+        }
+    }
+}
+
+/// Whether a profile's interval has passed since its last run, inside its allowed window
+fn is_due(profile: &SyncProfile, last_run: Option<DateTime<Local>>, now: DateTime<Local>) -> bool {
+    let Some(interval) = profile.interval_minutes else {
+        return false;
+    };
+    let window_open = match profile.allowed_window.as_deref().map(TimeWindow::parse) {
+        Some(Ok(window)) => window.opened_at(now.naive_local()).is_some(),
+        Some(Err(_)) => false,
+        None => true,
+    };
+    window_open && last_run.is_none_or(|last| (now - last).num_minutes() >= interval as i64)
+}
+
+/// How long the machine was suspended between two readings; the monotonic clock
+/// stops during suspend while the wall clock keeps going
+fn clock_jump(wall_before: SystemTime, monotonic_before: Instant, wall_now: SystemTime, monotonic_now: Instant) -> Option<Duration> {
+    let wall = wall_now.duration_since(wall_before).ok()?;
+    let monotonic = monotonic_now.duration_since(monotonic_before);
+    wall.checked_sub(monotonic).filter(|jump| *jump >= CLOCK_JUMP)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration as ChronoDuration;
+
+    #[test]
+    fn test_missed_runs_are_due_and_sleep_is_detected() {
+        let profile: SyncProfile = toml::from_str(
+            r#"
+            name = "laptop"
+            local_path = "/home/me/docs"
+            remote_path = "/volume1/docs"
+            sync_type = "one-way"
+            enabled = true
+            interval_minutes = 60
+            "#,
+        )
+        .unwrap();
+        let now = Local::now();
+        assert!(is_due(&profile, None, now));
+        assert!(!is_due(&profile, Some(now - ChronoDuration::minutes(20)), now));
+        assert!(is_due(&profile, Some(now - ChronoDuration::hours(9)), now));
+
+        let (wall, monotonic) = (SystemTime::now(), Instant::now());
+        assert_eq!(clock_jump(wall, monotonic, wall + Duration::from_secs(30), monotonic), None);
+        assert_eq!(
+            clock_jump(wall, monotonic, wall + Duration::from_secs(3600), monotonic),
+            Some(Duration::from_secs(3600))
+        );
+    }
+}
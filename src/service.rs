@@ -0,0 +1,180 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use crate::config::{SyncProfile, SyncType};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::debug;
+
+/// State databases of profiles owned by other users, one directory per user
+const USER_STATE_DIR: &str = "/var/lib/ugnassync/users";
+
+/// The user a profile from the service's config directory belongs to; its rsync
+/// and ssh processes run with this user's IDs
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProfileOwner {
+    pub name: String,
+    pub uid: u32,
+    pub gid: u32,
+    pub home: PathBuf,
+}
+
+impl ProfileOwner {
+    /// Look a user up with `getent passwd`, which also covers LDAP and similar sources
+    pub fn from_uid(uid: u32) -> Result<Self> {
+        let output = Command::new("getent")
+            .arg("passwd")
+            .arg(uid.to_string())
+            .output()
+            .context("Failed to execute getent")?;
+        if !output.status.success() {
+            anyhow::bail!("No user with UID {}", uid);
+        }
+        parse_passwd_line(&String::from_utf8_lossy(&output.stdout)).with_context(|| format!("Unexpected passwd entry for UID {}", uid))
+    }
+
+    /// Drop to this user in the process `cmd` starts
+    pub fn apply(&self, cmd: &mut Command) {
+        cmd.uid(self.uid)
+            .gid(self.gid)
+            .env("HOME", &self.home)
+            .env("USER", &self.name)
+            .env("LOGNAME", &self.name);
+    }
+}
+
+/// `name:x:uid:gid:gecos:home:shell`
+fn parse_passwd_line(line: &str) -> Option<ProfileOwner> {
+    let fields: Vec<&str> = line.trim().split(':').collect();
+    if fields.len() < 6 {
+        return None;
+    }
+    Some(ProfileOwner {
+        name: fields[0].to_string(),
+        uid: fields[2].parse().ok()?,
+        gid: fields[3].parse().ok()?,
+        home: PathBuf::from(fields[5]),
+    })
+}
+
+/// One user's file in the config directory
+#[derive(Deserialize)]
+struct UserProfiles {
+    #[serde(default)]
+    sync_profiles: Vec<SyncProfile>,
+}
+
+/// Profiles from every `*.toml` in `dir`; profiles in files not owned by root
+/// belong to the file's owner and get a state database of their own
+pub fn load_conf_dir(dir: &Path) -> Result<Vec<SyncProfile>> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .with_context(|| format!("Failed to read config directory {}", dir.display()))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+        .collect();
+    files.sort();
+
+    let mut profiles = Vec::new();
+    for file in files {
+        let metadata = fs::metadata(&file).with_context(|| format!("Failed to read {}", file.display()))?;
+        // Anyone who can edit the file could otherwise run syncs as its owner
+        if metadata.mode() & 0o022 != 0 {
+            anyhow::bail!("{} is writable by group or others", file.display());
+        }
+
+        let content = fs::read_to_string(&file).with_context(|| format!("Failed to read {}", file.display()))?;
+        let user: UserProfiles =
+            toml::from_str(&content).with_context(|| format!("Failed to parse {}", file.display()))?;
+
+        let owner = match metadata.uid() {
+            0 => None,
+            uid => Some(ProfileOwner::from_uid(uid)?),
+        };
+        debug!("Loaded {} profile(s) from {} (owner {:?})", user.sync_profiles.len(), file.display(), owner);
+
+        for mut profile in user.sync_profiles {
+            if let Some(owner) = &owner {
+                check_unprivileged(&profile, &file)?;
+                profile.state_db = Some(
+                    Path::new(USER_STATE_DIR)
+                        .join(&owner.name)
+                        .join("sync_state.db")
+                        .to_string_lossy()
+                        .to_string(),
+                );
+                profile.owner = Some(owner.clone());
+            }
+            profiles.push(profile);
+        }
+    }
+    Ok(profiles)
+}
+
+/// Options that would make the service act as root on a user's behalf
+fn check_unprivileged(profile: &SyncProfile, file: &Path) -> Result<()> {
+    let privileged = [
+        ("state_db", profile.state_db.is_some()),
+        ("dump_cmd", profile.dump_cmd.is_some()),
+        ("snapshot", profile.snapshot.is_some()),
+        ("use_smb_mount", profile.use_smb_mount),
+        ("source_host", profile.source_host.is_some()),
+        ("replicate_to", !profile.replicate_to.is_empty()),
+        // Read and mounted by root: /etc/shadow as an exclude file, any fstab entry
+        ("exclude_from", profile.exclude_from.is_some()),
+        ("requires_mount", profile.requires_mount.is_some()),
+        ("sync_type = \"archive\"", profile.sync_type == SyncType::Archive),
+    ];
+    if let Some((option, _)) = privileged.iter().find(|(_, set)| *set) {
+        anyhow::bail!(
+            "Profile '{}' in {} uses {}, which is only allowed in files owned by root",
+            profile.name,
+            file.display(),
+            option
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_passwd_line() {
+        assert_eq!(
+            parse_passwd_line("alice:x:1000:1000:Alice,,,:/home/alice:/bin/bash\n"),
+            Some(ProfileOwner {
+                name: "alice".to_string(),
+                uid: 1000,
+                gid: 1000,
+                home: PathBuf::from("/home/alice"),
+            })
+        );
+        assert_eq!(parse_passwd_line(""), None);
+    }
+
+    #[test]
+    fn test_user_files_cannot_use_root_file_access() {
+        let profile = |option: &str| -> SyncProfile {
+            toml::from_str(&format!(
+                "name = \"docs\"\nlocal_path = \"/home/alice/docs\"\nremote_path = \"/volume1/alice\"\n\
+                 sync_type = \"backup\"\nenabled = true\n{}",
+                option
+            ))
+            .unwrap()
+        };
+        let file = Path::new("/etc/ugnassync/conf.d/alice.toml");
+        assert!(check_unprivileged(&profile(""), file).is_ok());
+        let error = check_unprivileged(&profile("exclude_from = \"/etc/shadow\""), file).unwrap_err();
+        assert!(error.to_string().contains("uses exclude_from"));
+        let error = check_unprivileged(&profile("requires_mount = \"/mnt/backup\""), file).unwrap_err();
+        assert!(error.to_string().contains("uses requires_mount"));
+    }
+}
@@ -3,17 +3,21 @@
 // Author: Immanuel Jeyaraj <irj@sefier.com>
 // License: GPL-3.0
 
-use crate::config::SmbConfig;
+use crate::config::{SmbAuth, SmbConfig};
+use crate::error::SyncError;
+use crate::mounts::{self, MountManager};
 use anyhow::{Context, Result};
 use std::fs;
 use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::time::Duration;
 use tracing::{debug, error, info, warn};
 
 pub struct SmbMount {
     config: SmbConfig,
     credentials_file: Option<PathBuf>,
+    /// Counted as a user of the share; it stays mounted while any other user remains
     is_mounted: bool,
 }
 
@@ -39,40 +43,8 @@ impl SmbMount {
 
     /// Create credentials file for SMB mount
     fn create_credentials_file(&mut self) -> Result<PathBuf> {
-        // Create credentials directory
-        let creds_dir = dirs::home_dir()
-            .context("Failed to get home directory")?
-            .join(".ugnassync")
-            .join("smb_credentials");
-
-        fs::create_dir_all(&creds_dir)
-            .context("Failed to create credentials directory")?;
-
-        // Create temporary credentials file
-        let creds_file = creds_dir.join(format!("smb_creds_{}.tmp", std::process::id()));
-
-        let mut file = fs::File::create(&creds_file)
-            .context("Failed to create credentials file")?;
-
-        // Write credentials
-        writeln!(file, "username={}", self.config.username)?;
-        writeln!(file, "password={}", self.config.password)?;
-        if !self.config.domain.is_empty() {
-            writeln!(file, "domain={}", self.config.domain)?;
-        }
-
-        // Set file permissions to 600 (owner read/write only)
-        #[cfg(unix)]
-        {
-            use std::os::unix::fs::PermissionsExt;
-            let permissions = fs::Permissions::from_mode(0o600);
-            fs::set_permissions(&creds_file, permissions)
-                .context("Failed to set credentials file permissions")?;
-        }
-
-        debug!("Created credentials file: {}", creds_file.display());
+        let creds_file = write_credentials_file(&self.config, &format!("smb_creds_{}.tmp", std::process::id()))?;
         self.credentials_file = Some(creds_file.clone());
-
         Ok(creds_file)
     }
 
@@ -89,18 +61,87 @@ impl SmbMount {
         Ok(())
     }
 
-    /// Mount the SMB share
+    /// Mount the SMB share, unless another profile of this process already did
     pub async fn mount(&mut self) -> Result<()> {
+        if self.is_mounted {
+            return Ok(());
+        }
+
+        // mount(8) blocks, and so does waiting for another profile mounting under the lock
+        let mut share = SmbMount::new(self.config.clone());
+        let credentials_file = tokio::task::spawn_blocking(move || -> Result<Option<PathBuf>> {
+            let manager = MountManager::global();
+            let mount_point = PathBuf::from(&share.config.mount_point);
+            let mut in_use = true;
+            manager.acquire(&mount_point, || {
+                in_use = false;
+                share.mount_share()
+            })?;
+
+            // Mounted for an earlier profile, the share may have gone stale since
+            if in_use {
+                if let Err(e) = manager.exclusive(|| share.remount_if_stale()) {
+                    manager.release(&mount_point, |_| {});
+                    return Err(e);
+                }
+            }
+            Ok(share.credentials_file.take())
+        })
+        .await
+        .context("Mount task failed")??;
+
+        self.credentials_file = credentials_file;
+        self.is_mounted = true;
+        Ok(())
+    }
+
+    /// Mount unless already mounted; true when this call mounted it
+    fn mount_share(&mut self) -> Result<bool> {
         info!("SMB mount enabled for share: {}", self.config.share_path);
         info!("Checking mount status: {}", self.config.mount_point);
 
         // Check if already mounted
         if self.is_mount_point_active()? {
+            if self.remount_if_stale()? {
+                return Ok(true);
+            }
             info!("Mount point {} is already mounted", self.config.mount_point);
-            self.is_mounted = true;
-            return Ok(());
+            return Ok(false);
         }
+        self.mount_cifs()?;
+        Ok(true)
+    }
+
+    /// A mount that still shows as active after the server rebooted makes rsync hang,
+    /// so it is lazily unmounted and mounted again; true when it was remounted
+    fn remount_if_stale(&mut self) -> Result<bool> {
+        let probe_path = match &self.config.probe_path {
+            Some(path) => Path::new(&self.config.mount_point).join(path),
+            None => PathBuf::from(&self.config.mount_point),
+        };
+        let Some(reason) = mounts::probe(&probe_path, Duration::from_secs(self.config.mount_timeout.max(1))) else {
+            return Ok(false);
+        };
+
+        warn!("SMB mount {} is stale ({}), remounting", self.config.mount_point, reason);
+        let output = Command::new("umount")
+            .arg("-l")
+            .arg(&self.config.mount_point)
+            .output()
+            .context("Failed to execute lazy umount command")?;
+        if !output.status.success() {
+            return Err(SyncError::Mount(format!(
+                "Stale mount {} could not be unmounted: {}",
+                self.config.mount_point,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ))
+            .into());
+        }
+        self.mount_cifs()?;
+        Ok(true)
+    }
 
+    fn mount_cifs(&mut self) -> Result<()> {
         // Create mount point if it doesn't exist
         let mount_point = Path::new(&self.config.mount_point);
         if !mount_point.exists() {
@@ -109,19 +150,27 @@ impl SmbMount {
                 .context("Failed to create mount point directory")?;
         }
 
-        // Create credentials file
-        let creds_file = self.create_credentials_file()?;
-
         // Build mount command
         let mut cmd = Command::new("mount");
         cmd.arg("-t").arg("cifs");
         cmd.arg(&self.config.share_path);
         cmd.arg(&self.config.mount_point);
-        cmd.arg("-o").arg(format!("credentials={}", creds_file.display()));
 
-        // Add custom mount options if specified
-        if !self.config.mount_options.is_empty() {
-            cmd.arg("-o").arg(&self.config.mount_options);
+        match self.config.auth {
+            SmbAuth::Password => {
+                let creds_file = self.create_credentials_file()?;
+                cmd.arg("-o").arg(format!("credentials={}", creds_file.display()));
+            }
+            SmbAuth::Kerberos => {
+                ensure_kerberos_ticket(&self.config)?;
+                cmd.arg("-o").arg(format!("username={}", self.config.username));
+            }
+        }
+
+        // Add version, security and permission options
+        let options = self.config.mount_option_string();
+        if !options.is_empty() {
+            cmd.arg("-o").arg(options);
         }
 
         info!(
@@ -132,7 +181,7 @@ impl SmbMount {
         debug!("Mount command: {:?}", cmd);
 
         // Execute mount command
-        let output = cmd.output().context("Failed to execute mount command")?;
+        let output = cmd.output().map_err(|e| SyncError::from_spawn("mount", e))?;
 
         if !output.status.success() {
             let stderr = String::from_utf8_lossy(&output.stderr);
@@ -142,22 +191,22 @@ impl SmbMount {
             let _ = self.cleanup_credentials_file();
 
             // Provide helpful error messages
-            if stderr.contains("Permission denied") || stderr.contains("permission denied") {
-                anyhow::bail!(
+            let error = if stderr.contains("Permission denied") || stderr.contains("permission denied") {
+                SyncError::PermissionDenied(format!(
                     "Permission denied. Try running with sudo or adding user to required groups.\nError: {}",
                     stderr
-                );
+                ))
             } else if stderr.contains("Host is down") || stderr.contains("Network is unreachable") {
-                anyhow::bail!("Network unreachable: {}\nError: {}", self.config.share_path, stderr);
+                SyncError::Unreachable(format!("Network unreachable: {}\nError: {}", self.config.share_path, stderr))
             } else if stderr.contains("mount error(13)") {
-                anyhow::bail!("Invalid credentials or authentication failed.\nError: {}", stderr);
+                SyncError::AuthFailed(format!("Invalid credentials or authentication failed.\nError: {}", stderr))
             } else {
-                anyhow::bail!("Mount failed: {}", stderr);
-            }
+                SyncError::Mount(format!("Mount failed: {}", stderr))
+            };
+            return Err(error.into());
         }
 
         info!("SMB share mounted successfully");
-        self.is_mounted = true;
 
         // Clean up credentials file in persistent mode, keep it for auto_unmount
         if !self.config.auto_unmount {
@@ -168,12 +217,29 @@ impl SmbMount {
         Ok(())
     }
 
-    /// Unmount the SMB share
+    /// Unmount the SMB share once no other profile of this process uses it
     pub async fn unmount(&mut self) -> Result<()> {
         if !self.is_mounted {
             return Ok(());
         }
+        self.is_mounted = false;
+
+        let share = SmbMount::new(self.config.clone());
+        let result = tokio::task::spawn_blocking(move || {
+            let mut result = Ok(());
+            MountManager::global().release(Path::new(&share.config.mount_point), |_| result = share.unmount_share());
+            result
+        })
+        .await
+        .context("Unmount task failed")?;
+
+        // Clean up credentials file
+        let _ = self.cleanup_credentials_file();
+        result
+    }
 
+    /// Unmount regardless of other users, as `ugnassync umount` does
+    pub(crate) fn unmount_share(&self) -> Result<()> {
         info!("Unmounting SMB share: {}", self.config.mount_point);
 
         // Check if mount point is busy
@@ -205,11 +271,6 @@ impl SmbMount {
         } else {
             info!("SMB share unmounted successfully");
         }
-
-        // Clean up credentials file
-        let _ = self.cleanup_credentials_file();
-
-        self.is_mounted = false;
         Ok(())
     }
 
@@ -224,15 +285,131 @@ impl SmbMount {
     }
 
     /// Check if the share is currently mounted
+    #[allow(dead_code)]
     pub fn is_mounted(&self) -> bool {
         self.is_mounted
     }
 }
 
+/// With idle_unmount_minutes, unmount the share once no profile has used it for that
+/// long; the next sync mounts it again. Runs for the life of the daemon.
+pub async fn unmount_when_idle(config: SmbConfig) {
+    let Some(minutes) = config.idle_unmount_minutes else {
+        return;
+    };
+    let idle = Duration::from_secs(minutes * 60);
+    loop {
+        tokio::time::sleep(idle.min(Duration::from_secs(60))).await;
+        // umount(8) runs under the manager's lock, away from the runtime's workers
+        let mount = SmbMount::new(config.clone());
+        let check = tokio::task::spawn_blocking(move || {
+            let mount_point = PathBuf::from(&mount.config.mount_point);
+            MountManager::global().unmount_if_idle(&mount_point, idle, || {
+                if mount.is_mount_point_active().unwrap_or(false) {
+                    info!("SMB share unused for {} min, unmounting so the NAS disks can spin down", minutes);
+                    if let Err(e) = mount.unmount_share() {
+                        warn!("Idle unmount of {} failed: {:#}", mount_point.display(), e);
+                    }
+                }
+            });
+        });
+        if let Err(e) = check.await {
+            warn!("Idle unmount check failed: {}", e);
+        }
+    }
+}
+
+/// Credentials file (username, password, domain) for mount.cifs and smbclient, readable
+/// by the owner only
+pub(crate) fn write_credentials_file(config: &SmbConfig, name: &str) -> Result<PathBuf> {
+    // Create credentials directory
+    let creds_dir = dirs::home_dir()
+        .context("Failed to get home directory")?
+        .join(".ugnassync")
+        .join("smb_credentials");
+
+    fs::create_dir_all(&creds_dir)
+        .context("Failed to create credentials directory")?;
+
+    // Create temporary credentials file
+    let creds_file = creds_dir.join(name);
+
+    let mut file = fs::File::create(&creds_file)
+        .context("Failed to create credentials file")?;
+
+    // Write credentials
+    writeln!(file, "username={}", config.username)?;
+    writeln!(file, "password={}", config.password)?;
+    if !config.domain.is_empty() {
+        writeln!(file, "domain={}", config.domain)?;
+    }
+
+    // Set file permissions to 600 (owner read/write only)
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let permissions = fs::Permissions::from_mode(0o600);
+        fs::set_permissions(&creds_file, permissions)
+            .context("Failed to set credentials file permissions")?;
+    }
+
+    debug!("Created credentials file: {}", creds_file.display());
+    Ok(creds_file)
+}
+
+/// A valid Kerberos ticket for the share, from `kinit` with the keytab when there is none
+pub(crate) fn ensure_kerberos_ticket(config: &SmbConfig) -> Result<()> {
+    if has_kerberos_ticket() {
+        return Ok(());
+    }
+
+    let Some(keytab) = &config.keytab else {
+        return Err(SyncError::AuthFailed(
+            "No Kerberos ticket for the SMB share; run kinit or set keytab in [nas.smb]".to_string(),
+        )
+        .into());
+    };
+    let principal = config.principal.as_deref().unwrap_or(&config.username);
+    info!("Getting a Kerberos ticket for {} from {}", principal, keytab);
+    let output = Command::new("kinit")
+        .arg("-k")
+        .arg("-t")
+        .arg(keytab)
+        .arg(principal)
+        .output()
+        .map_err(|e| SyncError::from_spawn("kinit", e))?;
+    if !output.status.success() || !has_kerberos_ticket() {
+        return Err(SyncError::AuthFailed(format!(
+            "kinit with keytab {} failed for {}: {}",
+            keytab,
+            principal,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+/// `klist -s` succeeds when the credential cache holds a ticket that has not expired
+fn has_kerberos_ticket() -> bool {
+    Command::new("klist").arg("-s").status().is_ok_and(|status| status.success())
+}
+
 impl Drop for SmbMount {
     fn drop(&mut self) {
         // Clean up credentials file when dropped
         let _ = self.cleanup_credentials_file();
+
+        // Dropped without unmount() after a failed sync or with auto_unmount = false
+        if self.is_mounted {
+            MountManager::global().release(Path::new(&self.config.mount_point), |_| {
+                if !self.should_auto_unmount() {
+                    debug!("Leaving {} mounted", self.config.mount_point);
+                } else if let Err(e) = self.unmount_share() {
+                    warn!("Failed to unmount {}: {:#}", self.config.mount_point, e);
+                }
+            });
+        }
     }
 }
 
@@ -249,9 +426,21 @@ mod tests {
             domain: "".to_string(),
             username: "admin".to_string(),
             password: "password".to_string(),
+            auth: SmbAuth::Password,
+            keytab: None,
+            principal: None,
+            vers: None,
+            sec: None,
+            seal: false,
+            uid: None,
+            gid: None,
+            file_mode: None,
+            dir_mode: None,
             mount_options: "".to_string(),
             auto_unmount: true,
+            idle_unmount_minutes: None,
             mount_timeout: 30,
+            probe_path: None,
         };
 
         let mount = SmbMount::new(config);
@@ -0,0 +1,304 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use crate::config::{SmbAuth, SmbConfig};
+use crate::error::SyncError;
+use crate::push::{Backend, LocalFile, RemoteFile};
+use crate::smb;
+use crate::sync::FileError;
+use anyhow::{Context, Result};
+use chrono::NaiveDateTime;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use tracing::{debug, info, warn};
+
+/// Runs smbclient against `[nas.smb]` share_path, in the profile's remote_path
+pub struct SmbClient {
+    config: SmbConfig,
+    /// remote_path with backslashes, no leading or trailing separator
+    remote_dir: String,
+    credentials_file: Option<PathBuf>,
+}
+
+impl SmbClient {
+    pub fn new(config: &SmbConfig, remote_path: &str) -> Result<Self> {
+        let credentials_file = match config.auth {
+            SmbAuth::Password => Some(smb::write_credentials_file(
+                config,
+                &format!("smbclient_creds_{}.tmp", std::process::id()),
+            )?),
+            SmbAuth::Kerberos => {
+                smb::ensure_kerberos_ticket(config)?;
+                None
+            }
+        };
+        Ok(Self {
+            config: config.clone(),
+            remote_dir: remote_path.trim_matches('/').replace('/', "\\"),
+            credentials_file,
+        })
+    }
+
+    fn command(&self) -> Command {
+        let mut cmd = Command::new("smbclient");
+        cmd.arg(&self.config.share_path);
+        match &self.credentials_file {
+            Some(file) => cmd.arg("-A").arg(file),
+            None => cmd.arg("-U").arg(&self.config.username).arg("--use-kerberos=required"),
+        };
+        if let Some(protocol) = self.config.vers.as_deref().and_then(max_protocol) {
+            cmd.arg("-m").arg(protocol);
+        }
+        if self.config.seal {
+            cmd.arg("-e");
+        }
+        cmd
+    }
+
+    fn run(&self, cmd: &mut Command, what: &str) -> Result<Output> {
+        debug!("smbclient command: {:?}", cmd);
+        let output = cmd.output().map_err(|e| SyncError::from_spawn("smbclient", e))?;
+        if !output.status.success() {
+            return Err(error(what, &output).into());
+        }
+        Ok(output)
+    }
+
+    /// smbclient starting in remote_path; it exits before running any command when
+    /// that directory cannot be entered, so nothing happens at the share's root instead
+    fn in_remote_dir(&self) -> Command {
+        let mut cmd = self.command();
+        if !self.remote_dir.is_empty() {
+            cmd.arg("-D").arg(&self.remote_dir);
+        }
+        cmd
+    }
+
+    /// Create remote_path, one level at a time; existing levels are fine
+    fn create_remote_dir(&self) {
+        let mut dir = String::new();
+        for part in self.remote_dir.split('\\').filter(|part| !part.is_empty()) {
+            if !dir.is_empty() {
+                dir.push('\\');
+            }
+            dir.push_str(part);
+            let output = self.command().arg("-c").arg(format!("mkdir \"{}\"", dir)).output();
+            if let Ok(output) = output {
+                if !output.status.success() {
+                    debug!("mkdir {}: {}", dir, String::from_utf8_lossy(&output.stdout).trim());
+                }
+            }
+        }
+    }
+}
+
+impl Backend for SmbClient {
+    fn name(&self) -> &'static str {
+        "smbclient"
+    }
+
+    /// Files below remote_path; none when it does not exist yet
+    fn list(&self) -> Result<Vec<RemoteFile>> {
+        let output = self
+            .in_remote_dir()
+            .arg("-c")
+            .arg("recurse ON; ls")
+            .output()
+            .map_err(|e| SyncError::from_spawn("smbclient", e))?;
+        if output.status.success() {
+            return Ok(parse_listing(&String::from_utf8_lossy(&output.stdout)));
+        }
+        let error = error("list the share", &output);
+        let message = error.to_string();
+        if message.contains("NT_STATUS_OBJECT_NAME_NOT_FOUND") || message.contains("NT_STATUS_OBJECT_PATH_NOT_FOUND") {
+            debug!("{} does not exist on the share yet", self.remote_dir);
+            return Ok(Vec::new());
+        }
+        Err(error.into())
+    }
+
+    /// Stream `files` (relative to `root`) in a tar archive that smbclient unpacks on the
+    /// share, creating directories as needed
+    fn upload(&self, root: &Path, files: &[LocalFile], _remote: &[RemoteFile]) -> Result<Vec<FileError>> {
+        if files.is_empty() {
+            return Ok(Vec::new());
+        }
+        info!("Uploading {} file(s) to {}\\{}", files.len(), self.config.share_path, self.remote_dir);
+        self.create_remote_dir();
+
+        let list = std::env::temp_dir().join(format!("ugnassync-smbclient-{}.list", std::process::id()));
+        let mut names = Vec::new();
+        for file in files {
+            names.extend_from_slice(file.path.as_bytes());
+            names.push(0);
+        }
+        fs::File::create(&list)?.write_all(&names)?;
+
+        let mut tar = Command::new("tar")
+            .arg("-c")
+            .arg("-f")
+            .arg("-")
+            .arg("-C")
+            .arg(root)
+            .arg("--null")
+            .arg("--no-recursion")
+            .arg("-T")
+            .arg(&list)
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| SyncError::from_spawn("tar", e))?;
+        let archive = tar.stdout.take().context("tar has no output")?;
+
+        let mut cmd = self.in_remote_dir();
+        cmd.arg("-Tx").arg("-").stdin(archive);
+        let result = self.run(&mut cmd, "upload files");
+
+        let tar_status = tar.wait();
+        let _ = fs::remove_file(&list);
+        result?;
+        if !tar_status?.success() {
+            anyhow::bail!("tar failed while packing the files to upload");
+        }
+        Ok(Vec::new())
+    }
+
+    /// Names smbclient cannot quote are left with a warning
+    fn delete(&self, paths: &[String]) -> Result<Vec<String>> {
+        let (deletable, skipped): (Vec<&String>, Vec<&String>) = paths.iter().partition(|path| !path.contains(['"', ';']));
+        for path in &skipped {
+            warn!("Not deleting {} over smbclient: the name cannot be quoted", path);
+        }
+        for batch in deletable.chunks(100) {
+            let script: Vec<String> = batch.iter().map(|path| format!("del \"{}\"", path.replace('/', "\\"))).collect();
+            self.run(self.in_remote_dir().arg("-c").arg(script.join("; ")), "delete files")?;
+        }
+        Ok(deletable.into_iter().cloned().collect())
+    }
+}
+
+impl Drop for SmbClient {
+    fn drop(&mut self) {
+        if let Some(file) = self.credentials_file.take() {
+            let _ = fs::remove_file(file);
+        }
+    }
+}
+
+/// smbclient's `-m` name for a `vers` value of mount.cifs
+fn max_protocol(vers: &str) -> Option<&'static str> {
+    match vers {
+        "2.0" => Some("SMB2_02"),
+        "2.1" => Some("SMB2_10"),
+        "3" => Some("SMB3"),
+        "3.0" => Some("SMB3_00"),
+        "3.02" => Some("SMB3_02"),
+        "3.1.1" => Some("SMB3_11"),
+        _ => None,
+    }
+}
+
+fn error(what: &str, output: &Output) -> SyncError {
+    // smbclient prints most errors on stdout
+    let message = format!(
+        "smbclient could not {}: {}{}",
+        what,
+        String::from_utf8_lossy(&output.stderr).trim(),
+        String::from_utf8_lossy(&output.stdout).trim()
+    );
+    if message.contains("NT_STATUS_LOGON_FAILURE") || message.contains("NT_STATUS_ACCOUNT_") {
+        SyncError::AuthFailed(message)
+    } else if message.contains("NT_STATUS_ACCESS_DENIED") {
+        SyncError::PermissionDenied(message)
+    } else if message.contains("NT_STATUS_DISK_FULL") || message.contains("NT_STATUS_QUOTA_EXCEEDED") {
+        SyncError::DiskFull(message)
+    } else if message.contains("NT_STATUS_HOST_UNREACHABLE")
+        || message.contains("NT_STATUS_CONNECTION_REFUSED")
+        || message.contains("NT_STATUS_IO_TIMEOUT")
+    {
+        SyncError::Unreachable(message)
+    } else {
+        SyncError::Mount(message)
+    }
+}
+
+/// `recurse ON; ls` output: entries like `  a b.txt   A   1234  Mon Jan  1 12:00:00 2024`
+/// below `\sub\dir` headers, directories flagged `D`
+fn parse_listing(output: &str) -> Vec<RemoteFile> {
+    let mut dir = String::new();
+    let mut files = Vec::new();
+    for line in output.lines() {
+        if let Some(header) = line.strip_prefix('\\') {
+            dir = header.replace('\\', "/");
+            continue;
+        }
+        let Some(entry) = line.strip_prefix("  ") else {
+            continue;
+        };
+        let Some((name, attributes, size, modified)) = parse_entry(entry) else {
+            continue;
+        };
+        if attributes.contains('D') {
+            continue;
+        }
+        files.push(RemoteFile {
+            path: if dir.is_empty() { name.to_string() } else { format!("{}/{}", dir, name) },
+            size,
+            modified,
+        });
+    }
+    files
+}
+
+/// Split off the timestamp, size and attributes from the right, since names have spaces
+fn parse_entry(entry: &str) -> Option<(&str, &str, u64, NaiveDateTime)> {
+    let mut rest = entry.trim_end();
+    let mut date = Vec::new();
+    for _ in 0..5 {
+        let (before, token) = rest.rsplit_once(char::is_whitespace)?;
+        date.push(token);
+        rest = before.trim_end();
+    }
+    date.reverse();
+    let modified = NaiveDateTime::parse_from_str(&date.join(" "), "%a %b %d %H:%M:%S %Y").ok()?;
+
+    let (before, size) = rest.rsplit_once(char::is_whitespace)?;
+    let size = size.parse().ok()?;
+    rest = before.trim_end();
+
+    // Attributes (DAHSRN...) are missing on some servers for plain files
+    let (name, attributes) = match rest.rsplit_once(char::is_whitespace) {
+        Some((name, attributes)) if !attributes.is_empty() && attributes.chars().all(|c| "DAHSRNIL".contains(c)) => {
+            (name.trim_end(), attributes)
+        }
+        _ => (rest, ""),
+    };
+    Some((name, attributes, size, modified)).filter(|(name, ..)| !name.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_listing() {
+        let files = parse_listing(
+            "  .                                   D        0  Mon Jan  1 12:00:00 2024\n\
+             \x20 ..                                  D        0  Mon Jan  1 12:00:00 2024\n\
+             \x20 notes 2024.txt                      A     1234  Tue Feb 13 09:30:05 2024\n\
+             \x20 Photos                              D        0  Mon Jan  1 12:00:00 2024\n\
+             \n\
+             \\Photos\n\
+             \x20 .                                   D        0  Mon Jan  1 12:00:00 2024\n\
+             \x20 beach.jpg                           N  2048000  Sat Aug  3 18:01:59 2024\n\
+             \n\
+             \t\t9764864 blocks of size 1024. 5432101 blocks available\n",
+        );
+        let paths: Vec<(&str, u64)> = files.iter().map(|file| (file.path.as_str(), file.size)).collect();
+        assert_eq!(paths, vec![("notes 2024.txt", 1234), ("Photos/beach.jpg", 2048000)]);
+        assert_eq!(files[1].modified.to_string(), "2024-08-03 18:01:59");
+    }
+}
@@ -0,0 +1,210 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use crate::config::{SnapshotConfig, SnapshotKind, SyncProfile};
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::{debug, error, info};
+
+/// Name of the directory holding btrfs snapshots inside the subvolume
+pub const BTRFS_SNAPSHOT_DIR: &str = ".ugnassync-snapshots";
+
+/// A read-only snapshot of a profile's source filesystem, removed when dropped
+pub struct SourceSnapshot {
+    origin: PathBuf,
+    root: PathBuf,
+    /// LVM snapshot device, mounted at `root`
+    lvm_device: Option<String>,
+    released: bool,
+}
+
+impl SourceSnapshot {
+    pub fn create(profile: &SyncProfile, config: &SnapshotConfig) -> Result<Self> {
+        if profile.uses_relative_paths() {
+            anyhow::bail!("Source snapshots are not supported for profiles with several local_paths");
+        }
+
+        let name = format!(
+            "ugnassync-{}",
+            profile
+                .name
+                .chars()
+                .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+                .collect::<String>()
+        );
+        let origin = PathBuf::from(&config.root);
+
+        match config.kind {
+            SnapshotKind::Lvm => {
+                let volume = config.volume.as_deref().context("LVM snapshots require volume")?;
+                let device = Path::new(volume)
+                    .with_file_name(&name)
+                    .to_string_lossy()
+                    .to_string();
+                let root = config
+                    .mount_point
+                    .as_ref()
+                    .map(PathBuf::from)
+                    .unwrap_or_else(|| std::env::temp_dir().join(&name));
+
+                info!("Creating LVM snapshot {} of {}", device, volume);
+                run(Command::new("lvcreate")
+                    .arg("--snapshot")
+                    .arg("--size")
+                    .arg(&config.size)
+                    .arg("--name")
+                    .arg(&name)
+                    .arg(volume))?;
+
+                // From here on Drop removes the snapshot again
+                let snapshot = Self {
+                    origin,
+                    root,
+                    lvm_device: Some(device),
+                    released: false,
+                };
+
+                fs::create_dir_all(&snapshot.root)
+                    .with_context(|| format!("Failed to create {}", snapshot.root.display()))?;
+                let mut mount = Command::new("mount");
+                mount.arg("-o").arg(match &config.mount_options {
+                    Some(options) => format!("ro,{}", options),
+                    None => "ro".to_string(),
+                });
+                run(mount.arg(snapshot.lvm_device.as_deref().unwrap_or_default()).arg(&snapshot.root))?;
+
+                Ok(snapshot)
+            }
+            SnapshotKind::Btrfs => {
+                let dir = origin.join(BTRFS_SNAPSHOT_DIR);
+                fs::create_dir_all(&dir)
+                    .with_context(|| format!("Failed to create {}", dir.display()))?;
+                let root = dir.join(&name);
+
+                // A crashed run may have left its snapshot behind
+                if root.exists() {
+                    run(Command::new("btrfs").arg("subvolume").arg("delete").arg(&root))?;
+                }
+
+                info!("Creating btrfs snapshot {}", root.display());
+                run(Command::new("btrfs")
+                    .arg("subvolume")
+                    .arg("snapshot")
+                    .arg("-r")
+                    .arg(&origin)
+                    .arg(&root))?;
+
+                Ok(Self {
+                    origin,
+                    root,
+                    lvm_device: None,
+                    released: false,
+                })
+            }
+        }
+    }
+
+    /// The profile with its source path moved into the snapshot
+    pub fn apply(&self, profile: &SyncProfile) -> Result<SyncProfile> {
+        let mut snapshot_profile = profile.clone();
+        snapshot_profile.local_path = self.rebase(&profile.local_path)?;
+        if self.lvm_device.is_none() {
+            snapshot_profile.exclude.push(BTRFS_SNAPSHOT_DIR.to_string());
+        }
+        Ok(snapshot_profile)
+    }
+
+    fn rebase(&self, path: &str) -> Result<String> {
+        let relative = Path::new(path).strip_prefix(&self.origin).with_context(|| {
+            format!("{} is not inside snapshot root {}", path, self.origin.display())
+        })?;
+
+        // rsync would name the remote directory after the snapshot instead of the source
+        if relative.as_os_str().is_empty() && !path.ends_with('/') {
+            anyhow::bail!("Add a trailing slash to {} to sync it from a snapshot", path);
+        }
+
+        let mut rebased = self.root.join(relative).to_string_lossy().to_string();
+        // The trailing slash decides whether rsync copies the directory or its contents
+        if path.ends_with('/') && !rebased.ends_with('/') {
+            rebased.push('/');
+        }
+        Ok(rebased)
+    }
+
+    /// Unmount and delete the snapshot
+    pub fn release(mut self) -> Result<()> {
+        self.released = true;
+        self.remove()
+    }
+
+    fn remove(&self) -> Result<()> {
+        match &self.lvm_device {
+            Some(device) => {
+                // Try lvremove even if the snapshot never got mounted
+                let unmounted = run(Command::new("umount").arg(&self.root));
+                run(Command::new("lvremove").arg("-f").arg(device))?;
+                let _ = fs::remove_dir(&self.root);
+                unmounted?;
+            }
+            None => run(Command::new("btrfs").arg("subvolume").arg("delete").arg(&self.root))?,
+        }
+        info!("Removed source snapshot {}", self.root.display());
+        Ok(())
+    }
+}
+
+impl Drop for SourceSnapshot {
+    fn drop(&mut self) {
+        if !self.released {
+            if let Err(e) = self.remove() {
+                error!("Failed to remove source snapshot {}: {:#}", self.root.display(), e);
+            }
+        }
+    }
+}
+
+fn run(cmd: &mut Command) -> Result<()> {
+    debug!("Running {:?}", cmd);
+    let output = cmd
+        .output()
+        .with_context(|| format!("Failed to execute {:?}", cmd.get_program()))?;
+
+    if !output.status.success() {
+        anyhow::bail!(
+            "{:?} failed: {}",
+            cmd.get_program(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rebase_keeps_trailing_slash() {
+        let snapshot = SourceSnapshot {
+            origin: PathBuf::from("/srv/data"),
+            root: PathBuf::from("/srv/data/.ugnassync-snapshots/ugnassync-db"),
+            lvm_device: None,
+            released: true,
+        };
+
+        assert_eq!(
+            snapshot.rebase("/srv/data/postgres/").unwrap(),
+            "/srv/data/.ugnassync-snapshots/ugnassync-db/postgres/"
+        );
+        assert_eq!(
+            snapshot.rebase("/srv/data/postgres").unwrap(),
+            "/srv/data/.ugnassync-snapshots/ugnassync-db/postgres"
+        );
+        assert!(snapshot.rebase("/home/user").is_err());
+    }
+}
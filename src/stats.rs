@@ -0,0 +1,255 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use crate::config::MetricsConfig;
+use crate::conflict::{ConflictResolver, RunRecord};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, NaiveDate, TimeZone};
+use serde::Serialize;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+/// Measurement name of the pushed aggregates
+const MEASUREMENT: &str = "ugnassync_profile";
+
+/// Totals over all recorded runs of one profile
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct ProfileAggregate {
+    pub profile: String,
+    pub runs: u64,
+    pub succeeded: u64,
+    pub partial: u64,
+    pub failed: u64,
+    pub files: u64,
+    pub bytes: u64,
+    pub network_bytes: u64,
+    pub conflicts: u64,
+    pub duration_secs: f64,
+    pub last_run: Option<DateTime<Local>>,
+    pub last_success: Option<DateTime<Local>>,
+}
+
+/// `--since 2024-01-01` as local midnight
+pub fn parse_since(date: &str) -> Result<DateTime<Local>> {
+    let date = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .with_context(|| format!("Invalid date '{}', expected YYYY-MM-DD", date))?;
+    Local
+        .from_local_datetime(&date.and_hms_opt(0, 0, 0).unwrap_or_default())
+        .earliest()
+        .with_context(|| format!("{} has no local midnight", date))
+}
+
+/// Per-profile totals of `runs`, by profile name
+pub fn aggregate(runs: &[RunRecord]) -> Vec<ProfileAggregate> {
+    let mut by_profile: BTreeMap<&str, ProfileAggregate> = BTreeMap::new();
+    for run in runs {
+        let total = by_profile.entry(&run.profile).or_insert_with(|| ProfileAggregate {
+            profile: run.profile.clone(),
+            ..Default::default()
+        });
+        total.runs += 1;
+        match run.status.as_str() {
+            "success" => total.succeeded += 1,
+            "partial" => total.partial += 1,
+            _ => total.failed += 1,
+        }
+        total.files += run.files;
+        total.bytes += run.bytes;
+        total.network_bytes += run.network_bytes;
+        total.conflicts += run.conflicts;
+        total.duration_secs += run.duration_secs;
+        total.last_run = total.last_run.max(Some(run.started_at));
+        if run.status != "failed" {
+            total.last_success = total.last_success.max(Some(run.started_at));
+        }
+    }
+    by_profile.into_values().collect()
+}
+
+/// One row per run, timestamps in RFC 3339
+pub fn runs_csv(runs: &[RunRecord]) -> String {
+    let mut csv = String::from("run_id,profile,device,started_at,duration_secs,status,files,bytes,network_bytes,conflicts,error\n");
+    for run in runs {
+        csv.push_str(&format!(
+            "{},{},{},{},{:.3},{},{},{},{},{},{}\n",
+            run.run_id,
+            csv_field(&run.profile),
+            csv_field(&run.device),
+            run.started_at.to_rfc3339(),
+            run.duration_secs,
+            run.status,
+            run.files,
+            run.bytes,
+            run.network_bytes,
+            run.conflicts,
+            csv_field(run.error.as_deref().unwrap_or(""))
+        ));
+    }
+    csv
+}
+
+pub fn aggregates_csv(aggregates: &[ProfileAggregate]) -> String {
+    let mut csv = String::from(
+        "profile,runs,succeeded,partial,failed,files,bytes,network_bytes,conflicts,duration_secs,last_run,last_success\n",
+    );
+    let time = |at: Option<DateTime<Local>>| at.map(|at| at.to_rfc3339()).unwrap_or_default();
+    for total in aggregates {
+        csv.push_str(&format!(
+            "{},{},{},{},{},{},{},{},{},{:.3},{},{}\n",
+            csv_field(&total.profile),
+            total.runs,
+            total.succeeded,
+            total.partial,
+            total.failed,
+            total.files,
+            total.bytes,
+            total.network_bytes,
+            total.conflicts,
+            total.duration_secs,
+            time(total.last_run),
+            time(total.last_success)
+        ));
+    }
+    csv
+}
+
+/// Runs and per-profile aggregates in one document
+pub fn to_json(runs: &[RunRecord], aggregates: &[ProfileAggregate]) -> Result<String> {
+    serde_json::to_string_pretty(&json!({ "runs": runs, "profiles": aggregates }))
+        .context("Failed to serialize run history")
+}
+
+/// Quote a field containing separators, quotes or line breaks (RFC 4180)
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// InfluxDB line protocol, one line per profile
+pub fn line_protocol(aggregates: &[ProfileAggregate], at: DateTime<Local>) -> String {
+    let timestamp = at.timestamp_nanos_opt().unwrap_or_default();
+    aggregates
+        .iter()
+        .map(|total| {
+            format!(
+                "{},profile={} runs={}i,succeeded={}i,partial={}i,failed={}i,files={}i,bytes={}i,network_bytes={}i,conflicts={}i,duration_secs={} {}\n",
+                MEASUREMENT,
+                escape_tag(&total.profile),
+                total.runs,
+                total.succeeded,
+                total.partial,
+                total.failed,
+                total.files,
+                total.bytes,
+                total.network_bytes,
+                total.conflicts,
+                total.duration_secs,
+                timestamp
+            )
+        })
+        .collect()
+}
+
+fn escape_tag(value: &str) -> String {
+    value.replace('\\', "\\\\").replace(',', "\\,").replace('=', "\\=").replace(' ', "\\ ")
+}
+
+/// Write aggregates to the configured endpoint with curl; the token goes in on stdin
+pub fn push(metrics: &MetricsConfig, aggregates: &[ProfileAggregate]) -> Result<()> {
+    if aggregates.is_empty() {
+        debug!("No run history to push");
+        return Ok(());
+    }
+    let mut cmd = Command::new("curl");
+    cmd.arg("-fsS")
+        .arg("-m")
+        .arg("10")
+        .arg("-H")
+        .arg("Content-Type: text/plain; charset=utf-8")
+        .arg("--data-binary")
+        .arg(line_protocol(aggregates, Local::now()));
+    if metrics.token.is_some() {
+        cmd.arg("-H").arg("@-");
+    }
+    let mut child = cmd
+        .arg(&metrics.push_url)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to execute curl")?;
+    if let (Some(token), Some(mut stdin)) = (&metrics.token, child.stdin.take()) {
+        writeln!(stdin, "Authorization: Token {}", token).context("Failed to pass the metrics token to curl")?;
+    }
+    let output = child.wait_with_output().context("Failed to execute curl")?;
+    if !output.status.success() {
+        anyhow::bail!("Pushing metrics failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
+
+/// Push the aggregates of the whole run history every `push_interval_minutes`
+pub async fn push_loop(metrics: MetricsConfig) {
+    info!("Pushing run statistics to {} every {} minute(s)", metrics.push_url, metrics.push_interval_minutes);
+    let mut interval = tokio::time::interval(Duration::from_secs(metrics.push_interval_minutes * 60));
+    loop {
+        interval.tick().await;
+        let result = ConflictResolver::new()
+            .and_then(|resolver| resolver.run_history(None))
+            .and_then(|runs| push(&metrics, &aggregate(&runs)));
+        if let Err(e) = result {
+            warn!("Failed to push run statistics: {:#}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aggregate_and_export_formats() {
+        let at = parse_since("2025-03-10").unwrap();
+        let run = |profile: &str, status: &str, files: u64, error: Option<&str>| RunRecord {
+            run_id: "20250310-000000-3fa9c1".to_string(),
+            profile: profile.to_string(),
+            device: "laptop".to_string(),
+            started_at: at,
+            duration_secs: 1.5,
+            status: status.to_string(),
+            files,
+            bytes: files * 100,
+            network_bytes: files * 50,
+            conflicts: 0,
+            error: error.map(str::to_string),
+        };
+        let runs = vec![
+            run("docs", "success", 3, None),
+            run("docs", "failed", 0, Some("NAS unreachable, \"timeout\"")),
+            run("my photos", "partial", 2, None),
+        ];
+
+        let totals = aggregate(&runs);
+        assert_eq!(totals.len(), 2);
+        assert_eq!((totals[0].runs, totals[0].succeeded, totals[0].failed, totals[0].files), (2, 1, 1, 3));
+        assert_eq!(totals[0].last_success, Some(at));
+
+        let csv = runs_csv(&runs);
+        assert_eq!(csv.lines().count(), 4);
+        assert!(csv.lines().nth(2).unwrap().ends_with(",\"NAS unreachable, \"\"timeout\"\"\""));
+
+        let lines = line_protocol(&totals, at);
+        assert!(lines.starts_with("ugnassync_profile,profile=docs runs=2i,succeeded=1i,"));
+        assert!(lines.contains("profile=my\\ photos "));
+        assert!(parse_since("2025-13-01").is_err());
+    }
+}
@@ -3,22 +3,310 @@
 // Author: Immanuel Jeyaraj <irj@sefier.com>
 // License: GPL-3.0
 
-use crate::config::{ConflictResolution, NasConfig, SyncProfile, SyncType};
-use crate::conflict::ConflictResolver;
+use crate::archive;
+use crate::basestore::BaseStore;
+use crate::cascade;
+use crate::capabilities;
+use crate::clock;
+use crate::config::{
+    BaseStoreConfig, ConflictResolution, NamePolicy, NasConfig, QuotaCheck, RsyncRunner, SourceHost, SpecialFilePolicy,
+    StateFallback, SyncProfile, SyncType,
+};
+use crate::adopt;
+use crate::conflict::{self, state_key, ConflictResolver, RunRecord};
+use crate::device;
+use crate::connectivity;
+use crate::dump;
+use crate::eta;
+use crate::logging;
+use crate::markers;
+use crate::mounts::{self, RequiredMount};
+use crate::error::SyncError;
+use crate::ftp::FtpClient;
+use crate::preflight;
+use crate::push::{self, Backend};
+use crate::quota;
+use crate::ransomware::{self, Quarantine};
+use crate::names::{self, IssueKind, NameIssue};
+use crate::nashealth;
+use crate::origin;
+use crate::remote::{shell_quote, RemoteShell};
+use crate::rsync::{self, RsyncVersion};
 use crate::smb::SmbMount;
+use crate::smbclient::SmbClient;
+use crate::snapshot::SourceSnapshot;
+use crate::vendor;
+use crate::versions::{self, Side};
+use crate::watch::WatchManager;
 use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
 use std::time::Instant;
-use tracing::{debug, error, info, warn};
+use tracing::{debug, error, info, info_span, warn, Instrument};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize)]
 pub struct SyncStats {
     pub files_transferred: u64,
     pub bytes_transferred: u64,
+    /// Bytes sent and received over the network (after compression)
+    pub network_bytes: u64,
     pub duration_secs: f64,
     pub conflicts_detected: u64,
     pub conflicts_skipped: u64,
     pub conflicts_resolved: u64,
+    pub changed_files: Vec<String>,
+    /// Subset of changed_files that replace an existing destination file
+    pub replaced_files: Vec<String>,
+    pub deleted_files: Vec<String>,
+    pub planned_conflicts: Vec<PlannedConflict>,
+    pub phase_timings: Vec<PhaseTiming>,
+    /// Whether a sync state database was available for conflict detection
+    pub conflict_detection: bool,
+    pub warnings: Vec<String>,
+    /// Files moved to the NAS and deleted locally by an archive run
+    pub archived_files: Vec<String>,
+    pub status: RunStatus,
+    /// Files rsync reported errors for in a partial run
+    pub file_errors: Vec<FileError>,
+    /// Correlates log lines, history rows, reports and notifications of one run
+    pub run_id: String,
+}
+
+/// A file rsync could not transfer and why
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FileError {
+    /// Relative to the transfer root, or as printed by rsync if outside of it; the full
+    /// local path for files a scan could not read
+    pub path: String,
+    pub reason: String,
+}
+
+impl FileError {
+    /// A local file or directory skipped because reading it failed
+    pub fn unreadable(path: &Path, error: &io::Error) -> Self {
+        Self {
+            path: path.display().to_string(),
+            reason: format!("unreadable, skipped: {}", error),
+        }
+    }
+}
+
+/// Outcome of the rsync run(s) of a sync, by rsync's documented exit codes
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RunStatus {
+    Success,
+    /// Everything that could be transferred was (exit codes 23, 24 and 25)
+    Partial,
+    Failed,
+}
+
+impl RunStatus {
+    pub fn from_exit_code(code: Option<i32>) -> Self {
+        match code {
+            Some(0) => RunStatus::Success,
+            Some(23..=25) => RunStatus::Partial,
+            _ => RunStatus::Failed,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RunStatus::Success => "success",
+            RunStatus::Partial => "partial",
+            RunStatus::Failed => "failed",
+        }
+    }
+}
+
+/// Environment variable carrying the run ID to rsync and the commands it starts
+pub const RUN_ID_ENV: &str = "UGNASSYNC_RUN_ID";
+
+static RUN_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+tokio::task_local! {
+    static RUN_ID: String;
+}
+
+/// A run ID like `20250310-021500-3fa9c1`: start time plus a hash of time, process and counter
+pub fn new_run_id() -> String {
+    let now = Local::now();
+    let seed = format!(
+        "{}:{}:{}",
+        now.timestamp_nanos_opt().unwrap_or_default(),
+        std::process::id(),
+        RUN_COUNTER.fetch_add(1, Ordering::Relaxed)
+    );
+    let digest = format!("{:x}", Sha256::digest(seed.as_bytes()));
+    format!("{}-{}", now.format("%Y%m%d-%H%M%S"), &digest[..6])
+}
+
+/// ID of the run the current task syncs for, if any
+pub fn current_run_id() -> Option<String> {
+    RUN_ID.try_with(|run_id| run_id.clone()).ok()
+}
+
+/// Run history entry for the outcome of a profile's sync
+fn history_record(profile: &str, run_id: &str, started_at: DateTime<Local>, result: &Result<SyncStats>) -> RunRecord {
+    let duration_secs = (Local::now() - started_at).num_milliseconds() as f64 / 1000.0;
+    match result {
+        Ok(stats) => RunRecord {
+            run_id: run_id.to_string(),
+            profile: profile.to_string(),
+            device: device::current().name.clone(),
+            started_at,
+            duration_secs,
+            status: stats.status.as_str().to_string(),
+            files: stats.files_transferred,
+            bytes: stats.bytes_transferred,
+            network_bytes: stats.network_bytes,
+            conflicts: stats.conflicts_detected,
+            error: None,
+        },
+        Err(e) => RunRecord {
+            run_id: run_id.to_string(),
+            profile: profile.to_string(),
+            device: device::current().name.clone(),
+            started_at,
+            duration_secs,
+            status: RunStatus::Failed.as_str().to_string(),
+            files: 0,
+            bytes: 0,
+            network_bytes: 0,
+            conflicts: 0,
+            error: Some(format!("{:#}", e)),
+        },
+    }
+}
+
+/// Meaning of an rsync exit code as documented in rsync(1)
+fn rsync_exit_meaning(code: Option<i32>) -> &'static str {
+    match code {
+        Some(0) => "success",
+        Some(1) => "syntax or usage error",
+        Some(2) => "protocol incompatibility",
+        Some(3) => "errors selecting input/output files, dirs",
+        Some(4) => "requested action not supported",
+        Some(5) => "error starting client-server protocol",
+        Some(6) => "daemon unable to append to log-file",
+        Some(10) => "error in socket I/O",
+        Some(11) => "error in file I/O",
+        Some(12) => "error in rsync protocol data stream",
+        Some(13) => "errors with program diagnostics",
+        Some(14) => "error in IPC code",
+        Some(20) => "received SIGUSR1 or SIGINT",
+        Some(21) => "some error returned by waitpid()",
+        Some(22) => "error allocating core memory buffers",
+        Some(23) => "partial transfer due to error",
+        Some(24) => "partial transfer due to vanished source files",
+        Some(25) => "the --max-delete limit stopped deletions",
+        Some(30) => "timeout in data send/receive",
+        Some(35) => "timeout waiting for daemon connection",
+        Some(255) => "ssh connection failed",
+        Some(_) => "unknown error",
+        None => "killed by a signal",
+    }
+}
+
+/// Per-file errors from rsync's stderr, e.g. `file has vanished: "/src/a.txt"` or
+/// `rsync: [sender] send_files failed to open "/src/b.txt": Permission denied (13)`
+fn file_errors(stderr: &str, profile: &SyncProfile) -> Vec<FileError> {
+    let root = profile.transfer_root();
+    let remote_root = format!("{}/", profile.remote_path.trim_end_matches('/'));
+    let mut errors: Vec<FileError> = Vec::new();
+
+    for line in stderr.lines() {
+        if !line.starts_with("rsync:") && !line.starts_with("file has vanished:") {
+            continue;
+        }
+        let mut parts = line.splitn(3, '"');
+        let (Some(action), Some(path), Some(rest)) = (parts.next(), parts.next(), parts.next()) else {
+            continue;
+        };
+
+        // The cause follows the path ("...": Permission denied); vanished files only have the action
+        let cause = rest.trim_start_matches(" failed").trim_start_matches(':').trim();
+        let action = action
+            .trim_start_matches("rsync:")
+            .trim_start_matches(" [sender]")
+            .trim_start_matches(" [receiver]")
+            .trim_start_matches(" [generator]")
+            .trim()
+            .trim_end_matches(':');
+        let reason = if cause.is_empty() {
+            action.to_string()
+        } else {
+            format!("{}: {}", action, cause)
+        };
+
+        let path = path
+            .strip_prefix(root.as_str())
+            .or_else(|| path.strip_prefix(remote_root.as_str()))
+            .unwrap_or(path)
+            .to_string();
+        if errors.last().is_some_and(|last| last.path == path) {
+            continue;
+        }
+        errors.push(FileError { path, reason });
+    }
+    errors
+}
+
+/// A conflict found by dry-run analysis and the strategy that would handle it
+#[derive(Debug, Clone, Serialize)]
+pub struct PlannedConflict {
+    pub path: String,
+    pub strategy: ConflictResolution,
+    /// False when the remote copy could not be inspected and the conflict is only possible
+    pub confirmed: bool,
+}
+
+/// Wall-clock time spent in one phase of a sync run
+#[derive(Debug, Clone, Serialize)]
+pub struct PhaseTiming {
+    pub phase: String,
+    pub secs: f64,
+}
+
+impl SyncStats {
+    /// Record local files a scan could not read; the run goes on without them
+    pub fn skip_unreadable(&mut self, unreadable: Vec<FileError>) {
+        if unreadable.is_empty() {
+            return;
+        }
+        let warning = format!("Skipped {} local file(s) or directories that could not be read", unreadable.len());
+        warn!("{}", warning);
+        self.warnings.push(warning);
+        self.status = RunStatus::Partial;
+        self.file_errors.extend(unreadable);
+    }
+
+    /// Local files of the conflicts a run found
+    pub fn conflicting_local_paths(&self, profile: &SyncProfile) -> Vec<PathBuf> {
+        self.planned_conflicts
+            .iter()
+            .map(|conflict| SyncEngine::local_file_for(profile, &conflict.path))
+            .collect()
+    }
+
+    fn record_phase(&mut self, phase: &str, started: Instant) {
+        self.record_phase_secs(phase, started.elapsed().as_secs_f64());
+    }
+
+    fn record_phase_secs(&mut self, phase: &str, secs: f64) {
+        self.phase_timings.push(PhaseTiming {
+            phase: phase.to_string(),
+            secs,
+        });
+    }
 }
 
 impl Default for SyncStats {
@@ -26,10 +314,22 @@ impl Default for SyncStats {
         Self {
             files_transferred: 0,
             bytes_transferred: 0,
+            network_bytes: 0,
             duration_secs: 0.0,
             conflicts_detected: 0,
             conflicts_skipped: 0,
             conflicts_resolved: 0,
+            changed_files: Vec::new(),
+            replaced_files: Vec::new(),
+            deleted_files: Vec::new(),
+            planned_conflicts: Vec::new(),
+            phase_timings: Vec::new(),
+            conflict_detection: false,
+            warnings: Vec::new(),
+            archived_files: Vec::new(),
+            status: RunStatus::Success,
+            file_errors: Vec::new(),
+            run_id: String::new(),
         }
     }
 }
@@ -37,24 +337,385 @@ impl Default for SyncStats {
 pub struct SyncEngine {
     pub(crate) nas_config: NasConfig,
     conflict_resolver: Option<ConflictResolver>,
+    base_store: Option<BaseStore>,
+    /// Why the shared state database could not be opened
+    state_error: Option<String>,
+    low_priority: bool,
+    checksum: bool,
+    force_full: bool,
+    /// Skip the NAS health gate for this run
+    ignore_health: bool,
+    interactive: bool,
+    /// Transfer only the paths listed in this file (watch mode with `file_list_cache`)
+    files_from: Option<PathBuf>,
+    /// None if `rsync --version` could not be read; all options are assumed to work then
+    rsync_version: Option<RsyncVersion>,
+    /// ID of the latest run of each profile, for notifications about it
+    run_ids: Mutex<HashMap<String, String>>,
 }
 
 impl SyncEngine {
     pub fn new(nas_config: NasConfig) -> Self {
-        let conflict_resolver = ConflictResolver::new().ok();
+        match ConflictResolver::new() {
+            Ok(resolver) => Self::with_state(nas_config, resolver),
+            Err(e) => {
+                warn!("Sync state database unavailable: {:#}", e);
+                let mut engine = Self::build(nas_config, None);
+                engine.state_error = Some(format!("{:#}", e));
+                engine
+            }
+        }
+    }
+
+    /// An engine keeping its sync state in `resolver` instead of the shared database,
+    /// e.g. `ConflictResolver::in_memory` in tests
+    pub fn with_state(nas_config: NasConfig, resolver: ConflictResolver) -> Self {
+        Self::build(nas_config, Some(resolver))
+    }
+
+    fn build(nas_config: NasConfig, conflict_resolver: Option<ConflictResolver>) -> Self {
+        let rsync_version = rsync::detect(nas_config.rsync_binary());
         Self {
             nas_config,
             conflict_resolver,
+            base_store: None,
+            state_error: None,
+            low_priority: false,
+            checksum: false,
+            force_full: false,
+            ignore_health: false,
+            interactive: false,
+            files_from: None,
+            rsync_version,
+            run_ids: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// ID of the latest (or running) sync of a profile by this engine
+    pub fn run_id(&self, profile: &str) -> Option<String> {
+        self.run_ids.lock().unwrap().get(profile).cloned()
+    }
+
+    /// Compare files by checksum instead of mtime and size
+    pub fn with_checksum(mut self, checksum: bool) -> Self {
+        self.checksum = checksum;
+        self
+    }
+
+    /// Ignore quick-check and incremental shortcuts and re-evaluate every file
+    pub fn with_force_full(mut self, force_full: bool) -> Self {
+        self.force_full = force_full;
+        self
+    }
+
+    /// Run destructive syncs even when `health_gate` finds the NAS storage degraded
+    pub fn with_ignore_health(mut self, ignore_health: bool) -> Self {
+        self.ignore_health = ignore_health;
+        self
+    }
+
+    /// Ask on the terminal instead of aborting when a confirmation threshold is exceeded
+    pub fn with_interactive(mut self, interactive: bool) -> Self {
+        self.interactive = interactive;
+        self
+    }
+
+    /// Whether the local rsync has a feature, assuming it does if the version is unknown
+    pub fn rsync_supports(&self, feature: fn(&RsyncVersion) -> bool) -> bool {
+        self.rsync_version.as_ref().is_none_or(feature)
+    }
+
+    /// Transfer only the paths in `list`, relative to the profile's transfer root
+    pub fn with_files_from(mut self, list: PathBuf) -> Self {
+        self.files_from = Some(list);
+        self
+    }
+
+    /// Keep copies of synced files in the content-addressed base store
+    pub fn with_base_store(mut self, config: Option<&BaseStoreConfig>) -> Self {
+        self.base_store = config.and_then(|config| match BaseStore::new(config) {
+            Ok(store) => Some(store),
+            Err(e) => {
+                warn!("Base store unavailable: {:#}", e);
+                None
+            }
+        });
+        self
+    }
+
+    /// Run rsync on one remote end so data goes straight from the source host to the NAS
+    fn sync_remote_to_remote(
+        &self,
+        profile: &SyncProfile,
+        source: &SourceHost,
+        dry_run: bool,
+        mut stats: SyncStats,
+        start: Instant,
+    ) -> Result<SyncStats> {
+        let nas_host = connectivity::select_host(&self.nas_config)?;
+
+        if !dry_run && profile.has_confirmation_thresholds() {
+            let phase_start = Instant::now();
+            let (shell, command) = self.remote_rsync_command(profile, source, true, &nas_host)?;
+            let preview = self.run_remote_rsync(&shell, &command, profile, SyncStats::default())?;
+            let resolver = self.resolver_for(profile, &mut stats);
+            self.check_thresholds(profile, &preview, resolver.as_ref())?;
+            stats.record_phase("pre-sync scan", phase_start);
+        }
+
+        let (shell, command) = self.remote_rsync_command(profile, source, dry_run, &nas_host)?;
+        info!("Running rsync on {}: {}", shell.host(), command);
+        let phase_start = Instant::now();
+        stats = self.run_remote_rsync(&shell, &command, profile, stats)?;
+        stats.record_phase("transfer", phase_start);
+
+        stats.duration_secs = start.elapsed().as_secs_f64();
+
+        if !dry_run {
+            if let Some(resolver) = &self.conflict_resolver {
+                if let Err(e) = resolver.record_transfer(&profile.name, stats.network_bytes, stats.files_transferred) {
+                    warn!("Failed to record transfer totals: {:#}", e);
+                }
+            }
+        }
+
+        info!(
+            "Transferred {} files ({:.2} MB) from {} in {:.2}s",
+            stats.files_transferred,
+            stats.bytes_transferred as f64 / (1024.0 * 1024.0),
+            source.host,
+            stats.duration_secs
+        );
+        Ok(stats)
+    }
+
+    /// The shell that runs rsync for a remote-to-remote profile and its command line there
+    fn remote_rsync_command(
+        &self,
+        profile: &SyncProfile,
+        source: &SourceHost,
+        dry_run: bool,
+        nas_host: &str,
+    ) -> Result<(RemoteShell, String)> {
+        let (shell, cmd, rsh) = match source.run_on {
+            RsyncRunner::Source => {
+                let shell = RemoteShell::to(&source.host, source.port, &source.username, source.key_path.clone());
+                let cmd = self.build_rsync_command(profile, dry_run, Some(nas_host))?;
+                (shell, cmd, format!("ssh -o BatchMode=yes -p {}", self.nas_config.port))
+            }
+            RsyncRunner::Nas => {
+                // Sources become user@source:path and the destination a plain path on the NAS
+                let mut pull = profile.clone();
+                let prefix = |path: &String| format!("{}@{}:{}", source.username, source.host, path);
+                if !pull.local_path.is_empty() {
+                    pull.local_path = prefix(&pull.local_path);
+                }
+                pull.local_paths = pull.local_paths.iter().map(prefix).collect();
+
+                let shell = RemoteShell::with_host(&self.nas_config, nas_host.to_string());
+                let cmd = self.build_rsync_command(&pull, dry_run, None)?;
+                (shell, cmd, format!("ssh -o BatchMode=yes -p {}", source.port))
+            }
+        };
+
+        let command = remote_command_line(&cmd, self.nas_config.rsync_binary(), &rsh);
+        Ok((shell, command))
+    }
+
+    /// Run rsync through `shell`, failing or marking the run partial on its exit code like a local run
+    fn run_remote_rsync(&self, shell: &RemoteShell, command: &str, profile: &SyncProfile, mut stats: SyncStats) -> Result<SyncStats> {
+        let output = shell.output(command)?;
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let code = output.status.code();
+
+        match RunStatus::from_exit_code(code) {
+            RunStatus::Failed => {
+                error!("Rsync failed on {}: {}", shell.host(), stderr);
+                return Err(SyncError::from_rsync(
+                    code,
+                    format!(
+                        "Rsync command failed on {} (exit code {}, {}): {}",
+                        shell.host(),
+                        code.map_or("none".to_string(), |code| code.to_string()),
+                        rsync_exit_meaning(code),
+                        stderr.trim()
+                    ),
+                )
+                .into());
+            }
+            RunStatus::Partial => {
+                let failed = file_errors(&stderr, profile);
+                let warning = format!(
+                    "Partial transfer (rsync exit code {}, {}), {} file(s) affected",
+                    code.unwrap_or_default(),
+                    rsync_exit_meaning(code),
+                    failed.len()
+                );
+                warn!("{}", warning);
+                stats.warnings.push(warning);
+                stats.status = RunStatus::Partial;
+                stats.file_errors.extend(failed);
+            }
+            RunStatus::Success => {}
+        }
+
+        Ok(self.parse_rsync_output(&String::from_utf8_lossy(&output.stdout), stats))
+    }
+
+    /// Push file by file with `backend` where rsync cannot be used
+    fn sync_push(&self, profile: &SyncProfile, backend: &dyn Backend, dry_run: bool, mut stats: SyncStats, start: Instant) -> Result<SyncStats> {
+        if profile.dump_cmd.is_some() && !dry_run {
+            let phase_start = Instant::now();
+            dump::run(profile)?;
+            stats.record_phase("dump", phase_start);
+        }
+
+        let phase_start = Instant::now();
+        let (local, unreadable) = push::local_files(profile)?;
+        stats.skip_unreadable(unreadable);
+        let remote = backend.list()?;
+        let delete = profile.sync_type == SyncType::Mirror && !profile.read_only;
+        let update_only = profile.sync_type == SyncType::Incremental && !self.force_full;
+        let mut plan = push::plan(
+            &local,
+            &remote,
+            &profile.effective_excludes(),
+            delete,
+            update_only,
+            profile.mtime_tolerance(),
+            clock::offset(&self.nas_config),
+        );
+        if profile.read_only {
+            // Files that already exist on the NAS are never touched
+            let replaced = std::mem::take(&mut plan.replaced);
+            plan.upload.retain(|file| !replaced.contains(&file.path));
+        }
+        stats.record_phase("file list", phase_start);
+
+        // The guards of rsync runs, on the plan, before anything on the NAS is replaced or deleted
+        if !dry_run && (profile.has_confirmation_thresholds() || profile.ransomware_detection) {
+            let phase_start = Instant::now();
+            let resolver = self.resolver_for(profile, &mut stats);
+            let preview = SyncStats {
+                changed_files: plan.upload.iter().map(|file| file.path.clone()).collect(),
+                replaced_files: plan.replaced.clone(),
+                deleted_files: plan.delete.clone(),
+                ..SyncStats::default()
+            };
+            if profile.ransomware_detection {
+                Self::check_ransomware(profile, &preview, resolver.as_ref())?;
+            }
+            self.check_thresholds(profile, &preview, resolver.as_ref())?;
+            stats.record_phase("pre-sync scan", phase_start);
+        }
+
+        if dry_run {
+            stats.deleted_files = plan.delete.clone();
+            info!("Dry run completed - no files were actually transferred");
+        } else {
+            let phase_start = Instant::now();
+            let failed = backend.upload(Path::new(&profile.transfer_root()), &plan.upload, &remote)?;
+            stats.file_errors.extend(failed);
+            stats.deleted_files = backend.delete(&plan.delete)?;
+            stats.record_phase("transfer", phase_start);
+
+            // Failed files stay out of the counts and get uploaded (resumed) next time
+            if !stats.file_errors.is_empty() {
+                let failed: Vec<&str> = stats.file_errors.iter().map(|e| e.path.as_str()).collect();
+                plan.upload.retain(|file| !failed.contains(&file.path.as_str()));
+                plan.replaced.retain(|path| !failed.contains(&path.as_str()));
+                stats.status = RunStatus::Partial;
+            }
+        }
+
+        stats.files_transferred = plan.upload.len() as u64;
+        stats.bytes_transferred = plan.bytes();
+        stats.changed_files = plan.upload.iter().map(|file| file.path.clone()).collect();
+        stats.replaced_files = plan.replaced;
+        if !dry_run {
+            stats.network_bytes = stats.bytes_transferred;
+            if let Some(resolver) = &self.conflict_resolver {
+                if let Err(e) = resolver.record_transfer(&profile.name, stats.network_bytes, stats.files_transferred) {
+                    warn!("Failed to record transfer totals: {:#}", e);
+                }
+            }
         }
+        stats.duration_secs = start.elapsed().as_secs_f64();
+
+        info!(
+            "Transferred {} files ({:.2} MB) and deleted {} over {} in {:.2}s",
+            stats.files_transferred,
+            stats.bytes_transferred as f64 / (1024.0 * 1024.0),
+            stats.deleted_files.len(),
+            backend.name(),
+            stats.duration_secs
+        );
+        Ok(stats)
+    }
+
+    /// Run rsync with idle CPU and I/O priority (nice/ionice)
+    pub fn with_low_priority(mut self) -> Self {
+        self.low_priority = true;
+        self
     }
 
     pub async fn sync_profile(&self, profile: &SyncProfile, dry_run: bool) -> Result<SyncStats> {
+        let started_at = Local::now();
+        let run_id = new_run_id();
+        self.run_ids.lock().unwrap().insert(profile.name.clone(), run_id.clone());
+
+        let span = info_span!(logging::PROFILE_SPAN, profile = profile.name.as_str(), run_id = run_id.as_str());
+        let result = RUN_ID
+            .scope(run_id.clone(), self.run_profile(profile, dry_run).instrument(span))
+            .await
+            .map(|mut stats| {
+                stats.run_id = run_id.clone();
+                stats
+            });
+
+        if !dry_run {
+            if let Some(resolver) = &self.conflict_resolver {
+                let record = history_record(&profile.name, &run_id, started_at, &result);
+                if let Err(e) = resolver.record_history(&record) {
+                    warn!("Failed to record run history: {:#}", e);
+                }
+            }
+        }
+        result
+    }
+
+    async fn run_profile(&self, profile: &SyncProfile, dry_run: bool) -> Result<SyncStats> {
         info!("Starting sync profile: {}", profile.name);
         let start = Instant::now();
 
         let mut stats = SyncStats::default();
 
+        // Metered links: only essential profiles keep syncing once the month's cap is used up
+        if let (Some(cap_gb), false, false) = (self.nas_config.monthly_transfer_cap_gb, profile.essential, dry_run) {
+            self.check_transfer_cap(cap_gb)?;
+        }
+
+        if let Some(source) = &profile.source_host {
+            return self.sync_remote_to_remote(profile, source, dry_run, stats, start);
+        }
+        if profile.use_smbclient {
+            let smb = self.nas_config.smb.as_ref().context("smbclient transfers need [nas.smb]")?;
+            let client = SmbClient::new(smb, &profile.remote_path)?;
+            return self.sync_push(profile, &client, dry_run, stats, start);
+        }
+        if profile.use_ftp {
+            let client = FtpClient::new(&self.nas_config, &profile.remote_path)?;
+            return self.sync_push(profile, &client, dry_run, stats, start);
+        }
+
+        // Mounts the profile depends on stay up until every profile using them is done
+        let required_mount = match profile.requires_mount.as_deref() {
+            Some(path) => Some(RequiredMount::acquire(path).await?),
+            None => None,
+        };
+
         // Handle SMB mount if needed
+        let phase_start = Instant::now();
         let smb_mount = if profile.use_smb_mount {
             if let Some(smb_config) = &self.nas_config.smb {
                 if smb_config.enabled {
@@ -72,43 +733,363 @@ impl SyncEngine {
         } else {
             None
         };
+        if smb_mount.is_some() {
+            stats.record_phase("mount", phase_start);
+        }
+
+        // Pick a reachable address when several candidates are configured
+        let ssh_host = if smb_mount.is_none() {
+            let phase_start = Instant::now();
+            let host = connectivity::select_host(&self.nas_config)?;
+            stats.record_phase("connectivity check", phase_start);
+            Some(host)
+        } else {
+            None
+        };
+
+        // A missing or read-only destination otherwise only shows up as an rsync error
+        let phase_start = Instant::now();
+        let remote_fs_type = match &ssh_host {
+            Some(host) => {
+                // Before the preflight, whose mkdir would make a plain directory instead of a share
+                let vendor = vendor::for_nas(&self.nas_config, host);
+                if let Some(vendor) = &vendor {
+                    for warning in vendor.prepare(profile, dry_run)? {
+                        warn!("{}", warning);
+                        stats.warnings.push(warning);
+                    }
+                    if profile.sync_type == SyncType::Mirror && !dry_run {
+                        if let Some(snapshot) = vendor.snapshot(profile)? {
+                            info!("{} snapshot {} taken before mirroring", vendor.name(), snapshot);
+                        }
+                    }
+                }
+                let shell = RemoteShell::with_host(&self.nas_config, host.clone()).with_owner(profile.owner.as_ref());
+                preflight::check_remote(&shell, profile, dry_run)?;
+                self.check_capabilities(&shell, profile)?;
+                stats.warnings.extend(clock::check(&self.nas_config, &shell));
+                if self.nas_config.health_gate && nashealth::is_destructive(profile) && !dry_run {
+                    if self.ignore_health {
+                        warn!("NAS health gate skipped for {} (--ignore-health)", profile.name);
+                    } else {
+                        nashealth::gate(&shell, vendor.as_deref(), profile)?;
+                    }
+                }
+                remote_fs_type(&shell, &profile.remote_path)
+            }
+            None => {
+                preflight::check_local(profile, dry_run)?;
+                None
+            }
+        };
+        stats.record_phase("preflight", phase_start);
+
+        // Network and bind mounts in the tree would be synced as if they were local data
+        let nas_mount_point = self.nas_config.smb.as_ref().filter(|smb| smb.enabled).map(|smb| Path::new(&smb.mount_point));
+        let mounts = mounts::mounts();
+
+        // FAT and SMB targets round mtimes, which would otherwise look like changes
+        let target_fs_type = match &ssh_host {
+            Some(_) => remote_fs_type,
+            None => mounts::mount_of(&mounts, Path::new(&profile.remote_path)).map(|mount| mount.fs_type.clone()),
+        };
+        let detected = target_fs_type.as_deref().and_then(mounts::mtime_tolerance).filter(|_| profile.mtime_tolerance_secs.is_none());
+        let tuned_profile;
+        let profile = match detected {
+            Some(tolerance) => {
+                debug!("{} is on {}, allowing {}s of mtime rounding", profile.remote_path, target_fs_type.unwrap_or_default(), tolerance);
+                tuned_profile = SyncProfile { mtime_tolerance_secs: Some(tolerance), ..profile.clone() };
+                &tuned_profile
+            }
+            None => profile,
+        };
+
+        for mount in mounts::crossed_mounts(profile, &mounts, nas_mount_point) {
+            let kind = if mount.is_network() { mount.fs_type.as_str() } else if mount.is_bind() { "bind" } else { "NAS" };
+            let warning = format!(
+                "{} is a {} mount of {} inside the synced tree; set one_file_system = true or exclude it",
+                mount.mount_point.display(),
+                kind,
+                mount.source
+            );
+            warn!("{}", warning);
+            stats.warnings.push(warning);
+        }
+
+        let resolver = self.resolver_for(profile, &mut stats);
+        let resolver = resolver.as_ref();
+
+        // Database dumps must exist before anything scans or snapshots the tree
+        if profile.dump_cmd.is_some() {
+            if dry_run {
+                info!("Dry run: not running dump_cmd for profile {}", profile.name);
+            } else {
+                let phase_start = Instant::now();
+                dump::run(profile)?;
+                stats.record_phase("dump", phase_start);
+            }
+        }
+
+        // Names the NAS filesystem cannot store would make rsync fail midway
+        let phase_start = Instant::now();
+        let (named_profile, renames) = self.apply_name_policy(profile, names::scan(profile)?, &mut stats)?;
+        let named_profile = Self::exclude_remote_only(&named_profile, resolver)?;
+        let named_profile = Self::exclude_marked(&named_profile);
+        let profile = &named_profile;
+        if profile.validates_names() {
+            stats.record_phase("name scan", phase_start);
+        }
+
+        // Archive runs only transfer files old enough to be moved off this machine
+        let archive_batch = if profile.sync_type == SyncType::Archive {
+            let (selected, unreadable) = archive::select(profile)?;
+            stats.skip_unreadable(unreadable);
+            Some(selected)
+        } else {
+            None
+        };
+
+        // Read from a frozen copy of the source so live application data is crash-consistent
+        let snapshot = match &profile.snapshot {
+            Some(config) => {
+                let phase_start = Instant::now();
+                let snapshot = SourceSnapshot::create(profile, config)?;
+                stats.record_phase("snapshot", phase_start);
+                Some(snapshot)
+            }
+            None => None,
+        };
+        let rsync_profile = match &snapshot {
+            Some(snapshot) => snapshot.apply(profile)?,
+            None => profile.clone(),
+        };
+
+        // Guard against unusually large or suspicious syncs (e.g. mass-encrypted files) before touching the NAS
+        let mut estimated_bytes = None;
+        if !dry_run && (profile.has_confirmation_thresholds() || profile.ransomware_detection || profile.quota_check.is_some()) {
+            let phase_start = Instant::now();
+            let preview = self.preview(&rsync_profile, ssh_host.as_deref())?;
+            estimated_bytes = Some(Self::estimated_bytes(profile, &preview));
+            if profile.ransomware_detection {
+                // Reads a sample of every changed file, keep it off the runtime
+                let (scan_profile, scan_preview, scan_resolver) = (profile.clone(), preview.clone(), resolver.cloned());
+                tokio::task::spawn_blocking(move || Self::check_ransomware(&scan_profile, &scan_preview, scan_resolver.as_ref()))
+                    .await
+                    .context("Ransomware check failed")??;
+            }
+            self.check_thresholds(profile, &preview, resolver)?;
+            if let Some(policy) = profile.quota_check {
+                self.check_quota(profile, policy, &preview, ssh_host.as_deref(), &mut stats)?;
+            }
+            stats.record_phase("pre-sync scan", phase_start);
+        }
+
+        // The runtime budget counts from the start of the run, not of the transfer
+        let mut rsync_profile = rsync_profile;
+        if let Some(minutes) = profile.max_runtime_minutes {
+            let spent = start.elapsed().as_secs() / 60;
+            if spent >= minutes {
+                anyhow::bail!("Runtime budget of {} minute(s) used up before the transfer started", minutes);
+            }
+            rsync_profile.max_runtime_minutes = Some(minutes - spent);
+        }
 
-        // Handle two-way sync with conflict resolution
+        // Two-way runs leave files changed on both sides since their last sync for the user
         if profile.sync_type == SyncType::TwoWay {
-            if let Some(_resolver) = &self.conflict_resolver {
-                let resolution_strategy = profile
-                    .conflict_resolution
-                    .as_ref()
-                    .unwrap_or(&ConflictResolution::Skip);
+            let strategy = profile.conflict_resolution.clone().unwrap_or_default();
+            info!("Two-way sync with conflict resolution: {:?}", strategy);
+            if let (Some(resolver), false) = (resolver, dry_run) {
+                let phase_start = Instant::now();
+                let preview = self.preview(&rsync_profile, ssh_host.as_deref())?;
+                let remote_hashes = self.remote_hashes(profile, &preview.replaced_files, ssh_host.as_deref())?;
 
-                info!("Two-way sync with conflict resolution: {:?}", resolution_strategy);
-                // Note: Full two-way sync implementation would scan both directories
-                // and detect conflicts. For now, we'll do a one-way sync.
-                warn!("Two-way sync with conflict resolution is partially implemented");
+                // Other clients' changes would otherwise bounce between the machines
+                let side = self.side(profile, ssh_host.as_deref());
+                if let Err(e) = self.skip_propagated(profile, &side, &remote_hashes, resolver, &mut rsync_profile) {
+                    warn!("Could not read the origins of NAS files: {:#}", e);
+                }
+
+                if strategy == ConflictResolution::Skip {
+                    for path in Self::find_conflicts(profile, &preview.replaced_files, &remote_hashes, resolver) {
+                        warn!("Conflict, not transferring: {}", path);
+                        rsync_profile.exclude.push(format!("/{}", path));
+                        stats.planned_conflicts.push(PlannedConflict {
+                            path,
+                            strategy: ConflictResolution::Skip,
+                            confirmed: true,
+                        });
+                    }
+                    stats.conflicts_detected = stats.planned_conflicts.len() as u64;
+                    stats.conflicts_skipped = stats.conflicts_detected;
+                } else {
+                    warn!("Two-way sync with conflict resolution is partially implemented");
+                }
+                stats.record_phase("conflict scan", phase_start);
             }
         }
 
         // Build rsync command based on sync type
-        let mut cmd = self.build_rsync_command(profile, dry_run, smb_mount.is_some())?;
+        let jobs = self.rsync_jobs(&rsync_profile, dry_run, ssh_host.as_deref())?;
 
-        debug!("Executing rsync command: {:?}", cmd);
+        // How long this usually takes, from the profile's past runs, logged while rsync works
+        let expected_secs = if dry_run { None } else { eta::history(&profile.name).expected_secs(estimated_bytes) };
+        let progress = expected_secs.map(|secs| {
+            info!("Profile {}: {}", profile.name, eta::remaining(secs, start.elapsed().as_secs_f64()));
+            eta::Reporter::start(&profile.name, secs, start)
+        });
 
         // Execute rsync
-        let output = cmd
-            .output()
-            .context("Failed to execute rsync command")?;
+        let phase_start = Instant::now();
+        let outputs = run_jobs(jobs)?;
+        let rsync_secs = phase_start.elapsed().as_secs_f64();
+        drop(progress);
+
+        if let Some(snapshot) = snapshot {
+            if let Err(e) = snapshot.release() {
+                warn!("Failed to remove source snapshot: {:#}", e);
+                stats.warnings.push(format!("Failed to remove source snapshot: {:#}", e));
+            }
+        }
 
         stats.duration_secs = start.elapsed().as_secs_f64();
 
-        if !output.status.success() {
+        // rsync exits with its timeout code when --stop-after ends the transfer; an
+        // earlier exit with that code is a real I/O timeout
+        let budget_stop = |output: &std::process::Output| {
+            rsync_profile
+                .max_runtime_minutes
+                .is_some_and(|minutes| rsync_secs + 60.0 >= (minutes * 60) as f64)
+                && output.status.code() == Some(30)
+                && !dry_run
+        };
+
+        if let Some(output) = outputs
+            .iter()
+            .find(|output| RunStatus::from_exit_code(output.status.code()) == RunStatus::Failed && !budget_stop(output))
+        {
             let stderr = String::from_utf8_lossy(&output.stderr);
+            let code = output.status.code();
             error!("Rsync failed: {}", stderr);
-            anyhow::bail!("Rsync command failed: {}", stderr);
+
+            // Exit code 255 means ssh could not connect; re-probe addresses next time
+            if code == Some(255) {
+                connectivity::forget_selected_host();
+            }
+
+            return Err(SyncError::from_rsync(
+                code,
+                format!(
+                    "Rsync command failed (exit code {}, {}): {}",
+                    code.map_or("none".to_string(), |code| code.to_string()),
+                    rsync_exit_meaning(code),
+                    stderr.trim()
+                ),
+            )
+            .into());
+        }
+
+        // Partial runs transferred what they could; carry on and report the rest
+        for output in outputs.iter().filter(|output| !output.status.success()) {
+            if budget_stop(output) {
+                let warning = format!(
+                    "Stopped at the runtime budget of {} minute(s), the next run resumes the transfer",
+                    profile.max_runtime_minutes.unwrap_or_default()
+                );
+                warn!("{}", warning);
+                if !stats.warnings.contains(&warning) {
+                    stats.warnings.push(warning);
+                }
+                stats.status = RunStatus::Partial;
+                continue;
+            }
+            let code = output.status.code();
+            let failed = file_errors(&String::from_utf8_lossy(&output.stderr), profile);
+            let warning = format!(
+                "Partial transfer (rsync exit code {}, {}), {} file(s) affected",
+                code.unwrap_or_default(),
+                rsync_exit_meaning(code),
+                failed.len()
+            );
+            warn!("{}", warning);
+            stats.warnings.push(warning);
+            stats.status = RunStatus::Partial;
+            stats.file_errors.extend(failed);
         }
 
-        // Parse rsync output for statistics
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        stats = self.parse_rsync_output(&stdout, stats);
+        // Parse rsync output for statistics, summed over parallel jobs
+        let mut file_list_secs: f64 = 0.0;
+        for output in &outputs {
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            stats = self.parse_rsync_output(&stdout, stats);
+            file_list_secs = file_list_secs.max(Self::file_list_generation_secs(&stdout));
+        }
+
+        // rsync reports how long building the file list took; the rest is transfer
+        let file_list_secs = file_list_secs.min(rsync_secs);
+        stats.record_phase_secs("file list", file_list_secs);
+        stats.record_phase_secs("transfer", rsync_secs - file_list_secs);
+
+        // Files rsync could not transfer must not be recorded as synced
+        let failed: HashSet<String> = stats.file_errors.iter().map(|error| error.path.clone()).collect();
+        stats.changed_files.retain(|path| !failed.contains(path));
+
+        for issue in &renames {
+            if let Err(e) = self.transfer_renamed(profile, issue, dry_run, ssh_host.as_deref()) {
+                warn!("Failed to transfer {}: {:#}", issue.path.display(), e);
+                stats.warnings.push(format!("Failed to transfer {}: {:#}", issue, e));
+            }
+        }
+
+        if dry_run && profile.sync_type == SyncType::TwoWay {
+            stats.planned_conflicts = Self::preview_conflicts(profile, &stats, smb_mount.is_some(), resolver);
+            stats.conflicts_detected = stats.planned_conflicts.len() as u64;
+        }
+
+        if !dry_run {
+            let phase_start = Instant::now();
+            self.record_sync_state(profile, &stats, resolver).await;
+            if let (SyncType::TwoWay, Some(resolver)) = (&profile.sync_type, resolver) {
+                if let Err(e) = self.record_origins(profile, &stats, ssh_host.as_deref(), resolver) {
+                    warn!("Failed to record the origin of pushed files: {:#}", e);
+                    stats.warnings.push(format!("Failed to record the origin of pushed files: {:#}", e));
+                }
+            }
+            stats.record_phase("state update", phase_start);
+        }
+
+        if let (Some(_), false) = (profile.keep_versions, dry_run) {
+            let side = self.side(profile, ssh_host.as_deref());
+            if let Err(e) = versions::prune(&side, profile) {
+                warn!("Failed to prune old versions: {:#}", e);
+                stats.warnings.push(format!("Failed to prune old versions: {:#}", e));
+            }
+        }
+
+        if !dry_run {
+            if let Some(resolver) = &self.conflict_resolver {
+                if let Err(e) = resolver.record_transfer(&profile.name, stats.network_bytes, stats.files_transferred) {
+                    warn!("Failed to record transfer totals: {:#}", e);
+                }
+            }
+        }
+
+        if let (Some(selected), false) = (&archive_batch, dry_run) {
+            self.finish_archive(profile, selected, ssh_host.as_deref(), resolver, &mut stats).await;
+        }
+
+        // Carry the NAS copy on along the replication chain (3-2-1 backups)
+        let mut replication = Ok(());
+        let shell = ssh_host
+            .as_ref()
+            .map(|host| RemoteShell::with_host(&self.nas_config, host.clone()));
+        for (index, hop) in profile.replicate_to.iter().enumerate() {
+            let phase_start = Instant::now();
+            replication = cascade::replicate(shell.as_ref(), profile, index, dry_run);
+            if replication.is_err() {
+                break;
+            }
+            stats.record_phase(&format!("replicate to {}", hop.host), phase_start);
+        }
 
         info!(
             "Transferred {} files ({:.2} MB) in {:.2}s",
@@ -126,133 +1107,1547 @@ impl SyncEngine {
         // Unmount SMB share if needed
         if let Some(mut mount) = smb_mount {
             if mount.should_auto_unmount() {
+                let phase_start = Instant::now();
                 mount.unmount().await?;
+                stats.record_phase("unmount", phase_start);
             } else {
                 info!("Keeping SMB mount persistent (auto_unmount = false)");
             }
         }
+        if let Some(mount) = required_mount {
+            mount.release().await;
+        }
 
+        replication?;
         Ok(stats)
     }
 
-    fn build_rsync_command(&self, profile: &SyncProfile, dry_run: bool, use_smb: bool) -> Result<Command> {
-        let mut cmd = Command::new("rsync");
+    fn rsync_name(&self) -> String {
+        match &self.rsync_version {
+            Some(version) => version.to_string(),
+            None => self.nas_config.rsync_binary().to_string(),
+        }
+    }
 
-        // Common rsync flags
-        cmd.arg("-az") // archive mode + compression
-            .arg("--stats") // show statistics
-            .arg("--human-readable");
+    fn rsync(&self) -> Command {
+        let mut cmd = if self.low_priority {
+            let mut cmd = Command::new("nice");
+            cmd.arg("-n19").arg("ionice").arg("-c3").arg(self.nas_config.rsync_binary());
+            cmd
+        } else {
+            Command::new(self.nas_config.rsync_binary())
+        };
+        stable_number_format(&mut cmd);
+        if let Some(run_id) = current_run_id() {
+            cmd.env(RUN_ID_ENV, run_id);
+        }
+        cmd
+    }
+
+    /// The rsync commands for one sync: a single one, or with `parallel_jobs` a non-recursive
+    /// pass over the top level followed by jobs that each take a share of its directories
+    fn rsync_jobs(&self, profile: &SyncProfile, dry_run: bool, ssh_host: Option<&str>) -> Result<Vec<Command>> {
+        if profile.parallel_jobs <= 1 || self.files_from.is_some() {
+            return Ok(vec![self.build_rsync_command(profile, dry_run, ssh_host)?]);
+        }
+        if profile.hard_links {
+            // rsync only links files it sees in the same transfer
+            warn!("Profile {} preserves hard links, which needs a single rsync; ignoring parallel_jobs", profile.name);
+            return Ok(vec![self.build_rsync_command(profile, dry_run, ssh_host)?]);
+        }
+        if profile.uses_relative_paths() || profile.sync_type == SyncType::Archive {
+            warn!("parallel_jobs is not supported for profile {}, using one rsync", profile.name);
+            return Ok(vec![self.build_rsync_command(profile, dry_run, ssh_host)?]);
+        }
+
+        let root = profile.transfer_root();
+        let mut dirs = Vec::new();
+        for entry in std::fs::read_dir(&profile.local_path)
+            .with_context(|| format!("Failed to read {}", profile.local_path))?
+        {
+            let entry = entry?;
+            // Only leave out what rsync would exclude, or the directory would stay empty
+            let path = entry.path();
+            if entry.file_type()?.is_dir() && !profile.excludes(path.strip_prefix(&root).unwrap_or(&path)) {
+                dirs.push(path);
+            }
+        }
+        dirs.sort();
+
+        let jobs = profile.parallel_jobs.min(dirs.len());
+        if jobs <= 1 {
+            return Ok(vec![self.build_rsync_command(profile, dry_run, ssh_host)?]);
+        }
+
+        // `root/./dir` with --relative keeps the same destination layout as a single rsync
+        let relative = |path: &Path| format!("{}./{}", root, path.strip_prefix(&root).unwrap_or(path).display());
+
+        let mut job_profile = profile.clone();
+        job_profile.bwlimit_kbps = profile.bwlimit_kbps.map(|limit| (limit / jobs as u64).max(1));
+        job_profile.local_path = String::new();
+
+        // Top-level files and directory entries first; mirror deletions happen here too
+        let mut top = job_profile.clone();
+        top.local_paths = vec![format!("{}/", relative(Path::new(&profile.local_path)).trim_end_matches('/'))];
+        let mut cmd = self.build_rsync_command(&top, dry_run, ssh_host)?;
+        cmd.arg("--relative").arg("--no-recursive").arg("--dirs");
+        let mut commands = vec![cmd];
+
+        let mut groups = vec![Vec::new(); jobs];
+        for (i, dir) in dirs.iter().enumerate() {
+            groups[i % jobs].push(relative(dir));
+        }
+        for group in groups {
+            let mut job = job_profile.clone();
+            job.local_paths = group;
+            let mut cmd = self.build_rsync_command(&job, dry_run, ssh_host)?;
+            cmd.arg("--relative");
+            commands.push(cmd);
+        }
+
+        info!("Syncing profile {} with {} parallel rsync jobs", profile.name, jobs);
+        Ok(commands)
+    }
+
+    fn build_rsync_command(
+        &self,
+        profile: &SyncProfile,
+        dry_run: bool,
+        ssh_host: Option<&str>,
+    ) -> Result<Command> {
+        let mut cmd = self.rsync();
+
+        // Common rsync flags
+        cmd.arg("-az") // archive mode + compression
+            .arg("--stats") // show statistics
+            .arg("--human-readable");
 
         if dry_run {
             cmd.arg("--dry-run");
         }
 
-        // Verbose output for debugging
-        cmd.arg("-v");
+        if self.checksum {
+            cmd.arg("--checksum");
+        }
 
-        // Add exclusions
-        for exclude in &profile.exclude {
-            cmd.arg(format!("--exclude={}", exclude));
+        if self.force_full {
+            cmd.arg("--ignore-times");
         }
 
-        // Sync type specific flags
-        match profile.sync_type {
-            SyncType::Mirror => {
-                cmd.arg("--delete"); // Delete files not in source
+        if profile.sparse {
+            cmd.arg("--sparse");
+        }
+        if profile.one_file_system {
+            cmd.arg("--one-file-system");
+        }
+        if profile.hard_links {
+            cmd.arg("--hard-links");
+        }
+        if profile.preallocate {
+            if self.rsync_supports(RsyncVersion::supports_3_1) {
+                cmd.arg("--preallocate");
+            } else {
+                warn!("{} does not support --preallocate, ignoring it", self.rsync_name());
             }
-            SyncType::OneWay => {
-                // No delete flag - preserve extra files on destination
+        }
+        if let Some(choice) = &profile.compress_choice {
+            if self.rsync_supports(RsyncVersion::supports_compress_choice) {
+                cmd.arg(format!("--compress-choice={}", choice));
+            } else {
+                warn!("{} does not support --compress-choice, using its default compression", self.rsync_name());
             }
-            SyncType::TwoWay => {
-                // Two-way sync requires special handling (not directly supported by rsync)
-                warn!("Two-way sync requires conflict resolution - using one-way for now");
-                // This will be handled by the conflict module
+        }
+        if profile.devices == SpecialFilePolicy::Skip {
+            cmd.arg("--no-devices");
+        }
+        if profile.specials == SpecialFilePolicy::Skip {
+            cmd.arg("--no-specials");
+        }
+
+        if let Some(limit) = profile.bwlimit_kbps {
+            cmd.arg(format!("--bwlimit={}", limit));
+        }
+
+        // Rounded mtimes on the target: mtimes this far apart still count as equal
+        let modify_window = profile.mtime_tolerance();
+        if modify_window > 0 {
+            cmd.arg(format!("--modify-window={}", modify_window));
+        }
+
+        if let (Some(minutes), false) = (profile.max_runtime_minutes, dry_run) {
+            if self.rsync_supports(RsyncVersion::supports_stop_after) {
+                cmd.arg("--partial").arg(format!("--stop-after={}", minutes.max(1)));
+            } else {
+                warn!("{} does not support --stop-after, max_runtime_minutes is ignored", self.rsync_name());
             }
-            SyncType::Incremental => {
-                cmd.arg("--update"); // Skip files that are newer on destination
+        }
+
+        if let Some(iconv) = profile.effective_iconv() {
+            if self.rsync_supports(RsyncVersion::supports_iconv) {
+                cmd.arg(format!("--iconv={}", iconv));
+            } else {
+                warn!("{} does not support --iconv, file names are sent unconverted", self.rsync_name());
             }
-            SyncType::Backup => {
-                cmd.arg("--backup")
-                    .arg("--backup-dir=.backup");
+        }
+
+        // Verbose output for debugging, with per-file change codes for reports
+        cmd.arg("-v").arg("--itemize-changes");
+
+        // Keep the full source paths when syncing several directories to one remote root
+        if profile.uses_relative_paths() {
+            cmd.arg("--relative");
+        }
+
+        // Add exclusions
+        for exclude in &profile.effective_excludes() {
+            cmd.arg(format!("--exclude={}", exclude));
+        }
+
+        // Archive runs transfer the list written by archive::select
+        if profile.sync_type == SyncType::Archive {
+            cmd.arg(format!("--files-from={}", archive::list_path(profile).display()));
+        } else if let Some(list) = &self.files_from {
+            // Listed directories are new ones and need their whole contents
+            cmd.arg(format!("--files-from={}", list.display())).arg("--recursive");
+            // Listed paths that no longer exist locally were deleted since the last sync
+            if profile.sync_type == SyncType::Mirror && !profile.read_only {
+                cmd.arg("--delete-missing-args");
+            } else {
+                cmd.arg("--ignore-missing-args");
             }
         }
 
-        // Build remote path - use local path for SMB, SSH for direct rsync
-        let remote_path = if use_smb {
-            // For SMB mount, remote_path is a local path on the mounted share
-            info!("Starting rsync to local mount point");
-            profile.remote_path.clone()
+        // Sync type specific flags
+        if profile.read_only {
+            // Files that already exist on the destination are never touched
+            cmd.arg("--ignore-existing");
+            if profile.sync_type == SyncType::Mirror {
+                warn!("Profile {} is read-only, mirror deletions are disabled", profile.name);
+            }
         } else {
-            // Build remote path with SSH
-            if self.nas_config.key_path.is_some() {
-                let key_path = self.nas_config.key_path.as_ref().unwrap();
-                cmd.arg("-e")
-                    .arg(format!(
-                        "ssh -p {} -i {}",
-                        self.nas_config.port, key_path
-                    ));
+            // Replaced and deleted files move to this run's directory of the version tree
+            if profile.keep_versions.is_some() {
+                cmd.arg("--backup")
+                    .arg(format!("--backup-dir={}/{}", versions::VERSIONS_DIR, versions::run_stamp()))
+                    .arg(format!("--exclude=/{}/", versions::VERSIONS_DIR));
+            }
+            match profile.sync_type {
+                SyncType::Mirror => {
+                    cmd.arg("--delete"); // Delete files not in source
+                }
+                SyncType::OneWay => {
+                    // No delete flag - preserve extra files on destination
+                }
+                SyncType::TwoWay => {
+                    // Two-way sync requires special handling (not directly supported by rsync)
+                    warn!("Two-way sync requires conflict resolution - using one-way for now");
+                    // This will be handled by the conflict module
+                }
+                SyncType::Incremental => {
+                    if self.force_full {
+                        info!("Full re-evaluation requested, not skipping newer destination files");
+                    } else {
+                        cmd.arg("--update"); // Skip files that are newer on destination
+                    }
+                }
+                SyncType::Backup => {
+                    cmd.arg("--backup")
+                        .arg("--backup-dir=.backup");
+                }
+                SyncType::Archive => {
+                    // Local files are only deleted after verification, never by rsync
+                }
+            }
+        }
 
-                format!(
-                    "{}@{}:{}",
-                    self.nas_config.username,
-                    self.nas_config.host,
-                    profile.remote_path
-                )
-            } else {
-                // Using sshpass for password authentication (requires sshpass to be installed)
+        // Build remote path - use local path for SMB, SSH for direct rsync
+        let remote_path = if let Some(host) = ssh_host {
+            if self.nas_config.key_path.is_none() {
                 warn!("Using password authentication - consider using SSH keys for better security");
+            }
 
-                cmd.arg("-e")
-                    .arg(format!("ssh -p {}", self.nas_config.port));
-
-                format!(
-                    "{}@{}:{}",
-                    self.nas_config.username,
-                    self.nas_config.host,
-                    profile.remote_path
-                )
+            let shell = RemoteShell::with_host(&self.nas_config, host.to_string()).with_owner(profile.owner.as_ref());
+            cmd.arg("-e").arg(shell.rsync_rsh());
+            shell.authenticate(&mut cmd)?;
+            if let Some(remote_rsync) = profile.remote_rsync_command() {
+                cmd.arg(format!("--rsync-path={}", remote_rsync));
             }
+            shell.destination(&profile.remote_path)
+        } else {
+            // For SMB mount, remote_path is a local path on the mounted share
+            info!("Starting rsync to local mount point");
+            profile.remote_path.clone()
         };
 
-        // Add source and destination
-        cmd.arg(&profile.local_path)
-            .arg(&remote_path);
+        // Add sources and destination
+        if profile.sync_type == SyncType::Archive || self.files_from.is_some() {
+            cmd.arg(profile.transfer_root());
+        } else {
+            cmd.args(profile.source_paths());
+        }
+        cmd.arg(&remote_path);
+
+        if profile.read_only {
+            ensure_non_destructive(&cmd)?;
+        }
 
         Ok(cmd)
     }
 
     fn parse_rsync_output(&self, output: &str, mut stats: SyncStats) -> SyncStats {
         // Parse rsync statistics from output
+        let mut counted_files = false;
         for line in output.lines() {
-            if line.contains("Number of regular files transferred:") {
-                if let Some(num_str) = line.split(':').nth(1) {
-                    if let Ok(num) = num_str.trim().split_whitespace().next().unwrap_or("0").parse::<u64>() {
-                        stats.files_transferred = num;
-                    }
+            if let Some(path) = line.strip_prefix("*deleting") {
+                stats.deleted_files.push(path.trim().to_string());
+            } else if let Some((path, is_new)) = Self::itemized_file(line) {
+                stats.changed_files.push(path.to_string());
+                if !is_new {
+                    stats.replaced_files.push(path.to_string());
+                }
+            } else if line.starts_with("Number of regular files transferred:")
+                || line.starts_with("Number of files transferred:")
+            {
+                // rsync 3.1 added "regular"; older versions count all transferred files
+                if let Some(num) = line.split(':').nth(1).and_then(parse_size) {
+                    stats.files_transferred += num;
+                    counted_files = true;
                 }
             } else if line.contains("Total transferred file size:") {
-                if let Some(size_str) = line.split(':').nth(1) {
-                    if let Some(bytes_str) = size_str.trim().split_whitespace().next() {
-                        if let Ok(bytes) = bytes_str.replace(",", "").parse::<u64>() {
-                            stats.bytes_transferred = bytes;
-                        }
+                if let Some(bytes) = line.split(':').nth(1).and_then(parse_size) {
+                    stats.bytes_transferred += bytes;
+                }
+            } else if line.starts_with("Total bytes sent:") || line.starts_with("Total bytes received:") {
+                if let Some(bytes) = line.split(':').nth(1).and_then(parse_size) {
+                    stats.network_bytes += bytes;
+                }
+            }
+        }
+
+        // A stats block we could not read must not pass for an empty transfer
+        if !counted_files && output.lines().any(|line| line.starts_with("Number of files:")) {
+            warn!("Could not parse rsync stats, transfer counts are incomplete");
+            stats.warnings.push("Could not parse rsync stats, transfer counts are incomplete".to_string());
+        }
+
+        stats
+    }
+
+    /// Parse `File list generation time: 0.123 seconds` from rsync --stats output
+    fn file_list_generation_secs(output: &str) -> f64 {
+        output
+            .lines()
+            .find_map(|line| line.strip_prefix("File list generation time:"))
+            .and_then(|rest| rest.split_whitespace().next())
+            .and_then(|secs| secs.parse().ok())
+            .unwrap_or(0.0)
+    }
+
+    /// Extract the path from an itemized line for a transferred regular file (e.g. `>f+++++++++ a.txt`),
+    /// along with whether the file is new on the destination
+    fn itemized_file(line: &str) -> Option<(&str, bool)> {
+        let (codes, path) = line.split_once(' ')?;
+        let mut chars = codes.chars();
+        let update = chars.next()?;
+        let file_type = chars.next()?;
+
+        if codes.len() == 11 && matches!(update, '<' | '>' | 'c' | 'h') && file_type == 'f' {
+            Some((path.trim_start(), codes.ends_with("+++++++++")))
+        } else {
+            None
+        }
+    }
+
+    /// Local file corresponding to a path as reported by rsync
+    pub(crate) fn local_file_for(profile: &SyncProfile, transfer_path: &str) -> PathBuf {
+        if profile.uses_relative_paths() {
+            Path::new("/").join(transfer_path)
+        } else if profile.local_path.ends_with('/') {
+            Path::new(&profile.local_path).join(transfer_path)
+        } else {
+            Path::new(&profile.local_path)
+                .parent()
+                .unwrap_or(Path::new("/"))
+                .join(transfer_path)
+        }
+    }
+
+    /// Leave subtrees marked remote-only out of the transfer; rsync also protects
+    /// excluded paths on the receiving side from `--delete`
+    fn exclude_remote_only(profile: &SyncProfile, resolver: Option<&ConflictResolver>) -> Result<SyncProfile> {
+        let mut run_profile = profile.clone();
+        if let Some(resolver) = resolver {
+            for path in resolver.remote_only_paths(&profile.name)? {
+                debug!("Not syncing remote-only {}", path);
+                run_profile.exclude.push(format!("/{}/", path));
+            }
+        }
+        Ok(run_profile)
+    }
+
+    /// Leave out cache directories and nodump files when the profile respects those markers
+    fn exclude_marked(profile: &SyncProfile) -> SyncProfile {
+        let mut run_profile = profile.clone();
+        if profile.respect_backup_markers {
+            for path in markers::scan(profile) {
+                if let Some(pattern) = WatchManager::anchored_exclude(profile, &path) {
+                    run_profile.exclude.push(pattern);
+                }
+            }
+        }
+        run_profile
+    }
+
+    /// Exclude names the target cannot store, returning the adjusted profile and the files to rename
+    fn apply_name_policy(
+        &self,
+        profile: &SyncProfile,
+        issues: Vec<NameIssue>,
+        stats: &mut SyncStats,
+    ) -> Result<(SyncProfile, Vec<NameIssue>)> {
+        let mut run_profile = profile.clone();
+        if issues.is_empty() {
+            return Ok((run_profile, issues));
+        }
+
+        if profile.name_policy == NamePolicy::Abort {
+            let listed: Vec<String> = issues.iter().take(10).map(|issue| issue.to_string()).collect();
+            anyhow::bail!(
+                "{} name(s) cannot be stored on the NAS: {}{}",
+                issues.len(),
+                listed.join(", "),
+                if issues.len() > listed.len() { ", ..." } else { "" }
+            );
+        }
+
+        let mut renames = Vec::new();
+        for issue in issues {
+            if let Some(pattern) = WatchManager::anchored_exclude(profile, &issue.path) {
+                run_profile.exclude.push(pattern);
+            }
+
+            // Normalization was requested explicitly, so it always renames
+            let rename = profile.name_policy == NamePolicy::Rename || issue.kind == IssueKind::NotNormalized;
+            match (rename, &issue.new_name) {
+                (true, Some(new_name)) => {
+                    warn!("Renaming on NAS: {} -> {}", issue, new_name);
+                    stats.warnings.push(format!("Renamed {} -> {}", issue, new_name));
+                    renames.push(issue);
+                }
+                _ => {
+                    warn!("Skipping: {}", issue);
+                    stats.warnings.push(format!("Skipped {}", issue));
+                }
+            }
+        }
+
+        Ok((run_profile, renames))
+    }
+
+    /// Copy a file or directory whose name the target rejects to its mapped name
+    fn transfer_renamed(&self, profile: &SyncProfile, issue: &NameIssue, dry_run: bool, ssh_host: Option<&str>) -> Result<()> {
+        let anchored = WatchManager::anchored_exclude(profile, &issue.path)
+            .with_context(|| format!("{} is outside the profile sources", issue.path.display()))?;
+        let mut remote = Path::new(&profile.remote_path)
+            .join(anchored.trim_start_matches('/'))
+            .with_file_name(issue.new_name.as_deref().context("No replacement name")?)
+            .to_string_lossy()
+            .to_string();
+        let mut source = issue.path.to_string_lossy().to_string();
+
+        // Copy directory contents into the renamed directory
+        if issue.path.is_dir() {
+            source.push('/');
+            remote.push('/');
+        }
+
+        let mut cmd = self.rsync();
+        cmd.arg("-a");
+        if dry_run {
+            cmd.arg("--dry-run");
+        }
+        if let Some(host) = ssh_host {
+            let shell = RemoteShell::with_host(&self.nas_config, host.to_string()).with_owner(profile.owner.as_ref());
+            cmd.arg("-e").arg(shell.rsync_rsh());
+            shell.authenticate(&mut cmd)?;
+            if let Some(remote_rsync) = profile.remote_rsync_command() {
+                cmd.arg(format!("--rsync-path={}", remote_rsync));
+            }
+            remote = shell.destination(&remote);
+        }
+
+        let output = cmd.arg(&source).arg(&remote).output().context("Failed to execute rsync")?;
+        if !output.status.success() {
+            anyhow::bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+        }
+
+        Ok(())
+    }
+
+    /// State database for a profile, falling back as configured when it cannot be opened
+    fn resolver_for(&self, profile: &SyncProfile, stats: &mut SyncStats) -> Option<ConflictResolver> {
+        let opened = match &profile.state_db {
+            Some(_) => ConflictResolver::for_profile(profile).map_err(|e| format!("{:#}", e)),
+            None => self
+                .conflict_resolver
+                .clone()
+                .ok_or_else(|| self.state_error.clone().unwrap_or_default()),
+        };
+
+        let resolver = match opened {
+            Ok(resolver) => Some(
+                resolver
+                    .with_mtime_tolerance(profile.mtime_tolerance())
+                    .with_clock_offset(clock::offset(&self.nas_config)),
+            ),
+            Err(error) => {
+                let (resolver, fallback) = match profile.state_fallback {
+                    StateFallback::Memory => (
+                        ConflictResolver::in_memory(profile.state_db.as_deref().unwrap_or("shared"))
+                            .ok()
+                            .map(|resolver| {
+                                resolver
+                                    .with_mtime_tolerance(profile.mtime_tolerance())
+                                    .with_clock_offset(clock::offset(&self.nas_config))
+                            }),
+                        "using in-memory state",
+                    ),
+                    StateFallback::None => (None, "conflict detection disabled"),
+                };
+                let warning = format!("Sync state database unavailable ({}), {}", error, fallback);
+                warn!("{}", warning);
+                stats.warnings.push(warning);
+                resolver
+            }
+        };
+
+        stats.conflict_detection = resolver.is_some();
+        resolver
+    }
+
+    /// Dry-run the profile to see what a real sync would change
+    fn preview(&self, profile: &SyncProfile, ssh_host: Option<&str>) -> Result<SyncStats> {
+        let output = self
+            .build_rsync_command(profile, true, ssh_host)?
+            .output()
+            .map_err(|e| SyncError::from_spawn("rsync", e))?;
+        // Vanished or unreadable files do not make the preview useless
+        if RunStatus::from_exit_code(output.status.code()) == RunStatus::Failed {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(SyncError::from_rsync(output.status.code(), format!("Rsync preview failed: {}", stderr.trim())).into());
+        }
+        Ok(self.parse_rsync_output(&String::from_utf8_lossy(&output.stdout), SyncStats::default()))
+    }
+
+    /// Refuse to propagate changes that look like ransomware damage
+    fn check_ransomware(profile: &SyncProfile, preview: &SyncStats, resolver: Option<&ConflictResolver>) -> Result<()> {
+        let recorded: HashSet<PathBuf> = match resolver {
+            Some(resolver) => resolver
+                .recorded_files()?
+                .into_iter()
+                .map(|meta| PathBuf::from(meta.path))
+                .collect(),
+            None => HashSet::new(),
+        };
+
+        let replaced: HashSet<&String> = preview.replaced_files.iter().collect();
+        let new_files: Vec<PathBuf> = preview
+            .changed_files
+            .iter()
+            .filter(|path| !replaced.contains(path))
+            .map(|path| Self::local_file_for(profile, path))
+            .collect();
+        let replaced_files: Vec<PathBuf> = preview
+            .replaced_files
+            .iter()
+            .map(|path| Self::local_file_for(profile, path))
+            .collect();
+
+        match ransomware::inspect(&new_files, &replaced_files, &recorded) {
+            Some(reason) => Err(Quarantine {
+                profile: profile.name.clone(),
+                reason,
+            }
+            .into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Ask (or abort) when the previewed sync exceeds the profile's thresholds
+    fn check_thresholds(&self, profile: &SyncProfile, preview: &SyncStats, resolver: Option<&ConflictResolver>) -> Result<()> {
+        let mut reasons = Vec::new();
+
+        if let Some(limit) = profile.confirm_if_changed_files_over {
+            let changed = preview.changed_files.len() as u64;
+            if changed > limit {
+                reasons.push(format!("{} files would change (limit {})", changed, limit));
+            }
+        }
+
+        if let Some(limit_gb) = profile.confirm_if_deleted_over_gb {
+            let deleted_gb = Self::deleted_bytes(profile, &preview.deleted_files, resolver) as f64 / (1024.0 * 1024.0 * 1024.0);
+            if deleted_gb > limit_gb {
+                reasons.push(format!("{:.2} GB would be deleted (limit {} GB)", deleted_gb, limit_gb));
+            }
+        }
+
+        if reasons.is_empty() {
+            return Ok(());
+        }
+
+        let summary = reasons.join(", ");
+        warn!("Profile {} exceeds confirmation thresholds: {}", profile.name, summary);
+
+        if self.interactive && confirm(&format!("Profile {}: {}. Continue?", profile.name, summary))? {
+            info!("Large sync of profile {} confirmed", profile.name);
+            return Ok(());
+        }
+
+        Err(SyncError::NotConfirmed(format!("Sync of profile {} not confirmed: {}", profile.name, summary)).into())
+    }
+
+    fn check_transfer_cap(&self, cap_gb: f64) -> Result<()> {
+        let Some(resolver) = &self.conflict_resolver else {
+            warn!("Sync state unavailable, cannot enforce monthly_transfer_cap_gb");
+            return Ok(());
+        };
+
+        let used_gb = resolver.month_transfer_bytes()? as f64 / (1024.0 * 1024.0 * 1024.0);
+        if used_gb >= cap_gb {
+            return Err(TransferCapReached { used_gb, cap_gb }.into());
+        }
+        Ok(())
+    }
+
+    /// Fail early instead of running out of space (ENOSPC) halfway through the transfer
+    fn check_quota(
+        &self,
+        profile: &SyncProfile,
+        policy: QuotaCheck,
+        preview: &SyncStats,
+        ssh_host: Option<&str>,
+        stats: &mut SyncStats,
+    ) -> Result<()> {
+        let headroom = match ssh_host {
+            Some(host) => {
+                let shell = RemoteShell::with_host(&self.nas_config, host.to_string()).with_owner(profile.owner.as_ref());
+                quota::remote_headroom(&shell, &profile.remote_path)
+            }
+            None => quota::local_headroom(&profile.remote_path),
+        };
+        let headroom = match headroom {
+            Ok(Some(headroom)) => headroom,
+            Ok(None) => {
+                warn!("Could not determine free space for {}", profile.remote_path);
+                return Ok(());
+            }
+            Err(e) => {
+                warn!("Quota check failed for profile {}: {:#}", profile.name, e);
+                return Ok(());
+            }
+        };
+
+        let estimated = Self::estimated_bytes(profile, preview);
+
+        const GB: f64 = 1024.0 * 1024.0 * 1024.0;
+        debug!(
+            "Profile {} needs {:.2} GB, {:.2} GB left ({})",
+            profile.name,
+            estimated as f64 / GB,
+            headroom.bytes as f64 / GB,
+            headroom.limit
+        );
+        if estimated <= headroom.bytes {
+            return Ok(());
+        }
+
+        let message = format!(
+            "Profile {} would transfer {:.2} GB but only {:.2} GB is left on {} ({})",
+            profile.name,
+            estimated as f64 / GB,
+            headroom.bytes as f64 / GB,
+            profile.remote_path,
+            headroom.limit
+        );
+        match policy {
+            QuotaCheck::Warn => {
+                warn!("{}", message);
+                stats.warnings.push(message);
+                Ok(())
+            }
+            QuotaCheck::Abort => Err(SyncError::DiskFull(message).into()),
+        }
+    }
+
+    /// Bytes a previewed sync would write; replaced files are counted in full since rsync
+    /// writes a temporary copy next to them
+    fn estimated_bytes(profile: &SyncProfile, preview: &SyncStats) -> u64 {
+        let files: Vec<PathBuf> = preview.changed_files.iter().map(|path| Self::local_file_for(profile, path)).collect();
+        eta::size_of(&files)
+    }
+
+    /// Size of deleted files as recorded at their last sync (rsync does not report it)
+    fn deleted_bytes(profile: &SyncProfile, deleted_files: &[String], resolver: Option<&ConflictResolver>) -> u64 {
+        let Some(resolver) = resolver else {
+            return 0;
+        };
+        let recorded: HashMap<String, u64> = match resolver.recorded_files() {
+            Ok(files) => files.into_iter().map(|meta| (meta.path, meta.size)).collect(),
+            Err(e) => {
+                warn!("Could not read sync state: {:#}", e);
+                return 0;
+            }
+        };
+
+        deleted_files
+            .iter()
+            .filter_map(|path| {
+                let local_file = Self::local_file_for(profile, path);
+                recorded.get(&state_key(&local_file))
+            })
+            .sum()
+    }
+
+    /// Remember what was pushed so pending changes can be computed offline
+    async fn record_sync_state(&self, profile: &SyncProfile, stats: &SyncStats, resolver: Option<&ConflictResolver>) {
+        let Some(resolver) = resolver.cloned() else {
+            return;
+        };
+        let changed: Vec<PathBuf> = stats.changed_files.iter().map(|path| Self::local_file_for(profile, path)).collect();
+        let deleted: Vec<PathBuf> = stats.deleted_files.iter().map(|path| Self::local_file_for(profile, path)).collect();
+        let base_store = self.base_store.clone();
+
+        // Hashing and copying every transferred file can take minutes, keep it off the runtime
+        let task = tokio::task::spawn_blocking(move || Self::store_sync_state(&resolver, base_store.as_ref(), &changed, &deleted));
+        if let Err(e) = task.await {
+            warn!("Sync state update failed: {}", e);
+        }
+    }
+
+    fn store_sync_state(resolver: &ConflictResolver, base_store: Option<&BaseStore>, changed: &[PathBuf], deleted: &[PathBuf]) {
+        for local_file in changed {
+            let meta = match resolver.update_sync_state(local_file) {
+                Ok(meta) => meta,
+                Err(e) => {
+                    warn!("Failed to record sync state for {}: {:#}", local_file.display(), e);
+                    continue;
+                }
+            };
+
+            if let Some(store) = base_store {
+                if let Err(e) = store.put(local_file, &meta.hash) {
+                    warn!("Failed to store base version of {}: {:#}", local_file.display(), e);
+                }
+            }
+        }
+
+        if let Some(store) = base_store {
+            if let Err(e) = store.prune() {
+                warn!("Failed to prune base store: {:#}", e);
+            }
+        }
+
+        for local_file in deleted {
+            if let Err(e) = resolver.forget_sync_state(local_file) {
+                warn!("Failed to clear sync state for {}: {:#}", local_file.display(), e);
+            }
+        }
+    }
+
+    /// Compare archived files with the NAS by checksum and delete only the ones that match
+    async fn finish_archive(
+        &self,
+        profile: &SyncProfile,
+        selected: &[String],
+        ssh_host: Option<&str>,
+        resolver: Option<&ConflictResolver>,
+        stats: &mut SyncStats,
+    ) {
+        let phase_start = Instant::now();
+        let verify = self.build_rsync_command(profile, true, ssh_host).and_then(|mut cmd| {
+            let output = cmd.arg("--checksum").output().context("Failed to execute rsync")?;
+            if !output.status.success() {
+                anyhow::bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+            }
+            Ok(self.parse_rsync_output(&String::from_utf8_lossy(&output.stdout), SyncStats::default()))
+        });
+        stats.record_phase("verification", phase_start);
+        let mismatched: HashSet<String> = match verify {
+            Ok(preview) => preview.changed_files.into_iter().collect(),
+            Err(e) => {
+                warn!("Archive verification failed, keeping local files: {:#}", e);
+                stats.warnings.push(format!("Archive verification failed, keeping local files: {:#}", e));
+                return;
+            }
+        };
+
+        let phase_start = Instant::now();
+        let verified: Vec<String> = selected.iter().filter(|path| !mismatched.contains(*path)).cloned().collect();
+        for path in selected.iter().filter(|path| mismatched.contains(*path)) {
+            warn!("Checksum mismatch on NAS, keeping {}", path);
+            stats.warnings.push(format!("Not archived, checksum mismatch on NAS: {}", path));
+        }
+
+        // Hashing every archived file for the audit log can take minutes, keep it off the runtime
+        let (archive_profile, resolver) = (profile.clone(), resolver.cloned());
+        let task = tokio::task::spawn_blocking(move || Self::release_archived(&archive_profile, &verified, resolver.as_ref()));
+        let (released, warnings) = match task.await {
+            Ok(result) => result,
+            Err(e) => {
+                warn!("Archive release failed: {}", e);
+                (Vec::new(), vec![format!("Archive release failed: {}", e)])
+            }
+        };
+        stats.archived_files = released;
+        stats.warnings.extend(warnings);
+        stats.record_phase("archive", phase_start);
+    }
+
+    fn release_archived(profile: &SyncProfile, verified: &[String], resolver: Option<&ConflictResolver>) -> (Vec<String>, Vec<String>) {
+        let (released, warnings) = archive::release(profile, verified);
+        if let Some(resolver) = resolver {
+            for path in &released {
+                let local_file = Self::local_file_for(profile, path);
+                if let Err(e) = resolver.forget_sync_state(&local_file) {
+                    warn!("Failed to clear sync state for {}: {:#}", local_file.display(), e);
+                }
+            }
+        }
+        (released, warnings)
+    }
+
+    /// What the profile needs from the NAS, as probed on first contact; a failed probe is
+    /// left to the sync itself to report
+    fn check_capabilities(&self, shell: &RemoteShell, profile: &SyncProfile) -> Result<()> {
+        let capabilities = match capabilities::of(shell) {
+            Ok(capabilities) => capabilities,
+            Err(e) => {
+                warn!("{:#}", e);
+                return Ok(());
+            }
+        };
+        // A configured remote_rsync_path or sudo wrapper is not on the probed PATH
+        if profile.remote_rsync_command().is_none() {
+            capabilities.require(capabilities.rsync.is_some(), "rsync", "Syncing over SSH")?;
+        }
+        if let Some(version) = capabilities.rsync_version() {
+            if profile.effective_iconv().is_some() && !version.supports_iconv() {
+                warn!("{} on the NAS does not support --iconv, the sync will fail until it is upgraded", version);
+            }
+        }
+        Ok(())
+    }
+
+    /// SHA-256 of the NAS copies of files about to be replaced; files gone from the NAS are left out
+    fn remote_hashes(&self, profile: &SyncProfile, replaced: &[String], ssh_host: Option<&str>) -> Result<HashMap<String, String>> {
+        let remote_root = profile.remote_path.trim_end_matches('/');
+        let mut remote_hashes = HashMap::new();
+        match ssh_host {
+            Some(host) => {
+                let shell = RemoteShell::with_host(&self.nas_config, host.to_string()).with_owner(profile.owner.as_ref());
+                if !replaced.is_empty() {
+                    let capabilities = capabilities::of(&shell)?;
+                    capabilities.require(capabilities.sha256sum, "sha256sum", "Conflict detection")?;
+                }
+                for chunk in replaced.chunks(100) {
+                    let paths: Vec<String> = chunk.iter().map(|path| shell_quote(path)).collect();
+                    let output = shell.run(&format!("cd {} && sha256sum -- {}; true", shell_quote(remote_root), paths.join(" ")))?;
+                    remote_hashes.extend(adopt::parse_sha256sum(&output));
+                }
+            }
+            None => {
+                for path in replaced {
+                    if let Ok(hash) = conflict::hash_file(&Path::new(remote_root).join(path)) {
+                        remote_hashes.insert(path.clone(), hash);
                     }
                 }
             }
         }
+        Ok(remote_hashes)
+    }
+
+    /// Files a run would replace on the NAS that changed there too since their last sync
+    fn find_conflicts(
+        profile: &SyncProfile,
+        replaced: &[String],
+        remote_hashes: &HashMap<String, String>,
+        resolver: &ConflictResolver,
+    ) -> Vec<String> {
+        replaced
+            .iter()
+            .filter(|path| {
+                let Some(remote_hash) = remote_hashes.get(*path) else {
+                    return false;
+                };
+                let local_file = Self::local_file_for(profile, path);
+                resolver.changed_on_both_sides(&local_file, remote_hash).unwrap_or_else(|e| {
+                    warn!("Could not check {} for conflicts: {:#}", path, e);
+                    false
+                })
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Mark the files a two-way run pushed as this client's latest change in the NAS ledger
+    fn record_origins(&self, profile: &SyncProfile, stats: &SyncStats, ssh_host: Option<&str>, resolver: &ConflictResolver) -> Result<()> {
+        if stats.changed_files.is_empty() {
+            return Ok(());
+        }
+        let mut pushed = Vec::new();
+        for path in &stats.changed_files {
+            if let Some(record) = resolver.recorded(&Self::local_file_for(profile, path))? {
+                pushed.push((path.clone(), record.hash));
+            }
+        }
+        let me = device::current();
+        let seq = origin::update(&self.side(profile, ssh_host), profile, me, &pushed)?;
+        debug!("Recorded {} file(s) as change {} of {}", pushed.len(), seq, me.name);
+        Ok(())
+    }
+
+    /// Where commands against the profile's remote path run
+    fn side(&self, profile: &SyncProfile, ssh_host: Option<&str>) -> Side {
+        match ssh_host {
+            Some(host) => Side::Ssh(RemoteShell::with_host(&self.nas_config, host.to_string()).with_owner(profile.owner.as_ref())),
+            None => Side::Mounted,
+        }
+    }
+
+    /// Leave out files another two-way client pushed that this one has not changed since,
+    /// which it would otherwise push back over the newer NAS copy
+    fn skip_propagated(
+        &self,
+        profile: &SyncProfile,
+        side: &Side,
+        remote_hashes: &HashMap<String, String>,
+        resolver: &ConflictResolver,
+        rsync_profile: &mut SyncProfile,
+    ) -> Result<()> {
+        let ledger = origin::Ledger::read(side, profile)?;
+        let changed_here = |path: &str| {
+            resolver.changed_since_sync(&Self::local_file_for(profile, path)).unwrap_or_else(|e| {
+                warn!("Could not check {} for local changes: {:#}", path, e);
+                true
+            })
+        };
+        for (path, origin) in ledger.propagated(&device::current().id, remote_hashes, changed_here) {
+            info!(
+                "{} on the NAS is change {} of {}, not pushing the older local copy over it",
+                path, origin.seq, origin.name
+            );
+            rsync_profile.exclude.push(format!("/{}", path));
+        }
+        Ok(())
+    }
+
+    /// Check replaced files of a two-way dry run against the conflict resolver
+    fn preview_conflicts(
+        profile: &SyncProfile,
+        stats: &SyncStats,
+        remote_is_local: bool,
+        resolver: Option<&ConflictResolver>,
+    ) -> Vec<PlannedConflict> {
+        let strategy = profile.conflict_resolution.clone().unwrap_or_default();
 
         stats
+            .replaced_files
+            .iter()
+            .filter_map(|path| {
+                let remote_file = Path::new(&profile.remote_path).join(path);
+
+                // Without local access to the remote copy every replacement may be a conflict
+                let confirmed = match (resolver, remote_is_local) {
+                    (Some(resolver), true) => {
+                        let local_file = Self::local_file_for(profile, path);
+                        match resolver.detect_conflict(&local_file, &remote_file) {
+                            Ok(false) => return None,
+                            Ok(true) => true,
+                            Err(e) => {
+                                warn!("Could not check {} for conflicts: {:#}", path, e);
+                                false
+                            }
+                        }
+                    }
+                    _ => false,
+                };
+
+                Some(PlannedConflict {
+                    path: path.clone(),
+                    strategy: strategy.clone(),
+                    confirmed,
+                })
+            })
+            .collect()
     }
 }
 
+/// Ask a yes/no question on the terminal, defaulting to no
+pub(crate) fn confirm(prompt: &str) -> Result<bool> {
+    print!("{} [y/N] ", prompt);
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// rsync options that can remove or rewrite data on either side
+const DESTRUCTIVE_FLAGS: &[&str] = &["--delete", "--remove-source-files", "--inplace", "--backup"];
+
+/// Filesystem type of a directory on the NAS as `stat -f` names it; None before the
+/// first sync creates it, or where stat cannot tell
+fn remote_fs_type(shell: &RemoteShell, path: &str) -> Option<String> {
+    match shell.run(&format!("stat -f -c %T {}", shell_quote(path))) {
+        Ok(output) => Some(output.trim().to_string()).filter(|fs_type| !fs_type.is_empty()),
+        Err(e) => {
+            debug!("Could not read the filesystem type of {}: {:#}", path, e);
+            None
+        }
+    }
+}
+
+/// Refuse to run a read-only profile if any destructive option slipped into the command
+fn ensure_non_destructive(cmd: &Command) -> Result<()> {
+    for arg in cmd.get_args() {
+        let arg = arg.to_string_lossy();
+        if DESTRUCTIVE_FLAGS.iter().any(|flag| arg.starts_with(flag)) {
+            anyhow::bail!("Refusing to pass destructive option {} in read-only mode", arg);
+        }
+    }
+    Ok(())
+}
+
+/// Run the first command, then the rest concurrently
+fn run_jobs(mut jobs: Vec<Command>) -> Result<Vec<std::process::Output>> {
+    let mut outputs = Vec::new();
+    for (i, cmd) in jobs.iter().enumerate() {
+        debug!("Executing rsync command {}: {:?}", i, cmd);
+    }
+
+    let (first, rest) = jobs.split_first_mut().context("No rsync command to run")?;
+    outputs.push(first.output().map_err(|e| SyncError::from_spawn("rsync", e))?);
+
+    let rest = std::thread::scope(|scope| {
+        let handles: Vec<_> = rest.iter_mut().map(|cmd| scope.spawn(move || cmd.output())).collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("rsync job thread panicked"))
+            .collect::<io::Result<Vec<_>>>()
+    });
+    outputs.extend(rest.map_err(|e| SyncError::from_spawn("rsync", e))?);
+
+    Ok(outputs)
+}
+
+/// Shell command line for running a locally built rsync command on another host,
+/// with its remote shell (`-e`) replaced by one that works from there, the local rsync
+/// binary by the other host's, and the number format fixed like `stable_number_format`
+fn remote_command_line(cmd: &Command, binary: &str, rsh: &str) -> String {
+    let mut words = vec![cmd.get_program().to_string_lossy().to_string()];
+    let mut args = cmd.get_args().map(|arg| arg.to_string_lossy().to_string());
+    while let Some(arg) = args.next() {
+        if arg == "-e" {
+            args.next();
+        } else {
+            words.push(arg);
+        }
+    }
+
+    // After the rsync word itself, which follows nice/ionice when running at low priority
+    let at = match words.iter().position(|word| word == binary) {
+        Some(i) => {
+            words[i] = "rsync".to_string();
+            i + 1
+        }
+        None => 1,
+    };
+    words.insert(at, "-e".to_string());
+    words.insert(at + 1, rsh.to_string());
+
+    // The environment of the command does not travel, so the other host's shell sets it;
+    // an empty LC_ALL counts as unset, its character set moves to LC_CTYPE
+    let line = words.iter().map(|word| shell_quote(word)).collect::<Vec<_>>().join(" ");
+    format!("LC_CTYPE=\"${{LC_ALL:-$LC_CTYPE}}\" LC_ALL= LC_NUMERIC=C {}", line)
+}
+
+/// Make rsync print numbers as `1,234,567` / `1.23M` whatever the user's locale, while
+/// keeping the character set so non-ASCII file names are not escaped in its output
+pub(crate) fn stable_number_format(cmd: &mut Command) {
+    if let Some(all) = std::env::var_os("LC_ALL") {
+        cmd.env_remove("LC_ALL").env("LC_CTYPE", all);
+    }
+    cmd.env("LC_NUMERIC", "C");
+}
+
+/// `1,234,567`, `1.23M` or `12.5K bytes` as printed by rsync --stats (--human-readable uses units of 1000;
+/// rsync 3.0 printed a lowercase `k`)
+fn parse_size(value: &str) -> Option<u64> {
+    let value = value.split_whitespace().next()?.replace(',', "");
+    let (number, multiplier) = match value.chars().last()? {
+        'k' | 'K' => (&value[..value.len() - 1], 1e3),
+        'M' => (&value[..value.len() - 1], 1e6),
+        'G' => (&value[..value.len() - 1], 1e9),
+        'T' => (&value[..value.len() - 1], 1e12),
+        'P' => (&value[..value.len() - 1], 1e15),
+        _ => (value.as_str(), 1.0),
+    };
+    number.parse::<f64>().ok().map(|n| (n * multiplier).round() as u64)
+}
+
+/// A non-essential profile was not synced because the monthly transfer cap is used up
+#[derive(Debug)]
+pub struct TransferCapReached {
+    pub used_gb: f64,
+    pub cap_gb: f64,
+}
+
+impl fmt::Display for TransferCapReached {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "monthly transfer cap reached ({:.2} of {} GB)",
+            self.used_gb, self.cap_gb
+        )
+    }
+}
+
+impl std::error::Error for TransferCapReached {}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    /// An engine whose sync state lives in memory, leaving ~/.ugnassync alone
+    fn test_nas() -> NasConfig {
+        toml::from_str("host = \"nas\"\nport = 22\nusername = \"admin\"").unwrap()
+    }
+
+    fn test_engine(nas_config: NasConfig) -> SyncEngine {
+        SyncEngine::with_state(nas_config, ConflictResolver::in_memory("sync-tests").unwrap())
+    }
+
     #[test]
     fn test_sync_stats_default() {
         let stats = SyncStats::default();
         assert_eq!(stats.files_transferred, 0);
         assert_eq!(stats.bytes_transferred, 0);
     }
+
+    #[test]
+    fn test_remote_command_line_replaces_rsh() {
+        let mut cmd = Command::new("rsync");
+        cmd.arg("-az").arg("-e").arg("ssh -p 22 -i /home/me/.ssh/id").arg("/data/").arg("admin@nas:/volume1/my data");
+
+        assert_eq!(
+            remote_command_line(&cmd, "rsync", "ssh -o BatchMode=yes -p 22"),
+            "LC_CTYPE=\"${LC_ALL:-$LC_CTYPE}\" LC_ALL= LC_NUMERIC=C \
+             'rsync' '-e' 'ssh -o BatchMode=yes -p 22' '-az' '/data/' 'admin@nas:/volume1/my data'"
+        );
+    }
+
+    #[test]
+    fn test_partial_transfers_list_file_errors() {
+        assert_eq!(RunStatus::from_exit_code(Some(24)), RunStatus::Partial);
+        assert_eq!(RunStatus::from_exit_code(Some(12)), RunStatus::Failed);
+        assert_eq!(RunStatus::from_exit_code(None), RunStatus::Failed);
+
+        let profile: SyncProfile = toml::from_str(
+            "name = \"docs\"\nlocal_path = \"/home/user/Documents\"\nremote_path = \"/volume1/docs\"\nsync_type = \"mirror\"\nenabled = true",
+        )
+        .unwrap();
+        let stderr = "\
+file has vanished: \"/home/user/Documents/tmp/build.log\"
+rsync: [sender] send_files failed to open \"/home/user/Documents/private.key\": Permission denied (13)
+rsync error: some files/attrs were not transferred (see previous errors) (code 23) at main.c(1338) [sender=3.2.7]
+";
+        assert_eq!(
+            file_errors(stderr, &profile),
+            vec![
+                FileError {
+                    path: "Documents/tmp/build.log".to_string(),
+                    reason: "file has vanished".to_string(),
+                },
+                FileError {
+                    path: "Documents/private.key".to_string(),
+                    reason: "send_files failed to open: Permission denied (13)".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parallel_jobs_split_top_level_directories() {
+        let dir = std::env::temp_dir().join(format!("ugnassync-parallel-test-{}", std::process::id()));
+        for sub in ["a", "b", "c", "cache", "photo-cache-old"] {
+            std::fs::create_dir_all(dir.join("tree").join(sub)).unwrap();
+        }
+        let engine = test_engine(test_nas());
+        let profile: SyncProfile = toml::from_str(&format!(
+            r#"
+            name = "big"
+            local_path = "{}/tree"
+            remote_path = "/volume1/big"
+            sync_type = "mirror"
+            enabled = true
+            parallel_jobs = 2
+            bwlimit_kbps = 1000
+            exclude = ["cache"]
+            "#,
+            dir.display()
+        ))
+        .unwrap();
+
+        let jobs = engine.rsync_jobs(&profile, false, None).unwrap();
+        let args: Vec<Vec<String>> = jobs
+            .iter()
+            .map(|cmd| cmd.get_args().map(|a| a.to_string_lossy().to_string()).collect())
+            .collect();
+
+        assert_eq!(args.len(), 3);
+        let root = format!("{}/./tree", dir.display());
+        assert!(args[0].contains(&format!("{}/", root)) && args[0].contains(&"--dirs".to_string()));
+        assert!(args[1].contains(&format!("{}/a", root)) && args[1].contains(&format!("{}/c", root)));
+        assert!(args[2].contains(&format!("{}/b", root)));
+        // Only the directory rsync itself excludes is left out
+        assert!(args[2].contains(&format!("{}/photo-cache-old", root)));
+        assert!(!args.iter().flatten().any(|arg| arg == &format!("{}/cache", root)));
+        assert!(args.iter().all(|job| job.contains(&"--bwlimit=500".to_string())));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_hard_links_keep_one_rsync() {
+        let engine = test_engine(test_nas());
+        let profile: SyncProfile = toml::from_str(
+            r#"
+            name = "snapshots"
+            local_path = "/srv/rsnapshot"
+            remote_path = "/volume1/rsnapshot"
+            sync_type = "backup"
+            enabled = true
+            parallel_jobs = 4
+            hard_links = true
+            "#,
+        )
+        .unwrap();
+
+        let jobs = engine.rsync_jobs(&profile, false, None).unwrap();
+        assert_eq!(jobs.len(), 1);
+        assert!(jobs[0].get_args().any(|arg| arg == "--hard-links"));
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size(" 1,234,567 bytes"), Some(1_234_567));
+        assert_eq!(parse_size(" 1.50M bytes"), Some(1_500_000));
+        assert_eq!(parse_size("12.5K"), Some(12_500));
+        assert_eq!(parse_size("3.25k"), Some(3_250));
+        assert_eq!(parse_size("bytes"), None);
+    }
+
+    #[test]
+    fn test_parse_stats_of_several_rsync_versions() {
+        let engine = test_engine(test_nas());
+
+        // 3.0.x: no "regular", plain numbers
+        let v30 = "Number of files: 1234\n\
+            Number of files transferred: 12\n\
+            Total file size: 1234567890 bytes\n\
+            Total transferred file size: 45670000 bytes\n\
+            Total bytes sent: 45700000\n\
+            Total bytes received: 1230\n";
+        // 3.1.x with -h: thousands separators
+        let v31 = "Number of files: 1,234 (reg: 1,000, dir: 234)\n\
+            Number of regular files transferred: 12\n\
+            Total transferred file size: 45,670,000 bytes\n\
+            Total bytes sent: 45,700,000\n\
+            Total bytes received: 1,230\n";
+        // 3.2.x with -hh: unit suffixes
+        let v32 = "Number of files: 1,234 (reg: 1,000, dir: 234)\n\
+            Number of created files: 10 (reg: 8, dir: 2)\n\
+            Number of regular files transferred: 12\n\
+            Total transferred file size: 45.67M bytes\n\
+            Total bytes sent: 45.70M\n\
+            Total bytes received: 1.23K\n";
+
+        for output in [v30, v31, v32] {
+            let stats = engine.parse_rsync_output(output, SyncStats::default());
+            assert_eq!(stats.files_transferred, 12, "{}", output);
+            assert_eq!(stats.bytes_transferred, 45_670_000, "{}", output);
+            assert_eq!(stats.network_bytes, 45_701_230, "{}", output);
+            assert!(stats.warnings.is_empty());
+        }
+
+        let unknown = engine.parse_rsync_output("Number of files: 3\nAnzahl übertragener Dateien: 2\n", SyncStats::default());
+        assert_eq!(unknown.warnings.len(), 1);
+    }
+
+    #[test]
+    fn test_read_only_mirror_has_no_destructive_flags() {
+        let engine = test_engine(toml::from_str(
+            r#"
+            host = "nas"
+            port = 22
+            username = "admin"
+            "#,
+        ).unwrap());
+        let profile: SyncProfile = toml::from_str(
+            r#"
+            name = "archive"
+            local_path = "/data"
+            remote_path = "/volume1/archive"
+            sync_type = "mirror"
+            enabled = true
+            read_only = true
+            "#,
+        ).unwrap();
+
+        let cmd = engine.build_rsync_command(&profile, false, None).unwrap();
+        let args: Vec<_> = cmd.get_args().map(|a| a.to_string_lossy().to_string()).collect();
+        assert!(args.contains(&"--ignore-existing".to_string()));
+        assert!(!args.iter().any(|a| a.starts_with("--delete")));
+    }
+
+    #[test]
+    fn test_remote_only_subtrees_are_excluded() {
+        let engine = test_engine(test_nas());
+        let profile: SyncProfile = toml::from_str(
+            r#"
+            name = "photos"
+            local_path = "/data/photos/"
+            remote_path = "/volume1/photos"
+            sync_type = "mirror"
+            enabled = true
+            "#,
+        ).unwrap();
+
+        let resolver = ConflictResolver::in_memory("remote-only-test").unwrap();
+        resolver.set_remote_only("photos", "2009/raw", true).unwrap();
+        resolver.set_remote_only("photos", "2010", true).unwrap();
+        resolver.set_remote_only("photos", "2010", false).unwrap();
+        resolver.set_remote_only("other", "2011", true).unwrap();
+
+        let run_profile = SyncEngine::exclude_remote_only(&profile, Some(&resolver)).unwrap();
+        let cmd = engine.build_rsync_command(&run_profile, false, None).unwrap();
+        let args: Vec<_> = cmd.get_args().map(|a| a.to_string_lossy().to_string()).collect();
+        assert!(args.contains(&"--exclude=/2009/raw/".to_string()));
+        assert!(args.contains(&"--delete".to_string()));
+        assert!(!args.iter().any(|a| a.contains("2010") || a.contains("2011") || a == "--delete-excluded"));
+    }
+
+    #[test]
+    fn test_max_runtime_stops_rsync_with_partial() {
+        let mut engine = test_engine(test_nas());
+        let profile: SyncProfile = toml::from_str(
+            r#"
+            name = "nightly"
+            local_path = "/data"
+            remote_path = "/volume1/data"
+            sync_type = "one-way"
+            enabled = true
+            max_runtime_minutes = 240
+            "#,
+        ).unwrap();
+        let args = |engine: &SyncEngine, dry_run: bool| -> Vec<String> {
+            let cmd = engine.build_rsync_command(&profile, dry_run, None).unwrap();
+            cmd.get_args().map(|a| a.to_string_lossy().to_string()).collect()
+        };
+
+        engine.rsync_version = RsyncVersion::parse("rsync  version 3.2.7  protocol version 31");
+        assert!(args(&engine, false).contains(&"--stop-after=240".to_string()));
+        assert!(args(&engine, false).contains(&"--partial".to_string()));
+        assert!(!args(&engine, true).iter().any(|a| a.starts_with("--stop-after")));
+
+        engine.rsync_version = RsyncVersion::parse("rsync  version 3.1.3  protocol version 31");
+        assert!(!args(&engine, false).iter().any(|a| a.starts_with("--stop-after")));
+    }
+
+    #[test]
+    fn test_run_id_reaches_rsync_environment() {
+        let engine = test_engine(test_nas());
+        let run_id = new_run_id();
+        assert_eq!(run_id.len(), "20250310-021500-3fa9c1".len());
+        assert_ne!(run_id, new_run_id());
+
+        let env = |cmd: &Command| {
+            cmd.get_envs()
+                .find(|(name, _)| *name == RUN_ID_ENV)
+                .and_then(|(_, value)| value.map(|v| v.to_string_lossy().to_string()))
+        };
+        assert_eq!(env(&engine.rsync()), None);
+        let scoped = RUN_ID.sync_scope(run_id.clone(), || env(&engine.rsync()));
+        assert_eq!(scoped, Some(run_id));
+    }
+
+    #[test]
+    fn test_sparse_and_special_file_flags() {
+        let engine = test_engine(toml::from_str(
+            r#"
+            host = "nas"
+            port = 22
+            username = "admin"
+            "#,
+        ).unwrap());
+        let profile: SyncProfile = toml::from_str(
+            r#"
+            name = "vms"
+            local_path = "/var/lib/libvirt/images"
+            remote_path = "/volume1/vms"
+            sync_type = "one-way"
+            enabled = true
+            sparse = true
+            specials = "skip"
+            one_file_system = true
+            "#,
+        ).unwrap();
+
+        let cmd = engine.build_rsync_command(&profile, false, None).unwrap();
+        let args: Vec<_> = cmd.get_args().map(|a| a.to_string_lossy().to_string()).collect();
+        assert!(args.contains(&"--sparse".to_string()));
+        assert!(args.contains(&"--one-file-system".to_string()));
+        assert!(args.contains(&"--no-specials".to_string()));
+        assert!(!args.contains(&"--no-devices".to_string()));
+    }
+
+    #[test]
+    fn test_remote_rsync_path_with_sudo() {
+        let engine = test_engine(test_nas());
+        let mut profile: SyncProfile = toml::from_str(
+            r#"
+            name = "homes"
+            local_path = "/home"
+            remote_path = "/volume1/homes"
+            sync_type = "mirror"
+            enabled = true
+            remote_rsync_path = "/usr/bin/rsync"
+            remote_sudo = true
+            "#,
+        ).unwrap();
+
+        let args = |engine: &SyncEngine, profile: &SyncProfile| -> Vec<String> {
+            let cmd = engine.build_rsync_command(profile, false, Some("nas")).unwrap();
+            cmd.get_args().map(|a| a.to_string_lossy().to_string()).collect()
+        };
+        assert!(args(&engine, &profile).contains(&"--rsync-path=sudo /usr/bin/rsync".to_string()));
+
+        profile.remote_rsync_path = None;
+        assert!(args(&engine, &profile).contains(&"--rsync-path=sudo rsync".to_string()));
+
+        profile.remote_sudo = false;
+        assert!(!args(&engine, &profile).iter().any(|arg| arg.starts_with("--rsync-path")));
+    }
+
+    #[test]
+    fn test_parse_itemized_changes() {
+        let engine = test_engine(toml::from_str(
+            r#"
+            host = "nas"
+            port = 22
+            username = "admin"
+            "#,
+        ).unwrap());
+        let output = "sending incremental file list\n\
+            *deleting   old/report.pdf\n\
+            cd+++++++++ photos/\n\
+            >f+++++++++ photos/beach.jpg\n\
+            >f.st...... notes.txt\n\
+            .d..t...... ./\n";
+
+        let stats = engine.parse_rsync_output(output, SyncStats::default());
+        assert_eq!(stats.changed_files, vec!["photos/beach.jpg", "notes.txt"]);
+        assert_eq!(stats.replaced_files, vec!["notes.txt"]);
+        assert_eq!(stats.deleted_files, vec!["old/report.pdf"]);
+    }
+
+    #[test]
+    fn test_file_list_generation_secs() {
+        let output = "Number of files: 12\nFile list generation time: 0.250 seconds\n";
+        assert_eq!(SyncEngine::file_list_generation_secs(output), 0.25);
+        assert_eq!(SyncEngine::file_list_generation_secs("no stats"), 0.0);
+    }
+
+    /// A push backend (FTP, smbclient) that records what it was asked to change
+    struct RecordingBackend {
+        remote: Vec<push::RemoteFile>,
+        calls: Mutex<Vec<String>>,
+    }
+
+    impl Backend for RecordingBackend {
+        fn name(&self) -> &'static str {
+            "test"
+        }
+
+        fn list(&self) -> Result<Vec<push::RemoteFile>> {
+            Ok(self.remote.clone())
+        }
+
+        fn upload(&self, _root: &Path, files: &[push::LocalFile], _remote: &[push::RemoteFile]) -> Result<Vec<FileError>> {
+            self.calls.lock().unwrap().extend(files.iter().map(|file| format!("upload {}", file.path)));
+            Ok(Vec::new())
+        }
+
+        fn delete(&self, paths: &[String]) -> Result<Vec<String>> {
+            self.calls.lock().unwrap().extend(paths.iter().map(|path| format!("delete {}", path)));
+            Ok(paths.to_vec())
+        }
+    }
+
+    #[test]
+    fn test_push_backends_stop_at_thresholds() {
+        let dir = std::env::temp_dir().join(format!("ugnassync-push-gates-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("tree")).unwrap();
+        std::fs::write(dir.join("tree/new.txt"), "new").unwrap();
+        let resolver = ConflictResolver::in_memory("push-gates").unwrap();
+        let engine = SyncEngine::with_state(
+            toml::from_str("host = \"nas\"\nport = 21\nusername = \"admin\"").unwrap(),
+            resolver.clone(),
+        );
+        let mut profile: SyncProfile = toml::from_str(&format!(
+            r#"
+            name = "ftp"
+            local_path = "{}/tree"
+            remote_path = "/volume1/ftp"
+            sync_type = "mirror"
+            enabled = true
+            use_ftp = true
+            confirm_if_deleted_over_gb = 1.0
+            "#,
+            dir.display()
+        ))
+        .unwrap();
+
+        // A 3 GB file gone locally would be deleted from the NAS
+        let old = SyncEngine::local_file_for(&profile, "tree/old.iso");
+        resolver
+            .record_adopted(&[crate::conflict::FileMetadata {
+                path: state_key(&old),
+                size: 3 << 30,
+                modified: 0,
+                hash: String::new(),
+            }])
+            .unwrap();
+        let backend = RecordingBackend {
+            remote: vec![push::RemoteFile {
+                path: "tree/old.iso".to_string(),
+                size: 3 << 30,
+                modified: chrono::NaiveDateTime::default(),
+            }],
+            calls: Mutex::new(Vec::new()),
+        };
+
+        let refused = engine.sync_push(&profile, &backend, false, SyncStats::default(), Instant::now()).unwrap_err();
+        assert!(refused.to_string().contains("not confirmed"));
+        // Categorized, so the daemon and one-time runs notify about the refusal
+        assert_eq!(crate::error::kind(&refused), "not_confirmed");
+        assert!(backend.calls.lock().unwrap().is_empty());
+
+        profile.confirm_if_deleted_over_gb = None;
+        let stats = engine.sync_push(&profile, &backend, false, SyncStats::default(), Instant::now()).unwrap();
+        assert_eq!(stats.deleted_files, vec!["tree/old.iso"]);
+        assert_eq!(*backend.calls.lock().unwrap(), vec!["upload tree/new.txt", "delete tree/old.iso"]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -0,0 +1,223 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use crate::config::{SyncProfile, TrueNasConfig};
+use crate::error::SyncError;
+use crate::vendor::Vendor;
+use anyhow::{Context, Result};
+use chrono::Local;
+use serde_json::{json, Value};
+use std::io::Write;
+use std::process::{Command, Stdio};
+use tracing::{debug, info, warn};
+
+/// Pool states in which nothing should be written
+const UNUSABLE_POOL_STATES: &[&str] = &["FAULTED", "UNAVAIL", "OFFLINE", "REMOVED"];
+
+/// [nas.truenas], talking to the REST API v2.0
+pub struct TrueNas {
+    config: TrueNasConfig,
+    base: String,
+}
+
+impl TrueNas {
+    pub fn new(config: &TrueNasConfig, host: &str) -> Self {
+        let base = config.url.clone().unwrap_or_else(|| format!("https://{}", host));
+        Self {
+            config: config.clone(),
+            base: base.trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// `query` only goes with GET; the API key reaches curl on stdin
+    fn request(&self, method: &str, path: &str, query: &[(&str, &str)], body: Option<&Value>) -> Result<Value> {
+        let url = format!("{}/api/v2.0/{}", self.base, path);
+        let mut cmd = Command::new("curl");
+        cmd.arg("-fsS").arg("-m").arg("30").arg("-X").arg(method);
+        if self.config.insecure {
+            cmd.arg("--insecure");
+        }
+        cmd.arg("-H").arg("Content-Type: application/json").arg("-H").arg("@-");
+        if !query.is_empty() {
+            cmd.arg("-G");
+            for (name, value) in query {
+                cmd.arg("--data-urlencode").arg(format!("{}={}", name, value));
+            }
+        }
+        if let Some(body) = body {
+            cmd.arg("-d").arg(body.to_string());
+        }
+        debug!("TrueNAS request: {} {}", method, url);
+        let mut child = cmd
+            .arg(&url)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| SyncError::from_spawn("curl", e))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            writeln!(stdin, "Authorization: Bearer {}", self.config.api_key).context("Failed to pass the API key to curl")?;
+        }
+        let output = child.wait_with_output().context("Failed to execute curl")?;
+        if !output.status.success() {
+            let message = format!("TrueNAS request {} {} failed: {}", method, path, String::from_utf8_lossy(&output.stderr).trim());
+            return Err(if message.contains("401") {
+                SyncError::AuthFailed(format!("{}; check [nas.truenas] api_key", message)).into()
+            } else {
+                anyhow::anyhow!(message)
+            });
+        }
+        let body = String::from_utf8_lossy(&output.stdout);
+        if body.trim().is_empty() {
+            return Ok(Value::Null);
+        }
+        serde_json::from_str(&body).with_context(|| format!("TrueNAS answered {} with no JSON", path))
+    }
+
+    fn dataset_exists(&self, dataset: &str) -> Result<bool> {
+        let found = self.request("GET", "pool/dataset", &[("id", dataset)], None)?;
+        Ok(found.as_array().is_some_and(|datasets| !datasets.is_empty()))
+    }
+}
+
+impl Vendor for TrueNas {
+    fn name(&self) -> &'static str {
+        "TrueNAS"
+    }
+
+    /// Pool health and SMART alerts, and the dataset with its SMB share with create_datasets
+    fn prepare(&self, profile: &SyncProfile, dry_run: bool) -> Result<Vec<String>> {
+        let Some((pool, child)) = pool_path(&profile.remote_path) else {
+            debug!("{} is not below /mnt/<pool>, skipping TrueNAS checks", profile.remote_path);
+            return Ok(Vec::new());
+        };
+        let mut warnings = Vec::new();
+        let pools = self.request("GET", "pool", &[("name", pool)], None)?;
+        match pools.as_array().and_then(|pools| pools.first()) {
+            Some(found) => warnings.extend(check_pool(found)?),
+            None => warn!("TrueNAS reports no pool {}", pool),
+        }
+        warnings.extend(smart_warnings(&self.request("GET", "alert/list", &[], None)?));
+
+        if let (true, Some(child)) = (self.config.create_datasets, child) {
+            let dataset = format!("{}/{}", pool, child);
+            if !self.dataset_exists(&dataset)? {
+                if dry_run {
+                    info!("Would create dataset {} with an SMB share", dataset);
+                } else {
+                    info!("Creating dataset {} with an SMB share", dataset);
+                    self.request("POST", "pool/dataset", &[], Some(&json!({ "name": dataset })))?;
+                    self.request(
+                        "POST",
+                        "sharing/smb",
+                        &[],
+                        Some(&json!({ "path": format!("/mnt/{}", dataset), "name": child })),
+                    )?;
+                }
+            }
+        }
+        Ok(warnings)
+    }
+
+    /// Snapshot of the remote path's dataset below the pool, or of the pool itself
+    fn snapshot(&self, profile: &SyncProfile) -> Result<Option<String>> {
+        if !self.config.snapshot_before_mirror {
+            return Ok(None);
+        }
+        let Some((pool, child)) = pool_path(&profile.remote_path) else {
+            return Ok(None);
+        };
+        let dataset = match child.map(|child| format!("{}/{}", pool, child)) {
+            Some(dataset) if self.dataset_exists(&dataset)? => dataset,
+            _ => pool.to_string(),
+        };
+        let name = format!("ugnassync-{}", Local::now().format("%Y%m%d-%H%M%S"));
+        self.request("POST", "zfs/snapshot", &[], Some(&json!({ "dataset": dataset, "name": name })))
+            .context("Failed to snapshot before mirroring")?;
+        Ok(Some(format!("{}@{}", dataset, name)))
+    }
+
+    /// The pool being anything but ONLINE and healthy, or resilvering, and SMART alerts
+    fn health(&self, profile: &SyncProfile) -> Result<Vec<String>> {
+        let Some((pool, _)) = pool_path(&profile.remote_path) else {
+            return Ok(Vec::new());
+        };
+        let pools = self.request("GET", "pool", &[("name", pool)], None)?;
+        let mut problems: Vec<String> = pools.as_array().into_iter().flatten().filter_map(pool_problem).collect();
+        problems.extend(smart_warnings(&self.request("GET", "alert/list", &[], None)?));
+        Ok(problems)
+    }
+}
+
+/// `/mnt/tank/backups/laptop` -> (`tank`, `backups`)
+fn pool_path(remote_path: &str) -> Option<(&str, Option<&str>)> {
+    let mut parts = remote_path.strip_prefix("/mnt/")?.split('/').filter(|part| !part.is_empty());
+    Some((parts.next()?, parts.next()))
+}
+
+/// A degraded pool is a warning; one that cannot take writes stops the sync
+fn check_pool(pool: &Value) -> Result<Option<String>> {
+    let name = pool["name"].as_str().unwrap_or("?");
+    let status = pool["status"].as_str().unwrap_or("UNKNOWN");
+    if UNUSABLE_POOL_STATES.contains(&status) {
+        anyhow::bail!("Pool {} on the NAS is {}; not syncing to it", name, status);
+    }
+    let healthy = status == "ONLINE" && pool["healthy"].as_bool().unwrap_or(true);
+    Ok((!healthy).then(|| format!("Pool {} on the NAS is {}", name, status)))
+}
+
+/// Why a pool should not take a mirror sync right now, if it should not
+fn pool_problem(pool: &Value) -> Option<String> {
+    let name = pool["name"].as_str().unwrap_or("?");
+    let scan = &pool["scan"];
+    if scan["function"].as_str() == Some("RESILVER") && scan["state"].as_str() == Some("SCANNING") {
+        return Some(format!("Pool {} on the NAS is resilvering", name));
+    }
+    let status = pool["status"].as_str().unwrap_or("UNKNOWN");
+    (status != "ONLINE" || !pool["healthy"].as_bool().unwrap_or(true)).then(|| format!("Pool {} on the NAS is {}", name, status))
+}
+
+/// Active SMART alerts of the NAS
+fn smart_warnings(alerts: &Value) -> Vec<String> {
+    alerts
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|alert| !alert["dismissed"].as_bool().unwrap_or(false))
+        .filter(|alert| alert["klass"].as_str().is_some_and(|klass| klass.to_lowercase().starts_with("smart")))
+        .filter_map(|alert| alert["formatted"].as_str())
+        .map(|text| format!("TrueNAS: {}", text.trim()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pool_health() {
+        assert_eq!(pool_path("/mnt/tank/backups/laptop"), Some(("tank", Some("backups"))));
+        assert_eq!(pool_path("/mnt/tank"), Some(("tank", None)));
+        assert_eq!(pool_path("/volume1/backups"), None);
+
+        assert_eq!(check_pool(&json!({ "name": "tank", "status": "ONLINE", "healthy": true })).unwrap(), None);
+        assert_eq!(
+            check_pool(&json!({ "name": "tank", "status": "DEGRADED", "healthy": false })).unwrap().as_deref(),
+            Some("Pool tank on the NAS is DEGRADED")
+        );
+        assert!(check_pool(&json!({ "name": "tank", "status": "FAULTED" })).is_err());
+        let resilvering = json!({ "name": "tank", "status": "ONLINE", "healthy": true,
+            "scan": { "function": "RESILVER", "state": "SCANNING" } });
+        assert_eq!(pool_problem(&resilvering).as_deref(), Some("Pool tank on the NAS is resilvering"));
+        assert_eq!(pool_problem(&json!({ "name": "tank", "status": "ONLINE", "healthy": true })), None);
+
+        let alerts = json!([
+            { "klass": "SMART", "formatted": "Device: /dev/ada1, 8 Currently unreadable sectors.", "dismissed": false },
+            { "klass": "SMART", "formatted": "old", "dismissed": true },
+            { "klass": "UPSOnBattery", "formatted": "UPS on battery", "dismissed": false }
+        ]);
+        assert_eq!(smart_warnings(&alerts), vec!["TrueNAS: Device: /dev/ada1, 8 Currently unreadable sectors."]);
+    }
+}
@@ -0,0 +1,42 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use crate::config::{NasConfig, SyncProfile};
+use crate::dsm::Synology;
+use crate::qnap::Qnap;
+use crate::truenas::TrueNas;
+use anyhow::Result;
+
+/// Management API of a NAS vendor, used around SSH syncs
+pub trait Vendor {
+    fn name(&self) -> &'static str;
+
+    /// Check the storage behind the profile's remote_path and create its share or dataset
+    /// when configured; returns warnings for the run and fails when writing there is unsafe
+    fn prepare(&self, profile: &SyncProfile, dry_run: bool) -> Result<Vec<String>>;
+
+    /// Snapshot the remote side before a mirror sync deletes on it, returning the
+    /// snapshot's name; None when not configured or not offered by the vendor
+    fn snapshot(&self, _profile: &SyncProfile) -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    /// Degraded or rebuilding storage and failing disks behind the profile's remote_path,
+    /// for the health gate; empty when the vendor reports nothing wrong
+    fn health(&self, _profile: &SyncProfile) -> Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+}
+
+/// The vendor integration configured for the NAS (at most one), reached at `host`
+pub fn for_nas(nas: &NasConfig, host: &str) -> Option<Box<dyn Vendor>> {
+    if let Some(dsm) = &nas.dsm {
+        return Some(Box::new(Synology::new(dsm, host)));
+    }
+    if let Some(qnap) = &nas.qnap {
+        return Some(Box::new(Qnap::new(qnap, host)));
+    }
+    nas.truenas.as_ref().map(|truenas| Box::new(TrueNas::new(truenas, host)) as Box<dyn Vendor>)
+}
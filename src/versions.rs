@@ -0,0 +1,203 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use crate::config::{NasConfig, SyncProfile};
+use crate::remote::{shell_quote, RemoteShell};
+use crate::smb::SmbMount;
+use anyhow::{Context, Result};
+use chrono::Local;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::{debug, info};
+
+/// Directory below a profile's remote path holding one subdirectory per run
+pub const VERSIONS_DIR: &str = ".versions";
+
+/// Name of a run's directory below `.versions`, e.g. `2025-03-10_021500`
+pub fn run_stamp() -> String {
+    Local::now().format("%Y-%m-%d_%H%M%S").to_string()
+}
+
+/// An older copy of a file kept by a run, path relative to the transfer root
+#[derive(Debug, Clone, PartialEq)]
+pub struct Version {
+    pub stamp: String,
+    pub path: String,
+}
+
+/// Where commands against the remote path run: on the NAS over SSH, or here on an SMB mount
+pub enum Side {
+    Ssh(RemoteShell),
+    Mounted,
+}
+
+impl Side {
+    /// Run a shell command inside the profile's remote path
+    fn run(&self, profile: &SyncProfile, script: &str) -> Result<String> {
+        match self {
+            Side::Ssh(shell) => shell.run(&format!("cd {} && {}", shell_quote(&profile.remote_path), script)),
+            Side::Mounted => {
+                debug!("Running in {}: {}", profile.remote_path, script);
+                let output = Command::new("sh")
+                    .arg("-c")
+                    .arg(script)
+                    .current_dir(&profile.remote_path)
+                    .output()
+                    .context("Failed to execute sh")?;
+                if !output.status.success() {
+                    anyhow::bail!("'{}' failed: {}", script, String::from_utf8_lossy(&output.stderr).trim());
+                }
+                Ok(String::from_utf8_lossy(&output.stdout).to_string())
+            }
+        }
+    }
+}
+
+/// Every kept version of a profile, oldest run first
+pub fn list(side: &Side, profile: &SyncProfile) -> Result<Vec<Version>> {
+    let output = side.run(
+        profile,
+        &format!("if [ -d {dir} ]; then find {dir} -type f; fi", dir = VERSIONS_DIR),
+    )?;
+    let mut versions: Vec<Version> = output.lines().filter_map(parse_entry).collect();
+    versions.sort_by(|a, b| (&a.stamp, &a.path).cmp(&(&b.stamp, &b.path)));
+    Ok(versions)
+}
+
+/// `.versions/<stamp>/<path>`
+fn parse_entry(line: &str) -> Option<Version> {
+    let rest = line.trim_start_matches("./").strip_prefix(VERSIONS_DIR)?.strip_prefix('/')?;
+    let (stamp, path) = rest.split_once('/')?;
+    Some(Version {
+        stamp: stamp.to_string(),
+        path: path.to_string(),
+    })
+}
+
+/// Versions beyond the newest `keep` of each file
+fn prune_plan(versions: &[Version], keep: usize) -> Vec<&Version> {
+    let mut by_path: BTreeMap<&str, Vec<&Version>> = BTreeMap::new();
+    for version in versions {
+        by_path.entry(&version.path).or_default().push(version);
+    }
+    by_path
+        .into_values()
+        .flat_map(|mut copies| {
+            copies.sort_by(|a, b| b.stamp.cmp(&a.stamp));
+            copies.into_iter().skip(keep)
+        })
+        .collect()
+}
+
+/// Delete all but the newest `keep_versions` copies of each file, then empty run directories
+pub fn prune(side: &Side, profile: &SyncProfile) -> Result<usize> {
+    let Some(keep) = profile.keep_versions else {
+        return Ok(0);
+    };
+    let versions = list(side, profile)?;
+    let expired = prune_plan(&versions, keep);
+    for chunk in expired.chunks(100) {
+        let paths: Vec<String> = chunk
+            .iter()
+            .map(|version| shell_quote(&format!("{}/{}/{}", VERSIONS_DIR, version.stamp, version.path)))
+            .collect();
+        side.run(profile, &format!("rm -f -- {}", paths.join(" ")))?;
+    }
+    if !expired.is_empty() {
+        side.run(
+            profile,
+            &format!("find {} -depth -mindepth 1 -type d -empty -exec rmdir {{}} \\;", VERSIONS_DIR),
+        )?;
+        info!("Pruned {} old version(s) of profile {}", expired.len(), profile.name);
+    }
+    Ok(expired.len())
+}
+
+/// The remote side of a profile for the `versions` commands, mounting SMB shares as needed
+pub async fn open(nas: &NasConfig, profile: &SyncProfile) -> Result<(Side, Option<SmbMount>)> {
+    match (&nas.smb, profile.use_smb_mount) {
+        (Some(smb_config), true) if smb_config.enabled => {
+            let mut mount = SmbMount::new(smb_config.clone());
+            mount.mount().await?;
+            Ok((Side::Mounted, Some(mount)))
+        }
+        _ => Ok((Side::Ssh(RemoteShell::new(nas)?.with_owner(profile.owner.as_ref())), None)),
+    }
+}
+
+/// Path of a local file relative to the profile's transfer root, as used below `.versions`
+pub fn relative_path(profile: &SyncProfile, local: &Path) -> Result<String> {
+    local
+        .strip_prefix(profile.transfer_root())
+        .map(|relative| relative.to_string_lossy().to_string())
+        .with_context(|| format!("{} is not inside profile {}", local.display(), profile.name))
+}
+
+/// Copy a kept version back to `target`, moving an existing file there aside to `*.bak`
+pub fn restore(nas: &NasConfig, side: &Side, profile: &SyncProfile, version: &Version, target: &Path) -> Result<()> {
+    let source = format!(
+        "{}/{}/{}/{}",
+        profile.remote_path.trim_end_matches('/'),
+        VERSIONS_DIR,
+        version.stamp,
+        version.path
+    );
+    if target.exists() {
+        let mut backup = target.as_os_str().to_owned();
+        backup.push(".bak");
+        fs::rename(target, PathBuf::from(&backup)).with_context(|| format!("Failed to back up {}", target.display()))?;
+    }
+
+    match side {
+        Side::Mounted => {
+            fs::copy(&source, target).with_context(|| format!("Failed to copy {} to {}", source, target.display()))?;
+        }
+        Side::Ssh(shell) => {
+            let mut cmd = Command::new(nas.rsync_binary());
+            cmd.arg("-t").arg("-e").arg(shell.rsync_rsh());
+            shell.authenticate(&mut cmd)?;
+            if let Some(remote_rsync) = profile.remote_rsync_command() {
+                cmd.arg(format!("--rsync-path={}", remote_rsync));
+            }
+            let output = cmd
+                .arg(shell.destination(&source))
+                .arg(target)
+                .output()
+                .context("Failed to execute rsync")?;
+            if !output.status.success() {
+                anyhow::bail!("Fetching {} failed: {}", source, String::from_utf8_lossy(&output.stderr).trim());
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prune_keeps_newest_versions_per_file() {
+        let output = ".versions/2025-03-01_020000/docs/a.txt\n\
+                      .versions/2025-03-02_020000/docs/a.txt\n\
+                      .versions/2025-03-03_020000/docs/a.txt\n\
+                      .versions/2025-03-01_020000/docs/b.txt\n\
+                      docs/not-a-version.txt\n";
+        let versions: Vec<Version> = output.lines().filter_map(parse_entry).collect();
+        assert_eq!(versions.len(), 4);
+
+        let expired = prune_plan(&versions, 2);
+        assert_eq!(
+            expired,
+            vec![&Version {
+                stamp: "2025-03-01_020000".to_string(),
+                path: "docs/a.txt".to_string(),
+            }]
+        );
+        assert_eq!(prune_plan(&versions, 1).len(), 2);
+    }
+}
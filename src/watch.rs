@@ -3,81 +3,442 @@
 // Author: Immanuel Jeyaraj <irj@sefier.com>
 // License: GPL-3.0
 
-use crate::config::{NasConfig, SyncProfile};
-use crate::sync::SyncEngine;
+use crate::config::{BaseStoreConfig, Config, HealthConfig, InitialSync, LowChurnConfig, MetricsConfig, NasConfig, SyncProfile};
+use crate::control::{self, DaemonState};
+use crate::eta;
+use crate::filelist::{self, FileListCache};
+use crate::health;
+use crate::lowchurn;
+use crate::maintenance::Maintenance;
+use crate::mounts::{self, MountHold};
+use crate::notification::{Notifier, Severity};
+use crate::ransomware::Quarantine;
+use crate::rsync::RsyncVersion;
+use crate::schedule;
+use crate::smb;
+use crate::stats;
+use crate::sync::{RunStatus, SyncEngine, TransferCapReached};
 use anyhow::{Context, Result};
-use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
-use std::path::Path;
-use std::sync::mpsc::{channel, Receiver};
-use std::time::{Duration, Instant};
-use tokio::sync::Mutex;
+use chrono::Local;
+use notify::event::{ModifyKind, RenameMode};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+use tokio::sync::{Mutex, Semaphore};
+use tokio::task::{JoinHandle, JoinSet};
+use tokio::time::timeout;
 use tracing::{debug, error, info, warn};
 
+/// Restart delays for crashed watchers grow from the first to the last value
+const RESTART_BACKOFF_INITIAL: Duration = Duration::from_secs(1);
+const RESTART_BACKOFF_MAX: Duration = Duration::from_secs(300);
+
+/// A watcher that ran this long before dying is considered healthy again
+const RESTART_BACKOFF_RESET: Duration = Duration::from_secs(600);
+
+/// How long a removed or just created file waits for the rest of an atomic save
+const ATOMIC_SAVE_WINDOW: Duration = Duration::from_secs(2);
+
+/// A nested profile's claim on a change holds at least this long, covering the
+/// second in which both watchers poll for the same event
+const NESTED_CLAIM_MIN: Duration = Duration::from_secs(5);
+
+/// What the events of one path since the last sync amount to
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PathChange {
+    Modified,
+    Created,
+    Removed,
+    /// Removed and created again, as editors replace a file on save
+    Replaced,
+    /// Created and gone again (an editor's temp file), nothing to sync
+    Transient,
+}
+
+/// Coalesces the events of each pending path, so editors saving via write-temp-then-rename
+/// do not get their temp file, or the original missing, synced between the two halves
+#[derive(Debug, Default)]
+struct AtomicSaves {
+    changes: HashMap<PathBuf, (PathChange, Instant)>,
+}
+
+impl AtomicSaves {
+    fn record(&mut self, event: &Event, at: Instant) {
+        let (gone, appeared): (&[PathBuf], &[PathBuf]) = match event.kind {
+            EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(RenameMode::To)) => (&[], &event.paths),
+            EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(RenameMode::From)) => (&event.paths, &[]),
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) if event.paths.len() == 2 => event.paths.split_at(1),
+            _ => {
+                for path in &event.paths {
+                    self.update(path, PathChange::Modified, at);
+                }
+                return;
+            }
+        };
+        for path in gone {
+            self.update(path, PathChange::Removed, at);
+        }
+        for path in appeared {
+            self.update(path, PathChange::Created, at);
+        }
+    }
+
+    fn update(&mut self, path: &Path, change: PathChange, at: Instant) {
+        let previous = self.changes.get(path).map(|(change, _)| *change);
+        let change = match (previous, change) {
+            (Some(PathChange::Removed), PathChange::Created) => PathChange::Replaced,
+            (Some(PathChange::Created | PathChange::Transient), PathChange::Removed) => PathChange::Transient,
+            (Some(PathChange::Transient), PathChange::Created) => PathChange::Created,
+            (Some(previous), PathChange::Modified) => previous,
+            (_, change) => change,
+        };
+        self.changes.insert(path.to_path_buf(), (change, at));
+    }
+
+    /// Drop files that came and went from `pending`; returns the paths an atomic save
+    /// may still be in the middle of
+    fn settle(&mut self, pending: &mut HashSet<PathBuf>, now: Instant) -> HashSet<PathBuf> {
+        self.changes.retain(|path, (change, _)| {
+            if *change != PathChange::Transient {
+                return true;
+            }
+            debug!("{} was created and removed again, not syncing it", path.display());
+            pending.remove(path);
+            false
+        });
+        self.changes
+            .iter()
+            .filter(|(_, (change, at))| change != &PathChange::Modified && now.duration_since(*at) < ATOMIC_SAVE_WINDOW)
+            .map(|(path, _)| path.clone())
+            .collect()
+    }
+
+    /// Forget the paths a sync has covered
+    fn retain(&mut self, pending: &HashSet<PathBuf>) {
+        self.changes.retain(|path, _| pending.contains(path));
+    }
+}
+
 pub struct WatchManager {
     sync_engine: SyncEngine,
+    base_store: Option<BaseStoreConfig>,
+    notifier: Notifier,
+    state: DaemonState,
+    health: Option<HealthConfig>,
+    metrics: Option<MetricsConfig>,
+    low_churn: Option<LowChurnConfig>,
+    initial_sync_jobs: usize,
+    maintenance: Option<Maintenance>,
+}
+
+/// Handles shared by the supervisor, watcher and catch-up tasks of one profile
+#[derive(Clone)]
+struct WatchContext {
+    nas_config: NasConfig,
+    base_store: Option<BaseStoreConfig>,
+    notifier: Notifier,
+    state: DaemonState,
+    /// Serializes catch-up and event-driven syncs of the same profile
+    sync_lock: Arc<Mutex<()>>,
+    /// Roots of other watched profiles nested inside this one syncing to the same NAS
+    /// folder, with the profile's name; a change they queue is theirs to sync
+    nested_roots: Vec<(PathBuf, String)>,
+    /// Low-churn mode: sync at most once per window of this many minutes
+    window_minutes: Option<u64>,
+}
+
+impl WatchContext {
+    fn engine(&self) -> SyncEngine {
+        SyncEngine::new(self.nas_config.clone()).with_base_store(self.base_store.as_ref())
+    }
 }
 
 impl WatchManager {
-    pub fn new(nas_config: NasConfig) -> Self {
+    pub fn new(config: &Config) -> Self {
+        let state = DaemonState::new();
+        if let Some(low_churn) = &config.low_churn {
+            state.set_low_churn(low_churn.window_minutes);
+        }
         Self {
-            sync_engine: SyncEngine::new(nas_config),
+            sync_engine: SyncEngine::new(config.nas.clone()).with_base_store(config.base_store.as_ref()),
+            base_store: config.base_store.clone(),
+            notifier: Notifier::new(config.notifications.clone()).with_dsm_alerts(&config.nas),
+            state,
+            health: config.health.clone(),
+            metrics: config.metrics.clone(),
+            low_churn: config.low_churn.clone(),
+            initial_sync_jobs: config.initial_sync_jobs,
+            maintenance: (config.maintenance.interval_hours > 0).then(|| Maintenance::new(config, None)),
         }
     }
 
-    pub async fn start_watching(&self, profiles: Vec<&SyncProfile>) -> Result<()> {
-        if profiles.is_empty() {
+    pub async fn start_watching(&self, profiles: Vec<&SyncProfile>, scheduled: Vec<&SyncProfile>) -> Result<()> {
+        if profiles.is_empty() && scheduled.is_empty() {
             warn!("No profiles with watch mode enabled");
             return Ok(());
         }
 
         info!("Starting watch mode for {} profile(s)", profiles.len());
 
-        // Perform initial sync for all watch-enabled profiles
-        for profile in &profiles {
-            info!("Performing initial sync for: {}", profile.name);
-            match self.sync_engine.sync_profile(profile, false).await {
-                Ok(stats) => {
-                    info!(
-                        "Initial sync completed: {} files, {:.2} MB",
-                        stats.files_transferred,
-                        stats.bytes_transferred as f64 / (1024.0 * 1024.0)
-                    );
-                }
-                Err(e) => {
-                    error!("Initial sync failed for {}: {}", profile.name, e);
+        for profile in profiles.iter().chain(&scheduled) {
+            self.state.register(&profile.name);
+        }
+
+        // Shared mounts come up before any initial sync and stay for the whole session,
+        // except in low-churn mode where a mount between windows would keep the NAS busy
+        let all: Vec<&SyncProfile> = profiles.iter().chain(&scheduled).copied().collect();
+        let held_mounts = match self.low_churn {
+            Some(_) => MountHold::default(),
+            None => mounts::hold(&self.sync_engine.nas_config, &all).await,
+        };
+        if let Some(smb) = self.sync_engine.nas_config.smb.as_ref().filter(|smb| smb.enabled) {
+            if all.iter().any(|profile| profile.use_smb_mount) {
+                let mut smb = smb.clone();
+                if self.low_churn.is_some() && !smb.auto_unmount {
+                    smb.idle_unmount_minutes.get_or_insert(1);
                 }
+                tokio::spawn(smb::unmount_when_idle(smb));
             }
         }
 
-        // Create watchers for each profile
-        let mut handles = Vec::new();
+        let control_state = self.state.clone();
+        tokio::spawn(async move {
+            if let Err(e) = control::serve(control_state).await {
+                error!("Control socket failed: {:#}", e);
+            }
+        });
+
+        if let Some(health) = self.health.clone() {
+            let nas = self.sync_engine.nas_config.clone();
+            let health_state = self.state.clone();
+            let probe_nas = self.low_churn.is_none();
+            tokio::spawn(async move {
+                if let Err(e) = health::serve(health, nas, probe_nas, health_state).await {
+                    error!("Health endpoints failed: {:#}", e);
+                }
+            });
+        }
+
+        if let Some(metrics) = self.metrics.clone() {
+            tokio::spawn(stats::push_loop(metrics));
+        }
+
+        if let Some(maintenance) = self.maintenance.clone() {
+            tokio::spawn(maintenance.run_periodically(self.state.clone()));
+        }
 
-        for profile in profiles {
-            let profile_clone = profile.clone();
-            let engine = SyncEngine::new(self.sync_engine.nas_config.clone());
+        let mut handles = Vec::new();
+        let mut deferred = Vec::new();
+
+        if !scheduled.is_empty() {
+            handles.push(tokio::spawn(schedule::run(
+                scheduled.into_iter().cloned().collect(),
+                self.sync_engine.nas_config.clone(),
+                self.base_store.clone(),
+                self.notifier.clone(),
+                self.state.clone(),
+            )));
+        }
 
-            let handle = tokio::spawn(async move {
-                if let Err(e) = Self::watch_profile(engine, &profile_clone).await {
-                    error!("Watch failed for {}: {}", profile_clone.name, e);
+        for &profile in &profiles {
+            let nested_roots = Self::nested_roots(profile, &profiles);
+            let ctx = WatchContext {
+                nas_config: self.sync_engine.nas_config.clone(),
+                base_store: self.base_store.clone(),
+                notifier: self.notifier.clone(),
+                state: self.state.clone(),
+                sync_lock: Arc::new(Mutex::new(())),
+                nested_roots,
+                window_minutes: self.low_churn.as_ref().map(|low_churn| low_churn.window_minutes),
+            };
+
+            match profile.initial_sync {
+                InitialSync::Full => {
+                    deferred.push((profile, ctx));
+                    continue;
                 }
-            });
+                InitialSync::Skip => {
+                    info!("Skipping initial sync for: {}", profile.name);
+                }
+                InitialSync::Background => {
+                    info!("Starting background initial sync for: {}", profile.name);
+                    let engine = ctx.engine().with_low_priority();
+                    let profile_clone = profile.clone();
+                    let lock = ctx.sync_lock.clone();
+
+                    handles.push(tokio::spawn(async move {
+                        let _guard = lock.lock().await;
+                        Self::initial_sync(&engine, &profile_clone).await;
+                    }));
+                }
+            }
 
-            handles.push(handle);
+            handles.push(Self::spawn_supervisor(profile, ctx));
         }
 
-        // Wait for all watchers
+        // Full initial syncs run before each profile's watcher starts, highest priority
+        // first; profiles of equal priority share initial_sync_jobs concurrent syncs
+        for group in Self::priority_groups(deferred) {
+            let slots = Arc::new(Semaphore::new(self.initial_sync_jobs.max(1)));
+            let mut syncs = JoinSet::new();
+            for (profile, ctx) in group {
+                let (profile, slots) = (profile.clone(), slots.clone());
+                syncs.spawn(async move {
+                    let _slot = slots.acquire_owned().await;
+                    info!("Performing initial sync for: {} (priority {})", profile.name, profile.priority);
+                    Self::initial_sync(&ctx.engine(), &profile).await;
+                    (profile, ctx)
+                });
+            }
+            while let Some(synced) = syncs.join_next().await {
+                match synced {
+                    Ok((profile, ctx)) => handles.push(Self::spawn_supervisor(&profile, ctx)),
+                    Err(e) => error!("Initial sync task failed: {}", e),
+                }
+            }
+        }
+
+        // Wait for all supervisors
         for handle in handles {
             let _ = handle.await;
         }
 
+        held_mounts.release().await;
         Ok(())
     }
 
-    async fn watch_profile(engine: SyncEngine, profile: &SyncProfile) -> Result<()> {
+    /// Profiles by descending priority, those of equal priority together in config order
+    fn priority_groups<T>(mut profiles: Vec<(&SyncProfile, T)>) -> Vec<Vec<(&SyncProfile, T)>> {
+        profiles.sort_by_key(|(profile, _)| std::cmp::Reverse(profile.priority));
+        let mut groups: Vec<Vec<(&SyncProfile, T)>> = Vec::new();
+        for entry in profiles {
+            match groups.last_mut() {
+                Some(group) if group[0].0.priority == entry.0.priority => group.push(entry),
+                _ => groups.push(vec![entry]),
+            }
+        }
+        groups
+    }
+
+    /// Find watch roots of other profiles inside this profile's roots, warning about overlaps
+    fn nested_roots(profile: &SyncProfile, profiles: &[&SyncProfile]) -> Vec<(PathBuf, String)> {
+        let mut nested = Vec::new();
+
+        for other in profiles.iter().filter(|p| p.name != profile.name) {
+            for own_root in profile.source_paths() {
+                for other_root in other.source_paths() {
+                    let (own, theirs) = (Path::new(own_root), Path::new(other_root));
+
+                    if own == theirs {
+                        warn!(
+                            "Profiles '{}' and '{}' watch the same directory {}; both will sync on every change",
+                            profile.name, other.name, own_root
+                        );
+                    } else if theirs.starts_with(own) && Self::nas_path(profile, theirs) == Self::nas_path(other, theirs) {
+                        warn!(
+                            "Profile '{}' ({}) is nested inside '{}' ({}); changes there only trigger '{}'",
+                            other.name, other_root, profile.name, own_root, other.name
+                        );
+                        nested.push((theirs.to_path_buf(), other.name.clone()));
+                    }
+                }
+            }
+        }
+
+        nested
+    }
+
+    /// Where a profile puts a local directory on the NAS
+    fn nas_path(profile: &SyncProfile, local: &Path) -> Option<PathBuf> {
+        let relative = local.strip_prefix(profile.transfer_root()).ok()?;
+        Some(Path::new(&profile.remote_path).join(relative))
+    }
+
+    /// A change below a nested profile's root that the profile queued within `window` and
+    /// is not paused for; the parent leaves it to that profile instead of syncing it twice
+    fn handled_by_nested(nested_roots: &[(PathBuf, String)], state: &DaemonState, path: &Path, window: Duration) -> bool {
+        nested_roots.iter().any(|(root, name)| {
+            path.starts_with(root) && !state.is_paused(name) && state.claimed_within(name, path, window)
+        })
+    }
+
+    async fn initial_sync(engine: &SyncEngine, profile: &SyncProfile) {
+        match engine.sync_profile(profile, false).await {
+            Ok(stats) => {
+                info!(
+                    "Initial sync completed for {}: {} files, {:.2} MB",
+                    profile.name,
+                    stats.files_transferred,
+                    stats.bytes_transferred as f64 / (1024.0 * 1024.0)
+                );
+            }
+            Err(e) => {
+                error!("Initial sync failed for {}: {}", profile.name, e);
+            }
+        }
+    }
+
+    fn spawn_supervisor(profile: &SyncProfile, ctx: WatchContext) -> JoinHandle<()> {
+        let profile_clone = profile.clone();
+
+        tokio::spawn(async move {
+            Self::supervise_profile(profile_clone, ctx).await;
+        })
+    }
+
+    /// Keep a profile's watcher running, restarting it with backoff when it dies
+    async fn supervise_profile(profile: SyncProfile, ctx: WatchContext) {
+        let mut backoff = RESTART_BACKOFF_INITIAL;
+
+        loop {
+            let engine = ctx.engine();
+            let task_profile = profile.clone();
+            let task_ctx = ctx.clone();
+            let started = Instant::now();
+
+            let result = tokio::spawn(async move {
+                Self::watch_profile(engine, &task_profile, task_ctx).await
+            })
+            .await;
+
+            let reason = match result {
+                Ok(Ok(())) => "watcher stopped unexpectedly".to_string(),
+                Ok(Err(e)) => format!("watcher failed: {:#}", e),
+                Err(e) if e.is_panic() => "watcher task panicked".to_string(),
+                Err(e) => format!("watcher task aborted: {}", e),
+            };
+
+            ctx.state.update(&profile.name, |s| s.watcher_error = Some(reason.clone()));
+
+            if started.elapsed() >= RESTART_BACKOFF_RESET {
+                backoff = RESTART_BACKOFF_INITIAL;
+            }
+
+            error!(
+                "Watch for profile {} died ({}), restarting in {}s",
+                profile.name,
+                reason,
+                backoff.as_secs()
+            );
+            ctx.notifier.send(
+                Severity::Warning,
+                &format!("UGNasSync watcher restarted: {}", profile.name),
+                &reason,
+            );
+
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(RESTART_BACKOFF_MAX);
+        }
+    }
+
+    async fn watch_profile(engine: SyncEngine, profile: &SyncProfile, ctx: WatchContext) -> Result<()> {
         info!("Watch mode enabled for profile: {}", profile.name);
-        info!("Monitoring: {}", profile.local_path);
 
-        let (tx, rx) = channel();
+        let (tx, rx) = unbounded_channel();
         let mut watcher: RecommendedWatcher = Watcher::new(
             move |res: Result<Event, notify::Error>| {
                 if let Ok(event) = res {
@@ -88,16 +449,20 @@ impl WatchManager {
         )
         .context("Failed to create file watcher")?;
 
-        // Watch the local path
-        watcher
-            .watch(Path::new(&profile.local_path), RecursiveMode::Recursive)
-            .with_context(|| format!("Failed to watch directory: {}", profile.local_path))?;
+        // Register every local path of the profile with the same watcher
+        for local_path in profile.source_paths() {
+            info!("Monitoring: {}", local_path);
+            watcher
+                .watch(Path::new(local_path), RecursiveMode::Recursive)
+                .with_context(|| format!("Failed to watch directory: {}", local_path))?;
+        }
+        ctx.state.update(&profile.name, |s| s.watcher_error = None);
 
         // Debounce handling
         let debounce_duration = Duration::from_secs(profile.debounce_seconds);
         let last_sync = Mutex::new(Instant::now());
 
-        Self::handle_watch_events(engine, profile, rx, debounce_duration, last_sync).await?;
+        Self::handle_watch_events(engine, profile, rx, debounce_duration, last_sync, ctx).await?;
 
         Ok(())
     }
@@ -105,54 +470,216 @@ impl WatchManager {
     async fn handle_watch_events(
         engine: SyncEngine,
         profile: &SyncProfile,
-        rx: Receiver<Event>,
+        mut rx: UnboundedReceiver<Event>,
         debounce_duration: Duration,
         last_sync: Mutex<Instant>,
+        ctx: WatchContext,
     ) -> Result<()> {
-        let mut pending_changes = false;
+        let mut pending_paths: HashSet<PathBuf> = HashSet::new();
+        let mut saves = AtomicSaves::default();
+        // Built after the first full sync when file_list_cache is enabled
+        let mut file_list: Option<FileListCache> = None;
+        let exclude_patterns = profile.effective_excludes();
+        let stability_window = Duration::from_secs(profile.stability_seconds);
+        // Changes wait for the next window, the initial sync covered the current one
+        let mut synced_window = ctx.window_minutes.map(|minutes| lowchurn::window(Local::now(), minutes));
 
         loop {
-            match rx.recv_timeout(Duration::from_secs(1)) {
-                Ok(event) => {
+            match timeout(Duration::from_secs(1), rx.recv()).await {
+                Ok(Some(event)) => {
+                    // The watcher lost events, only a full sync can catch up
+                    if event.need_rescan() && file_list.take().is_some() {
+                        warn!("File watcher needs a rescan, dropping cached file list");
+                    }
+
                     // Filter events based on exclude patterns
-                    let should_process = event.paths.iter().all(|path| {
-                        !Self::is_excluded(path, &profile.exclude)
-                    });
+                    let should_process = event.paths.iter().all(|path| !Self::is_excluded(path, &exclude_patterns));
 
                     if should_process {
                         debug!("File change detected: {:?}", event.paths);
-                        pending_changes = true;
+                        for path in &event.paths {
+                            ctx.state.claim(&profile.name, path);
+                        }
+                        saves.record(&event, Instant::now());
+                        pending_paths.extend(event.paths);
+                        ctx.state.update(&profile.name, |s| s.pending_changes = pending_paths.len());
                     }
                 }
-                Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {
+                Err(_) => {
+                    // Paused profiles keep queueing events but never sync
+                    if ctx.state.is_paused(&profile.name) {
+                        continue;
+                    }
+
+                    let current_window = ctx.window_minutes.map(|minutes| lowchurn::window(Local::now(), minutes));
+                    if current_window.is_some() && current_window == synced_window {
+                        continue;
+                    }
+
                     // Check if we should trigger sync
-                    if pending_changes {
+                    if !pending_paths.is_empty() {
                         let mut last = last_sync.lock().await;
                         let elapsed = last.elapsed();
 
                         if elapsed >= debounce_duration {
+                            // Changes a nested profile queued in this debounce window are its own
+                            let window = debounce_duration.max(NESTED_CLAIM_MIN);
+                            pending_paths.retain(|path| !Self::handled_by_nested(&ctx.nested_roots, &ctx.state, path, window));
+                            saves.retain(&pending_paths);
+                            let saving = saves.settle(&mut pending_paths, Instant::now());
+                            if pending_paths.is_empty() {
+                                ctx.state.update(&profile.name, |s| s.pending_changes = 0);
+                                continue;
+                            }
+
+                            // Files still being written are held back for the next cycle
+                            let open = if profile.skip_open_files { Self::open_files() } else { HashSet::new() };
+                            let unstable: HashSet<PathBuf> = pending_paths
+                                .iter()
+                                .filter(|path| {
+                                    saving.contains(*path)
+                                        || Self::is_unstable(path, stability_window)
+                                        || fs::canonicalize(path).is_ok_and(|target| open.contains(&target))
+                                })
+                                .cloned()
+                                .collect();
+
+                            if unstable.len() == pending_paths.len() {
+                                debug!("All changed files are still being written, waiting...");
+                                continue;
+                            }
+
+                            let mut run_profile = profile.clone();
+                            for path in &unstable {
+                                info!("Skipping unstable file until next cycle: {}", path.display());
+                                if let Some(pattern) = Self::anchored_exclude(profile, path) {
+                                    run_profile.exclude.push(pattern);
+                                }
+                            }
+
+                            // With a cached NAS file list only the changed paths need a look
+                            let changed: Vec<PathBuf> = pending_paths.difference(&unstable).cloned().collect();
+                            let listed = file_list
+                                .as_ref()
+                                .filter(|cache| !cache.is_stale() && engine.rsync_supports(RsyncVersion::supports_3_1))
+                                .and_then(|cache| cache.plan(profile, &changed));
+                            if listed.as_ref().is_some_and(|paths| paths.is_empty()) {
+                                debug!("Changed paths already match the NAS, nothing to sync");
+                                pending_paths = unstable;
+                                saves.retain(&pending_paths);
+                                ctx.state.update(&profile.name, |s| s.pending_changes = pending_paths.len());
+                                continue;
+                            }
+
                             info!("Debounce period elapsed, starting sync...");
-                            pending_changes = false;
+                            pending_paths = unstable;
+                            saves.retain(&pending_paths);
                             *last = Instant::now();
+                            synced_window = current_window;
                             drop(last); // Release lock before sync
 
-                            match engine.sync_profile(profile, false).await {
+                            let _guard = ctx.sync_lock.lock().await;
+                            let expected_secs = eta::history(&profile.name).expected_secs(Some(eta::size_of(&changed)));
+                            ctx.state.record_nas_access();
+                            ctx.state.update(&profile.name, |s| {
+                                s.syncing = true;
+                                s.pending_changes = pending_paths.len();
+                                s.sync_started = Some(Local::now());
+                                s.expected_secs = expected_secs;
+                            });
+
+                            let listed_engine = listed.as_ref().and_then(|paths| match filelist::write_list(profile, paths) {
+                                Ok(list) => {
+                                    info!("Syncing {} changed path(s) from the cached file list", paths.len());
+                                    Some(ctx.engine().with_files_from(list))
+                                }
+                                Err(e) => {
+                                    warn!("{:#}, running a full sync", e);
+                                    None
+                                }
+                            });
+
+                            let sync_engine = listed_engine.as_ref().unwrap_or(&engine);
+                            let result = sync_engine.sync_profile(&run_profile, false).await;
+                            let notifier = ctx.notifier.for_run(sync_engine.run_id(&profile.name));
+                            let result = match result {
                                 Ok(stats) => {
+                                    ctx.state.update(&profile.name, |s| {
+                                        s.warnings = stats.warnings.clone();
+                                        s.last_success = Some(Local::now());
+                                    });
+                                    notifier.send_recovery(&profile.name);
+                                    if !stats.planned_conflicts.is_empty() {
+                                        notifier.send_conflicts(&profile.name, &stats.conflicting_local_paths(profile));
+                                    }
+                                    // Changed-path lists rely on --delete-missing-args (rsync 3.1)
+                                    if profile.file_list_cache && engine.rsync_supports(RsyncVersion::supports_3_1) {
+                                        match (listed_engine.is_some(), listed.as_deref(), file_list.as_mut()) {
+                                            (true, Some(paths), Some(cache)) => cache.update(profile, paths),
+                                            _ => {
+                                                file_list = FileListCache::build(&engine.nas_config, profile)
+                                                    .await
+                                                    .map_err(|e| warn!("Failed to cache NAS file list: {:#}", e))
+                                                    .ok();
+                                            }
+                                        }
+                                    }
                                     info!(
                                         "Transferred {} file(s) ({:.2} MB) in {:.2}s",
                                         stats.files_transferred,
                                         stats.bytes_transferred as f64 / (1024.0 * 1024.0),
                                         stats.duration_secs
                                     );
+                                    if stats.status == RunStatus::Partial {
+                                        format!(
+                                            "partial, {} file(s), {} not transferred",
+                                            stats.files_transferred,
+                                            stats.file_errors.len()
+                                        )
+                                    } else {
+                                        format!("ok, {} file(s)", stats.files_transferred)
+                                    }
                                 }
                                 Err(e) => {
                                     error!("Sync failed: {}", e);
+                                    // The NAS may be partly updated, start over with a full sync
+                                    file_list = None;
+                                    if let Some(quarantine) = e.downcast_ref::<Quarantine>() {
+                                        // Stay paused until someone inspects the files and resumes
+                                        ctx.state.set_paused(&profile.name, true);
+                                        notifier.send_failure(
+                                            &profile.name,
+                                            Severity::Critical,
+                                            &format!("Profile {} quarantined", profile.name),
+                                            &quarantine.reason,
+                                        );
+                                        format!("quarantined: {}", quarantine.reason)
+                                    } else if e.is::<TransferCapReached>() {
+                                        format!("skipped: {}", e)
+                                    } else if let Some(sync_error) = crate::error::find(&e) {
+                                        notifier.send_failure(
+                                            &profile.name,
+                                            sync_error.severity(),
+                                            &format!("Profile {} failed ({})", profile.name, sync_error.kind()),
+                                            &sync_error.to_string(),
+                                        );
+                                        format!("failed ({}): {}", sync_error.kind(), e)
+                                    } else {
+                                        format!("failed: {}", e)
+                                    }
                                 }
-                            }
+                            };
+
+                            ctx.state.record_nas_access();
+                            ctx.state.update(&profile.name, |s| {
+                                s.syncing = false;
+                                s.last_sync = Some(Local::now());
+                                s.last_result = Some(result);
+                            });
                         }
                     }
                 }
-                Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => {
+                Ok(None) => {
                     warn!("File watcher disconnected");
                     break;
                 }
@@ -162,17 +689,99 @@ impl WatchManager {
         Ok(())
     }
 
-    fn is_excluded(path: &Path, exclude_patterns: &[String]) -> bool {
+    /// Check whether a file was modified within the stability window
+    fn is_unstable(path: &Path, window: Duration) -> bool {
+        if window.is_zero() {
+            return false;
+        }
+
+        match fs::metadata(path).and_then(|m| m.modified()) {
+            Ok(modified) => SystemTime::now()
+                .duration_since(modified)
+                .map(|age| age < window)
+                .unwrap_or(false), // mtime in the future (wrong camera clock, unpacked archive)
+            Err(_) => false, // Deleted files are handled by rsync
+        }
+    }
+
+    /// Files any process holds open, from one scan of /proc per debounce cycle (Linux)
+    fn open_files() -> HashSet<PathBuf> {
+        let Ok(procs) = fs::read_dir("/proc") else {
+            return HashSet::new();
+        };
+
+        procs
+            .flatten()
+            .filter_map(|proc_entry| fs::read_dir(proc_entry.path().join("fd")).ok())
+            .flat_map(|fds| fds.flatten().filter_map(|fd| fs::read_link(fd.path()).ok()))
+            .collect()
+    }
+
+    /// Build an rsync exclude pattern anchored to the transfer root for a single file
+    pub(crate) fn anchored_exclude(profile: &SyncProfile, path: &Path) -> Option<String> {
+        // With --relative the full source path is part of the transfer
+        if profile.uses_relative_paths() {
+            return Some(path.display().to_string());
+        }
+
+        let local_path = profile.local_path.as_str();
+        let root = Path::new(local_path);
+        let relative = path.strip_prefix(root).ok()?;
+
+        // Without a trailing slash rsync transfers the directory itself
+        if local_path.ends_with('/') {
+            Some(format!("/{}", relative.display()))
+        } else {
+            let dir_name = root.file_name()?.to_string_lossy();
+            Some(format!("/{}/{}", dir_name, relative.display()))
+        }
+    }
+
+    pub(crate) fn is_excluded(path: &Path, exclude_patterns: &[String]) -> bool {
         let path_str = path.to_string_lossy();
 
         for pattern in exclude_patterns {
-            if path_str.contains(pattern) {
+            if pattern.contains('*') || pattern.contains('?') {
+                // Wildcard patterns match individual path components, like rsync
+                let matched = path
+                    .components()
+                    .any(|c| Self::glob_match(pattern, &c.as_os_str().to_string_lossy()));
+                if matched {
+                    return true;
+                }
+            } else if path_str.contains(pattern.as_str()) {
                 return true;
             }
         }
 
         false
     }
+
+    /// Minimal shell-style wildcard matching supporting `*` and `?`
+    fn glob_match(pattern: &str, name: &str) -> bool {
+        let pattern: Vec<char> = pattern.chars().collect();
+        let name: Vec<char> = name.chars().collect();
+        let (mut p, mut n) = (0, 0);
+        let mut backtrack: Option<(usize, usize)> = None;
+
+        while n < name.len() {
+            if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+                p += 1;
+                n += 1;
+            } else if p < pattern.len() && pattern[p] == '*' {
+                backtrack = Some((p, n));
+                p += 1;
+            } else if let Some((star_p, star_n)) = backtrack {
+                p = star_p + 1;
+                n = star_n + 1;
+                backtrack = Some((star_p, star_n + 1));
+            } else {
+                return false;
+            }
+        }
+
+        pattern[p..].iter().all(|&c| c == '*')
+    }
 }
 
 #[cfg(test)]
@@ -195,4 +804,166 @@ mod tests {
 
         assert!(!WatchManager::is_excluded(&path, &exclude));
     }
+
+    #[test]
+    fn test_default_excludes_match() {
+        let exclude: Vec<String> = crate::config::DEFAULT_EXCLUDES
+            .iter()
+            .map(|p| p.to_string())
+            .collect();
+
+        assert!(WatchManager::is_excluded(&PathBuf::from("/docs/.report.md.swp"), &exclude));
+        assert!(WatchManager::is_excluded(&PathBuf::from("/docs/~$budget.xlsx"), &exclude));
+        assert!(WatchManager::is_excluded(&PathBuf::from("/dl/movie.mkv.crdownload"), &exclude));
+        assert!(!WatchManager::is_excluded(&PathBuf::from("/docs/budget.xlsx"), &exclude));
+    }
+
+    fn test_profile(local_paths: &str) -> SyncProfile {
+        toml::from_str(&format!(
+            r#"
+            name = "test"
+            {}
+            remote_path = "/volume1/test"
+            sync_type = "mirror"
+            enabled = true
+            "#,
+            local_paths
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_nested_roots() {
+        let mut parent = test_profile(r#"local_path = "/data""#);
+        parent.name = "parent".to_string();
+        let mut child = test_profile(r#"local_path = "/data/photos""#);
+        child.name = "child".to_string();
+        child.remote_path = "/volume1/test/data".to_string();
+        let mut sibling = test_profile(r#"local_path = "/database""#);
+        sibling.name = "sibling".to_string();
+        // Synced to a folder of its own, so the parent keeps its copy up to date
+        let mut elsewhere = test_profile(r#"local_path = "/data/music""#);
+        elsewhere.name = "elsewhere".to_string();
+        let profiles = vec![&parent, &child, &sibling, &elsewhere];
+
+        assert_eq!(
+            WatchManager::nested_roots(&parent, &profiles),
+            vec![(PathBuf::from("/data/photos"), "child".to_string())]
+        );
+        assert!(WatchManager::nested_roots(&child, &profiles).is_empty());
+    }
+
+    #[test]
+    fn test_nested_profile_claims_expire() {
+        let nested = vec![(PathBuf::from("/data/photos"), "child".to_string())];
+        let state = DaemonState::new();
+        state.register("child");
+        let path = Path::new("/data/photos/cat.jpg");
+        let window = Duration::from_millis(50);
+
+        // Not queued by the child (disabled, excluded there): the parent syncs it
+        assert!(!WatchManager::handled_by_nested(&nested, &state, path, window));
+
+        state.claim("child", path);
+        assert!(WatchManager::handled_by_nested(&nested, &state, path, window));
+        assert!(!WatchManager::handled_by_nested(&nested, &state, Path::new("/data/notes.txt"), window));
+        state.set_paused("child", true);
+        assert!(!WatchManager::handled_by_nested(&nested, &state, path, window));
+        state.set_paused("child", false);
+
+        // A later change the child does not queue again is the parent's to sync
+        std::thread::sleep(window);
+        assert!(!WatchManager::handled_by_nested(&nested, &state, path, window));
+    }
+
+    #[test]
+    fn test_priority_groups() {
+        let profile = |name: &str, priority: i32| {
+            let mut profile = test_profile(r#"local_path = "/data""#);
+            profile.name = name.to_string();
+            profile.priority = priority;
+            profile
+        };
+        let (videos, documents, mail, photos) = (profile("videos", 0), profile("documents", 10), profile("mail", 10), profile("photos", 0));
+
+        let groups = WatchManager::priority_groups(vec![(&videos, ()), (&documents, ()), (&photos, ()), (&mail, ())]);
+        let names: Vec<Vec<&str>> = groups.iter().map(|group| group.iter().map(|(p, _)| p.name.as_str()).collect()).collect();
+        assert_eq!(names, vec![vec!["documents", "mail"], vec!["videos", "photos"]]);
+    }
+
+    #[test]
+    fn test_anchored_exclude() {
+        let path = PathBuf::from("/home/user/Documents/notes/todo.md");
+
+        assert_eq!(
+            WatchManager::anchored_exclude(&test_profile(r#"local_path = "/home/user/Documents""#), &path)
+                .as_deref(),
+            Some("/Documents/notes/todo.md")
+        );
+        assert_eq!(
+            WatchManager::anchored_exclude(&test_profile(r#"local_path = "/home/user/Documents/""#), &path)
+                .as_deref(),
+            Some("/notes/todo.md")
+        );
+        assert_eq!(
+            WatchManager::anchored_exclude(
+                &test_profile(r#"local_paths = ["/home/user/Documents", "/home/user/Music"]"#),
+                &path
+            )
+            .as_deref(),
+            Some("/home/user/Documents/notes/todo.md")
+        );
+    }
+
+    #[test]
+    fn test_future_mtime_is_stable() {
+        let file = std::env::temp_dir().join(format!("ugnassync-stability-{}", std::process::id()));
+        fs::write(&file, "x").unwrap();
+        assert!(WatchManager::is_unstable(&file, Duration::from_secs(60)));
+
+        // It would otherwise be held back on every cycle
+        let later = SystemTime::now() + Duration::from_secs(24 * 3600);
+        fs::File::options().write(true).open(&file).unwrap().set_modified(later).unwrap();
+        assert!(!WatchManager::is_unstable(&file, Duration::from_secs(60)));
+
+        fs::remove_file(&file).unwrap();
+    }
+
+    #[test]
+    fn test_open_files() {
+        let file = std::env::temp_dir().join(format!("ugnassync-open-{}", std::process::id()));
+        let handle = fs::File::create(&file).unwrap();
+        assert!(WatchManager::open_files().contains(&fs::canonicalize(&file).unwrap()));
+        drop(handle);
+        fs::remove_file(&file).unwrap();
+    }
+
+    #[test]
+    fn test_atomic_save_is_coalesced() {
+        use notify::event::{CreateKind, RemoveKind};
+        let event = |kind: EventKind, paths: &[&str]| Event {
+            kind,
+            paths: paths.iter().map(PathBuf::from).collect(),
+            attrs: Default::default(),
+        };
+        let (doc, temp) = (PathBuf::from("/docs/report.odt"), PathBuf::from("/docs/.~report.odt.tmp"));
+        let start = Instant::now();
+        let mut saves = AtomicSaves::default();
+        let mut pending: HashSet<PathBuf> = [doc.clone(), temp.clone()].into();
+
+        // Write the temp file, remove the original, rename the temp file over it
+        saves.record(&event(EventKind::Create(CreateKind::File), &["/docs/.~report.odt.tmp"]), start);
+        saves.record(&event(EventKind::Remove(RemoveKind::File), &["/docs/report.odt"]), start);
+        saves.record(
+            &event(
+                EventKind::Modify(ModifyKind::Name(RenameMode::Both)),
+                &["/docs/.~report.odt.tmp", "/docs/report.odt"],
+            ),
+            start,
+        );
+        assert_eq!(saves.settle(&mut pending, start + Duration::from_secs(1)), [doc.clone()].into());
+        assert_eq!(pending, [doc.clone()].into());
+        assert!(saves.settle(&mut pending, start + ATOMIC_SAVE_WINDOW).is_empty());
+        assert_eq!(saves.changes[&doc].0, PathChange::Replaced);
+    }
 }
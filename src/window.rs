@@ -0,0 +1,111 @@
+// UGNasSync - NAS Synchronization Tool
+// Copyright (c) 2025 Sefier AI
+// Author: Immanuel Jeyaraj <irj@sefier.com>
+// License: GPL-3.0
+
+use crate::config::SyncProfile;
+use crate::conflict::ConflictResolver;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Local, NaiveDateTime, NaiveTime};
+
+/// Daily local time span a scheduled profile may start in, e.g. `01:00-06:00`;
+/// a span like `22:00-04:00` runs over midnight
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimeWindow {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl TimeWindow {
+    pub fn parse(window: &str) -> Result<Self> {
+        let (start, end) = window
+            .split_once('-')
+            .with_context(|| format!("Time window '{}' should look like 01:00-06:00", window))?;
+        let time = |value: &str| {
+            NaiveTime::parse_from_str(value.trim(), "%H:%M")
+                .with_context(|| format!("Invalid time '{}' in window '{}'", value.trim(), window))
+        };
+        let (start, end) = (time(start)?, time(end)?);
+        if start == end {
+            anyhow::bail!("Time window '{}' is empty", window);
+        }
+        Ok(Self { start, end })
+    }
+
+    /// When the occurrence of the window that `now` falls in opened, if it is open
+    pub fn opened_at(&self, now: NaiveDateTime) -> Option<NaiveDateTime> {
+        let time = now.time();
+        let today = now.date().and_time(self.start);
+        if self.start < self.end {
+            (self.start <= time && time < self.end).then_some(today)
+        } else if time >= self.start {
+            Some(today)
+        } else if time < self.end {
+            Some(today - Duration::days(1))
+        } else {
+            None
+        }
+    }
+}
+
+/// Whether a scheduled run of a profile should start now
+#[derive(Debug, PartialEq)]
+pub enum WindowCheck {
+    /// No window, or open and the profile has not run in it yet
+    Run,
+    /// The window is closed; the run happens at the first invocation once it opens
+    Deferred(String),
+    /// Already synced in the current window
+    Done(String),
+}
+
+/// Check a profile's `allowed_window`. A profile runs once per window occurrence,
+/// so a window missed while the machine was off is caught up by the first
+/// invocation in the next one. Without a state database only the window itself
+/// is checked.
+pub fn check(profile: &SyncProfile, resolver: Option<&ConflictResolver>, now: DateTime<Local>) -> Result<WindowCheck> {
+    let Some(window) = &profile.allowed_window else {
+        return Ok(WindowCheck::Run);
+    };
+    let parsed = TimeWindow::parse(window)?;
+
+    let Some(opened) = parsed.opened_at(now.naive_local()) else {
+        return Ok(WindowCheck::Deferred(format!("outside allowed window {}", window)));
+    };
+    let last_run = match resolver {
+        Some(resolver) => resolver.last_run(&profile.name)?,
+        None => None,
+    };
+    match last_run {
+        Some(last) if last.naive_local() >= opened => {
+            Ok(WindowCheck::Done(format!("already synced in window {} at {}", window, last.format("%H:%M"))))
+        }
+        Some(last) if opened - last.naive_local() > Duration::days(1) => {
+            tracing::info!("Profile {} last ran {}, catching up missed windows", profile.name, last.format("%Y-%m-%d %H:%M"));
+            Ok(WindowCheck::Run)
+        }
+        _ => Ok(WindowCheck::Run),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_window_over_midnight() {
+        let window = TimeWindow::parse("22:00-04:00").unwrap();
+        let at = |day: u32, hour: u32| NaiveDate::from_ymd_opt(2025, 3, day).unwrap().and_hms_opt(hour, 30, 0).unwrap();
+
+        assert_eq!(window.opened_at(at(10, 23)), Some(at(10, 22) - Duration::minutes(30)));
+        assert_eq!(window.opened_at(at(11, 1)), Some(at(10, 22) - Duration::minutes(30)));
+        assert_eq!(window.opened_at(at(11, 12)), None);
+
+        let night = TimeWindow::parse("01:00-06:00").unwrap();
+        assert_eq!(night.opened_at(at(11, 6)), None);
+        assert!(night.opened_at(at(11, 5)).is_some());
+        assert!(TimeWindow::parse("01:00").is_err());
+        assert!(TimeWindow::parse("03:00-03:00").is_err());
+    }
+}